@@ -0,0 +1,131 @@
+use std::io::{self, Write};
+
+use backuppc_pool_reader::compress::BackupPCReader;
+use backuppc_pool_reader::decode_attribut::AttributeFile;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+/// Number of entries of the attrib file (a large directory).
+const ATTRIB_ENTRIES: u32 = 10_000;
+/// Size of the decompressed content of the pool file.
+const CONTENT_SIZE: usize = 8 * 1024 * 1024;
+
+fn push_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Builds an uncompressed attrib file with regular files, each one with a name, a digest, and a few xattrs.
+fn attrib_fixture() -> Vec<u8> {
+    let mut bytes = 0x1756_5353_u32.to_be_bytes().to_vec();
+    for i in 0..ATTRIB_ENTRIES {
+        let name = format!("file-{i:05}.txt");
+        push_varint(&mut bytes, name.len() as u64);
+        bytes.extend_from_slice(name.as_bytes());
+
+        let xattrs = u64::from(i % 3);
+        push_varint(&mut bytes, xattrs);
+        // type, mtime, mode, uid, gid, size, inode, compress, nlinks
+        for value in [0, 1_700_000_000 + u64::from(i), 0o644, 1000, 1000] {
+            push_varint(&mut bytes, value);
+        }
+        for value in [u64::from(i) * 1024, u64::from(i) + 1, 3, 1] {
+            push_varint(&mut bytes, value);
+        }
+
+        push_varint(&mut bytes, 16);
+        bytes.extend((0..16).map(|byte| (i as u8).wrapping_mul(31).wrapping_add(byte)));
+
+        for xattr in 0..xattrs {
+            let key = format!("user.attribute{xattr}\0");
+            let value = format!("value-{i}");
+            push_varint(&mut bytes, key.len() as u64);
+            push_varint(&mut bytes, value.len() as u64);
+            bytes.extend_from_slice(key.as_bytes());
+            bytes.extend_from_slice(value.as_bytes());
+        }
+    }
+
+    bytes
+}
+
+/// Builds a compressed pool file whose content is text-like (compressible, but not trivially).
+fn compressed_fixture() -> Vec<u8> {
+    let mut content = Vec::with_capacity(CONTENT_SIZE);
+    let mut line = 0u64;
+    while content.len() < CONTENT_SIZE {
+        let _ = writeln!(
+            content,
+            "{line:08} {:016x} the quick brown fox jumps over the lazy dog",
+            line.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        );
+        line += 1;
+    }
+    content.truncate(CONTENT_SIZE);
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&content).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn bench_attribute_file(c: &mut Criterion) {
+    let attrib = attrib_fixture();
+
+    let mut group = c.benchmark_group("attribute_file");
+    group.throughput(Throughput::Bytes(attrib.len() as u64));
+    group.bench_function("read_from", |b| {
+        b.iter(|| AttributeFile::from_bytes(black_box(&attrib)).unwrap());
+    });
+    group.finish();
+}
+
+fn bench_backuppc_reader(c: &mut Criterion) {
+    let compressed = compressed_fixture();
+
+    let mut group = c.benchmark_group("backuppc_reader");
+    group.throughput(Throughput::Bytes(CONTENT_SIZE as u64));
+    group.bench_function("decompress", |b| {
+        b.iter_batched(
+            || BackupPCReader::new(compressed.as_slice()),
+            |mut reader| io::copy(&mut reader, &mut io::sink()).unwrap(),
+            BatchSize::SmallInput,
+        );
+    });
+    group.finish();
+}
+
+#[cfg(feature = "mmap")]
+fn bench_pool_source(c: &mut Criterion) {
+    use backuppc_pool_reader::source::{LocalSource, MmapSource, PoolSource};
+
+    let attrib = attrib_fixture();
+    let path = std::env::temp_dir().join(format!("bpc_bench_attrib_{}", std::process::id()));
+    std::fs::write(&path, &attrib).unwrap();
+
+    let mut group = c.benchmark_group("pool_source");
+    group.throughput(Throughput::Bytes(attrib.len() as u64));
+    group.bench_function("local", |b| {
+        b.iter(|| io::copy(&mut LocalSource.open(&path).unwrap(), &mut io::sink()).unwrap());
+    });
+    group.bench_function("mmap", |b| {
+        b.iter(|| io::copy(&mut MmapSource.open(&path).unwrap(), &mut io::sink()).unwrap());
+    });
+    group.finish();
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(not(feature = "mmap"))]
+fn bench_pool_source(_c: &mut Criterion) {}
+
+criterion_group!(
+    benches,
+    bench_attribute_file,
+    bench_backuppc_reader,
+    bench_pool_source
+);
+criterion_main!(benches);
@@ -1,26 +1,37 @@
-use log::info;
+use log::{info, warn};
 #[cfg(test)]
-use mockall::{automock, predicate::*};
+use mockall::{mock, predicate::*};
 
-use std::{cmp::Ordering, fs::File};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use crate::{
-    compress::BackupPCReader,
-    decode_attribut::{AttributeFile, FileAttributes},
-    pool::find_file_in_backuppc,
-    util::{hex_string_to_vec, mangle, mangle_filename, Result},
+    config::PoolConfig,
+    decode_attribut::{AttributeFile, FileAttributes, InvalidMagicError},
+    pool::{find_file_in_pool_roots, open_content},
+    reader::read_all_to_vec,
+    source::{LocalSource, PoolSource},
+    util::{
+        inode_name, mangle, mangle_filename, mangled_path, parse_inode_name, try_hex_string_to_vec,
+        Result,
+    },
 };
 
-#[cfg_attr(test, automock)]
 pub trait SearchTrait: Send + Sync {
     /// Read the attributes from a file
     ///
-    /// If the file is compressed, uncompress it with special `BackupPCReader` and read the attributes.
+    /// If the file is compressed, uncompress it with special `BackupPCReader` and read the attributes. A file of the
+    /// pool directory is read as is, the compression of a file of the cpool directory is detected from its first
+    /// bytes (see `pool::open_content`), so a file stored uncompressed in the cpool directory is read correctly.
     ///
     /// # Arguments
     ///
     /// * `file` - The path to the file to read.
-    /// * `is_compressed` - A boolean to know if the file is compressed.
+    /// * `is_compressed` - `true` if the file is in the cpool directory.
     ///
     /// # Returns
     ///
@@ -50,12 +61,12 @@ pub trait SearchTrait: Send + Sync {
     ///
     /// If the file cannot be read or uncompressed.
     /// If the file is not found in the pool.
-    fn list_file_from_dir<'a, 'b>(
+    fn list_file_from_dir(
         &self,
         hostname: &str,
         backup_number: u32,
-        share: Option<&'a str>,
-        filename: Option<&'b str>,
+        share: Option<&str>,
+        filename: Option<&str>,
     ) -> Result<Vec<FileAttributes>>;
     /// List the attributes for hostname and backup knowning the attrib file
     ///
@@ -85,6 +96,41 @@ pub trait SearchTrait: Send + Sync {
         attrib_path: &str,
         attrib_file: &str,
     ) -> Result<Vec<FileAttributes>>;
+    /// Find the entry of an inode in an attrib file of the inode directory
+    ///
+    /// The entry is selected with `select_inode`, so a name padded with zero bytes is also accepted. By default, the
+    /// attrib file is read with `list_attributes`; `Search` decodes its entries one by one instead, and stops at the
+    /// first entry with the exact name of the inode, so a very large attrib file isn't kept in memory nor read whole.
+    /// (A name duplicated in the attrib file, which `BackupPC` doesn't write, can then resolve to its first entry
+    /// instead of the last one.)
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The name of the host to list the attributes.
+    /// * `backup_number` - The number of the backup to list the attributes.
+    /// * `attrib_path` - The path to the attributes file.
+    /// * `attrib_file` - The prefix of the attributes file (starting attrib_).
+    /// * `inode` - The inode number.
+    ///
+    /// # Returns
+    ///
+    /// The entry of the inode, or `None` if the inode isn't in the attrib file (or if there is no attrib file).
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read or uncompressed.
+    /// If the file is not found in the pool.
+    fn find_inode(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+        attrib_path: &str,
+        attrib_file: &str,
+        inode: u64,
+    ) -> Result<Option<FileAttributes>> {
+        let attributes = self.list_attributes(hostname, backup_number, attrib_path, attrib_file)?;
+        Ok(select_inode(attributes, inode))
+    }
     /// Return the attributes of a file
     ///
     /// # Arguments
@@ -109,61 +155,475 @@ pub trait SearchTrait: Send + Sync {
         share: &str,
         filename: &str,
     ) -> Result<Vec<FileAttributes>>;
+    /// Return the attributes of multiple files
+    ///
+    /// The files are grouped by directory, so each directory is listed only once.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The name of the host to list the attributes.
+    /// * `backup_number` - The number of the backup to list the attributes.
+    /// * `share` - The share where the files are stored.
+    /// * `filenames` - The filenames (path in the share) to list the attributes.
+    ///
+    /// # Returns
+    ///
+    /// A map of the attributes of the files found, indexed by the filename given in `filenames`. Files that are not
+    /// found are not in the map.
+    ///
+    /// # Errors
+    ///
+    /// If a file cannot be read or uncompressed.
+    /// If a file is not found in the pool.
+    fn get_files(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+        share: &str,
+        filenames: &[&str],
+    ) -> Result<HashMap<String, FileAttributes>> {
+        info!(
+            "Looking for {} files in {hostname}/{backup_number}/{share}",
+            filenames.len()
+        );
+
+        // Group the files by directory
+        let mut directories: HashMap<&str, Vec<(&str, &str)>> = HashMap::new();
+        for filename in filenames {
+            let (path, name) = filename.rsplit_once('/').unwrap_or(("", filename));
+            directories.entry(path).or_default().push((filename, name));
+        }
+
+        let mut result = HashMap::new();
+        for (path, files) in directories {
+            let mut attributes = self
+                .list_file_from_dir(hostname, backup_number, Some(share), Some(path))?
+                .into_iter()
+                .map(|attr| (attr.name.clone(), attr))
+                .collect::<HashMap<_, _>>();
+
+            for (filename, name) in files {
+                if let Some(attr) = attributes.remove(name) {
+                    result.insert(filename.to_string(), attr);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+    /// Return the attributes of exactly one file or directory
+    ///
+    /// The attributes are read from the listing of the parent directory. The attributes of the share itself (an
+    /// empty path) are read from the root of the backup, the share being matched regardless of its leading and
+    /// trailing slashes.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The name of the host to list the attributes.
+    /// * `backup_number` - The number of the backup to list the attributes.
+    /// * `share` - The share where the file is stored.
+    /// * `path` - The path of the file or the directory in the share.
+    ///
+    /// # Returns
+    ///
+    /// The `FileAttributes` of the file or the directory.
+    ///
+    /// # Errors
+    ///
+    /// If the parent directory cannot be read or uncompressed.
+    /// An error of kind `NotFound` if the file is not in the parent directory.
+    fn stat(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+        share: &str,
+        path: &str,
+    ) -> Result<FileAttributes> {
+        let components = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<&str>>();
+
+        let found = match components.split_last() {
+            None => {
+                let shares = self.list_file_from_dir(hostname, backup_number, None, None)?;
+                let exact = shares.iter().position(|attr| attr.name == share);
+                exact
+                    .or_else(|| shares.iter().position(|attr| same_share(&attr.name, share)))
+                    .map(|index| shares[index].clone())
+            }
+            Some((name, parent)) => self
+                .list_file_from_dir(
+                    hostname,
+                    backup_number,
+                    Some(share),
+                    Some(&parent.join("/")),
+                )?
+                .into_iter()
+                .find(|attr| attr.name == *name),
+        };
+
+        found.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("File {path} not found in {hostname}/{backup_number}/{share}"),
+            )
+            .into()
+        })
+    }
+}
+
+// The provided methods aren't mocked, so they are tested through the mocked methods they call
+#[cfg(test)]
+mock! {
+    pub SearchTrait {}
+
+    impl SearchTrait for SearchTrait {
+        fn read_attrib(&self, file: &str, is_compressed: bool) -> Result<Vec<FileAttributes>>;
+        fn list_file_from_dir<'a, 'b>(
+            &self,
+            hostname: &str,
+            backup_number: u32,
+            share: Option<&'a str>,
+            filename: Option<&'b str>,
+        ) -> Result<Vec<FileAttributes>>;
+        fn list_attributes(
+            &self,
+            hostname: &str,
+            backup_number: u32,
+            attrib_path: &str,
+            attrib_file: &str,
+        ) -> Result<Vec<FileAttributes>>;
+        fn find_inode(
+            &self,
+            hostname: &str,
+            backup_number: u32,
+            attrib_path: &str,
+            attrib_file: &str,
+            inode: u64,
+        ) -> Result<Option<FileAttributes>>;
+        fn get_file(
+            &self,
+            hostname: &str,
+            backup_number: u32,
+            share: &str,
+            filename: &str,
+        ) -> Result<Vec<FileAttributes>>;
+    }
+}
+
+/// Prefix of the attrib file of a directory of a backup (the attrib files of the inode directory are prefixed with
+/// `attribXX_`).
+pub const ATTRIB_PREFIX: &str = "attrib_";
+
+/// Ranks an entry of an attrib file of the inode directory for an inode (see `select_inode`): the entry named as
+/// `BackupPC` names the inode comes first (with the default rank, so a scan stops on it, see
+/// `AttributeFile::find_from`), then the entries padded with zero bytes, by name.
+fn inode_rank(attr: &FileAttributes, inode: u64, name: &str) -> Option<(bool, String)> {
+    if attr.name == name {
+        Some((false, String::new()))
+    } else if parse_inode_name(&attr.name) == Some(inode) {
+        Some((true, attr.name.clone()))
+    } else {
+        None
+    }
+}
+
+/// Selects the entry of an inode among the entries of an attrib file of the inode directory.
+///
+/// The entry named as `BackupPC` names the inode (see `util::inode_name`) is preferred. Otherwise, an entry whose name
+/// is padded with zero bytes is accepted (see `util::parse_inode_name`), the lowest name first. Among the entries of
+/// the same name, the last one is kept, as for the duplicated names of an attrib file.
+///
+/// # Arguments
+///
+/// * `attributes` - The entries of the attrib file.
+/// * `inode` - The inode number.
+///
+/// # Returns
+///
+/// The entry of the inode, or `None` if no entry matches the inode.
+#[must_use]
+pub fn select_inode(
+    attributes: impl IntoIterator<Item = FileAttributes>,
+    inode: u64,
+) -> Option<FileAttributes> {
+    let name = inode_name(inode);
+
+    let mut selected: Option<((bool, String), FileAttributes)> = None;
+    for attr in attributes {
+        let Some(rank) = inode_rank(&attr, inode, &name) else {
+            continue;
+        };
+        match &selected {
+            Some((selected_rank, _)) if rank > *selected_rank => {}
+            _ => selected = Some((rank, attr)),
+        }
+    }
+
+    selected.map(|(_, attr)| attr)
 }
 
 pub struct Search {
     topdir: String,
+    source: Arc<dyn PoolSource>,
+    pool_roots: Vec<PathBuf>,
+}
+
+/// Builds the path of the directory containing the attrib file of a directory of a share.
+///
+/// `BackupPC` mangles the share name as a single path element: the slashes of the share are escaped, so the share
+/// `/volume1/test` is stored in the directory `f%2fvolume1%2ftest`. The path inside the share is mangled component
+/// by component (`sub/dir` is stored in `fsub/fdir`).
+///
+/// # Arguments
+///
+/// * `share` - The share name.
+/// * `filename` - The path of the directory inside the share.
+///
+/// # Returns
+///
+/// The mangled path of the directory, relative to the backup directory.
+fn attrib_path(share: Option<&str>, filename: Option<&str>) -> String {
+    let share = share.map(mangle_filename);
+    let filename = filename.map(mangle);
+
+    [share, filename]
+        .iter()
+        .filter_map(|f| f.as_deref())
+        .filter(|f| !f.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
 }
 
 impl Search {
     #[must_use]
     pub fn new(topdir: &str) -> Self {
+        Self::new_with_source(topdir, Arc::new(LocalSource))
+    }
+
+    /// Creates a new `Search` reading the attrib files from the given pool source.
+    ///
+    /// # Arguments
+    ///
+    /// * `topdir` - The top directory path (on the machine hosting the pool).
+    /// * `source` - The source used to access the files of the top directory.
+    #[must_use]
+    pub fn new_with_source(topdir: &str, source: Arc<dyn PoolSource>) -> Self {
         Search {
             topdir: topdir.to_string(),
+            source,
+            pool_roots: Vec::new(),
         }
     }
 
-    fn search_attrib_file(
+    /// Creates a new `Search` reading the attrib files of the pool described by the configuration (its top directory,
+    /// its source, and its additional pool roots).
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the pool, shared with `Hosts::with_config`.
+    #[must_use]
+    pub fn with_config(config: &PoolConfig) -> Self {
+        Search {
+            topdir: config.topdir.clone(),
+            source: config.source(),
+            pool_roots: config.pool_roots.clone(),
+        }
+    }
+
+    /// Defines the additional pool roots where the attrib files are searched, in order, when they aren't in the pool
+    /// of the top directory (see `pool::find_file_in_pool_roots`).
+    ///
+    /// # Arguments
+    ///
+    /// * `pool_roots` - The additional pool roots.
+    pub fn set_pool_roots(&mut self, pool_roots: Vec<PathBuf>) {
+        self.pool_roots = pool_roots;
+    }
+
+    fn find_in_pool(&self, md5_hash: &[u8]) -> std::result::Result<(String, bool), String> {
+        find_file_in_pool_roots(
+            self.source.as_ref(),
+            &self.topdir,
+            &self.pool_roots,
+            md5_hash,
+            None,
+        )
+    }
+
+    /// Locates in the pool the attrib file of a directory of a backup (see `SearchTrait::list_attributes`).
+    ///
+    /// Returns the path of the pool file and `true` if it is in the cpool directory, or `None` if the directory has
+    /// no attrib file (or an empty one).
+    fn locate_attrib_file(
         &self,
-        backup_dir: &str,
+        hostname: &str,
+        backup_number: u32,
+        attrib_path: &str,
         attrib_file: &str,
-    ) -> Option<(String, std::path::PathBuf)> {
-        // Search for a file starting with the filename "attrib_" in the directory
-        let file = std::fs::read_dir(backup_dir)
+    ) -> Result<Option<(String, bool)>> {
+        let backup_dir = format!(
+            "{}/pc/{hostname}/{backup_number}/{}",
+            self.topdir, attrib_path,
+        );
+        info!("Looking for attributes in {backup_dir}");
+
+        let Some(digest) = self.search_attrib_file(&backup_dir, attrib_file) else {
+            return Ok(None);
+        };
+        if digest == "0" {
+            return Ok(None);
+        }
+
+        let md5_hash: Vec<u8> = try_hex_string_to_vec(&digest)?;
+        match self.find_in_pool(&md5_hash) {
+            Ok(found) => Ok(Some(found)),
+            Err(message) => {
+                Err(std::io::Error::new(std::io::ErrorKind::InvalidData, message).into())
+            }
+        }
+    }
+
+    /// Searches the attrib file of a directory.
+    ///
+    /// The attrib file of a directory is named `attrib_<digest>`, and the attrib files of the inode directory are
+    /// named `attribXX_<digest>` (see `view::inode_location`). The digest is the digest of the attrib file in the
+    /// pool, or `0` for an empty directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `backup_dir` - The directory containing the attrib file, mangled (see `util::mangled_path`).
+    /// * `prefix` - The prefix of the attrib file (`attrib_` or `attribXX_`).
+    ///
+    /// # Returns
+    ///
+    /// The digest (in hexadecimal) of the first file named with the prefix followed by a digest, if any.
+    fn search_attrib_file(&self, backup_dir: &str, prefix: &str) -> Option<String> {
+        self.source
+            .read_dir(&mangled_path(backup_dir))
             .ok()?
-            .filter_map(|entry| match entry {
-                Ok(entry) => entry
-                    .file_name()
-                    .to_str()
-                    .map(|s| (s.to_string(), entry.path())),
-                Err(err) => {
-                    eprintln!("Error reading directory: {backup_dir}, {err}");
-
-                    None
-                }
+            .into_iter()
+            .find_map(|entry| {
+                let (entry_prefix, digest) = split_attrib_name(&entry.name)?;
+                (entry_prefix == prefix).then(|| digest.to_string())
             })
-            .find(|(name, _)| name.starts_with(attrib_file));
+    }
+
+    /// Lists the attrib files of a directory of a backup, without reading them.
+    ///
+    /// The attrib files are the files `attrib_<digest>` of the directories of the shares and the files
+    /// `attribXX_<digest>` of the inode directory (ex: `inode/00`). The other files of the directory are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The name of the host of the backup.
+    /// * `backup_number` - The number of the backup.
+    /// * `subpath` - The path of the directory in the backup directory, as stored on disk (ex: `f%2fhome/fuser` or
+    ///   `inode/00`).
+    ///
+    /// # Returns
+    ///
+    /// The name of each attrib file, sorted, with the digest of the attrib file in the pool (`None` for the digest `0`
+    /// of an empty directory).
+    ///
+    /// # Errors
+    ///
+    /// If the directory can't be read.
+    pub fn list_attrib_files(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+        subpath: &str,
+    ) -> Result<Vec<(String, Option<Vec<u8>>)>> {
+        let backup_dir = format!("{}/pc/{hostname}/{backup_number}/{subpath}", self.topdir);
+
+        let mut attrib_files = Vec::new();
+        for entry in self.source.read_dir(&mangled_path(&backup_dir))? {
+            let Some((_, digest)) = split_attrib_name(&entry.name) else {
+                continue;
+            };
+            let digest = match digest {
+                "0" => None,
+                digest => Some(try_hex_string_to_vec(digest)?),
+            };
+            attrib_files.push((entry.name, digest));
+        }
+        attrib_files.sort();
+
+        Ok(attrib_files)
+    }
+
+    /// Resolves a share to the name of the share stored in the backup.
+    ///
+    /// The share can be given with or without its leading and trailing slashes (`volume1/test` or `/volume1/test/`
+    /// for the share `/volume1/test`): as the share is mangled as a single path element, the share must be given as
+    /// stored to find its directory. The shares of the backup are only read if the directory of the given share
+    /// doesn't exist.
+    ///
+    /// # Returns
+    ///
+    /// The name of the share of the backup matching the given share, or the given share if none matches.
+    ///
+    /// # Errors
+    ///
+    /// If the shares of the backup can't be read.
+    fn resolve_share(&self, hostname: &str, backup_number: u32, share: &str) -> Result<String> {
+        let share_dir = format!(
+            "{}/pc/{hostname}/{backup_number}/{}",
+            self.topdir,
+            mangle_filename(share)
+        );
+        if self.source.exists(Path::new(&share_dir)) {
+            return Ok(share.to_string());
+        }
 
-        file
+        let shares = self.list_attributes(hostname, backup_number, "", ATTRIB_PREFIX)?;
+        Ok(shares
+            .into_iter()
+            .map(|attr| attr.name)
+            .find(|name| same_share(name, share))
+            .unwrap_or_else(|| share.to_string()))
     }
 }
 
+/// Returns `true` if the two names are the same share, regardless of the leading, trailing, or repeated slashes.
+fn same_share(a: &str, b: &str) -> bool {
+    a.split('/')
+        .filter(|s| !s.is_empty())
+        .eq(b.split('/').filter(|s| !s.is_empty()))
+}
+
+/// Splits the name of an attrib file (`attrib_<digest>` or `attribXX_<digest>`) into its prefix (`attrib_` or
+/// `attribXX_`) and its digest (in hexadecimal, or `0` for an empty directory).
+///
+/// Returns `None` if the name isn't the name of an attrib file.
+fn split_attrib_name(name: &str) -> Option<(&str, &str)> {
+    let (prefix, digest) = name.split_at(name.find('_')? + 1);
+    let bucket = prefix.strip_prefix("attrib")?.strip_suffix('_')?;
+
+    let is_hex = |value: &str| value.chars().all(|c| c.is_ascii_hexdigit());
+    let valid_bucket = bucket.is_empty() || (bucket.len() == 2 && is_hex(bucket));
+    let valid_digest = !digest.is_empty() && is_hex(digest);
+
+    (valid_bucket && valid_digest).then_some((prefix, digest))
+}
+
 impl SearchTrait for Search {
     fn read_attrib(&self, file: &str, is_compressed: bool) -> Result<Vec<FileAttributes>> {
         info!("Reading attributes from file: {file} {is_compressed}");
 
-        let input_file = File::open(file)?;
-        if is_compressed {
-            let mut reader = BackupPCReader::new(input_file);
-            let attrs = AttributeFile::read_from(&mut reader)?;
+        // The whole content is decompressed at once and decoded from memory, the size of the file on disk is a lower
+        // bound of the size of the content
+        let size_hint = self.source.file_size(Path::new(file)).unwrap_or_default();
+        let content = read_all_to_vec(
+            open_content(self.source.open(Path::new(file))?, is_compressed)?,
+            size_hint,
+        )?;
+        let attrs = AttributeFile::read_from(&mut content.as_slice())
+            .map_err(|err| InvalidMagicError::with_path(err, file))?;
 
-            Ok(attrs.attributes)
-        } else {
-            let mut reader = std::io::BufReader::new(input_file);
-            let attrs = AttributeFile::read_from(&mut reader)?;
-
-            Ok(attrs.attributes)
-        }
+        Ok(attrs.attributes)
     }
 
     fn list_attributes(
@@ -173,44 +633,34 @@ impl SearchTrait for Search {
         attrib_path: &str,
         attrib_file: &str,
     ) -> Result<Vec<FileAttributes>> {
-        let backup_dir = format!(
-            "{}/pc/{hostname}/{backup_number}/{}",
-            self.topdir, attrib_path,
-        );
-        info!("Looking for attributes in {backup_dir}");
-
-        let file = self.search_attrib_file(&backup_dir, attrib_file);
-
-        if let Some((_, file)) = file {
-            // Get the hash at the right of the _ symbole
-            let file = file.to_str().ok_or_else(|| {
-                std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("Invalid file path: {file:?}"),
-                )
-            })?;
-            let file = file.split('_').collect::<Vec<&str>>();
-            let file = file[file.len() - 1];
-            if file == "0" {
-                return Ok(Vec::new());
-            }
-
-            let md5_hash: Vec<u8> = hex_string_to_vec(file);
-
-            match find_file_in_backuppc(&self.topdir, &md5_hash, None) {
-                Ok((file_path, is_compressed)) => {
-                    let attributes = self.read_attrib(&file_path, is_compressed)?;
-                    return Ok(attributes);
-                }
-                Err(message) => {
-                    return Err(
-                        std::io::Error::new(std::io::ErrorKind::InvalidData, message).into(),
-                    )
-                }
+        match self.locate_attrib_file(hostname, backup_number, attrib_path, attrib_file)? {
+            Some((file_path, is_compressed)) => {
+                let attributes = self.read_attrib(&file_path, is_compressed)?;
+                Ok(remove_duplicates(attributes, &file_path))
             }
+            None => Ok(Vec::new()),
         }
+    }
 
-        Ok(Vec::new())
+    fn find_inode(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+        attrib_path: &str,
+        attrib_file: &str,
+        inode: u64,
+    ) -> Result<Option<FileAttributes>> {
+        let Some((file_path, is_compressed)) =
+            self.locate_attrib_file(hostname, backup_number, attrib_path, attrib_file)?
+        else {
+            return Ok(None);
+        };
+        info!("Scanning attributes of file: {file_path}");
+
+        let name = inode_name(inode);
+        let mut reader = open_content(self.source.open(Path::new(&file_path))?, is_compressed)?;
+        AttributeFile::find_from(&mut reader, |attr| inode_rank(attr, inode, &name))
+            .map_err(|err| InvalidMagicError::with_path(err, &file_path))
     }
 
     fn list_file_from_dir(
@@ -220,16 +670,12 @@ impl SearchTrait for Search {
         share: Option<&str>,
         filename: Option<&str>,
     ) -> Result<Vec<FileAttributes>> {
-        let share = share.map(mangle_filename);
-        let filename = filename.map(mangle);
-
-        let attrib_path = [share, filename]
-            .iter()
-            .filter_map(|f| f.as_deref())
-            .collect::<Vec<_>>()
-            .join("/");
+        let share = share
+            .map(|share| self.resolve_share(hostname, backup_number, share))
+            .transpose()?;
+        let attrib_path = attrib_path(share.as_deref(), filename);
 
-        self.list_attributes(hostname, backup_number, &attrib_path, "attrib_")
+        self.list_attributes(hostname, backup_number, &attrib_path, ATTRIB_PREFIX)
     }
 
     fn get_file(
@@ -265,3 +711,366 @@ impl SearchTrait for Search {
         }
     }
 }
+
+/// Removes the entries of an attrib file whose name is listed more than once (the file is likely corrupted).
+///
+/// The last entry of a name is kept (as when the entries are inserted in a map by name), at the position of the
+/// first one, and a warning is logged for each duplicated name.
+///
+/// # Arguments
+///
+/// * `attributes` - The entries of the attrib file.
+/// * `file` - The path of the attrib file, for the warning.
+fn remove_duplicates(attributes: Vec<FileAttributes>, file: &str) -> Vec<FileAttributes> {
+    let mut positions: HashMap<Vec<u8>, usize> = HashMap::with_capacity(attributes.len());
+    let mut unique: Vec<FileAttributes> = Vec::with_capacity(attributes.len());
+
+    for attribute in attributes {
+        if let Some(&position) = positions.get(attribute.raw_name()) {
+            warn!(
+                "Duplicate entry {} in the attrib file {file}, keeping the last one",
+                attribute.display_name()
+            );
+            unique[position] = attribute;
+        } else {
+            positions.insert(attribute.raw_name().to_vec(), unique.len());
+            unique.push(attribute);
+        }
+    }
+
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::vec_to_hex_string;
+    use std::path::Path;
+
+    // Writes an uncompressed attrib file listing the given files in the directory `attrib_path` of the backup 1 of
+    // the host pc-1, and its content in the pool.
+    fn write_attrib_file<N: AsRef<[u8]>>(topdir: &Path, attrib_path: &str, seed: u8, names: &[N]) {
+        let mut content = 0x1756_5353_u32.to_be_bytes().to_vec();
+        for (i, name) in names.iter().enumerate() {
+            let name = name.as_ref();
+            content.push(u8::try_from(name.len()).unwrap());
+            content.extend_from_slice(name);
+            // xattrs, type, mtime, mode, uid, gid, size, inode, compress, nlinks, digest length
+            content.extend_from_slice(&[0, 0, 0, 0x24, 0, 0, u8::try_from(i).unwrap(), 0, 0, 1, 0]);
+        }
+
+        let digest: Vec<u8> = (seed..seed + 16).collect();
+        let pool_dir = topdir
+            .join("pool")
+            .join(format!("{:02x}", digest[0] & 0xfe))
+            .join(format!("{:02x}", digest[1] & 0xfe));
+        std::fs::create_dir_all(&pool_dir).unwrap();
+        std::fs::write(pool_dir.join(vec_to_hex_string(&digest)), content).unwrap();
+
+        let backup_dir = topdir
+            .join("pc")
+            .join("pc-1")
+            .join("1")
+            .join(mangled_path(attrib_path));
+        std::fs::create_dir_all(&backup_dir).unwrap();
+        std::fs::write(
+            backup_dir.join(format!("attrib_{}", vec_to_hex_string(&digest))),
+            "",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_attrib_path_of_multi_component_share() {
+        assert_eq!(attrib_path(None, None), "");
+        assert_eq!(
+            attrib_path(Some("/volume1/test"), Some("")),
+            "f%2fvolume1%2ftest"
+        );
+        assert_eq!(
+            attrib_path(Some("/volume1/test"), Some("supertest/de")),
+            "f%2fvolume1%2ftest/fsupertest/fde"
+        );
+        assert_eq!(attrib_path(Some("/"), Some("home")), "f%2f/fhome");
+    }
+
+    #[test]
+    fn test_list_files_of_share_with_special_characters() {
+        let topdir = std::env::temp_dir().join(format!("bpc_special_share_{}", std::process::id()));
+        write_attrib_file(&topdir, "fMy Documents", 0x30, &["notes.txt", "Sub Dir"]);
+        write_attrib_file(&topdir, "fMy Documents/fSub Dir", 0x40, &["a b.txt"]);
+        write_attrib_file(&topdir, "f%2fdata%2f100%25 Données", 0x50, &["été"]);
+
+        let search = Search::new(topdir.to_str().unwrap());
+        let names = |share: &str, path: &str| {
+            search
+                .list_file_from_dir("pc-1", 1, Some(share), Some(path))
+                .unwrap()
+                .into_iter()
+                .map(|attr| attr.name)
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(names("My Documents", ""), vec!["notes.txt", "Sub Dir"]);
+        assert_eq!(names("My Documents", "Sub Dir"), vec!["a b.txt"]);
+        assert_eq!(names("/data/100% Données", "/"), vec!["été"]);
+
+        let files = search
+            .get_file("pc-1", 1, "My Documents", "Sub Dir/a b.txt")
+            .unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "a b.txt");
+
+        // A directory named with bytes that aren't valid UTF-8 is found by its mangled path, the bytes being escaped
+        write_attrib_file(&topdir, "fMy Documents/fdossi%e9", 0x60, &["inner"]);
+        let files = search
+            .list_attributes("pc-1", 1, "fMy Documents/fdossi%e9", ATTRIB_PREFIX)
+            .unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "inner");
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_stat() {
+        let topdir = std::env::temp_dir().join(format!("bpc_stat_{}", std::process::id()));
+        write_attrib_file(&topdir, "", 0x60, &["/share"]);
+        write_attrib_file(&topdir, "f%2fshare", 0x70, &["a", "dir"]);
+        write_attrib_file(&topdir, "f%2fshare/fdir", 0x80, &["b", "c"]);
+
+        let search = Search::new(topdir.to_str().unwrap());
+        let stat = |path: &str| search.stat("pc-1", 1, "/share", path);
+
+        assert_eq!(stat("").unwrap().name, "/share");
+        assert_eq!(stat("/").unwrap().name, "/share");
+        assert_eq!(stat("dir").unwrap().size, 1);
+        assert_eq!(stat("/dir/c").unwrap().size, 1);
+        assert_eq!(stat("dir/b").unwrap().name, "b");
+        let err = stat("dir/missing").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<std::io::Error>().unwrap().kind(),
+            std::io::ErrorKind::NotFound
+        );
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_list_attributes_with_prefixes() {
+        let topdir = std::env::temp_dir().join(format!("bpc_attrib_prefix_{}", std::process::id()));
+        write_attrib_file(&topdir, "f%2fshare", 0x90, &["a"]);
+        write_attrib_file(&topdir, "inode/00", 0xa0, &["0a01"]);
+
+        // The attrib files of the inode directory are named attribXX_<digest>
+        let inode_dir = topdir
+            .join("pc")
+            .join("pc-1")
+            .join("1")
+            .join("inode")
+            .join("00");
+        let digest = vec_to_hex_string(&(0xa0..0xb0).collect::<Vec<u8>>());
+        std::fs::rename(
+            inode_dir.join(format!("attrib_{digest}")),
+            inode_dir.join(format!("attrib0a_{digest}")),
+        )
+        .unwrap();
+        std::fs::write(inode_dir.join("attrib0b_0"), "").unwrap();
+        std::fs::write(inode_dir.join("attrib_backup"), "").unwrap();
+
+        let search = Search::new(topdir.to_str().unwrap());
+        let names = |attrib_path: &str, prefix: &str| {
+            search
+                .list_attributes("pc-1", 1, attrib_path, prefix)
+                .unwrap()
+                .into_iter()
+                .map(|attr| attr.name)
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(names("f%2fshare", ATTRIB_PREFIX), vec!["a"]);
+        assert_eq!(names("inode/00", "attrib0a_"), vec!["0a01"]);
+        assert!(names("inode/00", "attrib0b_").is_empty());
+        assert!(names("inode/00", "attrib0c_").is_empty());
+        // A file whose name isn't followed by a digest isn't an attrib file
+        assert!(names("inode/00", ATTRIB_PREFIX).is_empty());
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_list_attributes_with_duplicates() {
+        let topdir = std::env::temp_dir().join(format!("bpc_attrib_dup_{}", std::process::id()));
+        write_attrib_file(&topdir, "f%2fshare", 0x30, &["a", "b", "a"]);
+
+        let search = Search::new(topdir.to_str().unwrap());
+        let attributes = search
+            .list_attributes("pc-1", 1, "f%2fshare", ATTRIB_PREFIX)
+            .unwrap();
+
+        // The last entry of a name is kept, at the position of the first one
+        let entries: Vec<(&str, u64)> = attributes
+            .iter()
+            .map(|attr| (attr.name.as_str(), attr.size))
+            .collect();
+        assert_eq!(entries, vec![("a", 2), ("b", 1)]);
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_find_inode() {
+        let topdir = std::env::temp_dir().join(format!("bpc_attrib_inode_{}", std::process::id()));
+        write_attrib_file(
+            &topdir,
+            "inode/01",
+            0xb0,
+            &[
+                "050802",
+                "0608020000",
+                "other",
+                "0708020000",
+                "070802",
+                "050802",
+            ],
+        );
+
+        let search = Search::new(topdir.to_str().unwrap());
+        let find = |attrib_path: &str, inode: u64| {
+            let found = search
+                .find_inode("pc-1", 1, attrib_path, ATTRIB_PREFIX, inode)
+                .unwrap()
+                .map(|attr| (attr.name, attr.size));
+            // The scan selects the same entry as the listing of the whole attrib file (except for a duplicated name)
+            if inode != 0x20805 {
+                let listed = search
+                    .list_attributes("pc-1", 1, attrib_path, ATTRIB_PREFIX)
+                    .unwrap();
+                let selected = select_inode(listed, inode).map(|attr| (attr.name, attr.size));
+                assert_eq!(found, selected, "inode {inode:x}");
+            }
+            found
+        };
+
+        // The scan stops at the first entry with the exact name of the inode, the listing keeps the last one
+        assert_eq!(find("inode/01", 0x20805), Some(("050802".to_string(), 0)));
+        let listed = search
+            .list_attributes("pc-1", 1, "inode/01", ATTRIB_PREFIX)
+            .unwrap();
+        assert_eq!(select_inode(listed, 0x20805).unwrap().size, 5);
+        // A name padded with zero bytes matches its inode, but the exact name is preferred
+        assert_eq!(
+            find("inode/01", 0x20806),
+            Some(("0608020000".to_string(), 1))
+        );
+        assert_eq!(find("inode/01", 0x20807), Some(("070802".to_string(), 4)));
+        assert_eq!(find("inode/01", 0x20808), None);
+        assert_eq!(find("inode/02", 0x20805), None);
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_share_with_or_without_slashes() {
+        let topdir = std::env::temp_dir().join(format!("bpc_share_slash_{}", std::process::id()));
+        write_attrib_file(&topdir, "", 0x10, &["/volume1/test"]);
+        write_attrib_file(&topdir, "f%2fvolume1%2ftest", 0x20, &["a", "dir"]);
+        write_attrib_file(&topdir, "f%2fvolume1%2ftest/fdir", 0x30, &["b"]);
+
+        let search = Search::new(topdir.to_str().unwrap());
+        for share in ["volume1/test", "/volume1/test", "/volume1/test/"] {
+            let names = |path: &str| {
+                search
+                    .list_file_from_dir("pc-1", 1, Some(share), Some(path))
+                    .unwrap()
+                    .into_iter()
+                    .map(|attr| attr.name)
+                    .collect::<Vec<String>>()
+            };
+            assert_eq!(names(""), vec!["a", "dir"], "share {share}");
+            assert_eq!(names("dir"), vec!["b"], "share {share}");
+            assert_eq!(
+                search.stat("pc-1", 1, share, "").unwrap().name,
+                "/volume1/test"
+            );
+            assert_eq!(search.get_file("pc-1", 1, share, "dir/b").unwrap().len(), 1);
+        }
+        assert!(search
+            .list_file_from_dir("pc-1", 1, Some("volume1"), Some(""))
+            .unwrap()
+            .is_empty());
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_list_attrib_files() {
+        let topdir = std::env::temp_dir().join(format!("bpc_attrib_files_{}", std::process::id()));
+        write_attrib_file(&topdir, "f%2fshare", 0xb0, &["a"]);
+        let share_dir = topdir.join("pc").join("pc-1").join("1").join("f%2fshare");
+        std::fs::create_dir_all(share_dir.join("fdir")).unwrap();
+        std::fs::write(share_dir.join("attrib0a_0"), "").unwrap();
+        std::fs::write(share_dir.join("attribute"), "").unwrap();
+
+        let search = Search::new(topdir.to_str().unwrap());
+        let digest = (0xb0..0xc0).collect::<Vec<u8>>();
+        assert_eq!(
+            search.list_attrib_files("pc-1", 1, "f%2fshare").unwrap(),
+            vec![
+                ("attrib0a_0".to_string(), None),
+                (
+                    format!("attrib_{}", vec_to_hex_string(&digest)),
+                    Some(digest)
+                ),
+            ]
+        );
+        assert!(search
+            .list_attrib_files("pc-1", 1, "f%2fshare/fdir")
+            .unwrap()
+            .is_empty());
+        assert!(search.list_attrib_files("pc-1", 1, "missing").is_err());
+
+        assert_eq!(split_attrib_name("attrib_0"), Some(("attrib_", "0")));
+        assert_eq!(split_attrib_name("attrib7f_ab"), Some(("attrib7f_", "ab")));
+        assert_eq!(split_attrib_name("attrib_"), None);
+        assert_eq!(split_attrib_name("attrib123_ab"), None);
+        assert_eq!(split_attrib_name("fattrib_ab"), None);
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_get_files_lists_each_directory_once() {
+        let file = |name: &str, size: u64| FileAttributes {
+            size,
+            ..FileAttributes::from_share(name.to_string())
+        };
+        let mut search = MockSearchTrait::new();
+        search
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share == &Some("/share") && path == &Some(""))
+            .times(1)
+            .returning(move |_, _, _, _| Ok(vec![file("a", 0), file("b", 1), file("dir", 2)]));
+        search
+            .expect_list_file_from_dir()
+            .withf(|_, _, _, path| path == &Some("dir"))
+            .times(1)
+            .returning(move |_, _, _, _| Ok(vec![file("c", 0), file("d", 1)]));
+
+        let files = search
+            .get_files(
+                "pc-1",
+                1,
+                "/share",
+                &["a", "dir/c", "dir/d", "dir/missing", "b"],
+            )
+            .unwrap();
+
+        assert_eq!(files.len(), 4);
+        assert_eq!(files["a"].size, 0);
+        assert_eq!(files["b"].size, 1);
+        assert_eq!(files["dir/c"].name, "c");
+        assert_eq!(files["dir/d"].size, 1);
+        assert!(!files.contains_key("dir/missing"));
+    }
+}
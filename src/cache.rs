@@ -0,0 +1,172 @@
+use lru::LruCache;
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+
+/// Counters of the accesses to a cache, to tune its capacity.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheMetrics {
+    /// Number of lookups that found the entry in the cache.
+    pub hits: u64,
+    /// Number of lookups that didn't find the entry in the cache.
+    pub misses: u64,
+    /// Number of entries removed from the cache to make room for a new entry.
+    pub evictions: u64,
+}
+
+impl CacheMetrics {
+    /// Returns the ratio of the lookups that found the entry in the cache (between 0 and 1, 0 without lookup).
+    #[must_use]
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            #[allow(clippy::cast_precision_loss)]
+            let rate = self.hits as f64 / lookups as f64;
+            rate
+        }
+    }
+}
+
+impl fmt::Display for CacheMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses ({:.1}% hits), {} evictions",
+            self.hits,
+            self.misses,
+            self.hit_rate() * 100.0,
+            self.evictions
+        )
+    }
+}
+
+/// A LRU cache counting its hits, misses, and evictions (see `CacheMetrics`).
+///
+/// ```
+/// use backuppc_pool_reader::cache::MeteredCache;
+/// use std::num::NonZeroUsize;
+///
+/// let mut cache = MeteredCache::new(NonZeroUsize::new(1).unwrap());
+/// cache.put("a", 1);
+/// assert_eq!(cache.get("a"), Some(&1));
+/// cache.put("b", 2);
+/// assert_eq!(cache.get("a"), None);
+///
+/// let metrics = cache.metrics();
+/// assert_eq!((metrics.hits, metrics.misses, metrics.evictions), (1, 1, 1));
+/// ```
+pub struct MeteredCache<K, V> {
+    cache: LruCache<K, V>,
+    metrics: CacheMetrics,
+}
+
+impl<K: Hash + Eq, V> MeteredCache<K, V> {
+    /// Creates a cache holding at most `capacity` entries.
+    #[must_use]
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        MeteredCache {
+            cache: LruCache::new(capacity),
+            metrics: CacheMetrics::default(),
+        }
+    }
+
+    /// Returns the value of the key, and marks it as the most recently used. The lookup is counted as a hit or a miss.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let value = self.cache.get(key);
+        if value.is_some() {
+            self.metrics.hits += 1;
+        } else {
+            self.metrics.misses += 1;
+        }
+        value
+    }
+
+    /// Inserts the value of the key, evicting the least recently used entry if the cache is full.
+    pub fn put(&mut self, key: K, value: V) {
+        if !self.cache.contains(&key) && self.cache.len() == self.cache.cap().get() {
+            self.metrics.evictions += 1;
+        }
+        self.cache.put(key, value);
+    }
+
+    /// Returns `true` if the key is in the cache, without marking it as used. The lookup isn't counted.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.contains(key)
+    }
+
+    /// Removes the key from the cache, returning its value. The removal isn't counted as an eviction.
+    pub fn pop<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.pop(key)
+    }
+
+    /// Returns the values of the cache, from the most recently used, without marking them as used. The lookups
+    /// aren't counted.
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.cache.iter().map(|(_, value)| value)
+    }
+
+    /// Removes all the entries of the cache. The counters are kept.
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Returns the counters of the accesses to the cache.
+    #[must_use]
+    pub fn metrics(&self) -> CacheMetrics {
+        self.metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metered_cache() {
+        let mut cache = MeteredCache::new(NonZeroUsize::new(2).unwrap());
+        cache.put(1, "a");
+        cache.put(2, "b");
+        // Replacing the value of a key doesn't evict another entry
+        cache.put(1, "c");
+        assert_eq!(cache.metrics().evictions, 0);
+
+        assert_eq!(cache.get(&1), Some(&"c"));
+        assert!(cache.contains(&2));
+        cache.put(3, "d");
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.pop(&3), Some("d"));
+        cache.clear();
+        assert_eq!(cache.get(&1), None);
+
+        let metrics = cache.metrics();
+        assert_eq!(
+            metrics,
+            CacheMetrics {
+                hits: 1,
+                misses: 2,
+                evictions: 1,
+            }
+        );
+        assert!((metrics.hit_rate() - 1.0 / 3.0).abs() < f64::EPSILON);
+        assert_eq!(
+            metrics.to_string(),
+            "1 hits, 2 misses (33.3% hits), 1 evictions"
+        );
+        assert!(CacheMetrics::default().hit_rate().abs() < f64::EPSILON);
+    }
+}
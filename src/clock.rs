@@ -0,0 +1,79 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The source of the current time used by the view and the file system.
+///
+/// The real clock (`SystemClock`) is used by default. A `ManualClock` can be injected instead (see
+/// `BackupPC::set_clock`) so the tests of the time-related features don't depend on the time they are run.
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+
+    /// Returns the current time, in seconds since the Unix epoch (0 for a time before the epoch).
+    fn timestamp(&self) -> u64 {
+        self.now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs())
+    }
+}
+
+/// The clock of the system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock whose time only changes when it is set or advanced.
+///
+/// ```
+/// use backuppc_pool_reader::clock::{Clock, ManualClock};
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::from_timestamp(1_700_000_000);
+/// clock.advance(Duration::from_secs(60));
+/// assert_eq!(clock.timestamp(), 1_700_000_060);
+/// ```
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    /// Creates a clock set to the given time.
+    #[must_use]
+    pub fn new(now: SystemTime) -> Self {
+        ManualClock {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Creates a clock set to the given time, in seconds since the Unix epoch.
+    #[must_use]
+    pub fn from_timestamp(timestamp: u64) -> Self {
+        Self::new(UNIX_EPOCH + Duration::from_secs(timestamp))
+    }
+
+    /// Sets the time of the clock.
+    pub fn set(&self, now: SystemTime) {
+        *self.lock() = now;
+    }
+
+    /// Moves the time of the clock forward.
+    pub fn advance(&self, duration: Duration) {
+        *self.lock() += duration;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SystemTime> {
+        self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.lock()
+    }
+}
@@ -1,6 +1,8 @@
 use flate2::bufread::ZlibDecoder;
 use std::io::{self, BufRead, BufReader, Read};
 
+use crate::reader::read_all_to_vec;
+
 /* InterpretAdapter */
 
 /// A struct representing an adapter for interpreting compressed data.
@@ -12,10 +14,15 @@ use std::io::{self, BufRead, BufReader, Read};
 /// can be handled by the `flate2` crate.
 ///
 /// `BackupPC` format is a serie of chunk of data where some bytes are replaced to define the checksum at the end.
+/// The checksum block starts with the byte `0xb3` after the compressed data: the adapter stops at this byte and keeps
+/// the block unread in the inner reader.
 struct InterpretAdapter<R: BufRead> {
     inner: R,
     first: bool,
     temp: Option<Vec<u8>>,
+    checksum: bool,
+    /// `true` once compressed data has been read, so a `0xb3` at the start of the file isn't a checksum block.
+    data: bool,
 }
 
 impl<R: BufRead> InterpretAdapter<R> {
@@ -33,6 +40,8 @@ impl<R: BufRead> InterpretAdapter<R> {
             inner,
             first: true,
             temp: None,
+            checksum: false,
+            data: false,
         }
     }
 
@@ -53,16 +62,24 @@ impl<R: BufRead> Read for InterpretAdapter<R> {
     ///
     /// The number of bytes read from the underlying reader, or an `io::Result` indicating the error encountered during the read operation.
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.checksum {
+            return Ok(0);
+        }
+        if self.first && self.data && self.inner.fill_buf()?.first() == Some(&0xb3) {
+            self.first = false;
+            self.checksum = true;
+            return Ok(0);
+        }
+
         let len = self.inner.read(buf)?;
 
         if self.first && len > 0 {
             self.first = false;
             if buf[0] == 0xd6 || buf[0] == 0xd7 {
                 buf[0] = 0x78;
-            } else if buf[0] == 0xb3 {
-                return Ok(0);
             }
         }
+        self.data |= len > 0;
 
         Ok(len)
     }
@@ -79,8 +96,9 @@ impl<R: BufRead> BufRead for InterpretAdapter<R> {
 
                 if buf[0] == 0xd6 || buf[0] == 0xd7 {
                     buf[0] = 0x78;
-                } else if buf[0] == 0xb3 {
-                    // EOF
+                } else if buf[0] == 0xb3 && self.data {
+                    // Start of the checksum block, kept in the inner reader
+                    self.checksum = true;
                     buf = Vec::new();
                 }
             }
@@ -94,16 +112,59 @@ impl<R: BufRead> BufRead for InterpretAdapter<R> {
     fn consume(&mut self, amt: usize) {
         if amt > 0 {
             self.temp = None;
+            self.data = true;
             self.inner.consume(amt);
         }
     }
 }
 
+/// Checks if the first bytes of a file are the header of a compressed `BackupPC` file.
+///
+/// Compressed files start with the zlib header `0x78`, or with `0xd6`/`0xd7` that `BackupPC` uses in place of the
+/// zlib header. The second byte must be a valid zlib flag byte (`(0x78 << 8 | flags) % 31 == 0`, without preset
+/// dictionary), so plain data starting with `x` isn't taken for compressed data.
+///
+/// # Arguments
+///
+/// * `header` - The first bytes of the file (at least 2 bytes, unless the file is shorter).
+///
+/// # Returns
+///
+/// `true` if the bytes are the header of a compressed file.
+///
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::compress::is_compressed_header;
+///
+/// assert!(is_compressed_header(&[0x78, 0x9c]));
+/// assert!(is_compressed_header(&[0xd7, 0x01]));
+/// assert!(!is_compressed_header(b"x = 1"));
+/// assert!(!is_compressed_header(b"x"));
+/// assert!(!is_compressed_header(&[0xb3, 0x01]));
+/// ```
+#[must_use]
+pub fn is_compressed_header(header: &[u8]) -> bool {
+    match header {
+        [0x78 | 0xd6 | 0xd7, flags, ..] => {
+            (0x7800 | u16::from(*flags)) % 31 == 0 && flags & 0x20 == 0
+        }
+        _ => false,
+    }
+}
+
+/// Magic number at the start of a zstd frame.
+pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
 /* BackupPCReader */
 
 /// A reader that decompresses data from a source using the `BackupPC` compression format.
 pub struct BackupPCReader<R: Read> {
     decoder: Option<ZlibDecoder<InterpretAdapter<BufReader<R>>>>,
+    tail: Option<InterpretAdapter<BufReader<R>>>,
+    plain: Option<BufReader<R>>,
+    #[cfg(feature = "zstd")]
+    zstd: Option<zstd::stream::read::Decoder<'static, BufReader<R>>>,
 }
 
 impl<R: Read> BackupPCReader<R> {
@@ -120,13 +181,80 @@ impl<R: Read> BackupPCReader<R> {
     ///
     /// A new `BackupPCReader` instance.
     pub fn new(reader: R) -> Self {
-        let reader = BufReader::new(reader);
+        Self::from_buf_reader(BufReader::new(reader))
+    }
+
+    /// Create a new `BackupPCReader` that detects if the content of the given reader is compressed.
+    ///
+    /// The first bytes of the reader are inspected: if they are the header of a compressed file, the content is
+    /// decompressed, otherwise the content is read as is. This handles files stored without compression in the
+    /// cpool directory.
+    ///
+    /// Content compressed with zstd (starting with `ZSTD_MAGIC`) is decompressed when the `zstd` feature is
+    /// enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader to be used for reading the data.
+    ///
+    /// # Returns
+    ///
+    /// A new `BackupPCReader` instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the first bytes can't be read, or if the content is compressed with zstd and the
+    /// `zstd` feature is not enabled.
+    pub fn autodetect(reader: R) -> io::Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let header = reader.fill_buf()?;
+
+        if header.starts_with(&ZSTD_MAGIC) {
+            return Self::from_zstd_reader(reader);
+        }
+
+        if header.is_empty() || is_compressed_header(header) {
+            Ok(Self::from_buf_reader(reader))
+        } else {
+            Ok(Self {
+                decoder: None,
+                tail: None,
+                plain: Some(reader),
+                #[cfg(feature = "zstd")]
+                zstd: None,
+            })
+        }
+    }
+
+    fn from_buf_reader(reader: BufReader<R>) -> Self {
         let reader = InterpretAdapter::new(reader);
         Self {
             decoder: Some(ZlibDecoder::new(reader)),
+            tail: None,
+            plain: None,
+            #[cfg(feature = "zstd")]
+            zstd: None,
         }
     }
 
+    #[cfg(feature = "zstd")]
+    fn from_zstd_reader(reader: BufReader<R>) -> io::Result<Self> {
+        Ok(Self {
+            decoder: None,
+            tail: None,
+            plain: None,
+            zstd: Some(zstd::stream::read::Decoder::with_buffer(reader)?),
+        })
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    fn from_zstd_reader(_reader: BufReader<R>) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Content is compressed with zstd, but the zstd feature is not enabled",
+        ))
+    }
+
     /// Reads bytes from the underlying decoder and fills the provided buffer.
     ///
     /// # Arguments
@@ -137,6 +265,14 @@ impl<R: Read> BackupPCReader<R> {
     ///
     /// Returns the number of bytes read and stored in the buffer, or an `io::Error` if an error occurred.
     fn read_some_bytes(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(plain) = self.plain.as_mut() {
+            return plain.read(buf);
+        }
+        #[cfg(feature = "zstd")]
+        if let Some(zstd) = self.zstd.as_mut() {
+            return zstd.read(buf);
+        }
+
         loop {
             let decoder = self.decoder.as_mut();
             if decoder.is_none() {
@@ -165,6 +301,7 @@ impl<R: Read> BackupPCReader<R> {
                     let mut reader = decoder.into_inner();
                     // S'il reste encore des octets à lire dans reader alors on continue, sinon on s'arrête
                     if reader.fill_buf()?.is_empty() {
+                        self.tail = Some(reader);
                         return Ok(0);
                     }
                     reader.reset();
@@ -174,6 +311,68 @@ impl<R: Read> BackupPCReader<R> {
             }
         }
     }
+
+    /// Takes the checksum block stored after the compressed data.
+    ///
+    /// `BackupPC` can append a block of checksums (used for the rsync delta transfers) after the compressed data of
+    /// a pool file. This block starts with the byte `0xb3`, and is returned as is (including this byte) so it can
+    /// be validated or reused.
+    ///
+    /// The block is only available once the whole content has been read (the reader returned `0`).
+    ///
+    /// # Returns
+    ///
+    /// The bytes of the checksum block, or `None` if the content isn't fully read, if the file has no checksum
+    /// block, or if the block has already been taken.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the checksum block can't be read.
+    pub fn take_checksum_block(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match self.tail.take() {
+            Some(mut tail) if tail.checksum => {
+                let mut block = Vec::new();
+                tail.inner.read_to_end(&mut block)?;
+                Ok(Some(block))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the exact decompressed length of the remaining content, by decompressing it.
+    ///
+    /// The content is decompressed and discarded: this is O(n) in the size of the file, so it should only be used
+    /// when the size stored in the attributes of the file is not reliable (zero, or suspect). Once called, the
+    /// content is fully read (the checksum block can still be taken).
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes decompressed from the current position to the end of the content.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the content can't be read or decompressed.
+    pub fn decompressed_len(&mut self) -> io::Result<u64> {
+        io::copy(self, &mut io::sink())
+    }
+
+    /// Decompresses the whole content, reserving the expected size of the content first (see
+    /// `reader::read_all_to_vec`).
+    ///
+    /// # Arguments
+    ///
+    /// * `size_hint` - The expected decompressed size (the size stored in the attributes of the file).
+    ///
+    /// # Returns
+    ///
+    /// The decompressed content.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the content can't be read or decompressed.
+    pub fn read_all_to_vec(self, size_hint: u64) -> io::Result<Vec<u8>> {
+        read_all_to_vec(self, size_hint)
+    }
 }
 
 /// Implements the `Read` trait for `BackupPCReader<R>`.
@@ -204,3 +403,133 @@ impl<R: Read> Read for BackupPCReader<R> {
         Ok(total_bytes_read)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn test_read_all_to_vec() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[7u8; 1000]).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let content = BackupPCReader::new(Cursor::new(compressed.clone()))
+            .read_all_to_vec(1000)
+            .unwrap();
+        assert_eq!(content, vec![7u8; 1000]);
+        assert_eq!(content.capacity(), 1000);
+
+        // The hint is only used to reserve the buffer
+        let content = BackupPCReader::new(Cursor::new(compressed))
+            .read_all_to_vec(10)
+            .unwrap();
+        assert_eq!(content.len(), 1000);
+    }
+
+    #[test]
+    fn test_autodetect_compressed_content() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"compressed content").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut reader = BackupPCReader::autodetect(Cursor::new(compressed)).unwrap();
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).unwrap();
+
+        assert_eq!(content, b"compressed content");
+    }
+
+    #[test]
+    fn test_decompressed_len() {
+        let content = b"compressed content ".repeat(1000);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&content).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut reader = BackupPCReader::new(Cursor::new(compressed));
+        let mut start = [0; 100];
+        reader.read_exact(&mut start).unwrap();
+        assert_eq!(
+            reader.decompressed_len().unwrap(),
+            content.len() as u64 - 100
+        );
+        assert_eq!(reader.decompressed_len().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_take_checksum_block() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"content with checksums").unwrap();
+        let mut compressed = encoder.finish().unwrap();
+        compressed[0] = 0xd7;
+        compressed.extend_from_slice(&[0xb3, 0x01, 0x02, 0x03, 0x04]);
+
+        let mut reader = BackupPCReader::autodetect(Cursor::new(compressed)).unwrap();
+        assert_eq!(reader.take_checksum_block().unwrap(), None);
+
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"content with checksums");
+        assert_eq!(
+            reader.take_checksum_block().unwrap(),
+            Some(vec![0xb3, 0x01, 0x02, 0x03, 0x04])
+        );
+        assert_eq!(reader.take_checksum_block().unwrap(), None);
+    }
+
+    #[test]
+    fn test_take_checksum_block_without_block() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"content").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut reader = BackupPCReader::autodetect(Cursor::new(compressed)).unwrap();
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).unwrap();
+
+        assert_eq!(content, b"content");
+        assert_eq!(reader.take_checksum_block().unwrap(), None);
+    }
+
+    #[test]
+    fn test_autodetect_plain_content() {
+        // "x " starts as a zlib header, but its second byte isn't a valid zlib flag byte
+        // 0xb3 only marks a checksum block after the compressed data
+        for plain in [&b"plain content"[..], b"x = 1", b"x", &[0xb3, 0x01, 0x02]] {
+            let mut reader = BackupPCReader::autodetect(Cursor::new(plain.to_vec())).unwrap();
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content).unwrap();
+
+            assert_eq!(content, plain);
+        }
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_autodetect_zstd_content() {
+        let compressed = zstd::encode_all(&b"zstd content"[..], 3).unwrap();
+        assert!(compressed.starts_with(&ZSTD_MAGIC));
+
+        let mut reader = BackupPCReader::autodetect(Cursor::new(compressed)).unwrap();
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).unwrap();
+
+        assert_eq!(content, b"zstd content");
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn test_autodetect_zstd_content_without_feature() {
+        let mut content = ZSTD_MAGIC.to_vec();
+        content.extend_from_slice(b"zstd content");
+
+        let err = BackupPCReader::autodetect(Cursor::new(content))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,228 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::source::{LocalSource, PoolSource, RetryPolicy};
+
+/// Default number of listings kept in the cache of the view.
+pub const VIEW_CACHE_SIZE: usize = 1000;
+/// Default number of directories kept in the cache of the file system.
+pub const FS_CACHE_SIZE: usize = 2048;
+/// Default number of files of the `.pool` directory kept in the cache of the file system.
+pub const POOL_FILES_CACHE_SIZE: usize = 1024;
+/// Default number of backups whose hard links are kept in the cache of the view.
+pub const INODE_PATHS_CACHE_SIZE: usize = 16;
+/// Number of pool files whose size is kept in the index of the view (see `BackupPC::pool_file_size`).
+pub const POOL_SIZES_CACHE_SIZE: usize = 64 * 1024;
+/// Default size of the chunks used to read (and skip) the content of a file.
+pub const READ_CHUNK_SIZE: usize = 32 * 1024;
+/// Default maximum size of the files whose content is kept in the cache of the view.
+pub const SMALL_FILE_SIZE: u64 = 256 * 1024;
+/// Default total size (in bytes) of the content of the small files kept in the cache of the view.
+pub const SMALL_FILE_CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Tuning of the memory used by the view and the file system.
+///
+/// The default values fit most machines. They can be lowered on low-memory machines (NAS), or raised to keep more
+/// listings in memory:
+///
+/// ```
+/// use backuppc_pool_reader::config::BackupPCConfig;
+///
+/// let config = BackupPCConfig::default()
+///     .with_view_cache_size(100)
+///     .with_read_chunk_size(4096);
+///
+/// assert_eq!(config.view_cache_size, 100);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupPCConfig {
+    /// Number of listings kept in the cache of the view.
+    pub view_cache_size: usize,
+    /// Number of directories kept in the cache of the file system.
+    pub fs_cache_size: usize,
+    /// Number of files of the `.pool` directory (looked up by their digest) kept in the cache of the file system.
+    pub pool_files_cache_size: usize,
+    /// Number of backups whose hard links (the paths of each inode, see `BackupPC::paths_for_inode`) are kept in the
+    /// cache of the view.
+    pub inode_paths_cache_size: usize,
+    /// Size of the chunks used to read (and skip) the content of a file.
+    pub read_chunk_size: usize,
+    /// Maximum size of the files whose decompressed content is kept in the cache of the view.
+    pub small_file_size: u64,
+    /// Total size (in bytes) of the content of the small files kept in the cache of the view (0 to disable the
+    /// cache).
+    pub small_file_cache_size: usize,
+}
+
+impl Default for BackupPCConfig {
+    fn default() -> Self {
+        BackupPCConfig {
+            view_cache_size: VIEW_CACHE_SIZE,
+            fs_cache_size: FS_CACHE_SIZE,
+            pool_files_cache_size: POOL_FILES_CACHE_SIZE,
+            inode_paths_cache_size: INODE_PATHS_CACHE_SIZE,
+            read_chunk_size: READ_CHUNK_SIZE,
+            small_file_size: SMALL_FILE_SIZE,
+            small_file_cache_size: SMALL_FILE_CACHE_SIZE,
+        }
+    }
+}
+
+impl BackupPCConfig {
+    /// Defines the number of listings kept in the cache of the view.
+    #[must_use]
+    pub fn with_view_cache_size(mut self, view_cache_size: usize) -> Self {
+        self.view_cache_size = view_cache_size;
+        self
+    }
+
+    /// Defines the number of directories kept in the cache of the file system.
+    #[must_use]
+    pub fn with_fs_cache_size(mut self, fs_cache_size: usize) -> Self {
+        self.fs_cache_size = fs_cache_size;
+        self
+    }
+
+    /// Defines the number of files of the `.pool` directory kept in the cache of the file system.
+    #[must_use]
+    pub fn with_pool_files_cache_size(mut self, pool_files_cache_size: usize) -> Self {
+        self.pool_files_cache_size = pool_files_cache_size;
+        self
+    }
+
+    /// Defines the number of backups whose hard links are kept in the cache of the view.
+    #[must_use]
+    pub fn with_inode_paths_cache_size(mut self, inode_paths_cache_size: usize) -> Self {
+        self.inode_paths_cache_size = inode_paths_cache_size;
+        self
+    }
+
+    /// Defines the size of the chunks used to read (and skip) the content of a file.
+    #[must_use]
+    pub fn with_read_chunk_size(mut self, read_chunk_size: usize) -> Self {
+        self.read_chunk_size = read_chunk_size;
+        self
+    }
+
+    /// Defines the maximum size of the files whose content is kept in the cache of the view.
+    #[must_use]
+    pub fn with_small_file_size(mut self, small_file_size: u64) -> Self {
+        self.small_file_size = small_file_size;
+        self
+    }
+
+    /// Defines the total size (in bytes) of the content of the small files kept in the cache of the view.
+    #[must_use]
+    pub fn with_small_file_cache_size(mut self, small_file_cache_size: usize) -> Self {
+        self.small_file_cache_size = small_file_cache_size;
+        self
+    }
+}
+
+/// Location of a `BackupPC` pool: the top directory, the source used to access it, and the additional pool roots.
+///
+/// A single configuration is shared by `Hosts::with_config`, `Search::with_config`, and `BackupPC::with_pool_config`
+/// so they all read the same pool in the same way:
+///
+/// ```
+/// use backuppc_pool_reader::attribute_file::Search;
+/// use backuppc_pool_reader::config::PoolConfig;
+/// use backuppc_pool_reader::hosts::Hosts;
+/// use backuppc_pool_reader::source::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let pool = PoolConfig::new("/var/lib/backuppc")
+///     .with_pool_roots(vec!["/mnt/pool2".into()])
+///     .with_retry(RetryPolicy::new(3, Duration::from_millis(100)));
+///
+/// let hosts = Hosts::with_config(&pool);
+/// let search = Search::with_config(&pool);
+/// assert_eq!(pool.topdir, "/var/lib/backuppc");
+/// ```
+#[derive(Clone)]
+pub struct PoolConfig {
+    /// The top directory path (on the machine hosting the pool).
+    pub topdir: String,
+    /// The source used to access the files of the top directory (the local filesystem by default), without the retry
+    /// policy (see `source`).
+    pub source: Arc<dyn PoolSource>,
+    /// The policy used to retry the reads of the source failing with a transient error (no retry by default).
+    pub retry: Option<RetryPolicy>,
+    /// Additional pool roots (directories containing a `pool` and/or a `cpool` directory), searched in order after
+    /// the top directory when a file isn't found in its pool (none by default).
+    pub pool_roots: Vec<PathBuf>,
+}
+
+impl PoolConfig {
+    /// Creates the configuration of the pool of the top directory, read from the local filesystem.
+    #[must_use]
+    pub fn new(topdir: &str) -> Self {
+        PoolConfig {
+            topdir: topdir.to_string(),
+            source: Arc::new(LocalSource),
+            retry: None,
+            pool_roots: Vec::new(),
+        }
+    }
+
+    /// Returns the source used to access the files of the top directory, wrapped with the retry policy if any (see
+    /// `RetryPolicy::wrap`).
+    #[must_use]
+    pub fn source(&self) -> Arc<dyn PoolSource> {
+        match self.retry {
+            Some(retry) => retry.wrap(self.source.clone()),
+            None => self.source.clone(),
+        }
+    }
+
+    /// Defines the source used to access the files of the top directory.
+    #[must_use]
+    pub fn with_source(mut self, source: Arc<dyn PoolSource>) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Retries the reads of the source failing with a transient error with the given policy (see `RetryPolicy`).
+    ///
+    /// The policy applies to the source defined before or after it (see `with_source`).
+    #[must_use]
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Defines the additional pool roots, searched in order after the top directory (see
+    /// `pool::find_file_in_pool_roots`).
+    #[must_use]
+    pub fn with_pool_roots(mut self, pool_roots: Vec<PathBuf>) -> Self {
+        self.pool_roots = pool_roots;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_retry_applies_to_the_source_defined_after() {
+        let source: Arc<dyn PoolSource> = Arc::new(LocalSource);
+        let retry = RetryPolicy::new(3, Duration::from_millis(1));
+
+        let pool = PoolConfig::new("/topdir").with_source(source.clone());
+        assert!(Arc::ptr_eq(&pool.source(), &source));
+
+        let retried_before = PoolConfig::new("/topdir")
+            .with_retry(retry)
+            .with_source(source.clone());
+        let retried_after = PoolConfig::new("/topdir")
+            .with_source(source.clone())
+            .with_retry(retry);
+        for pool in [retried_before, retried_after] {
+            assert_eq!(pool.retry, Some(retry));
+            assert!(Arc::ptr_eq(&pool.source, &source));
+            assert!(!Arc::ptr_eq(&pool.source(), &source));
+        }
+    }
+}
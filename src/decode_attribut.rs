@@ -1,11 +1,15 @@
+use std::borrow::Cow;
 use std::error::Error;
+use std::fmt;
 use std::hash::Hash;
 use std::io::{self, Read};
 
 use byteorder::{BigEndian, ReadBytesExt};
+use log::{error, warn};
 use num_traits::FromPrimitive;
 
 use crate::hosts::BackupInformation;
+use crate::util::vec_to_hex_string;
 
 const BPC_ATTRIB_TYPE_XATTR: u32 = 0x1756_5353;
 
@@ -34,30 +38,36 @@ pub enum FileType {
     Deleted = 9,
 }
 
+/// Maximum number of bytes of a variable-length integer (enough to encode a `u64`).
+const MAX_VARINT_BYTES: usize = 10;
+
 /// Trait for reading variable-length integers from a `Read` source.
 pub trait VarintRead: Read {
     /// Reads a variable-length integer from the source.
     ///
+    /// At most `MAX_VARINT_BYTES` bytes are read, so a corrupted stream that never clears the continuation bit
+    /// can't make the reader consume the whole source.
+    ///
     /// # Returns
     ///
     /// Returns the read integer as a `u64` wrapped in an `io::Result`.
     ///
     /// # Errors
     ///
-    /// Returns an `io::Error` if there was an error reading from the source or if the
-    /// read integer is too large to fit in a `u64`.
+    /// Returns an `io::Error` if there was an error reading from the source, if the integer is longer than
+    /// `MAX_VARINT_BYTES` bytes, or if the read integer is too large to fit in a `u64`.
     fn read_varint<T: FromPrimitive>(&mut self) -> io::Result<T> {
         let mut result = 0;
         let mut shift = 0;
 
-        loop {
+        for _ in 0..MAX_VARINT_BYTES {
             let mut buf: [u8; 1] = [0u8; 1];
             self.read_exact(&mut buf)?;
 
             let byte = buf[0];
             let val = u64::from(byte & 0x7F);
             if shift >= 64 || val << shift >> shift != val {
-                eprintln!("Varint too large: probably corrupted data");
+                warn!("Varint too large: probably corrupted data");
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
                     "Varint too large: probably corrupted data",
@@ -75,6 +85,12 @@ pub trait VarintRead: Read {
             }
             shift += 7;
         }
+
+        warn!("Varint longer than {MAX_VARINT_BYTES} bytes: probably corrupted data");
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Varint longer than {MAX_VARINT_BYTES} bytes: probably corrupted data"),
+        ))
     }
 }
 
@@ -86,8 +102,8 @@ impl<R: Read + ?Sized> VarintRead for R {}
 pub struct XattrEntry {
     /// The key of the extended attribute.
     pub key: String,
-    /// The value of the extended attribute.
-    pub value: String,
+    /// The raw value of the extended attribute (which can be binary, as an ACL or a capability).
+    pub value: Vec<u8>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
@@ -104,8 +120,14 @@ pub struct BpcDigest {
 pub struct FileAttributes {
     /// Name of the file.
     pub name: String,
+    /// Bytes of the name as stored in the attrib file, when they aren't valid UTF-8. The name is then empty, or the
+    /// name transcoded from the charset of the backup (see `transcode_name`).
+    pub name_bytes: Option<Vec<u8>>,
     /// Type of the file.
     pub type_: FileType,
+    /// Type of the file as stored in the attrib file. A type unknown to this version (ex: a type added by a newer
+    /// `BackupPC`) is decoded as `FileType::Unknown`, and is only kept here.
+    pub raw_type: u64,
     /// Compression level of the file.
     pub compress: u64,
 
@@ -135,11 +157,64 @@ pub struct FileAttributes {
 }
 
 impl FileAttributes {
+    /// Returns the name of the file as stored in the attrib file, to match it exactly (lookups of a path).
+    #[must_use]
+    pub fn raw_name(&self) -> &[u8] {
+        self.name_bytes.as_deref().unwrap_or(self.name.as_bytes())
+    }
+
+    /// Returns the name of the file for human output (listings): the bytes that aren't valid UTF-8 are replaced by
+    /// `U+FFFD`, so the output stays readable. A name transcoded from the charset of the backup (see
+    /// `transcode_name`) is returned as transcoded.
+    #[must_use]
+    pub fn display_name(&self) -> Cow<'_, str> {
+        if self.name.is_empty() {
+            String::from_utf8_lossy(self.raw_name())
+        } else {
+            Cow::Borrowed(&self.name)
+        }
+    }
+
+    /// Returns `true` if the file has the given name: the name stored in the attrib file, or the name transcoded
+    /// from the charset of the backup.
+    #[must_use]
+    pub fn has_name(&self, name: &str) -> bool {
+        self.raw_name() == name.as_bytes() || (!self.name.is_empty() && self.name == name)
+    }
+
+    /// Transcodes a name that isn't valid UTF-8 from the charset of the backup (ex: `cp1252` for a Windows backup).
+    ///
+    /// The transcoded name is stored in `name`, the bytes stored in the attrib file are kept in `name_bytes`. A
+    /// name that is valid UTF-8 is not changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `charset` - The charset of the backup (see `BackupInformation::charset`), as a label of the WHATWG Encoding
+    ///   Standard (`cp1252`, `iso-8859-1`, `shift_jis`, ...).
+    ///
+    /// # Returns
+    ///
+    /// `true` if the name has been transcoded, `false` if the name is valid UTF-8 or if the charset is unknown.
+    #[cfg(feature = "encoding")]
+    pub fn transcode_name(&mut self, charset: &str) -> bool {
+        let Some(bytes) = &self.name_bytes else {
+            return false;
+        };
+        let Some(encoding) = encoding_rs::Encoding::for_label(charset.as_bytes()) else {
+            return false;
+        };
+
+        self.name = encoding.decode_without_bom_handling(bytes).0.into_owned();
+        true
+    }
+
     #[must_use]
     pub fn from_host(host: String) -> Self {
         Self {
             name: host,
+            name_bytes: None,
             type_: FileType::Dir,
+            raw_type: FileType::Dir as u64,
             compress: 0,
             mode: 0,
             uid: 0,
@@ -161,7 +236,9 @@ impl FileAttributes {
     pub fn from_backup(backup: &BackupInformation) -> Self {
         Self {
             name: backup.num.to_string(),
+            name_bytes: None,
             type_: FileType::Dir,
+            raw_type: FileType::Dir as u64,
             compress: 0,
             mode: 0,
             uid: 0,
@@ -183,7 +260,9 @@ impl FileAttributes {
     pub fn from_share(share: String) -> Self {
         Self {
             name: share,
+            name_bytes: None,
             type_: FileType::Dir,
+            raw_type: FileType::Dir as u64,
             compress: 0,
             mode: 0,
             uid: 0,
@@ -219,7 +298,7 @@ impl FileAttributes {
     ///
     /// # Panics
     ///
-    /// This function will panic if the UTF-8 conversion of the file name or xattr key or value fails.
+    /// This function will panic if the UTF-8 conversion of the file name or xattr key fails.
     ///
     /// # Safety
     ///
@@ -245,13 +324,48 @@ impl FileAttributes {
     /// }
     /// ```
     pub fn read_from<R: Read + VarintRead>(reader: &mut R) -> io::Result<Self> {
+        Self::read_fields(&mut FieldReader::new(reader, 0, None))
+    }
+
+    /// Reads file attributes from a reader, and reports each raw field read with its offset.
+    ///
+    /// This is a debugging aid to inspect corrupted attrib files: the fields are decoded as with `read_from`, and
+    /// each one is pushed to `fields` with its bytes, as read from the reader, and its decoded value.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - A mutable reference to a reader implementing the `Read` trait.
+    /// * `offset` - The offset of the entry in the attrib file (used to compute the offset of each field).
+    /// * `fields` - The list where the fields read are pushed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the entry can't be decoded (see `read_from`). The fields read before the error
+    /// are still pushed to `fields`.
+    pub fn read_from_with_fields<R: Read>(
+        reader: &mut R,
+        offset: u64,
+        fields: &mut Vec<RawField>,
+    ) -> io::Result<Self> {
+        Self::read_fields(&mut FieldReader::new(reader, offset, Some(fields)))
+    }
+
+    fn read_fields<R: Read>(reader: &mut FieldReader<R>) -> io::Result<Self> {
         let filename_len: usize = reader.read_varint()?;
+        reader.end_field("name_len", &filename_len);
         let mut name = vec![0u8; filename_len];
         reader.read_exact(&mut name)?;
-        let name = String::from_utf8(name).unwrap_or_default();
+        let (name, name_bytes) = match String::from_utf8(name) {
+            Ok(name) => (name, None),
+            Err(err) => (String::new(), Some(err.into_bytes())),
+        };
+        reader.end_field("name", &name);
 
         let xattr_num_entries: u64 = reader.read_varint().unwrap_or_default();
-        let type_: FileType = match reader.read_varint().unwrap_or(9) {
+        reader.end_field("xattr_num_entries", &xattr_num_entries);
+        let raw_type: u64 = reader.read_varint().unwrap_or(9);
+        reader.end_field("type", &raw_type);
+        let type_: FileType = match raw_type {
             0 => FileType::File,
             1 => FileType::Hardlink,
             2 => FileType::Symlink,
@@ -260,49 +374,69 @@ impl FileAttributes {
             5 => FileType::Dir,
             6 => FileType::Fifo,
             8 => FileType::Socket,
-            7 | 9 => FileType::Unknown,
             10 => FileType::Deleted,
             other => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("Invalid file type {other}"),
-                ))
+                if other > 10 {
+                    warn!("Unknown file type {other} for {name}");
+                }
+                FileType::Unknown
             }
         };
         let mtime: u64 = reader.read_varint().unwrap_or_default();
+        reader.end_field("mtime", &mtime);
         let mode: u16 = reader.read_varint().unwrap_or_default();
+        reader.end_field("mode", &format_args!("{mode:o}"));
         let uid: u32 = reader.read_varint().unwrap_or_default();
+        reader.end_field("uid", &uid);
         let gid: u32 = reader.read_varint().unwrap_or_default();
+        reader.end_field("gid", &gid);
         let size: u64 = reader.read_varint().unwrap_or_default();
+        reader.end_field("size", &size);
         let inode: u64 = reader.read_varint().unwrap_or_default();
+        reader.end_field("inode", &inode);
         let compress: u64 = reader.read_varint().unwrap_or_default();
+        reader.end_field("compress", &compress);
         let nlinks: u32 = reader.read_varint().unwrap_or_default();
+        reader.end_field("nlinks", &nlinks);
 
         let digest_len: usize = reader.read_varint().unwrap_or_default();
+        reader.end_field("digest_len", &digest_len);
         let mut digest = vec![0u8; digest_len];
         if digest_len > 0 {
             reader.read_exact(&mut digest)?;
+            reader.end_field("digest", &vec_to_hex_string(&digest));
         }
 
+        // `bpc_attrib` writes both lengths before the key and the value. The key length includes the trailing NUL
+        // of the key.
         let mut xattrs = Vec::new();
         for _ in 0..xattr_num_entries {
             let key_len: usize = reader.read_varint().unwrap_or_default();
+            reader.end_field("xattr_key_len", &key_len);
+            let value_len: usize = reader.read_varint().unwrap_or_default();
+            reader.end_field("xattr_value_len", &value_len);
+
             let mut key = vec![0u8; key_len];
             reader.read_exact(&mut key)?;
+            if key.last() == Some(&0) {
+                key.pop();
+            }
             let key = String::from_utf8(key).unwrap_or_default();
+            reader.end_field("xattr_key", &key);
 
-            let value_len: usize = reader.read_varint().unwrap_or_default();
             let mut value = vec![0u8; value_len];
             reader.read_exact(&mut value)?;
-            let value = String::from_utf8(value).unwrap_or_default();
+            reader.end_field("xattr_value", &String::from_utf8_lossy(&value));
 
             xattrs.push(XattrEntry { key, value });
         }
 
         Ok(Self {
             name,
+            name_bytes,
             xattr_num_entries,
             type_,
+            raw_type,
             mtime,
             mode,
             uid,
@@ -318,6 +452,145 @@ impl FileAttributes {
             xattrs,
         })
     }
+
+    /// Decodes one file attributes entry from a slice of bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The bytes of the entry (the slice can continue after the entry).
+    ///
+    /// # Returns
+    ///
+    /// The decoded `FileAttributes` and the number of bytes consumed from the slice.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the entry can't be decoded (see `read_from`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use backuppc_pool_reader::decode_attribut::{FileAttributes, FileType};
+    ///
+    /// // name "a", no xattr, type file, mtime, mode, uid, gid, size, inode, compress, nlinks, digest length
+    /// let bytes = [1, b'a', 0, 0, 0, 0x24, 0, 0, 3, 0, 0, 1, 0, 0xff];
+    /// let (attributes, consumed) = FileAttributes::from_bytes(&bytes).unwrap();
+    ///
+    /// assert_eq!(attributes.name, "a");
+    /// assert_eq!(attributes.type_, FileType::File);
+    /// assert_eq!(attributes.size, 3);
+    /// assert_eq!(consumed, 13);
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<(Self, usize)> {
+        let mut cursor = io::Cursor::new(bytes);
+        let attributes = Self::read_from(&mut cursor)?;
+        let consumed = usize::try_from(cursor.position())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        Ok((attributes, consumed))
+    }
+}
+
+/// A raw field of an attrib file entry, as reported by `FileAttributes::read_from_with_fields`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RawField {
+    /// Name of the field (ex: `name_len`, `mtime`, `xattr_key`).
+    pub name: &'static str,
+    /// Offset of the first byte of the field.
+    pub offset: u64,
+    /// Bytes of the field, as read from the attrib file.
+    pub bytes: Vec<u8>,
+    /// Decoded value of the field.
+    pub value: String,
+}
+
+/// Reader that keeps track of the bytes of each field read, when fields are requested.
+struct FieldReader<'a, R> {
+    inner: &'a mut R,
+    position: u64,
+    current: Vec<u8>,
+    fields: Option<&'a mut Vec<RawField>>,
+}
+
+impl<'a, R: Read> FieldReader<'a, R> {
+    fn new(inner: &'a mut R, offset: u64, fields: Option<&'a mut Vec<RawField>>) -> Self {
+        Self {
+            inner,
+            position: offset,
+            current: Vec::new(),
+            fields,
+        }
+    }
+
+    fn end_field(&mut self, name: &'static str, value: &dyn fmt::Display) {
+        if let Some(fields) = self.fields.as_mut() {
+            let bytes = std::mem::take(&mut self.current);
+            fields.push(RawField {
+                name,
+                offset: self.position - bytes.len() as u64,
+                bytes,
+                value: value.to_string(),
+            });
+        }
+    }
+}
+
+impl<R: Read> Read for FieldReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.position += count as u64;
+        if self.fields.is_some() {
+            self.current.extend_from_slice(&buf[..count]);
+        }
+        Ok(count)
+    }
+}
+
+/// Error returned when a file doesn't start with the magic number of an attrib file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidMagicError {
+    /// The magic number read at the start of the file.
+    pub magic: u32,
+    /// The path of the file, when known by the caller.
+    pub path: Option<String>,
+}
+
+impl fmt::Display for InvalidMagicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid magic number {:#010x}", self.magic)?;
+        if let Some(path) = &self.path {
+            write!(f, " in {path}")?;
+        }
+        write!(
+            f,
+            " (expected {BPC_ATTRIB_TYPE_XATTR:#010x}): not an attrib file"
+        )
+    }
+}
+
+impl Error for InvalidMagicError {}
+
+impl InvalidMagicError {
+    /// Adds the path of the file to the error if it is an `InvalidMagicError`.
+    ///
+    /// # Arguments
+    ///
+    /// * `err` - The error returned by `AttributeFile::read_from`.
+    /// * `path` - The path of the file read.
+    ///
+    /// # Returns
+    ///
+    /// The error, with the path if it is an `InvalidMagicError`.
+    #[must_use]
+    pub fn with_path(err: Box<dyn Error>, path: &str) -> Box<dyn Error> {
+        match err.downcast::<InvalidMagicError>() {
+            Ok(mut err) => {
+                err.path = Some(path.to_string());
+                err
+            }
+            Err(err) => err,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -338,7 +611,8 @@ impl AttributeFile {
     ///
     /// # Errors
     ///
-    /// This function can return an `io::Error` if an error occurs while reading from the reader.
+    /// This function can return an `io::Error` if an error occurs while reading from the reader, or an
+    /// `InvalidMagicError` if the data doesn't start with the magic number of an attrib file.
     ///
     /// # Examples
     ///
@@ -355,25 +629,635 @@ impl AttributeFile {
     /// let attribute_file = result.unwrap();
     /// ```
     pub fn read_from<R: Read + VarintRead>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        Self::read_magic(reader).map_err(Self::magic_error)?;
+
+        let entries = AttributeEntries {
+            reader,
+            position: 4,
+            started: true,
+            done: false,
+        };
+        let attributes = entries
+            .filter_map(|attr| {
+                attr.map_err(|e| error!("Error reading file attributes: {e}"))
+                    .ok()
+            })
+            .collect();
+
+        Ok(Self { attributes })
+    }
+
+    /// Iterates lazily over the entries of an attrib file.
+    ///
+    /// Unlike `read_from`, the entries are not kept in memory: each entry is decoded when the iterator is advanced,
+    /// so a directory with hundreds of thousands of entries can be filtered or streamed.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader of the (uncompressed) attrib file.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the entries, stopping at the end of the file. An entry that can't be decoded is returned as
+    /// an error of kind `InvalidData`, and the iteration continues with the next entry. The iteration stops after
+    /// any other error (the magic number can't be read, or isn't the one of an attrib file, in which case the error
+    /// wraps an `InvalidMagicError`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use backuppc_pool_reader::decode_attribut::AttributeFile;
+    ///
+    /// let mut bytes = 0x1756_5353_u32.to_be_bytes().to_vec();
+    /// bytes.extend_from_slice(&[1, b'a', 0, 0, 0, 0xa4, 0x03, 0, 0, 0, 0, 3, 1, 0]);
+    ///
+    /// let names = AttributeFile::iter_from(bytes.as_slice())
+    ///     .map(|attr| attr.map(|attr| attr.name))
+    ///     .collect::<std::io::Result<Vec<String>>>()
+    ///     .unwrap();
+    /// assert_eq!(names, vec!["a"]);
+    /// ```
+    pub fn iter_from<R: Read>(reader: R) -> impl Iterator<Item = io::Result<FileAttributes>> {
+        AttributeEntries {
+            reader,
+            position: 0,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Finds the best entry of an attrib file, without keeping the other entries in memory.
+    ///
+    /// The entries are decoded one by one and only the best entry so far is kept, so the memory used doesn't depend on
+    /// the number of entries of the file. The first entry with the best possible rank (`K::default()`, the exact match
+    /// searched) is returned as soon as it is decoded, without reading the rest of the file. Otherwise, the whole file
+    /// is scanned: the entry with the lowest rank is returned, and among the entries of the same rank, the last one
+    /// (as for the duplicated names of an attrib file). As with `read_from`, an entry that can't be decoded is logged
+    /// and skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader of the (uncompressed) attrib file.
+    /// * `rank` - The rank of an entry, or `None` for an entry that isn't searched. No rank can be lower than
+    ///   `K::default()`.
+    ///
+    /// # Returns
+    ///
+    /// The entry with the lowest rank, or `None` if no entry is ranked.
+    ///
+    /// # Errors
+    ///
+    /// An `io::Error` if the file can't be read, or an `InvalidMagicError` if the data doesn't start with the magic
+    /// number of an attrib file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use backuppc_pool_reader::decode_attribut::AttributeFile;
+    ///
+    /// let mut bytes = 0x1756_5353_u32.to_be_bytes().to_vec();
+    /// bytes.extend_from_slice(&[1, b'a', 0, 0, 0, 0xa4, 0x03, 0, 0, 0, 0, 3, 1, 0]);
+    /// bytes.extend_from_slice(&[1, b'b', 0, 0, 0, 0xa4, 0x03, 0, 0, 0, 0, 3, 1, 0]);
+    /// bytes.extend_from_slice(&[1, b'b', 0, 0, 0, 0xa4, 0x03, 0, 0, 7, 0, 3, 1, 0]);
+    ///
+    /// // The first entry named `b` is an exact match: it is returned without reading the next entries
+    /// let found = AttributeFile::find_from(&mut bytes.as_slice(), |attr| (attr.name == "b").then_some(()));
+    /// assert_eq!(found.unwrap().unwrap().size, 0);
+    /// // Without an exact match, the last entry of the lowest rank is returned
+    /// let found = AttributeFile::find_from(&mut bytes.as_slice(), |attr| (attr.name != "a").then_some(1));
+    /// assert_eq!(found.unwrap().unwrap().size, 7);
+    /// // The entry named `b` is preferred to the entry named `a`
+    /// let found = AttributeFile::find_from(&mut bytes.as_slice(), |attr| Some(attr.name == "a"));
+    /// assert_eq!(found.unwrap().unwrap().name, "b");
+    /// assert!(AttributeFile::find_from(&mut bytes.as_slice(), |attr| (attr.name == "c").then_some(()))
+    ///     .unwrap()
+    ///     .is_none());
+    /// ```
+    pub fn find_from<R: Read, K: Ord + Default>(
+        reader: &mut R,
+        rank: impl Fn(&FileAttributes) -> Option<K>,
+    ) -> Result<Option<FileAttributes>, Box<dyn Error>> {
+        Self::read_magic(reader).map_err(Self::magic_error)?;
+
+        let entries = AttributeEntries {
+            reader,
+            position: 4,
+            started: true,
+            done: false,
+        };
+        let mut best: Option<(K, FileAttributes)> = None;
+        for attr in entries {
+            match attr {
+                Ok(attr) => {
+                    let Some(attr_rank) = rank(&attr) else {
+                        continue;
+                    };
+                    if attr_rank == K::default() {
+                        return Ok(Some(attr));
+                    }
+                    match &best {
+                        Some((best_rank, _)) if attr_rank > *best_rank => {}
+                        _ => best = Some((attr_rank, attr)),
+                    }
+                }
+                Err(e) => error!("Error reading file attributes: {e}"),
+            }
+        }
+
+        Ok(best.map(|(_, attr)| attr))
+    }
+
+    /// Finds the offset of an entry in an attrib file.
+    ///
+    /// The entries are decoded one by one until the entry is found, to locate where an entry begins when
+    /// inspecting a corrupted attrib file, or to decode it again from its offset.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader of the (uncompressed) attrib file.
+    /// * `name` - The name of the entry.
+    ///
+    /// # Returns
+    ///
+    /// The offset of the first byte of the entry in the attrib file (the magic number included), or `None` if no
+    /// entry has this name.
+    ///
+    /// # Errors
+    ///
+    /// If the file isn't an attrib file, or if an entry before the one searched can't be decoded (the error gives
+    /// the offset of this entry).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use backuppc_pool_reader::decode_attribut::AttributeFile;
+    ///
+    /// let mut bytes = 0x1756_5353_u32.to_be_bytes().to_vec();
+    /// bytes.extend_from_slice(&[1, b'a', 0, 0, 0, 0xa4, 0x03, 0, 0, 0, 0, 3, 1, 0]);
+    /// bytes.extend_from_slice(&[1, b'b', 0, 0, 0, 0xa4, 0x03, 0, 0, 0, 0, 3, 1, 0]);
+    ///
+    /// assert_eq!(AttributeFile::offset_of(bytes.as_slice(), "b").unwrap(), Some(18));
+    /// assert_eq!(AttributeFile::offset_of(bytes.as_slice(), "c").unwrap(), None);
+    /// ```
+    pub fn offset_of<R: Read>(reader: R, name: &str) -> io::Result<Option<u64>> {
+        let mut entries = AttributeEntries {
+            reader,
+            position: 0,
+            started: false,
+            done: false,
+        };
+
+        while let Some((offset, attr)) = entries.next_entry() {
+            match attr {
+                Ok(attr) if attr.raw_name() == name.as_bytes() => return Ok(Some(offset)),
+                Ok(_) => {}
+                Err(err) if offset == 0 => return Err(err),
+                Err(err) => {
+                    return Err(io::Error::new(
+                        err.kind(),
+                        format!("Entry at offset {offset} can't be decoded: {err}"),
+                    ))
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Reads the magic number of an attrib file.
+    ///
+    /// The error of an invalid magic number is an `InvalidMagicError`, wrapped in an `io::Error` of kind
+    /// `InvalidData`.
+    fn read_magic<R: Read>(reader: &mut R) -> io::Result<()> {
         let magic: u32 = reader.read_u32::<BigEndian>()?;
         if magic != BPC_ATTRIB_TYPE_XATTR {
-            return Err("Invalid magic number".into());
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                InvalidMagicError { magic, path: None },
+            ));
         }
 
-        let mut attributes = Vec::new();
+        Ok(())
+    }
+
+    /// Unwraps the `InvalidMagicError` of an error returned by `read_magic`.
+    fn magic_error(err: io::Error) -> Box<dyn Error> {
+        if err
+            .get_ref()
+            .is_some_and(|inner| inner.is::<InvalidMagicError>())
+        {
+            return err.into_inner().expect("The error has an inner error");
+        }
+
+        Box::new(err)
+    }
+
+    /// Decodes an attrib file already in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The (uncompressed) content of the attrib file.
+    ///
+    /// # Returns
+    ///
+    /// The decoded `AttributeFile`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the content can't be decoded (see `read_from`).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        Self::read_from(&mut io::Cursor::new(bytes))
+    }
+
+    /// Reads the entries of an attrib file from a reader, with the raw fields of each entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - A mutable reference to a reader implementing the `Read` trait.
+    ///
+    /// # Returns
+    ///
+    /// The decoded entries, each one with the list of its fields and their offsets in the attrib file (see
+    /// `FileAttributes::read_from_with_fields`). The decoding stops at the first entry that can't be decoded: its
+    /// fields are returned with an error message.
+    ///
+    /// # Errors
+    ///
+    /// This function can return an `io::Error` if the magic number can't be read, or an `InvalidMagicError` if the
+    /// data doesn't start with the magic number of an attrib file.
+    pub fn read_raw_from<R: Read>(reader: &mut R) -> Result<Vec<RawEntry>, Box<dyn Error>> {
+        Self::read_magic(reader).map_err(Self::magic_error)?;
+
+        let mut entries = Vec::new();
+        let mut offset = 4;
         loop {
-            match FileAttributes::read_from(reader) {
-                Ok(attr) => attributes.push(attr),
-                Err(e) => {
-                    if e.kind() == io::ErrorKind::UnexpectedEof {
-                        break;
-                    }
+            let mut fields = Vec::new();
+            let attributes = FileAttributes::read_from_with_fields(reader, offset, &mut fields);
+            if let Some(last) = fields.last() {
+                offset = last.offset + last.bytes.len() as u64;
+            }
 
-                    eprintln!("Error reading file attributes: {e}");
+            match attributes {
+                Ok(attributes) => entries.push(RawEntry {
+                    attributes: Ok(attributes),
+                    fields,
+                }),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && fields.is_empty() => break,
+                Err(e) => {
+                    entries.push(RawEntry {
+                        attributes: Err(e.to_string()),
+                        fields,
+                    });
+                    break;
                 }
             }
         }
 
-        Ok(Self { attributes })
+        Ok(entries)
+    }
+}
+
+/// The iterator over the entries of an attrib file (see `AttributeFile::iter_from`).
+struct AttributeEntries<R> {
+    reader: R,
+    /// Number of bytes read from the attrib file.
+    position: u64,
+    /// `true` once the magic number has been read.
+    started: bool,
+    done: bool,
+}
+
+impl<R: Read> AttributeEntries<R> {
+    /// Decodes the next entry.
+    ///
+    /// # Returns
+    ///
+    /// The offset of the entry in the attrib file (0 for an error reading the magic number), and the entry decoded.
+    fn next_entry(&mut self) -> Option<(u64, io::Result<FileAttributes>)> {
+        if self.done {
+            return None;
+        }
+
+        if !self.started {
+            self.started = true;
+            if let Err(err) = AttributeFile::read_magic(&mut self.reader) {
+                self.done = true;
+                return Some((0, Err(err)));
+            }
+            self.position = 4;
+        }
+
+        let offset = self.position;
+        let mut reader = FieldReader::new(&mut self.reader, offset, None);
+        let attr = FileAttributes::read_fields(&mut reader);
+        self.position = reader.position;
+
+        match attr {
+            Ok(attr) => Some((offset, Ok(attr))),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                // A decoding error skips the entry, an error of the reader ends the iteration
+                self.done = e.kind() != io::ErrorKind::InvalidData;
+                Some((offset, Err(e)))
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for AttributeEntries<R> {
+    type Item = io::Result<FileAttributes>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().map(|(_, attr)| attr)
+    }
+}
+
+/// An entry of an attrib file, with its raw fields (see `AttributeFile::read_raw_from`).
+#[derive(Debug)]
+pub struct RawEntry {
+    /// The decoded attributes, or the error message if the entry can't be decoded.
+    pub attributes: std::result::Result<FileAttributes, String>,
+    /// The fields read for the entry.
+    pub fields: Vec<RawField>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_varint() {
+        let mut cursor = Cursor::new(vec![0xac, 0x02]);
+        assert_eq!(cursor.read_varint::<u64>().unwrap(), 300);
+
+        let mut cursor = Cursor::new(vec![
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+        ]);
+        assert_eq!(cursor.read_varint::<u64>().unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn test_read_varint_stops_after_max_bytes() {
+        let mut cursor = Cursor::new(vec![0x80; 100]);
+        let err = cursor.read_varint::<u64>().unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(cursor.position(), MAX_VARINT_BYTES as u64);
+    }
+
+    #[test]
+    fn test_from_bytes_returns_consumed_length() {
+        let mut bytes = vec![2, b'a', b'b', 0, 5, 0x10, 0xed, 0x03, 0, 0, 0, 7, 0, 2, 0];
+        let first_len = bytes.len();
+        bytes.extend_from_slice(&[1, b'c', 0, 0, 0, 0x24, 0, 0, 4, 0, 0, 1, 0]);
+
+        let (first, consumed) = FileAttributes::from_bytes(&bytes).unwrap();
+        assert_eq!(first.name, "ab");
+        assert_eq!(first.type_, FileType::Dir);
+        assert_eq!(first.mode, 0o755);
+        assert_eq!(first.inode, 7);
+        assert_eq!(consumed, first_len);
+
+        let (second, consumed) = FileAttributes::from_bytes(&bytes[first_len..]).unwrap();
+        assert_eq!(second.name, "c");
+        assert_eq!(second.size, 4);
+        assert_eq!(consumed, bytes.len() - first_len);
+    }
+
+    #[test]
+    fn test_read_raw_from_reports_field_offsets() {
+        let mut bytes = BPC_ATTRIB_TYPE_XATTR.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&[2, b'a', b'b', 0, 5, 0x10, 0xed, 0x03, 0, 0, 0, 7, 0, 2, 0]);
+        bytes.extend_from_slice(&[1, b'c', 0, 0, 0, 0x24, 0, 0, 4, 0, 0, 1, 2, 0xab, 0xcd]);
+
+        let entries = AttributeFile::read_raw_from(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].attributes.as_ref().unwrap().name, "ab");
+
+        let mode = entries[0].fields.iter().find(|f| f.name == "mode").unwrap();
+        assert_eq!(mode.offset, 10);
+        assert_eq!(mode.bytes, vec![0xed, 0x03]);
+        assert_eq!(mode.value, "755");
+
+        let name = entries[1].fields.iter().find(|f| f.name == "name").unwrap();
+        assert_eq!(name.offset, 20);
+        let digest = entries[1].fields.last().unwrap();
+        assert_eq!(digest.name, "digest");
+        assert_eq!(digest.offset, 32);
+        assert_eq!(digest.value, "abcd");
+    }
+
+    #[test]
+    fn test_read_xattrs_with_lengths_before_bytes() {
+        // name "a", 2 xattrs, type file, mtime, mode, uid, gid, size, inode, compress, nlinks, no digest
+        let mut bytes = vec![1, b'a', 2, 0, 0, 0xa4, 0x03, 0, 0, 3, 0, 3, 1, 0];
+        bytes.extend_from_slice(&[11, 3]);
+        bytes.extend_from_slice(b"user.color\0red");
+        bytes.extend_from_slice(&[9, 2]);
+        bytes.extend_from_slice(b"user.tag\0\xff\0");
+        let first_len = bytes.len();
+        bytes.extend_from_slice(&[1, b'b', 0, 0, 0, 0xa4, 0x03, 0, 0, 0, 0, 3, 1, 0]);
+
+        let (first, consumed) = FileAttributes::from_bytes(&bytes).unwrap();
+        assert_eq!(first.name, "a");
+        assert_eq!(first.size, 3);
+        assert_eq!(
+            first.xattrs,
+            vec![
+                XattrEntry {
+                    key: "user.color".to_string(),
+                    value: b"red".to_vec()
+                },
+                XattrEntry {
+                    key: "user.tag".to_string(),
+                    value: vec![0xff, 0x00]
+                },
+            ]
+        );
+        assert_eq!(consumed, first_len);
+
+        let (second, _) = FileAttributes::from_bytes(&bytes[first_len..]).unwrap();
+        assert_eq!(second.name, "b");
+        assert_eq!(second.mode, 0o644);
+    }
+
+    #[test]
+    fn test_read_xattrs_of_attrib_file_fixture() {
+        // An attrib file as stored in the cpool directory: a directory with a user xattr, and a file with a SELinux
+        // label (its value ends with a NUL byte) and a user xattr
+        let compressed = include_bytes!("../tests/fixtures/attrib_xattrs");
+        let mut content = Vec::new();
+        crate::compress::BackupPCReader::new(&compressed[..])
+            .read_to_end(&mut content)
+            .unwrap();
+
+        let attributes = AttributeFile::read_from(&mut Cursor::new(content))
+            .unwrap()
+            .attributes;
+        assert_eq!(attributes.len(), 2);
+
+        let dir = &attributes[0];
+        assert_eq!((dir.name.as_str(), &dir.type_), ("docs", &FileType::Dir));
+        assert_eq!(dir.mode, 0o755);
+        assert_eq!(
+            dir.xattrs,
+            vec![XattrEntry {
+                key: "user.xdg.tags".to_string(),
+                value: b"work".to_vec()
+            }]
+        );
+
+        let file = &attributes[1];
+        assert_eq!(
+            (file.name.as_str(), &file.type_),
+            ("notes.txt", &FileType::File)
+        );
+        assert_eq!(
+            (file.mtime, file.mode, file.uid, file.gid),
+            (1_700_000_123, 0o644, 1000, 1000)
+        );
+        assert_eq!((file.size, file.inode, file.compress), (12, 3, 3));
+        assert_eq!(file.bpc_digest.len, 16);
+        assert_eq!(file.xattr_num_entries, 2);
+        assert_eq!(
+            file.xattrs,
+            vec![
+                XattrEntry {
+                    key: "security.selinux".to_string(),
+                    value: b"unconfined_u:object_r:user_home_t:s0\0".to_vec()
+                },
+                XattrEntry {
+                    key: "user.xdg.origin.url".to_string(),
+                    value: b"https://example.org/notes.txt".to_vec()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display_name_and_raw_name() {
+        let file = FileAttributes::from_host("pc-é".to_string());
+
+        assert_eq!(file.raw_name(), "pc-é".as_bytes());
+        assert_eq!(file.display_name(), "pc-é");
+    }
+
+    #[test]
+    fn test_iter_from_yields_entries_lazily() {
+        let mut bytes = BPC_ATTRIB_TYPE_XATTR.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&[2, b'a', b'b', 0, 5, 0x10, 0xed, 0x03, 0, 0, 0, 7, 0, 2, 0]);
+        bytes.extend_from_slice(&[1, b'c', 0, 0, 0, 0x24, 0, 0, 4, 0, 0, 1, 0]);
+
+        let mut cursor = Cursor::new(bytes);
+        let first = AttributeFile::iter_from(&mut cursor)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.name, "ab");
+        // Only the first entry has been read
+        assert_eq!(cursor.position(), 4 + 15);
+
+        cursor.set_position(0);
+        let names = AttributeFile::iter_from(&mut cursor)
+            .map(|attr| attr.unwrap().name)
+            .collect::<Vec<String>>();
+        assert_eq!(names, vec!["ab", "c"]);
+
+        let mut entries = AttributeFile::iter_from(&[0x78, 0x9c, 0x01, 0x02][..]);
+        let err = entries.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.into_inner().unwrap().is::<InvalidMagicError>());
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn test_read_unknown_file_types() {
+        let mut bytes = BPC_ATTRIB_TYPE_XATTR.to_be_bytes().to_vec();
+        // Entries "a" (type 12, from a newer version), "b" (type 7), and "c" (type 10)
+        for (name, type_) in [(b'a', 12), (b'b', 7), (b'c', 10)] {
+            bytes.extend_from_slice(&[1, name, 0, type_, 0, 0x24, 0, 0, 4, 0, 0, 1, 0]);
+        }
+
+        let attributes = AttributeFile::read_from(&mut Cursor::new(bytes))
+            .unwrap()
+            .attributes;
+        let types = attributes
+            .iter()
+            .map(|attr| (attr.name.as_str(), attr.type_.clone(), attr.raw_type))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                ("a", FileType::Unknown, 12),
+                ("b", FileType::Unknown, 7),
+                ("c", FileType::Deleted, 10),
+            ]
+        );
+        assert_eq!(attributes[0].size, 4);
+    }
+
+    #[test]
+    fn test_read_non_utf8_name() {
+        let mut bytes = BPC_ATTRIB_TYPE_XATTR.to_be_bytes().to_vec();
+        // The name "café" in cp1252
+        bytes.extend_from_slice(&[4, b'c', b'a', b'f', 0xe9, 0, 0, 0x24, 0, 0, 4, 0, 0, 1, 0]);
+
+        let attributes = AttributeFile::read_from(&mut Cursor::new(bytes))
+            .unwrap()
+            .attributes;
+        let mut file = attributes[0].clone();
+        assert_eq!(file.name, "");
+        assert_eq!(file.raw_name(), b"caf\xe9");
+        assert_eq!(file.display_name(), "caf\u{fffd}");
+        assert!(!file.has_name(""));
+
+        #[cfg(feature = "encoding")]
+        {
+            assert!(!file.transcode_name("unknown-charset"));
+            assert!(file.transcode_name("cp1252"));
+            assert_eq!(file.name, "café");
+            assert_eq!(file.raw_name(), b"caf\xe9");
+            assert_eq!(file.display_name(), "café");
+            assert!(file.has_name("café"));
+        }
+        file.name_bytes = None;
+        assert!(!file.has_name("caf\u{fffd}"));
+    }
+
+    #[test]
+    fn test_offset_of() {
+        let mut bytes = BPC_ATTRIB_TYPE_XATTR.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&[2, b'a', b'b', 0, 5, 0x10, 0xed, 0x03, 0, 0, 0, 7, 0, 2, 0]);
+        bytes.extend_from_slice(&[1, b'c', 0, 0, 0, 0x24, 0, 0, 4, 0, 0, 1, 2, 0xab, 0xcd]);
+
+        assert_eq!(AttributeFile::offset_of(&bytes[..], "ab").unwrap(), Some(4));
+        assert_eq!(AttributeFile::offset_of(&bytes[..], "c").unwrap(), Some(19));
+        assert_eq!(AttributeFile::offset_of(&bytes[..], "d").unwrap(), None);
+
+        // The entry at the offset can be decoded again
+        let (entry, _) = FileAttributes::from_bytes(&bytes[19..]).unwrap();
+        assert_eq!(entry.name, "c");
+
+        // An entry that can't be decoded (a name length longer than a varint) is reported with its offset
+        bytes[19..30].fill(0xff);
+        let err = AttributeFile::offset_of(&bytes[..], "d").unwrap_err();
+        assert!(err.to_string().contains("offset 19"), "{err}");
+    }
+
+    #[test]
+    fn test_read_attribute_file_with_invalid_magic() {
+        let mut cursor = Cursor::new(vec![0x78, 0x9c, 0x01, 0x02, 0x03]);
+        let err = AttributeFile::read_from(&mut cursor).unwrap_err();
+        let err = InvalidMagicError::with_path(err, "/var/lib/backuppc/pool/12/34/1234");
+
+        assert_eq!(
+            err.to_string(),
+            "Invalid magic number 0x789c0102 in /var/lib/backuppc/pool/12/34/1234 \
+             (expected 0x17565353): not an attrib file"
+        );
     }
 }
@@ -0,0 +1,75 @@
+use std::io::{self, Read};
+
+use md5::{Digest, Md5};
+
+/// A reader computing the MD5 digest of the content read through it, without buffering the content.
+///
+/// ```
+/// use backuppc_pool_reader::digest::DigestReader;
+/// use backuppc_pool_reader::util::vec_to_hex_string;
+/// use std::io::Read;
+///
+/// let mut reader = DigestReader::new(&b"hello world"[..]);
+/// std::io::copy(&mut reader, &mut std::io::sink()).unwrap();
+/// assert_eq!(vec_to_hex_string(&reader.digest()), "5eb63bbbe01eeed093cb22bb8f5acdc3");
+/// ```
+pub struct DigestReader<R> {
+    inner: R,
+    md5: Md5,
+}
+
+impl<R: Read> DigestReader<R> {
+    /// Creates a reader hashing the content of `inner`.
+    pub fn new(inner: R) -> Self {
+        DigestReader {
+            inner,
+            md5: Md5::new(),
+        }
+    }
+
+    /// Returns the digest of the content read so far.
+    #[must_use]
+    pub fn digest(&self) -> [u8; 16] {
+        self.md5.clone().finalize().into()
+    }
+}
+
+impl<R: Read> Read for DigestReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.md5.update(&buf[..count]);
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::vec_to_hex_string;
+
+    #[test]
+    fn test_digest_reader() {
+        let md5 = |data: &[u8]| {
+            let mut reader = DigestReader::new(data);
+            io::copy(&mut reader, &mut io::sink()).unwrap();
+            vec_to_hex_string(&reader.digest())
+        };
+
+        // Test suite of RFC 1321
+        assert_eq!(md5(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            md5(
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+            ),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+
+        // The digest doesn't depend on how the content is read
+        let content: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let mut reader = DigestReader::new(&content[..]);
+        let mut buffer = [0; 37];
+        while reader.read(&mut buffer).unwrap() > 0 {}
+        assert_eq!(vec_to_hex_string(&reader.digest()), md5(&content));
+    }
+}
@@ -0,0 +1,275 @@
+use std::io::Write;
+
+use crate::decode_attribut::{FileAttributes, FileType};
+use crate::util::{csv_escape, json_escape, vec_to_hex_string, Result};
+use crate::view::BackupPC;
+
+/// Format of the records written by `export`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One line per file, with a header line.
+    Csv,
+    /// One JSON object per line.
+    JsonLines,
+}
+
+/// Writes a record for each file of a directory of a backup, and of its sub-directories.
+///
+/// Each record gives the path of the file (relative to the share, starting with a slash), its type, size, mode (in
+/// octal, as a string in JSON), uid, gid, mtime, and digest (in hexadecimal, empty for a file without content). The
+/// files of a directory are written sorted by name, followed by the content of its sub-directories.
+///
+/// The walk is aborted with a `Cancelled` error as soon as the token of the view is cancelled (see
+/// `BackupPC::set_cancellation_token`).
+///
+/// # Arguments
+///
+/// * `view` - The view of the backups.
+/// * `out` - Where the records are written.
+/// * `hostname` - The hostname of the backup.
+/// * `backup_number` - The backup number.
+/// * `share` - The share of the backup (ex: `/home`).
+/// * `path` - The directory of the share to export (empty for the whole share).
+/// * `format` - The format of the records.
+///
+/// # Errors
+///
+/// An error can be returned if a directory can't be listed, or if a record can't be written.
+pub fn export<W: Write>(
+    view: &BackupPC,
+    out: &mut W,
+    hostname: &str,
+    backup_number: u32,
+    share: &str,
+    path: &str,
+    format: ExportFormat,
+) -> Result<()> {
+    let backup_number = backup_number.to_string();
+    let base = [hostname, backup_number.as_str()]
+        .into_iter()
+        .chain(share.split('/'))
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<&str>>();
+
+    if format == ExportFormat::Csv {
+        writeln!(out, "path,type,size,mode,uid,gid,mtime,digest")?;
+    }
+
+    let mut directories = vec![path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()];
+    while let Some(directory) = directories.pop() {
+        view.cancellation_token().check()?;
+        let full_path = base
+            .iter()
+            .copied()
+            .chain(directory.iter().map(String::as_str))
+            .collect::<Vec<&str>>();
+        let mut files = view.list(&full_path)?;
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut subdirectories = Vec::new();
+        for file in files {
+            let mut file_path = directory.clone();
+            file_path.push(file.name.clone());
+            let record_path = format!("/{}", file_path.join("/"));
+            write_record(out, format, &record_path, &file)?;
+
+            if file.type_ == FileType::Dir {
+                subdirectories.push(file_path);
+            }
+        }
+        directories.extend(subdirectories.into_iter().rev());
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn write_record<W: Write>(
+    out: &mut W,
+    format: ExportFormat,
+    path: &str,
+    file: &FileAttributes,
+) -> std::io::Result<()> {
+    let digest = vec_to_hex_string(&file.bpc_digest.digest);
+    match format {
+        ExportFormat::Csv => writeln!(
+            out,
+            "{},{:?},{},{:o},{},{},{},{}",
+            csv_escape(path),
+            file.type_,
+            file.size,
+            file.mode,
+            file.uid,
+            file.gid,
+            file.mtime,
+            digest
+        ),
+        ExportFormat::JsonLines => writeln!(
+            out,
+            "{{\"path\":\"{}\",\"type\":\"{:?}\",\"size\":{},\"mode\":\"{:o}\",\"uid\":{},\"gid\":{},\"mtime\":{},\"digest\":\"{}\"}}",
+            json_escape(path),
+            file.type_,
+            file.size,
+            file.mode,
+            file.uid,
+            file.gid,
+            file.mtime,
+            digest
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attribute_file::MockSearchTrait;
+    use crate::cancel::{CancellationToken, Cancelled};
+    use crate::decode_attribut::BpcDigest;
+    use crate::hosts::{BackupInformation, MockHostsTrait};
+    use mockall::predicate::*;
+
+    fn create_file_attributes(name: &str, type_: FileType, mode: u16, size: u64) -> FileAttributes {
+        FileAttributes {
+            name: name.to_string(),
+            name_bytes: None,
+            raw_type: type_.clone() as u64,
+            type_,
+            compress: 3,
+
+            mode,
+            uid: 1000,
+            gid: 100,
+            nlinks: 0,
+
+            mtime: 1_700_000_000,
+            size,
+            inode: 0,
+
+            bpc_digest: BpcDigest {
+                len: if size > 0 { 16 } else { 0 },
+                digest: if size > 0 {
+                    (0..16).collect()
+                } else {
+                    Vec::new()
+                },
+            },
+            xattr_num_entries: 0,
+            xattrs: Vec::new(),
+        }
+    }
+
+    fn create_mock_backup(num: u32) -> BackupInformation {
+        BackupInformation {
+            num,
+            backup_type: "full".to_string(),
+            start_time: 0,
+            end_time: 0,
+            n_files: 0,
+            size: 0,
+            n_files_exist: 0,
+            size_exist: 0,
+            n_files_new: 0,
+            size_new: 0,
+            xfer_errs: 0,
+            xfer_bad_file: 0,
+            xfer_bad_share: 0,
+            tar_errs: 0,
+            compress: 3,
+            size_exist_comp: 0,
+            size_new_comp: 0,
+            no_fill: 0,
+            fill_from_num: 0,
+            mangle: 1,
+            xfer_method: "rsync".to_string(),
+            level: 0,
+            charset: "utf-8".to_string(),
+            version: "4.0.0".to_string(),
+            inode_last: 0,
+        }
+    }
+
+    fn create_view() -> BackupPC {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+        hosts_mock
+            .expect_list_hosts()
+            .returning(|| Ok(vec!["pc-1".to_string()]));
+        hosts_mock
+            .expect_list_backups()
+            .with(eq("pc-1"))
+            .returning(|_| Ok(vec![create_mock_backup(1)]));
+        hosts_mock
+            .expect_list_backups_to_fill()
+            .with(eq("pc-1"), eq(1))
+            .returning(|_, _| Ok(vec![create_mock_backup(1)]));
+        search_mock
+            .expect_list_file_from_dir()
+            .returning(|_, _, share, path| match (share, path) {
+                (None, None) => Ok(vec![create_file_attributes(
+                    "/home",
+                    FileType::Dir,
+                    0o755,
+                    0,
+                )]),
+                (Some(_), Some("")) => Ok(vec![
+                    create_file_attributes("b.txt", FileType::File, 0o644, 5),
+                    create_file_attributes("a, \"dir\"", FileType::Dir, 0o750, 0),
+                ]),
+                (Some(_), Some("a, \"dir\"")) => Ok(vec![create_file_attributes(
+                    "link",
+                    FileType::Symlink,
+                    0o777,
+                    6,
+                )]),
+                _ => Ok(Vec::new()),
+            });
+
+        BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock)
+    }
+
+    fn export_to_string(view: &BackupPC, path: &str, format: ExportFormat) -> String {
+        let mut out = Vec::new();
+        export(view, &mut out, "pc-1", 1, "/home", path, format).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let view = create_view();
+
+        assert_eq!(
+            export_to_string(&view, "", ExportFormat::Csv),
+            "path,type,size,mode,uid,gid,mtime,digest\n\
+             \"/a, \"\"dir\"\"\",Dir,0,750,1000,100,1700000000,\n\
+             /b.txt,File,5,644,1000,100,1700000000,000102030405060708090a0b0c0d0e0f\n\
+             \"/a, \"\"dir\"\"/link\",Symlink,6,777,1000,100,1700000000,000102030405060708090a0b0c0d0e0f\n"
+        );
+    }
+
+    #[test]
+    fn test_export_json_lines() {
+        let view = create_view();
+
+        assert_eq!(
+            export_to_string(&view, "/a, \"dir\"", ExportFormat::JsonLines),
+            "{\"path\":\"/a, \\\"dir\\\"/link\",\"type\":\"Symlink\",\"size\":6,\"mode\":\"777\",\"uid\":1000,\
+             \"gid\":100,\"mtime\":1700000000,\"digest\":\"000102030405060708090a0b0c0d0e0f\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_export_cancelled() {
+        let mut view = create_view();
+        let token = CancellationToken::new();
+        view.set_cancellation_token(token.clone());
+        token.cancel();
+
+        let mut out = Vec::new();
+        let err = export(&view, &mut out, "pc-1", 1, "/home", "", ExportFormat::Csv).unwrap_err();
+        assert!(err.is::<Cancelled>());
+    }
+}
@@ -1,8 +1,9 @@
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use lru::LruCache;
 use std::hash::Hasher;
 use std::io::Read;
 use std::num::NonZeroUsize;
+use std::str::FromStr;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use twox_hash::XxHash64;
 
@@ -10,25 +11,30 @@ extern crate fuser;
 extern crate libc;
 
 use fuser::{
-    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry,
-    ReplyOpen, Request,
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory, ReplyEmpty,
+    ReplyEntry, ReplyOpen, ReplyWrite, Request, TimeOrNow,
 };
-use libc::ENOENT;
-use std::{collections::HashMap, ffi::OsStr};
+use libc::{
+    EACCES, EINTR, EINVAL, EIO, ENOENT, EROFS, O_ACCMODE, O_RDONLY, O_TRUNC, R_OK, W_OK, X_OK,
+};
+use std::{collections::HashMap, ffi::OsStr, path::Path};
 
-use crate::attribute_file::Search;
+use crate::cache::{CacheMetrics, MeteredCache};
+use crate::cancel::Cancelled;
+use crate::config::{BackupPCConfig, PoolConfig};
 use crate::decode_attribut::{FileAttributes, FileType as BackupPCFileType};
-use crate::hosts::Hosts;
+use crate::reader::{read_all_to_vec, skip};
 use crate::util::Result;
-use crate::view::BackupPC;
+use crate::view::{BackupOrder, BackupPC};
 
 const TTL_HOST: Duration = Duration::from_secs(86_400);
 const TTL_BACKUPS: Duration = Duration::from_secs(3_600);
 const TTL_REST: Duration = Duration::from_secs(1_000_000);
 
-const CACHE_SIZE: usize = 2048;
+const MAX_OPENED_FILES: usize = 256;
 
-const CREATE_TIME: SystemTime = UNIX_EPOCH;
+/// Name of the synthetic directory giving access to the files of the pool by their digest.
+const POOL_DIR: &str = ".pool";
 
 #[derive(PartialEq, Default, Debug)]
 struct CacheElement {
@@ -50,16 +56,32 @@ pub struct BackupPCFileAttribute {
 
 impl BackupPCFileAttribute {
     pub fn from_file_attribute(file: FileAttributes, child_ino: u64) -> Self {
+        Self::from_file_attribute_with_options(file, child_ino, &FilesystemOptions::default())
+    }
+
+    /// Creates the attributes of a file, the timestamps not stored by `BackupPC` (atime, ctime) being computed as
+    /// defined in the options, and the owner being replaced by the one of the options if any.
+    pub fn from_file_attribute_with_options(
+        file: FileAttributes,
+        child_ino: u64,
+        options: &FilesystemOptions,
+    ) -> Self {
+        // A deleted file (tombstone) is shown as an empty file without permissions
+        let (size, mode) = match file.type_ {
+            BackupPCFileType::Deleted => (0, 0),
+            _ => (file.size, file.mode),
+        };
+
         BackupPCFileAttribute {
             name: file.name,
             attr: FileAttr {
                 ino: child_ino,
-                size: file.size,
-                blocks: file.size / 512,
+                size,
+                blocks: size / 512,
                 blksize: 512,
-                atime: UNIX_EPOCH + Duration::from_secs(file.mtime),
+                atime: options.atime.timestamp(file.mtime),
                 mtime: UNIX_EPOCH + Duration::from_secs(file.mtime),
-                ctime: UNIX_EPOCH + Duration::from_secs(file.mtime),
+                ctime: options.ctime.timestamp(file.mtime),
                 crtime: UNIX_EPOCH + Duration::from_secs(file.mtime),
                 kind: match file.type_ {
                     BackupPCFileType::Symlink => FileType::Symlink,
@@ -70,10 +92,10 @@ impl BackupPCFileAttribute {
                     BackupPCFileType::Socket => FileType::Socket,
                     _ => FileType::RegularFile,
                 },
-                perm: file.mode,
+                perm: mode,
                 nlink: file.nlinks,
-                uid: file.uid,
-                gid: file.gid,
+                uid: options.uid.unwrap_or(file.uid),
+                gid: options.gid.unwrap_or(file.gid),
                 rdev: 0,
                 flags: 0,
             },
@@ -86,10 +108,10 @@ const ROOT_ELEMENT_ATTR: FileAttr = FileAttr {
     size: 0,
     blocks: 0,
     blksize: 0,
-    atime: CREATE_TIME,
-    mtime: CREATE_TIME,
-    ctime: CREATE_TIME,
-    crtime: CREATE_TIME,
+    atime: UNIX_EPOCH,
+    mtime: UNIX_EPOCH,
+    ctime: UNIX_EPOCH,
+    crtime: UNIX_EPOCH,
     kind: FileType::Directory,
     perm: 0o755,
     nlink: 1,
@@ -99,31 +121,308 @@ const ROOT_ELEMENT_ATTR: FileAttr = FileAttr {
     flags: 0,
 };
 
+/// Returns the errno to reply for an error of the view.
+///
+/// A path that doesn't exist (an `io::Error` with the kind `NotFound`) is reported as `ENOENT`, an operation that
+/// doesn't apply to the file (kind `InvalidInput`, ex: reading the target of a file that isn't a symbolic link) as
+/// `EINVAL`, a traversal cancelled by the unmount (`Cancelled`) as `EINTR`, the other errors as `EIO`.
+fn errno_of(err: &(dyn std::error::Error + 'static)) -> i32 {
+    if err.is::<Cancelled>() {
+        return EINTR;
+    }
+    match err.downcast_ref::<std::io::Error>() {
+        Some(io_err) if io_err.kind() == std::io::ErrorKind::NotFound => ENOENT,
+        Some(io_err) if io_err.kind() == std::io::ErrorKind::InvalidInput => EINVAL,
+        _ => EIO,
+    }
+}
+
+/// Checks if a user can access a file with the given mask (see `access(2)`).
+///
+/// The mount is read-only: a write access (`W_OK`) is always refused with `EROFS`. Read and execute accesses are
+/// checked against the permissions of the owner, the group, or the others, as the kernel would do. The root user can
+/// read everything, and can execute a file if at least one execute bit is set.
+///
+/// # Arguments
+///
+/// * `attr` - The attributes of the file.
+/// * `uid` - The user id of the process accessing the file.
+/// * `gid` - The group id of the process accessing the file.
+/// * `mask` - The access mask (`F_OK`, or a combination of `R_OK`, `W_OK`, and `X_OK`).
+///
+/// # Errors
+///
+/// The errno to return (`EROFS` or `EACCES`) if the access is refused.
+fn check_access(attr: &FileAttr, uid: u32, gid: u32, mask: i32) -> std::result::Result<(), i32> {
+    if mask & W_OK != 0 {
+        return Err(EROFS);
+    }
+
+    let perm = i32::from(attr.perm);
+    let granted = if uid == 0 {
+        let any_execute = if perm & 0o111 != 0 { X_OK } else { 0 };
+        R_OK | any_execute
+    } else if uid == attr.uid {
+        (perm >> 6) & 0o7
+    } else if gid == attr.gid {
+        (perm >> 3) & 0o7
+    } else {
+        perm & 0o7
+    };
+
+    let requested = mask & (R_OK | X_OK);
+    if granted & requested == requested {
+        Ok(())
+    } else {
+        Err(EACCES)
+    }
+}
+
+/// Checks the flags of an open: the file system is read-only, so a file can't be opened for writing or truncated.
+///
+/// # Errors
+///
+/// `EROFS` if the flags would modify the file.
+fn check_open_flags(flags: i32) -> std::result::Result<(), i32> {
+    if flags & O_ACCMODE != O_RDONLY || flags & O_TRUNC != 0 {
+        return Err(EROFS);
+    }
+
+    Ok(())
+}
+
+/// Normalizes the target of a symbolic link read from the pool.
+///
+/// The trailing `\0` and `\n` bytes of the target (stray bytes, padding up to the size of the file) are removed.
+///
+/// # Errors
+///
+/// If the normalized target is empty or still contains a `\0` byte, as it can't be a valid path.
+fn normalize_link_target(target: Vec<u8>) -> Result<Vec<u8>> {
+    let len = target
+        .iter()
+        .rposition(|byte| !matches!(byte, b'\0' | b'\n'))
+        .map_or(0, |position| position + 1);
+
+    if len != target.len() {
+        warn!(
+            "Symbolic link target {:?} normalized to {:?}",
+            String::from_utf8_lossy(&target),
+            String::from_utf8_lossy(&target[..len])
+        );
+    }
+
+    let mut target = target;
+    target.truncate(len);
+
+    if target.is_empty() || target.contains(&b'\0') {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "Invalid symbolic link target: {:?}",
+                String::from_utf8_lossy(&target)
+            ),
+        )
+        .into());
+    }
+
+    Ok(target)
+}
+
+/// The reader of an opened file handle, and its position.
+///
+/// The reader is stateful: a read at an offset lower than the position reopens the file, and a read at a greater
+/// offset skips the content up to the offset. `fuser` dispatches the requests with an exclusive reference to the file
+/// system, so the reads of a file handle are serialized and can't interleave into the same reader. The reader is
+/// `Send`, so the file system can be moved to another thread; the concurrent reads are served by the view (see
+/// `SharedBackupPC`).
 pub struct OpenedFile {
     pub offset: i64,
-    pub reader: Box<dyn Read>,
+    pub reader: Box<dyn Read + Send>,
+}
+
+/// Source of a timestamp that `BackupPC` doesn't store (access time, change time).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimestampSource {
+    /// Use the modification time of the file.
+    #[default]
+    Mtime,
+    /// Use the Unix epoch, so the timestamp is stable and can't be mistaken for a real one.
+    Epoch,
+}
+
+impl TimestampSource {
+    /// Computes the timestamp from the modification time of the file (in seconds since the Unix epoch).
+    #[must_use]
+    pub fn timestamp(self, mtime: u64) -> SystemTime {
+        match self {
+            TimestampSource::Mtime => UNIX_EPOCH + Duration::from_secs(mtime),
+            TimestampSource::Epoch => UNIX_EPOCH,
+        }
+    }
+}
+
+impl FromStr for TimestampSource {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "mtime" => Ok(TimestampSource::Mtime),
+            "epoch" | "zero" => Ok(TimestampSource::Epoch),
+            _ => Err(format!(
+                "Invalid timestamp source {value} (expected mtime or epoch)"
+            )),
+        }
+    }
+}
+
+/// Options of the file system, defined at mount time.
+#[derive(Clone, Debug)]
+pub struct FilesystemOptions {
+    /// Maximum number of readers kept open at the same time. When the limit is reached, the least recently used
+    /// reader is closed, and recreated if the file handle is read again.
+    pub max_opened_files: usize,
+    /// Source of the access time of the files.
+    pub atime: TimestampSource,
+    /// Source of the change time of the files.
+    pub ctime: TimestampSource,
+    /// Show the files deleted in an incremental backup as empty files without permissions.
+    pub show_deleted: bool,
+    /// Hide the partial backups.
+    pub hide_partial: bool,
+    /// Add a `.pool` directory at the root of the file system, where each file of the pool can be opened by its
+    /// digest (`.pool/<digest>`).
+    pub pool_view: bool,
+    /// Order of the backups listed in the directory of a host.
+    pub backup_order: BackupOrder,
+    /// Owner reported for all the files of the backups instead of the uid stored in the backup (useful when the
+    /// users of the backed up host don't exist locally).
+    pub uid: Option<u32>,
+    /// Group reported for all the files of the backups instead of the gid stored in the backup.
+    pub gid: Option<u32>,
+    /// List the directories past a corrupt attrib file (see `BackupPC::set_lenient`), so a corrupt directory is shown
+    /// as empty instead of failing.
+    pub lenient: bool,
+    /// Scan the attrib files of the inodes of the hard links instead of caching them (see
+    /// `BackupPC::set_scan_inodes`), for the backups with very large inode attrib files.
+    pub scan_inodes: bool,
+    /// Transcode the names that aren't valid UTF-8 from the charset of their backup (see
+    /// `BackupPC::set_transcode_names`).
+    #[cfg(feature = "encoding")]
+    pub transcode_names: bool,
+    /// Cache capacities and read chunk size of the view and the file system.
+    pub config: BackupPCConfig,
+}
+
+impl Default for FilesystemOptions {
+    fn default() -> Self {
+        FilesystemOptions {
+            max_opened_files: MAX_OPENED_FILES,
+            atime: TimestampSource::default(),
+            ctime: TimestampSource::default(),
+            show_deleted: false,
+            hide_partial: false,
+            pool_view: false,
+            backup_order: BackupOrder::default(),
+            uid: None,
+            gid: None,
+            lenient: false,
+            scan_inodes: false,
+            #[cfg(feature = "encoding")]
+            transcode_names: false,
+            config: BackupPCConfig::default(),
+        }
+    }
 }
 
 pub struct BackupPCFS {
     view: BackupPC,
     inodes: HashMap<u64, CacheElement>,
-    cache: LruCache<u64, Vec<BackupPCFileAttribute>>,
-    opened: HashMap<u64, OpenedFile>,
+    cache: MeteredCache<u64, Vec<BackupPCFileAttribute>>,
+    opened: LruCache<u64, OpenedFile>,
+    pool_files: MeteredCache<u64, BackupPCFileAttribute>,
+    options: FilesystemOptions,
+    /// Time of the mount, given to the directories that aren't in a backup (the root and the `.pool` directory).
+    mount_time: SystemTime,
 }
 
 impl BackupPCFS {
     pub fn new(topdir: &str) -> Self {
-        let hosts = Box::new(Hosts::new(topdir));
-        let search = Box::new(Search::new(topdir));
+        Self::new_with_options(topdir, FilesystemOptions::default())
+    }
+
+    /// Creates a new file system reading the pool of `topdir` with the given options.
+    ///
+    /// The pool is read from the local filesystem, without retry nor additional pool roots (see `with_pool_config`).
+    ///
+    /// # Panics
+    ///
+    /// If the maximum number of opened files or a cache capacity is zero.
+    pub fn new_with_options(topdir: &str, options: FilesystemOptions) -> Self {
+        Self::with_pool_config(&PoolConfig::new(topdir), options)
+    }
+
+    /// Creates a new file system reading the pool described by the configuration (see `BackupPC::with_pool_config`).
+    ///
+    /// # Panics
+    ///
+    /// If the maximum number of opened files or one of the cache capacities is zero.
+    #[must_use]
+    pub fn with_pool_config(pool: &PoolConfig, options: FilesystemOptions) -> Self {
+        let view = BackupPC::with_pool_config(pool, &options.config);
+
+        Self::new_with_view(view, options)
+    }
+
+    /// Creates a new file system on top of an existing view.
+    ///
+    /// The time of the mount is taken from the clock of the view (see `BackupPC::set_clock`).
+    ///
+    /// # Panics
+    ///
+    /// If the maximum number of opened files or the cache capacity of the file system is zero.
+    pub fn new_with_view(mut view: BackupPC, options: FilesystemOptions) -> Self {
+        view.set_show_deleted(options.show_deleted);
+        view.set_hide_partial(options.hide_partial);
+        view.set_backup_order(options.backup_order);
+        view.set_lenient(options.lenient);
+        view.set_scan_inodes(options.scan_inodes);
+        #[cfg(feature = "encoding")]
+        view.set_transcode_names(options.transcode_names);
+        let mount_time = view.clock().now();
 
         BackupPCFS {
             inodes: HashMap::new(),
-            view: BackupPC::new(topdir, hosts, search),
-            cache: LruCache::new(NonZeroUsize::new(CACHE_SIZE).unwrap()),
-            opened: HashMap::new(),
+            view,
+            cache: MeteredCache::new(NonZeroUsize::new(options.config.fs_cache_size).unwrap()),
+            opened: LruCache::new(NonZeroUsize::new(options.max_opened_files).unwrap()),
+            pool_files: MeteredCache::new(
+                NonZeroUsize::new(options.config.pool_files_cache_size).unwrap(),
+            ),
+            options,
+            mount_time,
         }
     }
 
+    /// Returns the attributes of the root directory, dated at the mount.
+    fn root_attr(&self) -> FileAttr {
+        FileAttr {
+            atime: self.mount_time,
+            mtime: self.mount_time,
+            ctime: self.mount_time,
+            crtime: self.mount_time,
+            ..ROOT_ELEMENT_ATTR
+        }
+    }
+
+    /// Returns the counters of the accesses to the cache of the listings of the file system, to tune its capacity
+    /// (see `BackupPCConfig::fs_cache_size`). The counters of the cache of the view are returned by
+    /// `BackupPC::cache_metrics`.
+    #[must_use]
+    pub fn cache_metrics(&self) -> CacheMetrics {
+        self.cache.metrics()
+    }
+
     fn generate_new_ino(&self, elt: &CacheElement) -> u64 {
         let mut hasher = XxHash64::with_seed(0);
         let key = elt.path.join("/");
@@ -148,22 +447,29 @@ impl BackupPCFS {
         // Random file handle not used in opened files
         loop {
             let handle = rand::random::<u64>();
-            if !self.opened.contains_key(&handle) {
+            if !self.opened.contains(&handle) {
                 return handle;
             }
         }
     }
 
     fn list_files(&mut self, ino: u64, path: Vec<&str>) -> Result<Vec<BackupPCFileAttribute>> {
+        // The pool can't be listed: only the files already looked up by their digest are listed
+        if self.options.pool_view && path == [POOL_DIR] {
+            let mut files = self.pool_files.values().cloned().collect::<Vec<_>>();
+            files.sort_by(|a, b| a.name.cmp(&b.name));
+            return Ok(files);
+        }
+
         let files = self.view.list(&path)?;
+        let options = self.options.clone();
+        let is_root = path.is_empty();
 
-        let result = files
+        let mut result: Vec<BackupPCFileAttribute> = files
             .into_iter()
-            .filter_map(move |file| {
-                if file.type_ == BackupPCFileType::Unknown
-                    || file.type_ == BackupPCFileType::Deleted
-                {
-                    error!("Unknown or deleted file: {:?}", file);
+            .filter_map(|file| {
+                if file.type_ == BackupPCFileType::Unknown {
+                    debug!("Unknown file: {:?}", file);
                     return None;
                 }
 
@@ -179,33 +485,100 @@ impl BackupPCFS {
 
                 self.inodes.insert(child_ino, key);
 
-                Some(BackupPCFileAttribute::from_file_attribute(file, child_ino))
+                Some(BackupPCFileAttribute::from_file_attribute_with_options(
+                    file, child_ino, &options,
+                ))
             })
             .collect();
 
+        if self.options.pool_view && is_root {
+            let key = CacheElement {
+                path: vec![POOL_DIR.to_string()],
+                parent_ino: ino,
+            };
+            let pool_ino = self.generate_new_ino(&key);
+            self.inodes.insert(pool_ino, key);
+
+            result.push(BackupPCFileAttribute {
+                name: POOL_DIR.to_string(),
+                attr: FileAttr {
+                    ino: pool_ino,
+                    perm: 0o555,
+                    ..self.root_attr()
+                },
+            });
+            result.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+
         Ok(result)
     }
 
+    /// Looks up a file of the `.pool` directory: the name is the digest of the file in the pool.
+    ///
+    /// The inode of the file is created on demand. The size of the file is read from the attributes of a file with
+    /// this content, or from the pool (see `BackupPC::pool_file_size`), and kept in a bounded cache (see
+    /// `BackupPCConfig::pool_files_cache_size`).
+    fn lookup_pool_file(&mut self, parent_ino: u64, name: &OsStr) -> Option<(Duration, FileAttr)> {
+        let digest = name.to_str()?;
+        let key = CacheElement {
+            path: vec![POOL_DIR.to_string(), digest.to_string()],
+            parent_ino,
+        };
+        let child_ino = self.generate_new_ino(&key);
+        if let Some(file) = self.pool_files.get(&child_ino) {
+            return Some((TTL_REST, file.attr));
+        }
+
+        let size = match self.view.pool_file_size(digest) {
+            Ok(size) => size,
+            Err(err) => {
+                debug!("Pool file {digest} not found: {err}");
+                return None;
+            }
+        };
+
+        let file = BackupPCFileAttribute {
+            name: digest.to_string(),
+            attr: FileAttr {
+                ino: child_ino,
+                size,
+                blocks: size / 512,
+                blksize: 512,
+                kind: FileType::RegularFile,
+                perm: 0o444,
+                uid: 0,
+                gid: 0,
+                ..self.root_attr()
+            },
+        };
+        self.inodes.insert(child_ino, key);
+        self.pool_files.put(child_ino, file.clone());
+        // The listing of the pool directory now contains the file
+        self.cache.pop(&parent_ino);
+
+        Some((TTL_REST, file.attr))
+    }
+
     fn list_attributes(&mut self, ino: u64) -> Result<Vec<BackupPCFileAttribute>> {
         let binding = ROOT_ELEMENT;
         let cache_element = match ino {
             0 => {
                 return Ok(vec![BackupPCFileAttribute {
                     name: "..".to_string(),
-                    attr: ROOT_ELEMENT_ATTR,
+                    attr: self.root_attr(),
                 }])
             }
             1 => Some(&binding),
             _ => self.inodes.get(&ino),
         }
-        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "No value"))?;
+        .ok_or_else(|| std::io::Error::other("No value"))?;
 
         let path = cache_element.path.clone();
 
         match self.list_files(ino, path.iter().map(std::string::String::as_str).collect()) {
             Ok(files) => Ok(files),
             Err(err) => {
-                eprintln!("Error listing files of {}: {}", path.join("/"), err);
+                error!("Error listing files of {}: {}", path.join("/"), err);
                 Err(err)
             }
         }
@@ -216,8 +589,8 @@ impl BackupPCFS {
             return Ok(cached_result.clone());
         }
 
-        let mut result = self.list_attributes(ino)?;
-        result.sort_by(|a, b| a.name.cmp(&b.name));
+        // The files are listed in the order of the view (by name, except the backups of a host, see `BackupOrder`)
+        let result = self.list_attributes(ino)?;
         self.cache.put(ino, result.clone());
 
         Ok(result)
@@ -230,6 +603,10 @@ impl BackupPCFS {
             _ => self.inodes.get(&ino),
         }?;
 
+        if self.options.pool_view && cache_element.path == [POOL_DIR] {
+            return self.lookup_pool_file(ino, name);
+        }
+
         let duration = match cache_element.path.len() {
             0 => TTL_HOST,
             1 => TTL_BACKUPS,
@@ -301,6 +678,15 @@ impl BackupPCFS {
 
         let parent_ino = cache_element.parent_ino;
 
+        // A file of the pool evicted from the cache of the pool files is looked up again by its digest
+        if self.options.pool_view
+            && cache_element.path.len() == 2
+            && cache_element.path[0] == POOL_DIR
+        {
+            let digest = cache_element.path[1].clone();
+            return self.lookup_pool_file(parent_ino, OsStr::new(&digest));
+        }
+
         let attributes = self.list_attributes_with_cache(parent_ino);
         let attribute = match attributes {
             Ok(attrs) => attrs.into_iter().find(|attr| attr.attr.ino == ino),
@@ -310,60 +696,74 @@ impl BackupPCFS {
         attribute.map(|attr| (duration, attr.attr))
     }
 
-    fn create_reader(&mut self, ino: u64) -> Result<Box<dyn Read>> {
+    fn create_reader(&mut self, ino: u64) -> Result<Box<dyn Read + Send>> {
         let binding = ROOT_ELEMENT;
         let cache_element = match ino {
             1 => Some(&binding),
             _ => self.inodes.get(&ino),
         }
-        .ok_or(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Failed to get filename",
-        ))?;
+        .ok_or(std::io::Error::other("Failed to get filename"))?;
 
         let path = cache_element.path.clone();
         let path_refs: Vec<&str> = path.iter().map(std::string::String::as_str).collect();
 
+        if let (true, [POOL_DIR, digest]) = (self.options.pool_view, path_refs.as_slice()) {
+            return Ok(self.view.read_by_digest(digest)?);
+        }
+
         match self.view.read_file(&path_refs) {
             Ok(reader) => Ok(reader),
             Err(err) => {
-                eprintln!("Can't open the file {}: {}", path.join("/"), err);
+                error!("Can't open the file {}: {}", path.join("/"), err);
                 Err(err)
             }
         }
     }
 
     fn read_link(&mut self, ino: u64) -> Result<Vec<u8>> {
-        let mut reader = self.create_reader(ino)?;
-        let mut buf = Vec::<u8>::new();
-        reader.read_to_end(&mut buf)?;
+        // Only a symbolic link has a target: the content of a regular file (or of a file whose type doesn't match
+        // its content) must not be returned as a target
+        let (_, attr) = self
+            .get_attr(ino)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "File not found"))?;
+        if attr.kind != FileType::Symlink {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("ino {ino} is not a symbolic link"),
+            )
+            .into());
+        }
+
+        let target = read_all_to_vec(self.create_reader(ino)?, attr.size)?;
 
-        Ok(buf)
+        normalize_link_target(target)
     }
 
     fn open(&mut self, ino: u64) -> Result<u64> {
         let reader = self.create_reader(ino)?;
         let fh = self.generate_file_handle();
-        self.opened.insert(
-            fh,
-            OpenedFile {
-                offset: 0,
-                reader: Box::new(reader),
-            },
-        );
+        self.opened.put(fh, OpenedFile { offset: 0, reader });
 
         Ok(fh)
     }
 
     fn release(&mut self, fh: u64) {
-        self.opened.remove(&fh);
+        self.opened.pop(&fh);
     }
 
     fn read_ino(&mut self, ino: u64, fh: u64, offset: i64, size: u32) -> Result<Vec<u8>> {
+        // If the reader of the file handle has been evicted, recreate it from the start of the file
+        if !self.opened.contains(&fh) {
+            info!("Reader of file handle {fh} was closed, reopen ino {ino}");
+            let reader = self.create_reader(ino)?;
+            self.opened.put(fh, OpenedFile { offset: 0, reader });
+        }
+
+        let read_chunk_size = self.options.config.read_chunk_size;
         let opened_file = self
             .opened
             .get(&fh)
-            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "File not opened"))?;
+            .ok_or_else(|| std::io::Error::other("File not opened"))?;
 
         // If the offset is lesser than the current offset, we need to reset the reader
         if offset < opened_file.offset {
@@ -376,39 +776,45 @@ impl BackupPCFS {
 
         let opened_file = self.opened.get_mut(&fh).unwrap();
 
-        // If the offset is greater that the current offset, we need to fast forward (by reading data by 32k chunk )
+        // If the offset is greater that the current offset, we need to fast forward (by reading data by chunk)
         if offset > opened_file.offset {
-            let mut buffer = vec![0; 32 * 1024];
-            let mut remaining = offset - opened_file.offset;
-
-            while remaining > 0 {
-                let to_read = std::cmp::min(remaining, buffer.len() as i64);
-                let to_read = usize::try_from(to_read)?;
-                let size: usize = opened_file.reader.read(&mut buffer[..to_read])?;
-                remaining -= size as i64;
-                if size == 0 {
-                    info!("End of file reached");
-                    break;
-                }
+            let count = u64::try_from(offset - opened_file.offset)?;
+            if skip(opened_file.reader.as_mut(), count, read_chunk_size)? < count {
+                info!("End of file reached");
             }
             opened_file.offset = offset;
         }
 
-        // Read the data
+        // Read the data until the buffer is full or the end of file is reached: FUSE consider a short read as the end
+        // of the file.
         let reader = opened_file.reader.as_mut();
         let mut buffer = vec![0; size as usize];
 
-        let size = reader.read(&mut buffer)?;
-        opened_file.offset += size as i64;
+        let mut total = 0;
+        while total < buffer.len() {
+            let count = reader.read(&mut buffer[total..])?;
+            if count == 0 {
+                break;
+            }
+            total += count;
+        }
+        opened_file.offset += total as i64;
 
         // Reduce the size of the buffer to the actual size read
-        buffer.truncate(size);
+        buffer.truncate(total);
 
         Ok(buffer)
     }
 }
 
 impl Filesystem for BackupPCFS {
+    fn destroy(&mut self) {
+        // Stop the traversals of the view still in progress (the file system is unmounted)
+        self.view.cancellation_token().cancel();
+        info!("Cache of the file system: {}", self.cache_metrics());
+        info!("Cache of the view: {}", self.view.cache_metrics());
+    }
+
     fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
         let attr = self.get_file_attr(parent, name);
         debug!("Lookup parent: {parent}, name: {name:?}, attr: {attr:?}");
@@ -429,6 +835,19 @@ impl Filesystem for BackupPCFS {
         }
     }
 
+    fn access(&mut self, req: &Request<'_>, ino: u64, mask: i32, reply: ReplyEmpty) {
+        let attr = self.get_attr(ino);
+        debug!("Access ino: {ino}, mask: {mask}, attr: {attr:?}");
+
+        match attr {
+            Some((_, attr)) => match check_access(&attr, req.uid(), req.gid(), mask) {
+                Ok(()) => reply.ok(),
+                Err(errno) => reply.error(errno),
+            },
+            None => reply.error(ENOENT),
+        }
+    }
+
     fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
         let link_to = self.read_link(ino);
         debug!("Readlink ino: {ino}, attr: {link_to:?}");
@@ -436,18 +855,23 @@ impl Filesystem for BackupPCFS {
         match link_to {
             Ok(data) => reply.data(&data),
             Err(err) => {
-                eprintln!("Error reading link ino {ino}: {err}");
-                reply.error(ENOENT);
+                error!("Error reading link ino {ino}: {err}");
+                reply.error(errno_of(err.as_ref()));
             }
         }
     }
 
-    fn open(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        if let Err(errno) = check_open_flags(flags) {
+            reply.error(errno);
+            return;
+        }
+
         match self.open(ino) {
             Ok(fh) => reply.opened(fh, 0),
             Err(err) => {
-                eprintln!("Error opening ino {ino}: {err}");
-                reply.error(ENOENT);
+                error!("Error opening ino {ino}: {err}");
+                reply.error(errno_of(err.as_ref()));
             }
         }
     }
@@ -466,8 +890,8 @@ impl Filesystem for BackupPCFS {
         match self.read_ino(ino, fh, offset, size) {
             Ok(data) => reply.data(&data),
             Err(err) => {
-                eprintln!("Error reading ino {ino}: {err}");
-                reply.error(ENOENT);
+                error!("Error reading ino {ino}: {err}");
+                reply.error(errno_of(err.as_ref()));
             }
         }
     }
@@ -501,9 +925,528 @@ impl Filesystem for BackupPCFS {
                 reply.ok();
             }
             Err(e) => {
-                eprintln!("Error reading dir {ino}: {e}");
-                reply.error(ENOENT);
+                error!("Error reading dir {ino}: {e}");
+                reply.error(errno_of(e.as_ref()));
             }
         }
     }
+
+    // The file system is read-only: all the operations modifying it fail with EROFS (instead of ENOSYS, which the
+    // applications report as an unsupported operation).
+
+    fn setattr(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        _size: Option<u64>,
+        _atime: Option<TimeOrNow>,
+        _mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        reply.error(EROFS);
+    }
+
+    fn mknod(
+        &mut self,
+        _req: &Request<'_>,
+        _parent: u64,
+        _name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _rdev: u32,
+        reply: ReplyEntry,
+    ) {
+        reply.error(EROFS);
+    }
+
+    fn mkdir(
+        &mut self,
+        _req: &Request<'_>,
+        _parent: u64,
+        _name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        reply.error(EROFS);
+    }
+
+    fn unlink(&mut self, _req: &Request<'_>, _parent: u64, _name: &OsStr, reply: ReplyEmpty) {
+        reply.error(EROFS);
+    }
+
+    fn rmdir(&mut self, _req: &Request<'_>, _parent: u64, _name: &OsStr, reply: ReplyEmpty) {
+        reply.error(EROFS);
+    }
+
+    fn symlink(
+        &mut self,
+        _req: &Request<'_>,
+        _parent: u64,
+        _link_name: &OsStr,
+        _target: &Path,
+        reply: ReplyEntry,
+    ) {
+        reply.error(EROFS);
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request<'_>,
+        _parent: u64,
+        _name: &OsStr,
+        _newparent: u64,
+        _newname: &OsStr,
+        _flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        reply.error(EROFS);
+    }
+
+    fn link(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _newparent: u64,
+        _newname: &OsStr,
+        reply: ReplyEntry,
+    ) {
+        reply.error(EROFS);
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _offset: i64,
+        _data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        reply.error(EROFS);
+    }
+
+    fn setxattr(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _name: &OsStr,
+        _value: &[u8],
+        _flags: i32,
+        _position: u32,
+        reply: ReplyEmpty,
+    ) {
+        reply.error(EROFS);
+    }
+
+    fn removexattr(&mut self, _req: &Request<'_>, _ino: u64, _name: &OsStr, reply: ReplyEmpty) {
+        reply.error(EROFS);
+    }
+
+    fn create(
+        &mut self,
+        _req: &Request<'_>,
+        _parent: u64,
+        _name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        reply.error(EROFS);
+    }
+
+    fn fallocate(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _offset: i64,
+        _length: i64,
+        _mode: i32,
+        reply: ReplyEmpty,
+    ) {
+        reply.error(EROFS);
+    }
+
+    fn copy_file_range(
+        &mut self,
+        _req: &Request<'_>,
+        _ino_in: u64,
+        _fh_in: u64,
+        _offset_in: i64,
+        _ino_out: u64,
+        _fh_out: u64,
+        _offset_out: i64,
+        _len: u64,
+        _flags: u32,
+        reply: ReplyWrite,
+    ) {
+        reply.error(EROFS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attribute_file::MockSearchTrait;
+    use crate::clock::ManualClock;
+    use crate::decode_attribut::BpcDigest;
+    use crate::hosts::{BackupInformation, MockHostsTrait};
+    use crate::util::vec_to_hex_string;
+    use mockall::predicate::*;
+    use std::sync::Arc;
+
+    fn create_mock_backup(num: u32) -> BackupInformation {
+        BackupInformation {
+            num,
+            backup_type: "full".to_string(),
+            start_time: 0,
+            end_time: 0,
+            n_files: 0,
+            size: 0,
+            n_files_exist: 0,
+            size_exist: 0,
+            n_files_new: 0,
+            size_new: 0,
+            xfer_errs: 0,
+            xfer_bad_file: 0,
+            xfer_bad_share: 0,
+            tar_errs: 0,
+            compress: 0,
+            size_exist_comp: 0,
+            size_new_comp: 0,
+            no_fill: 0,
+            fill_from_num: -1,
+            mangle: 0,
+            xfer_method: "rsync".to_string(),
+            level: 0,
+            charset: "utf-8".to_string(),
+            version: "4.0.0".to_string(),
+            inode_last: 0,
+        }
+    }
+
+    fn create_file_attributes(name: &str, type_: BackupPCFileType) -> FileAttributes {
+        FileAttributes {
+            name: name.to_string(),
+            name_bytes: None,
+            raw_type: type_.clone() as u64,
+            type_,
+            compress: 0,
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+            nlinks: 0,
+            mtime: 1_700_000_000,
+            size: 0,
+            inode: 0,
+            bpc_digest: BpcDigest {
+                len: 0,
+                digest: Vec::new(),
+            },
+            xattr_num_entries: 0,
+            xattrs: Vec::new(),
+        }
+    }
+
+    // The view contains the host pc-1, with the backup 1, and the share /share containing the given files.
+    fn create_view(topdir: &Path, files: Vec<FileAttributes>) -> BackupPC {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+
+        hosts_mock
+            .expect_list_hosts()
+            .returning(|| Ok(vec!["pc-1".to_string()]));
+        hosts_mock
+            .expect_list_backups()
+            .with(eq("pc-1"))
+            .returning(|_| Ok(vec![create_mock_backup(1)]));
+        hosts_mock
+            .expect_list_backups_to_fill()
+            .with(eq("pc-1"), eq(1))
+            .returning(|_, _| Ok(vec![create_mock_backup(1)]));
+
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_none() && path.is_none())
+            .returning(|_, _, _, _| {
+                Ok(vec![create_file_attributes(
+                    "/share",
+                    BackupPCFileType::Dir,
+                )])
+            });
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| {
+                share.is_some_and(|share| share == "/share")
+                    && path.is_some_and(|path| path.is_empty())
+            })
+            .returning(move |_, _, _, _| Ok(files.clone()));
+
+        BackupPC::new(topdir.to_str().unwrap(), hosts_mock, search_mock)
+    }
+
+    // The file system contains the host pc-1, with the backup 1, the share /share and the files file0 to file9.
+    fn create_filesystem(options: FilesystemOptions) -> BackupPCFS {
+        let files = (0..10)
+            .map(|i| create_file_attributes(&format!("file{i}"), BackupPCFileType::File))
+            .collect();
+        let view = create_view(Path::new("/var/lib/backuppc"), files);
+
+        BackupPCFS::new_with_view(view, options)
+    }
+
+    fn lookup_path(fs: &mut BackupPCFS, path: &[&str]) -> u64 {
+        let mut ino = 1;
+        for name in path {
+            let (_, attr) = fs.get_file_attr(ino, OsStr::new(name)).unwrap();
+            ino = attr.ino;
+        }
+        ino
+    }
+
+    #[test]
+    fn test_open_more_files_than_max_opened_files() {
+        let mut fs = create_filesystem(FilesystemOptions {
+            max_opened_files: 2,
+            ..FilesystemOptions::default()
+        });
+
+        let files = (0..10)
+            .map(|i| {
+                let ino = lookup_path(&mut fs, &["pc-1", "1", "share", &format!("file{i}")]);
+                let fh = fs.open(ino).unwrap();
+                (ino, fh)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(fs.opened.len(), 2);
+
+        // Evicted file handles are reopened on read
+        for (ino, fh) in files {
+            let data = fs.read_ino(ino, fh, 0, 1024).unwrap();
+            assert!(data.is_empty());
+            assert!(fs.opened.len() <= 2);
+        }
+    }
+
+    #[test]
+    fn test_timestamps_of_files() {
+        let mut fs = create_filesystem(FilesystemOptions::default());
+        let share = lookup_path(&mut fs, &["pc-1", "1", "share"]);
+        let (_, attr) = fs.get_file_attr(share, OsStr::new("file0")).unwrap();
+        assert_eq!(attr.mtime, UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        assert_eq!(attr.atime, attr.mtime);
+        assert_eq!(attr.ctime, attr.mtime);
+
+        let mut fs = create_filesystem(FilesystemOptions {
+            atime: TimestampSource::Epoch,
+            ctime: "zero".parse().unwrap(),
+            ..FilesystemOptions::default()
+        });
+        let share = lookup_path(&mut fs, &["pc-1", "1", "share"]);
+        let (_, attr) = fs.get_file_attr(share, OsStr::new("file0")).unwrap();
+        assert_eq!(attr.atime, UNIX_EPOCH);
+        assert_eq!(attr.ctime, UNIX_EPOCH);
+        assert_eq!(attr.mtime, UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    }
+
+    #[test]
+    fn test_owner_of_files() {
+        let mut fs = create_filesystem(FilesystemOptions::default());
+        let share = lookup_path(&mut fs, &["pc-1", "1", "share"]);
+        let (_, attr) = fs.get_file_attr(share, OsStr::new("file0")).unwrap();
+        assert_eq!((attr.uid, attr.gid), (0, 0));
+
+        let mut fs = create_filesystem(FilesystemOptions {
+            uid: Some(1000),
+            gid: Some(100),
+            ..FilesystemOptions::default()
+        });
+        let share = lookup_path(&mut fs, &["pc-1", "1", "share"]);
+        let (_, attr) = fs.get_file_attr(share, OsStr::new("file0")).unwrap();
+        assert_eq!((attr.uid, attr.gid), (1000, 100));
+    }
+
+    #[test]
+    fn test_pool_view() {
+        let topdir = std::env::temp_dir().join(format!("bpc_pool_view_{}", std::process::id()));
+        let digest = "101112131415161718191a1b1c1d1e1f";
+        let pool_dir = topdir.join("pool").join("10").join("10");
+        std::fs::create_dir_all(&pool_dir).unwrap();
+        std::fs::write(pool_dir.join(digest), b"0123456789").unwrap();
+
+        let mut view = create_view(&topdir, Vec::new());
+        // The directories outside the backups are dated at the mount
+        view.set_clock(Arc::new(ManualClock::from_timestamp(1_800_000_000)));
+        let mut fs = BackupPCFS::new_with_view(
+            view,
+            FilesystemOptions {
+                pool_view: true,
+                ..FilesystemOptions::default()
+            },
+        );
+
+        let (_, attr) = fs.get_attr(1).unwrap();
+        assert_eq!(attr.mtime, UNIX_EPOCH + Duration::from_secs(1_800_000_000));
+        let pool = lookup_path(&mut fs, &[POOL_DIR]);
+        let (_, attr) = fs.get_attr(pool).unwrap();
+        assert_eq!(attr.mtime, UNIX_EPOCH + Duration::from_secs(1_800_000_000));
+        assert!(fs.list_attributes_with_cache(pool).unwrap().is_empty());
+        assert!(fs.get_file_attr(pool, OsStr::new("0000")).is_none());
+
+        let (_, attr) = fs.get_file_attr(pool, OsStr::new(digest)).unwrap();
+        assert_eq!(attr.size, 10);
+        assert_eq!(attr.kind, FileType::RegularFile);
+        let fh = fs.open(attr.ino).unwrap();
+        assert_eq!(fs.read_ino(attr.ino, fh, 2, 5).unwrap(), b"23456");
+
+        let files = fs.list_attributes_with_cache(pool).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, digest);
+
+        // A pool file evicted from the cache is found again by its digest
+        fs.pool_files.clear();
+        let (_, evicted) = fs.get_attr(attr.ino).unwrap();
+        assert_eq!((evicted.ino, evicted.size), (attr.ino, 10));
+
+        std::fs::remove_dir_all(&topdir).unwrap();
+    }
+
+    #[test]
+    fn test_read_fills_the_buffer_of_a_sparse_file() {
+        use flate2::write::ZlibEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let topdir = std::env::temp_dir().join(format!("bpc_sparse_read_{}", std::process::id()));
+        let digest: Vec<u8> = (0x20..0x30).collect();
+        let pool_dir = topdir.join("cpool").join("20").join("20");
+        std::fs::create_dir_all(&pool_dir).unwrap();
+        // The compressed content is larger than the small files, so it is decompressed by chunks at each read
+        let content = (0..300_000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&content).unwrap();
+        std::fs::write(
+            pool_dir.join(vec_to_hex_string(&digest)),
+            encoder.finish().unwrap(),
+        )
+        .unwrap();
+
+        // The file is 10000 bytes longer than its content in the pool (a sparse file)
+        let mut file = create_file_attributes("sparse", BackupPCFileType::File);
+        file.compress = 3;
+        file.size = 310_000;
+        file.bpc_digest.len = 16;
+        file.bpc_digest.digest.clone_from(&digest);
+
+        let view = create_view(&topdir, vec![file]);
+        let mut fs = BackupPCFS::new_with_view(view, FilesystemOptions::default());
+        let ino = lookup_path(&mut fs, &["pc-1", "1", "share", "sparse"]);
+        let fh = fs.open(ino).unwrap();
+
+        // A short read is the end of the file for FUSE: each read returns the whole buffer, up to the size of the file
+        let data = fs.read_ino(ino, fh, 0, 131_072).unwrap();
+        assert_eq!(data, content[..131_072]);
+        let data = fs.read_ino(ino, fh, 290_000, 131_072).unwrap();
+        assert_eq!(data.len(), 20_000);
+        assert_eq!(data[..10_000], content[290_000..]);
+        assert!(data[10_000..].iter().all(|&byte| byte == 0));
+
+        std::fs::remove_dir_all(&topdir).unwrap();
+    }
+
+    #[test]
+    fn test_errno_of() {
+        let not_found: Box<dyn std::error::Error> =
+            std::io::Error::from(std::io::ErrorKind::NotFound).into();
+        let other: Box<dyn std::error::Error> = std::io::Error::other("broken").into();
+
+        assert_eq!(errno_of(not_found.as_ref()), ENOENT);
+        assert_eq!(errno_of(other.as_ref()), EIO);
+        let invalid: Box<dyn std::error::Error> =
+            std::io::Error::from(std::io::ErrorKind::InvalidInput).into();
+        assert_eq!(errno_of(invalid.as_ref()), EINVAL);
+        let cancelled: Box<dyn std::error::Error> = Box::new(Cancelled);
+        assert_eq!(errno_of(cancelled.as_ref()), EINTR);
+        // A content that doesn't match its size (see `BackupPC::set_strict_size`) isn't a missing file
+        let corrupt: Box<dyn std::error::Error> =
+            std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into();
+        assert_eq!(errno_of(corrupt.as_ref()), EIO);
+    }
+
+    #[test]
+    fn test_read_link_of_regular_file() {
+        let mut fs = create_filesystem(FilesystemOptions::default());
+        let file = lookup_path(&mut fs, &["pc-1", "1", "share", "file0"]);
+
+        // The content of a regular file is never returned as the target of a link
+        let err = fs.read_link(file).unwrap_err();
+        assert_eq!(errno_of(err.as_ref()), EINVAL);
+
+        let share = lookup_path(&mut fs, &["pc-1", "1", "share"]);
+        let err = fs.read_link(share).unwrap_err();
+        assert_eq!(errno_of(err.as_ref()), EINVAL);
+    }
+
+    #[test]
+    fn test_check_access() {
+        let mut attr = BackupPCFileAttribute::from_file_attribute(
+            create_file_attributes("file.txt", BackupPCFileType::File),
+            2,
+        )
+        .attr;
+        attr.perm = 0o750;
+        attr.uid = 1000;
+        attr.gid = 100;
+
+        assert_eq!(check_access(&attr, 1000, 100, libc::F_OK), Ok(()));
+        assert_eq!(check_access(&attr, 1000, 100, R_OK | X_OK), Ok(()));
+        assert_eq!(check_access(&attr, 1000, 100, W_OK), Err(EROFS));
+        assert_eq!(check_access(&attr, 1001, 100, R_OK | X_OK), Ok(()));
+        assert_eq!(check_access(&attr, 1001, 101, R_OK), Err(EACCES));
+        assert_eq!(check_access(&attr, 0, 0, R_OK | X_OK), Ok(()));
+        assert_eq!(check_access(&attr, 0, 0, W_OK), Err(EROFS));
+
+        attr.perm = 0o640;
+        assert_eq!(check_access(&attr, 1001, 100, R_OK), Ok(()));
+        assert_eq!(check_access(&attr, 1001, 100, X_OK), Err(EACCES));
+        assert_eq!(check_access(&attr, 0, 0, X_OK), Err(EACCES));
+    }
+
+    #[test]
+    fn test_check_open_flags() {
+        assert_eq!(check_open_flags(libc::O_RDONLY), Ok(()));
+        assert_eq!(check_open_flags(libc::O_RDONLY | libc::O_NOFOLLOW), Ok(()));
+        assert_eq!(check_open_flags(libc::O_WRONLY), Err(EROFS));
+        assert_eq!(check_open_flags(libc::O_RDWR), Err(EROFS));
+        assert_eq!(check_open_flags(libc::O_RDONLY | O_TRUNC), Err(EROFS));
+    }
+
+    #[test]
+    fn test_normalize_link_target() {
+        assert_eq!(
+            normalize_link_target(b"../target\n\0\0".to_vec()).unwrap(),
+            b"../target"
+        );
+        assert_eq!(
+            normalize_link_target(b"/etc/passwd".to_vec()).unwrap(),
+            b"/etc/passwd"
+        );
+        assert!(normalize_link_target(b"\0\n".to_vec()).is_err());
+        assert!(normalize_link_target(b"bad\0target".to_vec()).is_err());
+    }
 }
@@ -1,7 +1,10 @@
-use log::{debug, info};
+use log::{debug, info, warn};
 #[cfg(test)]
 use mockall::{automock, predicate::*};
 
+use crate::compress::BackupPCReader;
+use crate::config::PoolConfig;
+use crate::source::{LocalSource, PoolSource};
 use crate::util::Result;
 
 /// This module is used to list all available hosts in the backuppc pool
@@ -11,10 +14,41 @@ use crate::util::Result;
 /// The list of host can be found by loading all folders in the topdir/pc directory.
 ///
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
+    collections::{BTreeMap, HashMap},
+    io::{BufRead, BufReader, Read},
+    path::Path,
+    sync::Arc,
 };
 
+/// Name of the fields of a line of the backups file (and of the keys of the backupInfo file).
+const BACKUP_FIELDS: [&str; 25] = [
+    "num",
+    "type",
+    "startTime",
+    "endTime",
+    "nFiles",
+    "size",
+    "nFilesExist",
+    "sizeExist",
+    "nFilesNew",
+    "sizeNew",
+    "xferErrs",
+    "xferBadFile",
+    "xferBadShare",
+    "tarErrs",
+    "compress",
+    "sizeExistComp",
+    "sizeNewComp",
+    "noFill",
+    "fillFromNum",
+    "mangle",
+    "xferMethod",
+    "level",
+    "charset",
+    "version",
+    "inodeLast",
+];
+
 ///
 /// Read all the backup numbers for the hosts.
 ///
@@ -76,6 +110,60 @@ pub struct BackupInformation {
     pub inode_last: u64,
 }
 
+impl BackupInformation {
+    /// Returns `true` if the backup is partial.
+    ///
+    /// `BackupPC` keeps the files of a backup that failed in a backup of type `partial`, so the next backup can resume
+    /// from it. A partial backup doesn't contain all the files of the host.
+    #[must_use]
+    pub fn is_partial(&self) -> bool {
+        self.backup_type == "partial"
+    }
+
+    /// Creates the information of a backup from its fields, indexed by their name in `BACKUP_FIELDS`.
+    pub(crate) fn from_values(values: &HashMap<String, String>) -> Self {
+        let text = |key: &str| values.get(key).cloned().unwrap_or_default();
+        let number = |key: &str| {
+            values
+                .get(key)
+                .and_then(|value| value.parse::<u64>().ok())
+                .unwrap_or_default()
+        };
+        let small = |key: &str| u32::try_from(number(key)).unwrap_or_default();
+
+        BackupInformation {
+            num: small("num"),
+            backup_type: text("type"),
+            start_time: number("startTime"),
+            end_time: number("endTime"),
+            n_files: small("nFiles"),
+            size: number("size"),
+            n_files_exist: small("nFilesExist"),
+            size_exist: number("sizeExist"),
+            n_files_new: small("nFilesNew"),
+            size_new: number("sizeNew"),
+            xfer_errs: small("xferErrs"),
+            xfer_bad_file: small("xferBadFile"),
+            xfer_bad_share: small("xferBadShare"),
+            tar_errs: small("tarErrs"),
+            compress: small("compress"),
+            size_exist_comp: number("sizeExistComp"),
+            size_new_comp: number("sizeNewComp"),
+            no_fill: small("noFill"),
+            fill_from_num: values
+                .get("fillFromNum")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(-1),
+            mangle: number("mangle"),
+            xfer_method: text("xferMethod"),
+            level: small("level"),
+            charset: text("charset"),
+            version: text("version"),
+            inode_last: number("inodeLast"),
+        }
+    }
+}
+
 #[cfg_attr(test, automock)]
 pub trait HostsTrait: Send + Sync {
     /// List all the hosts in the backuppc pool.
@@ -92,7 +180,8 @@ pub trait HostsTrait: Send + Sync {
     ///
     /// List all the backups for a given host (used the format separed by tab).
     ///
-    /// The backups are stored in the file topdir/pc/<hostname>/backups.
+    /// The backups are stored in the file topdir/pc/<hostname>/backups. An incomplete line is completed by the
+    /// backupInfo file of its backup (see `Hosts::read_backup_info`), and a line without a backup number is skipped.
     ///
     /// # Arguments
     ///
@@ -100,7 +189,7 @@ pub trait HostsTrait: Send + Sync {
     ///
     /// # Returns
     ///
-    /// A vector of `BackupInformation` containing the list of backups.
+    /// A vector of `BackupInformation` containing the list of backups, sorted by number.
     ///
     /// # Errors
     ///
@@ -109,7 +198,12 @@ pub trait HostsTrait: Send + Sync {
 
     /// List all the backups until the filled backup for a given backup.
     ///
-    /// Used to complete the missing backup
+    /// Used to complete the missing backup: `BackupPC` v4 stores the older backups as reverse deltas of the newer
+    /// ones. The chain starts at the backup itself and goes to the newer backups until (and including) the first
+    /// filled one. If the backup is filled, the chain only contains the backup.
+    ///
+    /// Each backup of the chain is followed by the backup referenced by its `fill_from_num`. When it is unset (`-1`),
+    /// or doesn't reference a newer backup, the backup is followed by the next backup.
     ///
     /// # Arguments
     ///
@@ -118,56 +212,418 @@ pub trait HostsTrait: Send + Sync {
     ///
     /// # Returns
     ///
-    /// A vector of `BackupInformation` containing the list of backups.
+    /// A vector of `BackupInformation` containing the list of backups, the newest (filled) backup first and the
+    /// requested backup last. The vector is empty if the backup doesn't exist.
     ///
     /// # Errors
     ///
-    /// If the file topdir/pc/<hostname>/backups cannot be read.
+    /// If the file topdir/pc/<hostname>/backups exists but cannot be read. A missing file means that the host has
+    /// no backup yet and an empty vector is returned.
     ///
-    fn list_backups_to_fill(&self, hostname: &str, backup_number: u32) -> Vec<BackupInformation>;
+    fn list_backups_to_fill(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+    ) -> Result<Vec<BackupInformation>>;
+}
+
+/// The backups of all the hosts (see `Hosts::list_all_backups_with_errors`).
+#[derive(Debug, Default)]
+pub struct AllBackups {
+    /// The backups of each host. A host without backups file has no backup yet.
+    pub backups: HashMap<String, Vec<BackupInformation>>,
+    /// The error of each host whose backups can't be read.
+    pub errors: HashMap<String, String>,
+}
+
+/// A backup of a host with the backup it is filled from (see `Hosts::backup_tree`).
+#[derive(Debug, Clone)]
+pub struct BackupNode {
+    /// The backup.
+    pub backup: BackupInformation,
+    /// The number of the backup this backup is filled from, or `None` for a filled backup (the root of a tree).
+    pub parent: Option<u32>,
+}
+
+/// Returns the number of the backup a backup is filled from.
+///
+/// A filled backup has no parent. Another backup is filled from the backup referenced by its `fill_from_num`, or
+/// from the next backup when it is unset (`-1`) or doesn't reference a newer backup.
+fn fill_parent_of(
+    backup: &BackupInformation,
+    backups: &BTreeMap<u32, BackupInformation>,
+) -> Option<u32> {
+    if backup.no_fill == 0 {
+        return None;
+    }
+
+    u32::try_from(backup.fill_from_num)
+        .ok()
+        .filter(|num| *num > backup.num && backups.contains_key(num))
+        .or_else(|| Some(*backups.range(backup.num + 1..).next()?.0))
+}
+
+/// A host of the `hosts` file of the configuration of `BackupPC`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostEntry {
+    /// The name of the host.
+    pub host: String,
+    /// `true` if the address of the host is given by DHCP.
+    pub dhcp: bool,
+    /// The user owning the host.
+    pub user: String,
+    /// The other users the host is shared with.
+    pub more_users: Vec<String>,
+}
+
+impl HostEntry {
+    /// Returns `true` if the host is owned by the user, or shared with the user.
+    #[must_use]
+    pub fn is_accessible_by(&self, user: &str) -> bool {
+        self.user == user || self.more_users.iter().any(|more_user| more_user == user)
+    }
 }
 
 pub struct Hosts {
     topdir: String,
+    source: Arc<dyn PoolSource>,
 }
 
 impl Hosts {
     #[must_use]
     pub fn new(topdir: &str) -> Self {
+        Self::new_with_source(topdir, Arc::new(LocalSource))
+    }
+
+    /// Creates a new `Hosts` reading the hosts and backups from the given pool source.
+    ///
+    /// # Arguments
+    ///
+    /// * `topdir` - The top directory path (on the machine hosting the pool).
+    /// * `source` - The source used to access the files of the top directory.
+    #[must_use]
+    pub fn new_with_source(topdir: &str, source: Arc<dyn PoolSource>) -> Self {
         Hosts {
             topdir: topdir.to_string(),
+            source,
         }
     }
-}
 
-// Implements trait
-impl HostsTrait for Hosts {
-    fn list_hosts(&self) -> Result<Vec<String>> {
-        info!("Listing hosts in {}", self.topdir);
-        let pc_dir = std::path::Path::new(&self.topdir).join("pc");
-        let mut hosts = Vec::new();
-
-        for entry in std::fs::read_dir(pc_dir)? {
-            match entry {
-                Ok(entry) => {
-                    let path = entry.path();
-                    if path.is_dir() {
-                        let host = path
-                            .file_name()
-                            .unwrap_or_default()
-                            .to_str()
-                            .unwrap_or_default()
-                            .to_string();
-
-                        hosts.push(host);
-                    }
+    /// Creates a new `Hosts` reading the hosts and backups of the pool described by the configuration (its top
+    /// directory and its source).
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the pool, shared with `Search::with_config`.
+    #[must_use]
+    pub fn with_config(config: &PoolConfig) -> Self {
+        Self::new_with_source(&config.topdir, config.source())
+    }
+
+    /// Lists the backups of all the hosts.
+    ///
+    /// The hosts whose backups can't be read are skipped (see `list_all_backups_with_errors` to get their errors).
+    ///
+    /// # Returns
+    ///
+    /// The backups of each host.
+    ///
+    /// # Errors
+    ///
+    /// If the hosts can't be listed.
+    pub fn list_all_backups(&self) -> Result<HashMap<String, Vec<BackupInformation>>> {
+        let all_backups = self.list_all_backups_with_errors()?;
+        for (host, err) in &all_backups.errors {
+            warn!("Can't list the backups of {host}: {err}");
+        }
+
+        Ok(all_backups.backups)
+    }
+
+    /// Lists the backups of a host with the backup each one is filled from, to render the dependency tree of the
+    /// backups.
+    ///
+    /// `BackupPC` v4 stores the older backups as reverse deltas of the newer ones: the parent of a backup is the
+    /// backup it is filled from, as followed by `list_backups_to_fill`. The filled backups are the roots of the
+    /// trees.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The name of the host to list the backups.
+    ///
+    /// # Returns
+    ///
+    /// A `BackupNode` for each backup of the host, sorted by backup number. The vector is empty if the host has no
+    /// backups file.
+    ///
+    /// # Errors
+    ///
+    /// If the file topdir/pc/<hostname>/backups exists but cannot be read.
+    pub fn backup_tree(&self, hostname: &str) -> Result<Vec<BackupNode>> {
+        let backups = match self.list_backups(hostname) {
+            Ok(backups) => backups,
+            Err(err) => match err.downcast_ref::<std::io::Error>() {
+                // The host exists but has no backups yet
+                Some(io_err) if io_err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                _ => return Err(err),
+            },
+        };
+        let backups = backups
+            .into_iter()
+            .map(|backup| (backup.num, backup))
+            .collect::<BTreeMap<u32, BackupInformation>>();
+
+        Ok(backups
+            .values()
+            .map(|backup| BackupNode {
+                parent: fill_parent_of(backup, &backups),
+                backup: backup.clone(),
+            })
+            .collect())
+    }
+
+    /// Lists the backups of all the hosts, with the error of each host whose backups can't be read.
+    ///
+    /// The backups files of the hosts are read in parallel when the `parallel` feature is enabled. An unreadable
+    /// host doesn't fail the whole listing: its error is returned in `AllBackups::errors`.
+    ///
+    /// # Returns
+    ///
+    /// The backups of each host, and the errors of the hosts whose backups can't be read.
+    ///
+    /// # Errors
+    ///
+    /// If the hosts can't be listed.
+    pub fn list_all_backups_with_errors(&self) -> Result<AllBackups> {
+        let hosts = self.list_hosts()?;
+
+        let list_backups = |host: String| {
+            let backups = match self.list_backups(&host) {
+                Ok(backups) => Ok(backups),
+                Err(err) => match err.downcast_ref::<std::io::Error>() {
+                    // The host exists but has no backups yet
+                    Some(io_err) if io_err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+                    _ => Err(err.to_string()),
+                },
+            };
+            (host, backups)
+        };
+
+        #[cfg(feature = "parallel")]
+        let results: Vec<(String, std::result::Result<Vec<BackupInformation>, String>)> = {
+            use rayon::prelude::*;
+            hosts.into_par_iter().map(list_backups).collect()
+        };
+        #[cfg(not(feature = "parallel"))]
+        let results: Vec<(String, std::result::Result<Vec<BackupInformation>, String>)> =
+            hosts.into_iter().map(list_backups).collect();
+
+        let mut all_backups = AllBackups::default();
+        for (host, backups) in results {
+            match backups {
+                Ok(backups) => {
+                    all_backups.backups.insert(host, backups);
                 }
                 Err(err) => {
-                    eprintln!("Error reading pc directory: {err}");
+                    all_backups.errors.insert(host, err);
                 }
             }
         }
 
+        Ok(all_backups)
+    }
+
+    /// Read the information of a backup from its backupInfo file.
+    ///
+    /// The file topdir/pc/<hostname>/<num>/backupInfo is a Perl hash (written with `Data::Dumper`) containing the
+    /// same fields as the line of the backup in the backups file. It is used when this line is missing or incomplete.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The name of the host of the backup.
+    /// * `backup_number` - The number of the backup.
+    ///
+    /// # Returns
+    ///
+    /// The `BackupInformation` of the backup. Missing fields have their default value.
+    ///
+    /// # Errors
+    ///
+    /// If the file topdir/pc/<hostname>/<num>/backupInfo cannot be read.
+    pub fn read_backup_info(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+    ) -> Result<BackupInformation> {
+        let path = format!("{}/pc/{hostname}/{backup_number}/backupInfo", &self.topdir);
+        info!("Reading backup information from {path}");
+
+        let mut content = String::new();
+        self.source
+            .open(Path::new(&path))?
+            .read_to_string(&mut content)?;
+        let mut values = parse_perl_hash(&content);
+        values
+            .entry("num".to_string())
+            .or_insert_with(|| backup_number.to_string());
+
+        Ok(BackupInformation::from_values(&values))
+    }
+
+    /// Reads the hosts of the `hosts` file of the configuration of `BackupPC`.
+    ///
+    /// The `hosts` file (conf_dir/hosts, ex: `/etc/BackupPC/hosts`) maps each host to the user owning it (`user`) and
+    /// to the users it is shared with (`moreUsers`).
+    ///
+    /// # Arguments
+    ///
+    /// * `conf_dir` - The configuration directory of `BackupPC`.
+    ///
+    /// # Returns
+    ///
+    /// The hosts of the file, in the order of the file.
+    ///
+    /// # Errors
+    ///
+    /// If the file conf_dir/hosts cannot be read.
+    pub fn read_hosts_file(&self, conf_dir: &str) -> Result<Vec<HostEntry>> {
+        let path = Path::new(conf_dir).join("hosts");
+        info!("Reading hosts from {}", path.display());
+
+        let mut content = String::new();
+        self.source.open(&path)?.read_to_string(&mut content)?;
+
+        Ok(parse_hosts_file(&content))
+    }
+
+    /// Lists the hosts owned by a user, or shared with the user.
+    ///
+    /// # Arguments
+    ///
+    /// * `conf_dir` - The configuration directory of `BackupPC` (containing the `hosts` file).
+    /// * `user` - The name of the user.
+    ///
+    /// # Returns
+    ///
+    /// The names of the hosts whose `user` or `moreUsers` column contains the user, in the order of the file.
+    ///
+    /// # Errors
+    ///
+    /// If the file conf_dir/hosts cannot be read.
+    pub fn list_hosts_for_user(&self, conf_dir: &str, user: &str) -> Result<Vec<String>> {
+        Ok(self
+            .read_hosts_file(conf_dir)?
+            .into_iter()
+            .filter(|entry| entry.is_accessible_by(user))
+            .map(|entry| entry.host)
+            .collect())
+    }
+
+    /// Opens the transfer log of a backup.
+    ///
+    /// `BackupPC` writes the log of the transfer of each backup in the directory of the host, compressed like the
+    /// files of the cpool (topdir/pc/<hostname>/XferLOG.<num>.z). The log of a pool without compression is stored as
+    /// is (topdir/pc/<hostname>/XferLOG.<num>).
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The name of the host of the backup.
+    /// * `backup_number` - The number of the backup.
+    ///
+    /// # Returns
+    ///
+    /// A reader on the (uncompressed) content of the log, decompressed while reading.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `NotFound` if the backup has no transfer log, or if the log can't be opened.
+    pub fn read_xfer_log(&self, hostname: &str, backup_number: u32) -> Result<Box<dyn Read>> {
+        let host_dir = Path::new(&self.topdir).join("pc").join(hostname);
+
+        let compressed = host_dir.join(format!("XferLOG.{backup_number}.z"));
+        if self.source.exists(&compressed) {
+            info!("Reading transfer log from {}", compressed.display());
+            let file = self.source.open(&compressed)?;
+            return Ok(Box::new(BackupPCReader::autodetect(file)?));
+        }
+
+        let path = host_dir.join(format!("XferLOG.{backup_number}"));
+        info!("Reading transfer log from {}", path.display());
+        Ok(self.source.open(&path)?)
+    }
+}
+
+/// Parses the `hosts` file of the configuration of `BackupPC`.
+///
+/// Each line contains the columns `host`, `dhcp`, `user`, and `moreUsers` (a comma-separated list of users), separated
+/// by whitespace. The comments (starting with `#`), the empty lines, and the header line (`host dhcp user moreUsers`)
+/// are ignored, as the lines without a user.
+fn parse_hosts_file(content: &str) -> Vec<HostEntry> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.split_once('#').map_or(line, |(line, _)| line);
+            let mut columns = line.split_whitespace();
+            let host = columns.next()?;
+            let dhcp = columns.next()?;
+            let user = columns.next()?;
+            if host == "host" && user == "user" {
+                return None;
+            }
+
+            let more_users = columns
+                .next()
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|more_user| !more_user.is_empty())
+                .map(ToString::to_string)
+                .collect();
+
+            Some(HostEntry {
+                host: host.to_string(),
+                dhcp: dhcp == "1",
+                user: user.to_string(),
+                more_users,
+            })
+        })
+        .collect()
+}
+
+/// Parses the scalar values of a Perl hash written by `Data::Dumper` (`'key' => 'value',` or `'key' => 42,`).
+///
+/// Nested structures are ignored.
+fn parse_perl_hash(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once("=>")?;
+            let key = key.trim().trim_matches('\'');
+            let value = value.trim().trim_end_matches(',').trim();
+            let value = match value.strip_prefix('\'') {
+                Some(value) => value.strip_suffix('\'')?.replace("\\'", "'"),
+                None if value.parse::<f64>().is_ok() => value.to_string(),
+                None => return None,
+            };
+
+            Some((key.to_string(), value))
+        })
+        .collect()
+}
+
+// Implements trait
+impl HostsTrait for Hosts {
+    fn list_hosts(&self) -> Result<Vec<String>> {
+        info!("Listing hosts in {}", self.topdir);
+        let pc_dir = Path::new(&self.topdir).join("pc");
+        let hosts = self
+            .source
+            .read_dir(&pc_dir)?
+            .into_iter()
+            .filter(|entry| entry.is_dir)
+            .map(|entry| entry.name)
+            .collect::<Vec<String>>();
+
         debug!("Found {} hosts", hosts.len());
 
         Ok(hosts)
@@ -182,63 +638,415 @@ impl HostsTrait for Hosts {
         // Open the file and read each line
         // Fields are separated by tab
 
-        let file = File::open(path)?;
+        let file = self.source.open(Path::new(&path))?;
         let reader = BufReader::new(file);
 
         for line in reader.lines() {
-            let line = line.unwrap();
+            let line = line?;
             let fields: Vec<&str> = line.split('\t').collect();
-            let backup = BackupInformation {
-                num: fields[0].parse().unwrap_or_default(),
-                backup_type: fields[1].to_string(),
-                start_time: fields[2].parse().unwrap_or_default(),
-                end_time: fields[3].parse().unwrap_or_default(),
-                n_files: fields[4].parse().unwrap_or_default(),
-                size: fields[5].parse().unwrap_or_default(),
-                n_files_exist: fields[6].parse().unwrap_or_default(),
-                size_exist: fields[7].parse().unwrap_or_default(),
-                n_files_new: fields[8].parse().unwrap_or_default(),
-                size_new: fields[9].parse().unwrap_or_default(),
-                xfer_errs: fields[10].parse().unwrap_or_default(),
-                xfer_bad_file: fields[11].parse().unwrap_or_default(),
-                xfer_bad_share: fields[12].parse().unwrap_or_default(),
-                tar_errs: fields[13].parse().unwrap_or_default(),
-                compress: fields[14].parse().unwrap_or_default(),
-                size_exist_comp: fields[15].parse().unwrap_or_default(),
-                size_new_comp: fields[16].parse().unwrap_or_default(),
-                no_fill: fields[17].parse().unwrap_or_default(),
-                fill_from_num: fields[18].parse().unwrap_or(-1),
-                mangle: fields[19].parse().unwrap_or_default(),
-                xfer_method: fields[20].to_string(),
-                level: fields[21].parse().unwrap_or_default(),
-                charset: fields[22].to_string(),
-                version: fields[23].to_string(),
-                inode_last: fields[24].parse().unwrap_or_default(),
+            let values: HashMap<String, String> = BACKUP_FIELDS
+                .iter()
+                .zip(fields.iter())
+                .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+                .collect();
+
+            let Some(num) = values.get("num").and_then(|num| num.parse::<u32>().ok()) else {
+                if !line.trim().is_empty() {
+                    warn!("Skip the line without a backup number in {path}: {line}");
+                }
+                continue;
             };
 
-            backups.push(backup);
+            // An incomplete line is completed by the backupInfo file of the backup
+            if fields.len() < BACKUP_FIELDS.len() {
+                match self.read_backup_info(hostname, num) {
+                    Ok(backup) => {
+                        backups.push(backup);
+                        continue;
+                    }
+                    Err(err) => debug!("No backupInfo for backup {num} of {hostname}: {err}"),
+                }
+            }
+
+            backups.push(BackupInformation::from_values(&values));
         }
+        backups.sort_by_key(|backup| backup.num);
 
         debug!("Found {} backups", backups.len());
 
         Ok(backups)
     }
 
-    fn list_backups_to_fill(&self, hostname: &str, backup_number: u32) -> Vec<BackupInformation> {
-        let backups = self.list_backups(hostname).unwrap_or_else(|_| Vec::new());
-        let backups = backups.iter().filter(|backup| backup.num >= backup_number);
-        let mut backups_to_search: Vec<crate::hosts::BackupInformation> = Vec::new();
-
-        for backup in backups {
-            backups_to_search.push(backup.clone());
+    fn list_backups_to_fill(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+    ) -> Result<Vec<BackupInformation>> {
+        let backups = match self.list_backups(hostname) {
+            Ok(backups) => backups,
+            Err(err) => match err.downcast_ref::<std::io::Error>() {
+                // The host exists but has no backups yet
+                Some(io_err) if io_err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                _ => return Err(err),
+            },
+        };
+        let mut backups = backups
+            .into_iter()
+            .filter(|backup| backup.num >= backup_number)
+            .map(|backup| (backup.num, backup))
+            .collect::<BTreeMap<u32, BackupInformation>>();
 
-            if backup.no_fill > 0 {
-                continue;
-            }
-            break;
+        // Follow the `fillFromNum` pointers of the backups. A backup without pointer (or with a pointer to a missing
+        // or older backup) is filled from the next backup.
+        let mut backups_to_search: Vec<BackupInformation> = Vec::new();
+        let mut next = backups.remove(&backup_number);
+        while let Some(backup) = next {
+            next = fill_parent_of(&backup, &backups).and_then(|num| backups.remove(&num));
+            backups_to_search.push(backup);
         }
         backups_to_search.reverse();
 
-        backups_to_search
+        Ok(backups_to_search)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_topdir(name: &str) -> std::path::PathBuf {
+        let topdir = std::env::temp_dir().join(format!("bpc_{name}_{}", std::process::id()));
+        std::fs::create_dir_all(topdir.join("pc").join("pc-1")).unwrap();
+        topdir
+    }
+
+    /// Writes the backups file of the host pc-1 with the given backups (number, type, and no fill flag).
+    fn write_backups_file(topdir: &std::path::Path, backups: &[(u32, &str, u32)]) {
+        let backups = backups
+            .iter()
+            .map(|(num, type_, no_fill)| (*num, *type_, *no_fill, -1))
+            .collect::<Vec<_>>();
+        write_backups_file_with_fill(topdir, &backups);
+    }
+
+    /// Writes the backups file of the host pc-1 with the given backups (number, type, no fill flag, and fill from
+    /// number).
+    fn write_backups_file_with_fill(topdir: &std::path::Path, backups: &[(u32, &str, u32, i32)]) {
+        let content = backups
+            .iter()
+            .map(|(num, type_, no_fill, fill_from_num)| {
+                let mut fields = vec!["0".to_string(); BACKUP_FIELDS.len()];
+                fields[0] = num.to_string();
+                fields[1] = (*type_).to_string();
+                fields[17] = no_fill.to_string();
+                fields[18] = fill_from_num.to_string();
+                format!("{}\n", fields.join("\t"))
+            })
+            .collect::<String>();
+        std::fs::write(topdir.join("pc").join("pc-1").join("backups"), content).unwrap();
+    }
+
+    fn backups_to_fill(hosts: &Hosts, backup_number: u32) -> Vec<u32> {
+        hosts
+            .list_backups_to_fill("pc-1", backup_number)
+            .unwrap()
+            .iter()
+            .map(|backup| backup.num)
+            .collect()
+    }
+
+    #[test]
+    fn test_list_backups_to_fill_of_full_backups() {
+        let topdir = create_topdir("hosts_fill_full");
+        write_backups_file(&topdir, &[(1, "full", 0), (2, "full", 0)]);
+        let hosts = Hosts::new(topdir.to_str().unwrap());
+
+        assert_eq!(backups_to_fill(&hosts, 1), vec![1]);
+        assert_eq!(backups_to_fill(&hosts, 2), vec![2]);
+        assert!(backups_to_fill(&hosts, 3).is_empty());
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_list_backups_to_fill_of_incremental_chains() {
+        let topdir = create_topdir("hosts_fill_incr");
+        write_backups_file(
+            &topdir,
+            &[
+                (4, "full", 0),
+                (1, "full", 0),
+                (2, "incr", 1),
+                (3, "incr", 1),
+                (5, "incr", 1),
+                (6, "incr", 0),
+            ],
+        );
+        let hosts = Hosts::new(topdir.to_str().unwrap());
+
+        assert_eq!(backups_to_fill(&hosts, 1), vec![1]);
+        assert_eq!(backups_to_fill(&hosts, 3), vec![4, 3]);
+        assert_eq!(backups_to_fill(&hosts, 2), vec![4, 3, 2]);
+        assert_eq!(backups_to_fill(&hosts, 5), vec![6, 5]);
+        assert_eq!(backups_to_fill(&hosts, 6), vec![6]);
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_list_backups_to_fill_with_fill_from_num() {
+        let topdir = create_topdir("hosts_fill_from_num");
+        write_backups_file_with_fill(
+            &topdir,
+            &[
+                (1, "incr", 1, 4),
+                (2, "incr", 1, 3),
+                (3, "incr", 1, -1),
+                (4, "full", 0, -1),
+                (5, "incr", 1, 2),
+                (6, "full", 0, -1),
+            ],
+        );
+        let hosts = Hosts::new(topdir.to_str().unwrap());
+
+        // The backup 1 is filled from the backup 4, the backups 2 and 3 are skipped
+        assert_eq!(backups_to_fill(&hosts, 1), vec![4, 1]);
+        assert_eq!(backups_to_fill(&hosts, 2), vec![4, 3, 2]);
+        // A pointer to an older backup is ignored
+        assert_eq!(backups_to_fill(&hosts, 5), vec![6, 5]);
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_backup_tree() {
+        let topdir = create_topdir("hosts_backup_tree");
+        write_backups_file_with_fill(
+            &topdir,
+            &[
+                (1, "incr", 1, 4),
+                (2, "incr", 1, -1),
+                (4, "full", 0, -1),
+                (5, "incr", 1, 2),
+                (6, "incr", 0, -1),
+            ],
+        );
+        let hosts = Hosts::new(topdir.to_str().unwrap());
+
+        let tree = hosts
+            .backup_tree("pc-1")
+            .unwrap()
+            .into_iter()
+            .map(|node| (node.backup.num, node.parent))
+            .collect::<Vec<(u32, Option<u32>)>>();
+        // A pointer to an older backup is ignored: the backup 5 is filled from the next backup
+        assert_eq!(
+            tree,
+            vec![
+                (1, Some(4)),
+                (2, Some(4)),
+                (4, None),
+                (5, Some(6)),
+                (6, None)
+            ]
+        );
+        assert!(hosts.backup_tree("pc-2").unwrap().is_empty());
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_is_partial() {
+        let topdir = create_topdir("hosts_partial");
+        write_backups_file(&topdir, &[(1, "full", 0), (2, "partial", 1)]);
+        let hosts = Hosts::new(topdir.to_str().unwrap());
+
+        let backups = hosts.list_backups("pc-1").unwrap();
+        assert!(!backups[0].is_partial());
+        assert!(backups[1].is_partial());
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_list_all_backups_with_errors() {
+        let topdir = create_topdir("hosts_all_backups");
+        write_backups_file(&topdir, &[(1, "full", 0), (2, "incr", 0)]);
+        std::fs::create_dir_all(topdir.join("pc").join("pc-2")).unwrap();
+        std::fs::create_dir_all(topdir.join("pc").join("pc-3").join("backups")).unwrap();
+        let hosts = Hosts::new(topdir.to_str().unwrap());
+
+        let all_backups = hosts.list_all_backups_with_errors().unwrap();
+        assert_eq!(all_backups.backups.len(), 2);
+        assert_eq!(all_backups.backups["pc-1"].len(), 2);
+        assert!(all_backups.backups["pc-2"].is_empty());
+        assert!(all_backups.errors.contains_key("pc-3"));
+
+        let backups = hosts.list_all_backups().unwrap();
+        assert_eq!(backups.len(), 2);
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_list_backups_to_fill_without_backups_file() {
+        let topdir = create_topdir("hosts_missing_backups");
+        let hosts = Hosts::new(topdir.to_str().unwrap());
+
+        assert!(hosts.list_backups_to_fill("pc-1", 1).unwrap().is_empty());
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_list_backups_to_fill_with_unreadable_backups_file() {
+        let topdir = create_topdir("hosts_unreadable_backups");
+        // A directory can't be read as a file, even by root (permissions are not enforced for root)
+        std::fs::create_dir_all(topdir.join("pc").join("pc-1").join("backups")).unwrap();
+        let hosts = Hosts::new(topdir.to_str().unwrap());
+
+        assert!(hosts.list_backups_to_fill("pc-1", 1).is_err());
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_list_hosts_for_user() {
+        let topdir = create_topdir("hosts_for_user");
+        let conf_dir = topdir.join("conf");
+        std::fs::create_dir_all(&conf_dir).unwrap();
+        std::fs::write(
+            conf_dir.join("hosts"),
+            "# Comment\n\
+             host        dhcp    user    moreUsers     # <--- do not edit this line\n\
+             \n\
+             farside     0       craig   jill,jeff     # shared host\n\
+             pc-1\t1\tjill\n\
+             pc-2 0 jeff , \n\
+             incomplete 0\n",
+        )
+        .unwrap();
+
+        let hosts = Hosts::new(topdir.to_str().unwrap());
+        let conf_dir = conf_dir.to_str().unwrap();
+        let entries = hosts.read_hosts_file(conf_dir).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[0],
+            HostEntry {
+                host: "farside".to_string(),
+                dhcp: false,
+                user: "craig".to_string(),
+                more_users: vec!["jill".to_string(), "jeff".to_string()],
+            }
+        );
+        assert!(entries[1].dhcp);
+
+        assert_eq!(
+            hosts.list_hosts_for_user(conf_dir, "jill").unwrap(),
+            vec!["farside", "pc-1"]
+        );
+        assert_eq!(
+            hosts.list_hosts_for_user(conf_dir, "jeff").unwrap(),
+            vec!["farside", "pc-2"]
+        );
+        assert_eq!(
+            hosts.list_hosts_for_user(conf_dir, "craig").unwrap(),
+            vec!["farside"]
+        );
+        assert!(hosts
+            .list_hosts_for_user(conf_dir, "ji")
+            .unwrap()
+            .is_empty());
+        assert!(hosts.list_hosts_for_user("/nonexistent", "jill").is_err());
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_read_xfer_log() {
+        use flate2::write::ZlibEncoder;
+        use std::io::Write;
+
+        let topdir = create_topdir("hosts_xfer_log");
+        let host_dir = topdir.join("pc").join("pc-1");
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"Contents of file /etc/hosts\n").unwrap();
+        std::fs::write(host_dir.join("XferLOG.1.z"), encoder.finish().unwrap()).unwrap();
+        std::fs::write(host_dir.join("XferLOG.2"), "Done: 0 errors\n").unwrap();
+        let hosts = Hosts::new(topdir.to_str().unwrap());
+
+        let read = |backup_number| {
+            let mut content = String::new();
+            hosts
+                .read_xfer_log("pc-1", backup_number)
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            content
+        };
+        assert_eq!(read(1), "Contents of file /etc/hosts\n");
+        assert_eq!(read(2), "Done: 0 errors\n");
+
+        let err = hosts.read_xfer_log("pc-1", 3).err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<std::io::Error>().unwrap().kind(),
+            std::io::ErrorKind::NotFound
+        );
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_read_backup_info() {
+        let topdir = create_topdir("hosts_backup_info");
+        let backup_dir = topdir.join("pc").join("pc-1").join("3");
+        std::fs::create_dir_all(&backup_dir).unwrap();
+        std::fs::write(
+            backup_dir.join("backupInfo"),
+            "%backupInfo = (\n  'charset' => '',\n  'inodeLast' => 1234,\n  'nFiles' => 42,\n  \
+             'num' => 3,\n  'size' => 4096,\n  'startTime' => 1700000000,\n  'type' => 'incr',\n  \
+             'version' => '4.4.0'\n);\n",
+        )
+        .unwrap();
+        let hosts = Hosts::new(topdir.to_str().unwrap());
+
+        let backup = hosts.read_backup_info("pc-1", 3).unwrap();
+        assert_eq!(backup.num, 3);
+        assert_eq!(backup.backup_type, "incr");
+        assert_eq!(backup.start_time, 1_700_000_000);
+        assert_eq!(backup.n_files, 42);
+        assert_eq!(backup.size, 4096);
+        assert_eq!(backup.version, "4.4.0");
+        assert_eq!(backup.inode_last, 1234);
+        assert_eq!(backup.fill_from_num, -1);
+
+        // The lines of the backups 2, 3 and 4 are incomplete: only the backup 3 has a backupInfo file. The lines
+        // without a backup number are skipped, the backups are listed by number whatever their order in the backups
+        // file, and a backup missing from the backups file (being made or deleted) isn't listed.
+        let other_backup_dir = topdir.join("pc").join("pc-1").join("5");
+        std::fs::create_dir_all(&other_backup_dir).unwrap();
+        std::fs::copy(
+            backup_dir.join("backupInfo"),
+            other_backup_dir.join("backupInfo"),
+        )
+        .unwrap();
+        std::fs::write(
+            topdir.join("pc").join("pc-1").join("backups"),
+            "4\tincr\t1650000000\n3\n\tfull\n2\tfull\t1600000000\n\n",
+        )
+        .unwrap();
+        let backups = hosts.list_backups("pc-1").unwrap();
+        assert_eq!(
+            backups
+                .iter()
+                .map(|backup| backup.num)
+                .collect::<Vec<u32>>(),
+            vec![2, 3, 4]
+        );
+        assert_eq!(backups[0].start_time, 1_600_000_000);
+        assert_eq!(backups[1].inode_last, 1234);
+
+        std::fs::remove_dir_all(topdir).unwrap();
     }
 }
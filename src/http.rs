@@ -0,0 +1,352 @@
+use log::{error, info};
+use std::fmt::Write;
+use std::io::{self, Read};
+use std::time::Duration;
+use threadpool::ThreadPool;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+use crate::cancel::Cancelled;
+use crate::decode_attribut::{FileAttributes, FileType};
+use crate::reader::{skip, LimitedReader};
+use crate::util::Result;
+use crate::view::{FileReader, SharedBackupPC};
+
+/// Number of threads handling the requests.
+const WORKERS: usize = 8;
+
+/// Interval at which the server checks if the token of the view is cancelled, while waiting for a request.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Serves the view read-only over HTTP.
+///
+/// - Directories (hosts, backups, shares, and directories of a share) are listed as HTML, or as JSON when the
+///   query string contains `format=json` or when the client accepts `application/json`.
+/// - Files are returned with a `GET` request. A single byte range can be requested with the `Range` header.
+///
+/// The requests are handled by a fixed pool of `WORKERS` threads, all threads sharing the same view. The requests
+/// received while all the threads are busy wait for a free thread.
+///
+/// The server stops when the token of the view is cancelled (see `BackupPC::set_cancellation_token`): the requests
+/// handled after that are answered with `503`, and the traversals in progress are aborted.
+///
+/// # Arguments
+///
+/// * `view` - The view to serve.
+/// * `bind` - The address to listen on (ex: `127.0.0.1:8080`).
+///
+/// # Errors
+///
+/// If the server can't listen on the given address, or can't receive the requests.
+pub fn serve(view: SharedBackupPC, bind: &str) -> Result<()> {
+    let server = Server::http(bind).map_err(|err| io::Error::other(err.to_string()))?;
+    info!("Listening on http://{bind}");
+
+    let workers = ThreadPool::new(WORKERS);
+    while !view.cancellation_token().is_cancelled() {
+        let Some(request) = server.recv_timeout(CANCEL_POLL_INTERVAL)? else {
+            continue;
+        };
+        let view = view.clone();
+        workers.execute(move || {
+            let url = request.url().to_string();
+            if let Err(err) = handle_request(&view, request) {
+                error!("Error responding to {url}: {err}");
+            }
+        });
+    }
+    info!("Stop listening on http://{bind}");
+
+    Ok(())
+}
+
+fn handle_request(view: &SharedBackupPC, request: Request) -> io::Result<()> {
+    if view.cancellation_token().is_cancelled() {
+        return respond_error(request, 503, "Cancelled");
+    }
+    if request.method() != &Method::Get && request.method() != &Method::Head {
+        return request.respond(Response::empty(405));
+    }
+
+    let (path, query) = match request.url().split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (request.url().to_string(), String::new()),
+    };
+    let path = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(percent_decode)
+        .collect::<Vec<String>>();
+    let path = path.iter().map(String::as_str).collect::<Vec<&str>>();
+
+    // The directories are read as empty files: the type of the file tells if it must be listed
+    let file = if path.is_empty() {
+        None
+    } else {
+        match view.read_file_with_meta(&path) {
+            Ok(file) => Some(file),
+            Err(err) => return respond_view_error(request, err.as_ref()),
+        }
+    };
+
+    match file {
+        Some(file) if file.type_ != FileType::Dir => {
+            respond_file(request, file, view.read_chunk_size())
+        }
+        _ => {
+            let as_json = query.split('&').any(|param| param == "format=json")
+                || request.headers().iter().any(|header| {
+                    header.field.equiv("Accept")
+                        && header.value.as_str().contains("application/json")
+                });
+            respond_listing(view, request, &path, as_json)
+        }
+    }
+}
+
+fn respond_error(request: Request, status: u16, message: &str) -> io::Result<()> {
+    request.respond(Response::from_string(message).with_status_code(status))
+}
+
+/// Responds with `404` if the path doesn't exist, `503` if the view is cancelled, `500` for the other errors of the
+/// view.
+fn respond_view_error(request: Request, err: &(dyn std::error::Error + 'static)) -> io::Result<()> {
+    if err.is::<Cancelled>() {
+        return respond_error(request, 503, "Cancelled");
+    }
+    match err.downcast_ref::<io::Error>() {
+        Some(io_err) if io_err.kind() == io::ErrorKind::NotFound => {
+            respond_error(request, 404, "Not found")
+        }
+        _ => respond_error(request, 500, &err.to_string()),
+    }
+}
+
+fn respond_listing(
+    view: &SharedBackupPC,
+    request: Request,
+    path: &[&str],
+    as_json: bool,
+) -> io::Result<()> {
+    let files = match view.list(path) {
+        Ok(files) => files,
+        Err(err) => return respond_view_error(request, err.as_ref()),
+    };
+
+    let (body, content_type) = if as_json {
+        (listing_to_json(&files), "application/json")
+    } else {
+        (listing_to_html(path, &files), "text/html; charset=utf-8")
+    };
+
+    let response = Response::from_string(body).with_header(header("Content-Type", content_type));
+    request.respond(response)
+}
+
+fn respond_file(request: Request, file: FileReader, read_chunk_size: usize) -> io::Result<()> {
+    let range = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Range"))
+        .map(|header| parse_range(header.value.as_str(), file.size));
+
+    let (start, len, status) = match range {
+        None => (0, file.size, 200),
+        Some(Some((start, end))) => (start, end - start + 1, 206),
+        Some(None) => {
+            let response = Response::empty(416)
+                .with_header(header("Content-Range", &format!("bytes */{}", file.size)));
+            return request.respond(response);
+        }
+    };
+
+    let mut reader = file.reader;
+    skip(&mut reader, start, read_chunk_size)?;
+
+    // The whole content must match its length, a range ends before the end of the content
+    let body: Box<dyn Read + Send> = if status == 206 {
+        Box::new(reader.take(len))
+    } else {
+        Box::new(LimitedReader::new(reader, len))
+    };
+    let mut response = Response::new(
+        StatusCode(status),
+        vec![
+            header("Content-Type", "application/octet-stream"),
+            header("Accept-Ranges", "bytes"),
+        ],
+        body,
+        usize::try_from(len).ok(),
+        None,
+    );
+    if status == 206 {
+        let range = format!("bytes {start}-{}/{}", start + len - 1, file.size);
+        response.add_header(header("Content-Range", &range));
+    }
+
+    request.respond(response)
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("Invalid header")
+}
+
+/// Parses the value of a `Range` header (a single range in bytes).
+///
+/// # Returns
+///
+/// The first and last byte of the range (inclusive), clamped to the size of the file, or `None` if the range can't
+/// be satisfied.
+fn parse_range(value: &str, size: u64) -> Option<(u64, u64)> {
+    let range = value.trim().strip_prefix("bytes=")?;
+    let (start, end) = range.split_once('-')?;
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (size.saturating_sub(suffix), size.checked_sub(1)?)
+        }
+        (start, "") => (start.parse().ok()?, size.checked_sub(1)?),
+        (start, end) => (
+            start.parse().ok()?,
+            end.parse::<u64>().ok()?.min(size.checked_sub(1)?),
+        ),
+    };
+
+    if start > end || start >= size {
+        return None;
+    }
+
+    Some((start, end))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(byte) = value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn percent_encode(value: &str) -> String {
+    value.bytes().fold(String::new(), |mut output, byte| {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            output.push(byte as char);
+        } else {
+            let _ = write!(output, "%{byte:02X}");
+        }
+        output
+    })
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn listing_to_json(files: &[FileAttributes]) -> String {
+    let entries = files
+        .iter()
+        .map(|file| {
+            serde_json::json!({
+                "name": file.name,
+                "type": format!("{:?}", file.type_),
+                "size": file.size,
+                "mode": file.mode,
+                "uid": file.uid,
+                "gid": file.gid,
+                "mtime": file.mtime,
+            })
+        })
+        .collect::<Vec<serde_json::Value>>();
+
+    serde_json::Value::Array(entries).to_string()
+}
+
+fn listing_to_html(path: &[&str], files: &[FileAttributes]) -> String {
+    let base = path
+        .iter()
+        .map(|component| format!("/{}", percent_encode(component)))
+        .collect::<String>();
+    let title = html_escape(&format!("/{}", path.join("/")));
+
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><title>{title}</title></head><body><h1>{title}</h1><ul>"
+    );
+    if let Some((_, parent)) = path.split_last() {
+        let parent = parent
+            .iter()
+            .map(|component| format!("/{}", percent_encode(component)))
+            .collect::<String>();
+        let _ = write!(html, "<li><a href=\"{parent}/\">..</a></li>");
+    }
+    for file in files {
+        let suffix = if file.type_ == FileType::Dir { "/" } else { "" };
+        let _ = write!(
+            html,
+            "<li><a href=\"{base}/{}{suffix}\">{}{suffix}</a></li>",
+            percent_encode(&file.name),
+            html_escape(&file.name)
+        );
+    }
+    html.push_str("</ul></body></html>");
+
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attribute_file::MockSearchTrait;
+    use crate::hosts::MockHostsTrait;
+    use crate::view::BackupPC;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(parse_range("bytes=900-", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=500-2000", 1000), Some((500, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=0-0", 0), None);
+        assert_eq!(parse_range("items=0-10", 1000), None);
+    }
+
+    #[test]
+    fn test_percent_encoding() {
+        assert_eq!(percent_encode("My Documents/é"), "My%20Documents%2F%C3%A9");
+        assert_eq!(percent_decode("My%20Documents%2F%C3%A9"), "My Documents/é");
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+
+    #[test]
+    fn test_serve_cancelled() {
+        let view = BackupPC::new(
+            "/var/lib/backuppc",
+            Box::new(MockHostsTrait::new()),
+            Box::new(MockSearchTrait::new()),
+        );
+        let view = SharedBackupPC::new(view);
+        view.cancellation_token().cancel();
+
+        // The server stops as soon as the token of the view is cancelled
+        serve(view, "127.0.0.1:0").unwrap();
+    }
+}
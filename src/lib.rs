@@ -1,10 +1,24 @@
 pub mod attribute_file;
+pub mod cache;
+pub mod cancel;
+pub mod clock;
 pub mod compress;
+pub mod config;
 pub mod decode_attribut;
+pub mod digest;
+pub mod export;
 pub mod hosts;
 pub mod pool;
+pub mod reader;
+#[cfg(unix)]
+pub mod restore;
+pub mod selector;
+pub mod source;
+pub mod time_style;
 pub mod util;
 pub mod view;
 
 #[cfg(feature = "fuse")]
 pub mod filesystem;
+#[cfg(feature = "http")]
+pub mod http;
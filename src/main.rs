@@ -1,27 +1,134 @@
 
 use backuppc_pool_reader::attribute_file::{Search, SearchTrait};
+use backuppc_pool_reader::cancel::Cancelled;
+use backuppc_pool_reader::clock::{Clock, SystemClock};
 use backuppc_pool_reader::compress::BackupPCReader;
-use backuppc_pool_reader::decode_attribut::{AttributeFile, FileAttributes, FileType};
-use backuppc_pool_reader::filesystem::BackupPCFS;
+use backuppc_pool_reader::config::{
+    BackupPCConfig, PoolConfig, FS_CACHE_SIZE, POOL_FILES_CACHE_SIZE, READ_CHUNK_SIZE,
+    SMALL_FILE_CACHE_SIZE, SMALL_FILE_SIZE, VIEW_CACHE_SIZE,
+};
+use backuppc_pool_reader::decode_attribut::{
+    AttributeFile, FileAttributes, FileType, InvalidMagicError, RawEntry,
+};
+use backuppc_pool_reader::digest::DigestReader;
+use backuppc_pool_reader::export;
+use backuppc_pool_reader::filesystem::{BackupPCFS, FilesystemOptions, TimestampSource};
 use backuppc_pool_reader::hosts::{Hosts, HostsTrait};
-use backuppc_pool_reader::pool::find_file_in_backuppc;
-use backuppc_pool_reader::util::{hex_string_to_vec, vec_to_hex_string};
+use backuppc_pool_reader::pool::{self, read_by_digest_from_pool_roots, TruncatedPoolFileError};
+use backuppc_pool_reader::restore::{restore_file, MetadataOptions, Ownership};
+use backuppc_pool_reader::selector::BackupSelector;
+use backuppc_pool_reader::source::RetryPolicy;
+use backuppc_pool_reader::time_style::TimeStyle;
+use backuppc_pool_reader::util::{human_size, vec_to_hex_string};
+#[cfg(feature = "http")]
+use backuppc_pool_reader::view::SharedBackupPC;
+use backuppc_pool_reader::view::{BackupOrder, BackupPC};
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use flate2::write::GzEncoder;
+use fuser::MountOption;
 use log::info;
 use std::env;
 use std::{
     fs::File,
-    io::{Error, Read, Write},
+    io::{BufWriter, Error, ErrorKind, Read, Stdout, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
 };
 
-const CHUNK_SIZE: usize = 4 * 65536;
+/// Exit code of a command failing for any other reason than the ones below.
+const EXIT_ERROR: u8 = 1;
+/// Exit code of a command failing because a host, backup, file, or pool file is not found.
+const EXIT_NOT_FOUND: u8 = 2;
+/// Exit code of a command failing because a file of the pool is corrupted (invalid or truncated content).
+const EXIT_CORRUPT: u8 = 3;
+/// Exit code of a command interrupted with Ctrl-C (as a shell reports a process killed by `SIGINT`).
+const EXIT_CANCELLED: u8 = 130;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     subcommand: Option<Commands>,
+    /// Size of the chunks used to read (and skip) the content of a file
+    #[clap(long, global = true, default_value_t = NonZeroUsize::new(READ_CHUNK_SIZE).unwrap())]
+    read_chunk_size: NonZeroUsize,
+    /// Additional pool root (a directory containing a pool and/or a cpool directory), searched after the top
+    /// directory when a file isn't in its pool (can be repeated, the roots are searched in order)
+    #[clap(long = "pool-root", global = true)]
+    pool_roots: Vec<PathBuf>,
+    /// Transcode the names that aren't valid UTF-8 from the charset of their backup (ex: the Latin-1 names of a
+    /// Windows backup), for the commands reading the backups through the view (cat, get, export, check-names, mount,
+    /// serve)
+    #[cfg(feature = "encoding")]
+    #[clap(long, global = true)]
+    transcode_names: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputCompression {
+    /// gzip stream (readable with gunzip)
+    Gzip,
+    /// zstd stream (readable with unzstd)
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+/// The standard output, optionally compressed by an encoder.
+enum Output {
+    Plain(Stdout),
+    Gzip(GzEncoder<Stdout>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, Stdout>),
+}
+
+impl Output {
+    fn new(compress: Option<OutputCompression>) -> Result<Self, Error> {
+        let stdout = std::io::stdout();
+        Ok(match compress {
+            None => Output::Plain(stdout),
+            Some(OutputCompression::Gzip) => {
+                Output::Gzip(GzEncoder::new(stdout, flate2::Compression::default()))
+            }
+            #[cfg(feature = "zstd")]
+            Some(OutputCompression::Zstd) => {
+                Output::Zstd(zstd::stream::write::Encoder::new(stdout, 0)?)
+            }
+        })
+    }
+
+    /// Writes the end of the compressed stream, then flushes the standard output.
+    fn finish(self) -> Result<(), Error> {
+        let mut stdout = match self {
+            Output::Plain(stdout) => stdout,
+            Output::Gzip(encoder) => encoder.finish()?,
+            #[cfg(feature = "zstd")]
+            Output::Zstd(encoder) => encoder.finish()?,
+        };
+        stdout.flush()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Plain(stdout) => stdout.write(buf),
+            Output::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            Output::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Plain(stdout) => stdout.flush(),
+            Output::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Output::Zstd(encoder) => encoder.flush(),
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -32,94 +139,503 @@ enum Commands {
         /// host
         #[clap(long)]
         host: Option<String>,
-        /// backup number
+        /// backup number, or backup selector (latest, latest-full, 10-1, ...)
         #[clap(long)]
-        number: Option<u32>,
+        number: Option<BackupSelector>,
         /// share name
         #[clap(long)]
         share: Option<String>,
+        /// Print the digest of the pool and the MD5 of the content written on stderr (the command fails if they
+        /// differ)
+        #[clap(long)]
+        with_digest: bool,
+        /// Compress the content written on stdout (after its decompression from the pool)
+        #[clap(long, value_enum)]
+        compress: Option<OutputCompression>,
+    },
+
+    CatHash {
+        /// The digest (in hexadecimal) of the file in the pool
+        digest: String,
+        /// Compress the content written on stdout (after its decompression from the pool)
+        #[clap(long, value_enum)]
+        compress: Option<OutputCompression>,
+    },
+
+    PoolPath {
+        /// The digest (in hexadecimal) of the file in the pool
+        digest: String,
     },
 
     DecodeAttribute {
         /// The path to the file to read
         path: String,
+        /// Print every raw field of each entry, with its offset, instead of the ls-style output
+        #[clap(long)]
+        raw: bool,
     },
 
+    // `-h` prints the sizes in a human readable format, as `ls -h`: the help is only printed with `--help`
+    #[command(disable_help_flag = true)]
     Ls {
         /// host
         host: String,
-        /// backup number
-        number: u32,
+        /// backup number, backup selector (latest, latest-full, 10-1, ...), or date (RFC 3339, ex:
+        /// 2024-03-01T12:00:00Z) to list the directory in the latest backup completed at or before it
+        number: BackupSelector,
         /// share name
         share: String,
-        /// The path to the file to read
+        /// The path to the directory to list
+        path: String,
+        /// Only print the names of the files, each one terminated by a NUL character (for `xargs -0`)
+        #[clap(short = '0', long)]
+        print0: bool,
+        /// Print the size taken on disk by the pool file of each file (after its size), and a line with the total
+        /// savings of the compression (each pool file is looked up, so the listing is slower)
+        #[clap(long)]
+        sizes: bool,
+        /// Print the sizes and the block counts in a human readable format (ex: 1.5K, 20M), as `ls -h`
+        #[clap(short = 'h', long)]
+        human_readable: bool,
+        /// Format of the modification times (in the local time zone, see TZ): locale, iso, long-iso, full-iso, or
+        /// +FORMAT (a format of strftime, ex: +%Y-%m-%d)
+        #[clap(long, default_value = "locale")]
+        time_style: TimeStyle,
+        /// Print help (`-h` is the human readable format)
+        #[clap(long, action = clap::ArgAction::Help)]
+        help: Option<bool>,
+    },
+
+    Export {
+        /// host
+        host: String,
+        /// backup number, or backup selector (latest, latest-full, 10-1, ...)
+        number: BackupSelector,
+        /// share name
+        share: String,
+        /// The path of the directory to export (the whole share by default)
+        #[clap(default_value = "/")]
         path: String,
+        /// Format of the records
+        #[clap(long, value_enum, default_value = "csv")]
+        format: ExportFormat,
     },
 
-    Hosts {},
+    Get {
+        /// host
+        host: String,
+        /// backup number, or backup selector (latest, latest-full, 10-1, ...)
+        number: BackupSelector,
+        /// share name
+        share: String,
+        /// The path of the file in the share
+        path: String,
+        /// The file to write (or the directory where the file is written with its name)
+        dest: PathBuf,
+        /// Give the file the uid and gid of the backup (needs to be root), instead of the user doing the restore
+        #[clap(long)]
+        numeric_ids: bool,
+        /// Don't apply the permissions of the backup
+        #[clap(long)]
+        no_mode: bool,
+        /// Don't apply the modification time of the backup
+        #[clap(long)]
+        no_mtime: bool,
+    },
+
+    Hosts {
+        /// Only list the hosts owned by this user, or shared with this user (read from the hosts file of the
+        /// configuration of BackupPC)
+        #[clap(long)]
+        user: Option<String>,
+        /// The configuration directory of BackupPC, containing the hosts file
+        #[clap(long, default_value = "/etc/BackupPC")]
+        conf_dir: String,
+    },
 
     Backups {
         /// host
         host: String,
+        /// Only print the number of the backup selected (latest, latest-full, 10-1, ...), the filters are ignored
+        selector: Option<BackupSelector>,
+        /// Only list the backups of this type (full, incr, ...)
+        #[clap(long = "type")]
+        backup_type: Option<String>,
+        /// Sort the backups by number or by start time (default: by number)
+        #[clap(long, value_enum)]
+        sort: Option<BackupSort>,
+        /// Reverse the order of the backups (newest first when sorted)
+        #[clap(long)]
+        reverse: bool,
+        /// Don't list the partial backups
+        #[clap(long)]
+        hide_partial: bool,
+    },
+
+    Chain {
+        /// host
+        host: String,
+        /// backup number, or backup selector (latest, latest-full, 10-1, ...)
+        number: BackupSelector,
+    },
+
+    Log {
+        /// host
+        host: String,
+        /// backup number, or backup selector (latest, latest-full, 10-1, ...)
+        number: BackupSelector,
+    },
+
+    CheckNames {
+        /// host
+        host: String,
+        /// backup number, or backup selector (latest, latest-full, 10-1, ...)
+        number: BackupSelector,
+    },
+
+    PoolStats {
+        /// Print the number of files of each two-level bucket
+        #[clap(long)]
+        buckets: bool,
     },
 
     Mount {
         /// The path to the file to read
         path: String,
+        /// Maximum number of files kept open at the same time
+        #[clap(long, default_value_t = NonZeroUsize::new(256).unwrap())]
+        max_opened_files: NonZeroUsize,
+        /// Access time reported for the files: the modification time (mtime) or the Unix epoch (epoch)
+        #[clap(long, default_value = "mtime")]
+        atime: TimestampSource,
+        /// Change time reported for the files: the modification time (mtime) or the Unix epoch (epoch)
+        #[clap(long, default_value = "mtime")]
+        ctime: TimestampSource,
+        /// Show the files deleted in an incremental backup as empty files without permissions
+        #[clap(long)]
+        show_deleted: bool,
+        /// Hide the partial backups (backups that failed before all the files were transferred)
+        #[clap(long)]
+        hide_partial: bool,
+        /// Add a `.pool` directory where the files of the pool can be opened by their digest (`.pool/<digest>`)
+        #[clap(long)]
+        pool_view: bool,
+        /// Order of the backups in the directory of a host: by name (name) or the most recent first (newest-first)
+        #[clap(long, default_value = "name")]
+        backup_order: BackupOrder,
+        /// Number of listings kept in the cache of the view
+        #[clap(long, default_value_t = NonZeroUsize::new(VIEW_CACHE_SIZE).unwrap())]
+        view_cache_size: NonZeroUsize,
+        /// Number of directories kept in the cache of the file system
+        #[clap(long, default_value_t = NonZeroUsize::new(FS_CACHE_SIZE).unwrap())]
+        fs_cache_size: NonZeroUsize,
+        /// Number of files of the `.pool` directory kept in the cache of the file system
+        #[clap(long, default_value_t = NonZeroUsize::new(POOL_FILES_CACHE_SIZE).unwrap())]
+        pool_files_cache_size: NonZeroUsize,
+        /// Maximum size of the files whose content is kept in memory
+        #[clap(long, default_value_t = SMALL_FILE_SIZE)]
+        small_file_size: u64,
+        /// Total size of the content of the small files kept in memory (0 to disable the cache)
+        #[clap(long, default_value_t = SMALL_FILE_CACHE_SIZE)]
+        small_file_cache_size: usize,
+        /// Number of retries of the reads of the pool failing with a transient error (EIO, ESTALE, timeout, ...)
+        #[clap(long, default_value_t = 0)]
+        retries: u32,
+        /// Time (in milliseconds) waited before the first retry, doubled before each new retry
+        #[clap(long, default_value_t = 100)]
+        retry_backoff: u64,
+        /// Allow the other users (including root) to access the mount (needs `user_allow_other` in
+        /// /etc/fuse.conf when not mounted by root)
+        #[clap(long)]
+        allow_other: bool,
+        /// Owner reported for all the files instead of the uid stored in the backups
+        #[clap(long)]
+        uid: Option<u32>,
+        /// Group reported for all the files instead of the gid stored in the backups
+        #[clap(long)]
+        gid: Option<u32>,
+        /// Name of the mounted file system (shown by mount and df)
+        #[clap(long, default_value = "backuppc")]
+        fsname: String,
+        /// Continue the listing past a corrupt attrib file: the error is logged and the directory is shown as empty
+        #[clap(long)]
+        lenient: bool,
+        /// Scan the attrib file of the inode of each hard link for the inode instead of caching the whole file (for
+        /// the backups with millions of inodes)
+        #[clap(long)]
+        scan_inodes: bool,
     },
+
+    #[cfg(feature = "http")]
+    Serve {
+        /// The address to listen on
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        bind: String,
+        /// Maximum number of bytes of the content of a file served, to guard against decompression bombs (no limit
+        /// by default)
+        #[clap(long)]
+        max_read_size: Option<u64>,
+    },
+}
+
+impl Commands {
+    /// Returns `true` if the command reads the files of the top directory: `pool-path` only computes paths, and `cat`
+    /// or `decode-attribute` can read a local file.
+    fn reads_pool(&self) -> bool {
+        match self {
+            Commands::PoolPath { .. } => false,
+            Commands::Cat { path, host, .. } => host.is_some() || !Path::new(path).exists(),
+            Commands::DecodeAttribute { path, .. } => !Path::new(path).exists(),
+            _ => true,
+        }
+    }
 }
 
-fn attrib_to_stdout<R: Read>(mut reader: &mut R) -> Result<(), Error> {
-    let attrib = AttributeFile::read_from(&mut reader).unwrap();
-    print_ls(attrib.attributes);
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ExportFormat {
+    /// One line per file, with a header line
+    Csv,
+    /// One JSON object per line
+    JsonLines,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BackupSort {
+    /// Sort by backup number
+    Num,
+    /// Sort by start time
+    Time,
+}
+
+fn attrib_to_stdout<R: Read>(
+    mut reader: &mut R,
+    file: &str,
+    raw: bool,
+    clock: &dyn Clock,
+) -> Result<(), Error> {
+    let invalid_attrib = |err| {
+        Error::new(
+            std::io::ErrorKind::InvalidData,
+            InvalidMagicError::with_path(err, file).to_string(),
+        )
+    };
+
+    if raw {
+        let entries = AttributeFile::read_raw_from(&mut reader).map_err(invalid_attrib)?;
+        print_raw_entries(&entries);
+    } else {
+        let attrib = AttributeFile::read_from(&mut reader).map_err(invalid_attrib)?;
+        print_ls(attrib.attributes, None, false, &TimeStyle::default(), clock);
+    }
     Ok(())
 }
 
-fn reader_to_stdout<R: Read>(reader: &mut R) -> Result<(), Error> {
-    let mut buffer = vec![0; CHUNK_SIZE];
+fn print_raw_entries(entries: &[RawEntry]) {
+    for (index, entry) in entries.iter().enumerate() {
+        match &entry.attributes {
+            Ok(attributes) => println!("entry {index}: {}", attributes.name),
+            Err(message) => println!("entry {index}: error: {message}"),
+        }
+        for field in &entry.fields {
+            println!(
+                "  {:#010x} {:<18} {:<24} {}",
+                field.offset,
+                field.name,
+                vec_to_hex_string(&field.bytes),
+                field.value
+            );
+        }
+    }
+}
+
+/// Writes the content of the reader on stdout by chunks of `chunk_size` bytes, compressed with `compress` if given.
+/// The compressed stream is finished before returning.
+fn reader_to_stdout<R: Read>(
+    reader: &mut R,
+    compress: Option<OutputCompression>,
+    chunk_size: usize,
+) -> Result<(), Error> {
+    let mut out = Output::new(compress)?;
+    let mut buffer = vec![0; chunk_size];
     loop {
-        let count = reader.read(&mut buffer).unwrap();
+        let count = reader.read(&mut buffer)?;
         if count == 0 {
-            return Ok(());
+            return out.finish();
         }
-        std::io::stdout().write_all(&buffer[..count]).unwrap();
+        out.write_all(&buffer[..count])?;
+    }
+}
+
+/// Writes the content of the reader on stdout, then the expected digest and the MD5 of the content on stderr.
+///
+/// The MD5 is computed while the content is written, so the content isn't buffered (it is the MD5 of the content
+/// before its compression by `compress`). The expected digest can be followed by the extension bytes of a pool
+/// collision, only its start is compared.
+fn reader_to_stdout_with_digest<R: Read>(
+    reader: &mut R,
+    expected: &str,
+    compress: Option<OutputCompression>,
+    chunk_size: usize,
+) -> Result<(), Error> {
+    let mut reader = DigestReader::new(reader);
+    reader_to_stdout(&mut reader, compress, chunk_size)?;
+
+    let computed = vec_to_hex_string(&reader.digest());
+    eprintln!("expected digest: {expected}");
+    eprintln!("computed digest: {computed}");
+
+    if !expected.to_lowercase().starts_with(&computed) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("The MD5 of the content ({computed}) doesn't match the digest {expected}"),
+        ));
     }
+
+    Ok(())
 }
 
 fn uncompress_to(input_file: &str) -> Result<Box<dyn Read>, Error> {
     let input_file = File::open(input_file)?;
-    Ok(Box::new(BackupPCReader::new(input_file)))
+    Ok(Box::new(BackupPCReader::autodetect(input_file)?))
 }
 
-fn plain_to(input_file: &str) -> Result<Box<dyn Read>, Error> {
-    let input_file = File::open(input_file)?;
-    Ok(Box::new(std::io::BufReader::new(input_file)))
+fn pool_file_to_stdout(pool: &PoolConfig, file_hash: &str) -> Result<Box<dyn Read>, Error> {
+    match read_by_digest_from_pool_roots(
+        pool.source().as_ref(),
+        &pool.topdir,
+        &pool.pool_roots,
+        file_hash,
+    ) {
+        Ok(reader) => Ok(reader),
+        Err(err) => Err(to_io_error(err.as_ref())),
+    }
 }
 
-fn pool_file_to_stdout(topdir: &str, file_hash: &str) -> Result<Box<dyn Read>, Error> {
-    let md5_hash: Vec<u8> = hex_string_to_vec(file_hash);
+/// Converts an error of the library to an `io::Error`, keeping its kind (`InvalidData` for a corrupted file).
+fn to_io_error(err: &(dyn std::error::Error + 'static)) -> Error {
+    let kind = if let Some(err) = err.downcast_ref::<Error>() {
+        err.kind()
+    } else if err.is::<InvalidMagicError>() || err.is::<TruncatedPoolFileError>() {
+        ErrorKind::InvalidData
+    } else if err.is::<Cancelled>() {
+        ErrorKind::Interrupted
+    } else {
+        ErrorKind::Other
+    };
+
+    Error::new(kind, err.to_string())
+}
 
-    match find_file_in_backuppc(topdir, &md5_hash, None) {
-        Ok((file_path, is_compressed)) => {
-            if is_compressed {
-                uncompress_to(&file_path)
-            } else {
-                plain_to(&file_path)
-            }
-        }
-        Err(message) => Err(Error::new(
-            std::io::ErrorKind::InvalidData,
-            message.to_string(),
-        )),
+/// Returns the exit code of a command failing with this error.
+fn exit_code_of(err: &Error) -> u8 {
+    match err.kind() {
+        ErrorKind::NotFound => EXIT_NOT_FOUND,
+        ErrorKind::InvalidData | ErrorKind::UnexpectedEof => EXIT_CORRUPT,
+        ErrorKind::Interrupted => EXIT_CANCELLED,
+        _ => EXIT_ERROR,
     }
 }
 
-fn print_ls(mut attrs: Vec<FileAttributes>) {
-    // Print each elements as the "ls -lsh" command will do.
-    // Data must be aligned
-    // Sorted by name
+/// Cancels the traversals of the view on Ctrl-C (see `BackupPC::set_cancellation_token`), so a long walk of a backup
+/// stops with an error. A second Ctrl-C exits immediately, for a command blocked outside of a traversal.
+fn cancel_on_ctrl_c(view: &BackupPC) -> Result<(), Error> {
+    let token = view.cancellation_token().clone();
+    ctrlc::set_handler(move || {
+        if token.is_cancelled() {
+            std::process::exit(EXIT_CANCELLED.into());
+        }
+        token.cancel();
+    })
+    .map_err(|err| Error::other(err.to_string()))
+}
+
+/// Opens the view of the pool, transcoding the names that aren't valid UTF-8 if asked (see
+/// `BackupPC::set_transcode_names`).
+#[cfg(feature = "encoding")]
+fn open_view(pool: &PoolConfig, config: &BackupPCConfig, transcode_names: bool) -> BackupPC {
+    let mut view = BackupPC::with_pool_config(pool, config);
+    view.set_transcode_names(transcode_names);
+    view
+}
+
+/// Opens the view of the pool (the names can't be transcoded without the `encoding` feature).
+#[cfg(not(feature = "encoding"))]
+fn open_view(pool: &PoolConfig, config: &BackupPCConfig, _transcode_names: bool) -> BackupPC {
+    BackupPC::with_pool_config(pool, config)
+}
+
+fn print_error(err: &Error) {
+    eprintln!("error: {err}");
+}
+
+/// Prints the files as the "ls -ls" command will do, sorted by name.
+///
+/// A first line gives the total of the block counts of the files, and each line starts with the block count of the
+/// file (in 512-byte blocks, as reported by the file system). With `human_readable`, the sizes and the block counts
+/// are printed as `ls -lsh` does (see `util::human_size`). The modification times are formatted with `time_style`, in
+/// the local time zone, as recent or old times relatively to the time of `clock`.
+///
+/// With `pool`, the size of the pool file of each file is printed after its size ("-" for a file without pool
+/// file, "?" for a pool file not found), and the first line is replaced by a last line with the total size of the
+/// files, the total size of their pool files, and the savings of the compression.
+fn print_ls(
+    mut attrs: Vec<FileAttributes>,
+    pool: Option<&PoolConfig>,
+    human_readable: bool,
+    time_style: &TimeStyle,
+    clock: &dyn Clock,
+) {
+    let now = clock.timestamp();
+    let mut logical_total = 0;
+    let mut stored_total = 0;
+    let format_size = |size: u64| {
+        if human_readable {
+            human_size(size)
+        } else {
+            size.to_string()
+        }
+    };
+    let format_blocks = |blocks: u64| {
+        if human_readable {
+            human_size(blocks * 512)
+        } else {
+            blocks.to_string()
+        }
+    };
+
+    // With the sizes of the pool files, the total is printed after the files, with the savings of the compression
+    if pool.is_none() {
+        let total_blocks: u64 = attrs.iter().map(|attr| attr.size / 512).sum();
+        println!("total {}", format_blocks(total_blocks));
+    }
+
     attrs.sort_by(|a, b| a.name.cmp(&b.name));
     for attr in attrs {
+        let stored = pool.map(|pool| {
+            if attr.bpc_digest.len == 0 {
+                return "-".to_string();
+            }
+            match pool::stored_size(
+                pool.source().as_ref(),
+                &pool.topdir,
+                &pool.pool_roots,
+                &attr.bpc_digest.digest,
+            ) {
+                Ok((size, _)) => {
+                    logical_total += attr.size;
+                    stored_total += size;
+                    format_size(size)
+                }
+                Err(err) => {
+                    info!("Can't stat the pool file of {}: {err}", attr.name);
+                    "?".to_string()
+                }
+            }
+        });
+        let stored = stored
+            .map(|stored| format!(" {stored: <10}"))
+            .unwrap_or_default();
+
         // Show the mode in the form drwxr-xr-x with the help of attr.mode and attr.type_
         let mode = match attr.type_ {
             FileType::File | FileType::Hardlink => "-",
@@ -147,26 +663,89 @@ fn print_ls(mut attrs: Vec<FileAttributes>) {
         );
 
         println!(
-            "{} {} {: <5} {: <5} {: <10} {: <12} {} {}",
+            "{: >6} {} {} {: <5} {: <5} {: <10}{} {: <12} {} {}",
+            format_blocks(attr.size / 512),
             mode,
             attr.nlinks,
             attr.uid,
             attr.gid,
-            attr.size,
-            attr.mtime,
-            attr.name,
+            format_size(attr.size),
+            stored,
+            time_style.format_local(attr.mtime, now),
+            attr.display_name(),
             vec_to_hex_string(&attr.bpc_digest.digest)
         );
     }
+
+    if pool.is_some() {
+        let saved = i128::from(logical_total) - i128::from(stored_total);
+        #[allow(clippy::cast_precision_loss)]
+        let percent = if logical_total == 0 {
+            0.0
+        } else {
+            saved as f64 * 100.0 / logical_total as f64
+        };
+        let sign = if saved < 0 { "-" } else { "" };
+        let saved = format_size(u64::try_from(saved.unsigned_abs()).unwrap_or(u64::MAX));
+        let unit = if human_readable { "" } else { " bytes" };
+        println!(
+            "total {}{unit}, {}{unit} on disk, {sign}{saved}{unit} saved ({percent:.1}%)",
+            format_size(logical_total),
+            format_size(stored_total)
+        );
+    }
 }
 
+/// Prints the names of the files, sorted, each one terminated by a NUL character.
+///
+/// The names are written as stored in the backup, so they can be passed as is to `xargs -0`.
+fn print_names0(mut attrs: Vec<FileAttributes>) -> Result<(), Error> {
+    attrs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    for attr in attrs {
+        out.write_all(attr.raw_name())?;
+        out.write_all(b"\0")?;
+    }
+
+    out.flush()
+}
+
+/// Writes a file of a backup to `dest` (or to a file with the same name in `dest` if it is a directory), and applies
+/// the permissions and the modification time of the backup.
+fn get_to_file(
+    view: &BackupPC,
+    host: &str,
+    number: u32,
+    share: &str,
+    path: &str,
+    dest: &Path,
+    metadata: &MetadataOptions,
+) -> Result<(), Error> {
+    let number = number.to_string();
+    let full_path = [host, number.as_str()]
+        .into_iter()
+        .chain(share.split('/'))
+        .chain(path.split('/'))
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<&str>>();
+
+    restore_file(view, &full_path, dest, metadata)
+        .map(|_| ())
+        .map_err(|err| to_io_error(err.as_ref()))
+}
+
+#[allow(clippy::too_many_arguments)]
 fn read_file_to_stdout(
-    search: &dyn SearchTrait,
-    topdir: &str,
+    view: &BackupPC,
+    pool: &PoolConfig,
     hostname: Option<String>,
-    number: Option<u32>,
+    number: Option<BackupSelector>,
     share: Option<String>,
     file: &str,
+    with_digest: bool,
+    compress: Option<OutputCompression>,
+    chunk_size: usize,
 ) -> Result<(), Error> {
     if hostname.is_some() || number.is_some() || share.is_some() {
         let Some(hostname) = hostname else {
@@ -175,12 +754,15 @@ fn read_file_to_stdout(
                 "No host specified",
             ));
         };
-        let Some(backup_number) = number else {
+        let Some(selector) = number else {
             return Err(Error::new(
                 std::io::ErrorKind::InvalidInput,
                 "No backup number specified",
             ));
         };
+        let backup_number = view
+            .resolve_backup(&hostname, &selector)
+            .map_err(|err| to_io_error(err.as_ref()))?;
 
         let Some(share) = share else {
             return Err(Error::new(
@@ -189,60 +771,144 @@ fn read_file_to_stdout(
             ));
         };
 
-        let attrs = search
-            .get_file(&hostname, backup_number, &share, file)
-            .unwrap();
-        if attrs.len() == 1 {
-            if attrs[0].bpc_digest.len > 0 {
-                let hex = vec_to_hex_string(&attrs[0].bpc_digest.digest);
-                info!("Show file with hash {hex}");
-                let mut reader = pool_file_to_stdout(topdir, &hex)?;
-                reader_to_stdout(&mut reader)?;
-            } else {
-                return Err(Error::new(std::io::ErrorKind::InvalidData, "No hash found"));
-            }
+        let backup_number = backup_number.to_string();
+        let path = [hostname.as_str(), backup_number.as_str()]
+            .into_iter()
+            .chain(share.split('/'))
+            .chain(file.split('/'))
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<&str>>();
+        let mut reader = view
+            .read_file_with_meta(&path)
+            .map_err(|err| to_io_error(err.as_ref()))?;
+        let hex = vec_to_hex_string(&reader.digest);
+        info!("Show file with hash {hex}");
+        // A file without content has no digest to check
+        if with_digest && !reader.digest.is_empty() {
+            reader_to_stdout_with_digest(&mut reader, &hex, compress, chunk_size)?;
         } else {
-            return Err(Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Multiple or no files found",
-            ));
+            reader_to_stdout(&mut reader, compress, chunk_size)?;
         }
         return Ok(());
     }
 
     let file_path = std::path::Path::new(&file);
-    let mut reader = if file_path.exists() {
-        uncompress_to(file)?
+    let (mut reader, digest) = if file_path.exists() {
+        let digest = if with_digest {
+            local_pool_file_digest(pool, file_path)?
+        } else {
+            String::new()
+        };
+        (uncompress_to(file)?, digest)
+    } else {
+        (pool_file_to_stdout(pool, file)?, file.to_string())
+    };
+
+    if with_digest {
+        reader_to_stdout_with_digest(&mut reader, &digest, compress, chunk_size)
     } else {
-        pool_file_to_stdout(topdir, file)?
+        reader_to_stdout(&mut reader, compress, chunk_size)
+    }
+}
+
+/// Returns the digest of a local file of the pool.
+///
+/// The files of the pool are named by their digest: the pool file of the digest given by the name is looked up in the
+/// pool, and must be the local file. A renamed file, or a file outside of the pool, has no known digest.
+fn local_pool_file_digest(pool: &PoolConfig, path: &Path) -> Result<String, Error> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let not_in_pool = || {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} is not a file of the pool, its digest is unknown",
+                path.display()
+            ),
+        )
     };
 
-    reader_to_stdout(&mut reader)
+    let digest = pool::parse_digest(&name).map_err(|_| not_in_pool())?;
+    let (pool_path, _) = pool::find_file_in_pool_roots(
+        pool.source().as_ref(),
+        &pool.topdir,
+        &pool.pool_roots,
+        &digest,
+        None,
+    )
+    .map_err(|_| not_in_pool())?;
+    if std::fs::canonicalize(pool_path)? != std::fs::canonicalize(path)? {
+        return Err(not_in_pool());
+    }
+
+    Ok(name)
 }
 
-pub fn read_file_attribute_to_stdout(topdir: &str, file: &str) -> Result<(), Error> {
+pub fn read_file_attribute_to_stdout(
+    pool: &PoolConfig,
+    file: &str,
+    raw: bool,
+    clock: &dyn Clock,
+) -> Result<(), Error> {
     let file_path = std::path::Path::new(&file);
     let mut reader = if file_path.exists() {
         uncompress_to(file)?
     } else {
-        pool_file_to_stdout(topdir, file)?
+        pool_file_to_stdout(pool, file)?
     };
 
-    attrib_to_stdout(&mut reader)
+    attrib_to_stdout(&mut reader, file, raw, clock)
 }
 
-fn main() {
+fn main() -> ExitCode {
     env_logger::init();
 
     let args = Cli::parse();
-    let subcommand = args.subcommand.expect("No subcommand provided");
+    let Some(subcommand) = args.subcommand else {
+        print_error(&Error::new(
+            ErrorKind::InvalidInput,
+            "No subcommand provided",
+        ));
+        return ExitCode::from(EXIT_ERROR);
+    };
 
     let topdir = match env::var("BPC_TOPDIR") {
         Ok(value) => value,
         Err(_) => "/var/lib/backuppc".to_string(),
     };
-    let search = Search::new(&topdir);
-    let hosts = Hosts::new(&topdir);
+
+    let config = BackupPCConfig::default().with_read_chunk_size(args.read_chunk_size.get());
+    let pool = PoolConfig::new(&topdir).with_pool_roots(args.pool_roots);
+    #[cfg(feature = "encoding")]
+    let transcode_names = args.transcode_names;
+    #[cfg(not(feature = "encoding"))]
+    let transcode_names = false;
+    match run(&pool, &config, &SystemClock, transcode_names, subcommand) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            print_error(&err);
+            ExitCode::from(exit_code_of(&err))
+        }
+    }
+}
+
+fn run(
+    pool: &PoolConfig,
+    config: &BackupPCConfig,
+    clock: &dyn Clock,
+    transcode_names: bool,
+    subcommand: Commands,
+) -> Result<(), Error> {
+    let topdir = pool.topdir.as_str();
+    if subcommand.reads_pool() {
+        BackupPC::validate_topdir(pool.source().as_ref(), topdir)
+            .map_err(|err| to_io_error(err.as_ref()))?;
+    }
+
+    let search = Search::with_config(pool);
+    let hosts = Hosts::with_config(pool);
 
     match subcommand {
         Commands::Cat {
@@ -250,53 +916,297 @@ fn main() {
             host,
             number,
             share,
+            with_digest,
+            compress,
         } => {
-            read_file_to_stdout(&search, &topdir, host, number, share, &path).unwrap();
+            read_file_to_stdout(
+                &open_view(pool, config, transcode_names),
+                pool,
+                host,
+                number,
+                share,
+                &path,
+                with_digest,
+                compress,
+                config.read_chunk_size,
+            )?;
+        }
+        Commands::CatHash { digest, compress } => {
+            let mut reader = pool_file_to_stdout(pool, &digest)?;
+            reader_to_stdout(&mut reader, compress, config.read_chunk_size)?;
         }
-        Commands::DecodeAttribute { path } => {
-            read_file_attribute_to_stdout(&topdir, &path).unwrap();
+        Commands::PoolPath { digest } => {
+            let digest = pool::parse_digest(&digest)?;
+            let (pool_path, cpool_path) = pool::bucket_path(topdir, &digest);
+            println!("{}", pool_path.display());
+            println!("{}", cpool_path.display());
+        }
+        Commands::DecodeAttribute { path, raw } => {
+            read_file_attribute_to_stdout(pool, &path, raw, clock)?;
         }
         Commands::Ls {
             host,
             number,
             share,
             path,
+            print0,
+            sizes,
+            human_readable,
+            time_style,
+            help: _,
         } => {
+            let number = number
+                .resolve_for_host(&hosts, &host)
+                .map_err(|err| to_io_error(err.as_ref()))?;
             let attrs = search
                 .list_file_from_dir(&host, number, Some(&share), Some(&path))
-                .unwrap();
-            print_ls(attrs);
+                .map_err(|err| to_io_error(err.as_ref()))?;
+            if print0 {
+                print_names0(attrs)?;
+            } else {
+                print_ls(
+                    attrs,
+                    sizes.then_some(pool),
+                    human_readable,
+                    &time_style,
+                    clock,
+                );
+            }
         }
-        Commands::Hosts {} => {
-            let hosts = hosts.list_hosts();
-            match hosts {
-                Ok(hosts) => {
-                    for host in hosts {
-                        println!("{host}");
-                    }
-                }
-                Err(message) => {
-                    println!("{message}");
-                }
+        Commands::Export {
+            host,
+            number,
+            share,
+            path,
+            format,
+        } => {
+            let view = open_view(pool, config, transcode_names);
+            cancel_on_ctrl_c(&view)?;
+
+            let number = view
+                .resolve_backup(&host, &number)
+                .map_err(|err| to_io_error(err.as_ref()))?;
+            let format = match format {
+                ExportFormat::Csv => export::ExportFormat::Csv,
+                ExportFormat::JsonLines => export::ExportFormat::JsonLines,
+            };
+            let mut out = BufWriter::new(std::io::stdout().lock());
+            export::export(&view, &mut out, &host, number, &share, &path, format)
+                .map_err(|err| to_io_error(err.as_ref()))?;
+        }
+        Commands::Get {
+            host,
+            number,
+            share,
+            path,
+            dest,
+            numeric_ids,
+            no_mode,
+            no_mtime,
+        } => {
+            let view = open_view(pool, config, transcode_names);
+
+            let number = view
+                .resolve_backup(&host, &number)
+                .map_err(|err| to_io_error(err.as_ref()))?;
+            let metadata = MetadataOptions {
+                ownership: if numeric_ids {
+                    Ownership::Numeric
+                } else {
+                    Ownership::CurrentUser
+                },
+                apply_mode: !no_mode,
+                apply_mtime: !no_mtime,
+            };
+            get_to_file(&view, &host, number, &share, &path, &dest, &metadata)?;
+        }
+        Commands::Hosts { user, conf_dir } => {
+            let hosts = match user {
+                Some(user) => hosts.list_hosts_for_user(&conf_dir, &user),
+                None => hosts.list_hosts(),
+            }
+            .map_err(|err| to_io_error(err.as_ref()))?;
+            for host in hosts {
+                println!("{host}");
             }
         }
-        Commands::Backups { host } => {
-            let backups = hosts.list_backups(&host);
-            match backups {
-                Ok(backups) => {
-                    for backup in backups {
-                        println!("{}", backup.num);
-                    }
-                }
-                Err(message) => {
-                    println!("{message}");
+        Commands::Backups {
+            host,
+            selector: Some(selector),
+            ..
+        } => {
+            let number = selector
+                .resolve_for_host(&hosts, &host)
+                .map_err(|err| to_io_error(err.as_ref()))?;
+            println!("{number}");
+        }
+        Commands::Backups {
+            host,
+            selector: None,
+            backup_type,
+            sort,
+            reverse,
+            hide_partial,
+        } => {
+            let mut backups = hosts
+                .list_backups(&host)
+                .map_err(|err| to_io_error(err.as_ref()))?;
+            if let Some(backup_type) = backup_type {
+                backups.retain(|backup| backup.backup_type == backup_type);
+            }
+            if hide_partial {
+                backups.retain(|backup| !backup.is_partial());
+            }
+            match sort {
+                Some(BackupSort::Num) => backups.sort_by_key(|backup| backup.num),
+                Some(BackupSort::Time) => backups.sort_by_key(|backup| backup.start_time),
+                None => {}
+            }
+            if reverse {
+                backups.reverse();
+            }
+
+            for backup in backups {
+                println!("{}", backup.num);
+            }
+        }
+        Commands::Chain { host, number } => {
+            let number = number
+                .resolve_for_host(&hosts, &host)
+                .map_err(|err| to_io_error(err.as_ref()))?;
+            let chain = hosts
+                .list_backups_to_fill(&host, number)
+                .map_err(|err| to_io_error(err.as_ref()))?;
+            for backup in chain {
+                let fill = if backup.no_fill == 0 {
+                    "filled"
+                } else {
+                    "unfilled"
+                };
+                println!(
+                    "{}\t{}\t{fill}\t{}",
+                    backup.num, backup.backup_type, backup.fill_from_num
+                );
+            }
+        }
+        Commands::Log { host, number } => {
+            let number = number
+                .resolve_for_host(&hosts, &host)
+                .map_err(|err| to_io_error(err.as_ref()))?;
+            let mut reader = hosts
+                .read_xfer_log(&host, number)
+                .map_err(|err| to_io_error(err.as_ref()))?;
+            reader_to_stdout(&mut reader, None, config.read_chunk_size)?;
+        }
+        Commands::CheckNames { host, number } => {
+            let view = open_view(pool, config, transcode_names);
+            cancel_on_ctrl_c(&view)?;
+
+            let number = view
+                .resolve_backup(&host, &number)
+                .map_err(|err| to_io_error(err.as_ref()))?;
+            let names = view
+                .list_non_utf8_names(&host, number)
+                .map_err(|err| to_io_error(err.as_ref()))?;
+            for (dir, name) in names {
+                println!(
+                    "{dir}/{}\t{}",
+                    String::from_utf8_lossy(&name),
+                    vec_to_hex_string(&name)
+                );
+            }
+        }
+        Commands::PoolStats { buckets } => {
+            let stats = pool::scan_pool(topdir).map_err(|err| to_io_error(err.as_ref()))?;
+            println!("Files: {}", stats.files);
+            println!("Size: {}", stats.size);
+            println!(
+                "Compressed: {} files, {} bytes",
+                stats.compressed_files, stats.compressed_size
+            );
+            println!(
+                "Uncompressed: {} files, {} bytes",
+                stats.uncompressed_files, stats.uncompressed_size
+            );
+            println!("Buckets: {}", stats.buckets.len());
+            println!("Errors: {}", stats.errors);
+            if buckets {
+                for (bucket, count) in stats.buckets {
+                    println!("{bucket}\t{count}");
                 }
             }
         }
-        Commands::Mount { path } => {
-            let options = [];
+        Commands::Mount {
+            path,
+            max_opened_files,
+            atime,
+            ctime,
+            show_deleted,
+            hide_partial,
+            pool_view,
+            backup_order,
+            view_cache_size,
+            fs_cache_size,
+            pool_files_cache_size,
+            small_file_size,
+            small_file_cache_size,
+            retries,
+            retry_backoff,
+            allow_other,
+            uid,
+            gid,
+            fsname,
+            lenient,
+            scan_inodes,
+        } => {
+            let mut options = vec![MountOption::RO, MountOption::FSName(fsname)];
+            if allow_other {
+                options.push(MountOption::AllowOther);
+            }
+            let fs_options = FilesystemOptions {
+                max_opened_files: max_opened_files.get(),
+                atime,
+                ctime,
+                show_deleted,
+                hide_partial,
+                pool_view,
+                backup_order,
+                uid,
+                gid,
+                lenient,
+                scan_inodes,
+                #[cfg(feature = "encoding")]
+                transcode_names,
+                config: config
+                    .clone()
+                    .with_view_cache_size(view_cache_size.get())
+                    .with_fs_cache_size(fs_cache_size.get())
+                    .with_pool_files_cache_size(pool_files_cache_size.get())
+                    .with_small_file_size(small_file_size)
+                    .with_small_file_cache_size(small_file_cache_size),
+            };
+            let retry = RetryPolicy::new(retries, Duration::from_millis(retry_backoff));
+            let pool = pool.clone().with_retry(retry);
+
+            fuser::mount2(
+                BackupPCFS::with_pool_config(&pool, fs_options),
+                path,
+                &options,
+            )?;
+        }
+        #[cfg(feature = "http")]
+        Commands::Serve {
+            bind,
+            max_read_size,
+        } => {
+            let mut view = open_view(pool, config, transcode_names);
+            view.set_max_read_size(max_read_size);
+            cancel_on_ctrl_c(&view)?;
 
-            fuser::mount2(BackupPCFS::new(&topdir), path, &options).unwrap();
+            backuppc_pool_reader::http::serve(SharedBackupPC::new(view), &bind)
+                .map_err(|err| to_io_error(err.as_ref()))?;
         }
     }
+
+    Ok(())
 }
@@ -1,9 +1,18 @@
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
 
-use log::debug;
+use log::{debug, warn};
 
+use crate::compress::{is_compressed_header, BackupPCReader, ZSTD_MAGIC};
+use crate::source::{LocalSource, PoolSource};
 use crate::util;
 
+/// Minimal length of a digest in bytes (a MD5 digest).
+const MIN_DIGEST_LEN: usize = 16;
+
 /// Finds a file in the `BackupPC` pool directory based on its file hash.
 ///
 /// The function takes the top directory path, the file hash as a vector of bytes,
@@ -59,39 +68,137 @@ pub fn find_file_in_backuppc(
     file_hash: &[u8],
     collid: Option<u64>,
 ) -> Result<(String, bool), String> {
-    if file_hash.len() < 2 {
-        return Err(format!(
-            "File hash {} must be at least 2 bytes long",
-            util::vec_to_hex_string(file_hash)
+    find_file_in_source(&LocalSource, topdir, file_hash, collid)
+}
+
+/// Parses the digest of a file of the pool, given as an hexadecimal string.
+///
+/// # Arguments
+///
+/// * `digest` - The digest of the file as an hexadecimal string.
+///
+/// # Returns
+///
+/// The bytes of the digest.
+///
+/// # Errors
+///
+/// An error of kind `InvalidInput` if the digest isn't a valid hexadecimal string or is shorter than a MD5 digest.
+///
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::pool::parse_digest;
+///
+/// assert_eq!(parse_digest("0123456789abcdef0123456789abcdef").unwrap()[..2], [0x01, 0x23]);
+/// assert!(parse_digest("0123").is_err());
+/// ```
+pub fn parse_digest(digest: &str) -> std::io::Result<Vec<u8>> {
+    if digest.len() % 2 == 1 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Digest {digest} is not a valid hexadecimal string"),
+        ));
+    }
+    if digest.len() < MIN_DIGEST_LEN * 2 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Digest {digest} must be at least {MIN_DIGEST_LEN} bytes long"),
         ));
     }
 
-    let firsts = format!("{:02x}", (file_hash[0] & 0xfe));
-    let seconds = format!("{:02x}", (file_hash[1] & 0xfe));
-    let file_hash = util::vec_to_hex_string(file_hash);
+    util::try_hex_string_to_vec(digest)
+}
+
+/// Returns the paths where a file of the pool is stored, in the pool and in the cpool directories, whether the file
+/// exists or not.
+///
+/// The file is stored in the bucket `<digest[0] & 0xfe>/<digest[1] & 0xfe>` of the directory, and is named with the
+/// digest in hexadecimal.
+///
+/// # Arguments
+///
+/// * `topdir` - The top directory path where the `BackupPC` pool is located.
+/// * `digest` - The digest of the file.
+///
+/// # Returns
+///
+/// The path of the file in the pool directory, and its path in the cpool directory.
+///
+/// # Panics
+///
+/// If the digest is shorter than 2 bytes (see `parse_digest` to validate a digest).
+///
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::pool::bucket_path;
+/// use std::path::Path;
+///
+/// let (pool, cpool) = bucket_path("/var/lib/backuppc", &[0x01, 0x23, 0x45]);
+/// assert_eq!(pool, Path::new("/var/lib/backuppc/pool/00/22/012345"));
+/// assert_eq!(cpool, Path::new("/var/lib/backuppc/cpool/00/22/012345"));
+/// ```
+#[must_use]
+pub fn bucket_path(topdir: &str, digest: &[u8]) -> (PathBuf, PathBuf) {
+    bucket_path_with_collid(topdir, digest, None)
+}
+
+fn bucket_path_with_collid(topdir: &str, digest: &[u8], collid: Option<u64>) -> (PathBuf, PathBuf) {
+    let firsts = format!("{:02x}", (digest[0] & 0xfe));
+    let seconds = format!("{:02x}", (digest[1] & 0xfe));
+    let file_hash = util::vec_to_hex_string(digest);
     let collid = match collid {
         Some(collid) => format!("{collid:02x}"),
         None => String::new(),
     };
     let file_hash = format!("{collid}{file_hash}");
 
-    let pool_path = Path::new(topdir)
-        .join("pool")
-        .join(&firsts)
-        .join(&seconds)
-        .join(&file_hash);
+    let bucket = |dir: &str| {
+        Path::new(topdir)
+            .join(dir)
+            .join(&firsts)
+            .join(&seconds)
+            .join(&file_hash)
+    };
+
+    (bucket("pool"), bucket("cpool"))
+}
+
+/// Finds a file in the `BackupPC` pool directory of a pool source (see `find_file_in_backuppc`).
+///
+/// # Arguments
+///
+/// * `source` - The source used to access the files of the top directory.
+/// * `topdir` - The top directory path where the `BackupPC` pool is located.
+/// * `file_hash` - The file hash as a vector of bytes.
+/// * `collid` - An optional collision ID.
+///
+/// # Errors
+///
+/// - If the file hash is less than 2 bytes long, an error message is returned.
+/// - If the file is not found in the pool or cpool directory, an error message is returned.
+pub fn find_file_in_source(
+    source: &dyn PoolSource,
+    topdir: &str,
+    file_hash: &[u8],
+    collid: Option<u64>,
+) -> Result<(String, bool), String> {
+    if file_hash.len() < 2 {
+        return Err(format!(
+            "File hash {} must be at least 2 bytes long",
+            util::vec_to_hex_string(file_hash)
+        ));
+    }
 
-    let cpool_path = Path::new(topdir)
-        .join("cpool")
-        .join(&firsts)
-        .join(&seconds)
-        .join(&file_hash);
+    let (pool_path, cpool_path) = bucket_path_with_collid(topdir, file_hash, collid);
+    let file_hash = pool_path.file_name().unwrap_or_default().to_string_lossy();
 
-    if pool_path.exists() {
+    if source.exists(&pool_path) {
         debug!("Found file in pool: {:?}", pool_path);
         let path = pool_path.to_str().ok_or("pool path not exists")?;
         Ok((path.to_string(), false))
-    } else if cpool_path.exists() {
+    } else if source.exists(&cpool_path) {
         debug!("Found file in cpool: {:?}", cpool_path);
         let path = cpool_path.to_str().ok_or("cpool path not exists")?;
         Ok((path.to_string(), true))
@@ -100,3 +207,581 @@ pub fn find_file_in_backuppc(
         Err(format!("File {file_hash} does not exist"))
     }
 }
+
+/// Finds a file in the pool of the top directory, then in each additional pool root (see `find_file_in_source`).
+///
+/// Large installations can split the pool across several physical locations: each pool root is a directory
+/// containing a `pool` and/or a `cpool` directory, laid out as the ones of the top directory. The roots are searched
+/// in order, and the first file found is returned.
+///
+/// # Arguments
+///
+/// * `source` - The source used to access the files of the pool.
+/// * `topdir` - The top directory path where the `BackupPC` pool is located.
+/// * `pool_roots` - The additional pool roots, searched after the top directory.
+/// * `file_hash` - The file hash as a vector of bytes.
+/// * `collid` - An optional collision ID.
+///
+/// # Errors
+///
+/// - If the file hash is less than 2 bytes long, an error message is returned.
+/// - If the file is not found in any pool root, the error message of the last pool root is returned.
+pub fn find_file_in_pool_roots(
+    source: &dyn PoolSource,
+    topdir: &str,
+    pool_roots: &[PathBuf],
+    file_hash: &[u8],
+    collid: Option<u64>,
+) -> Result<(String, bool), String> {
+    let mut result = find_file_in_source(source, topdir, file_hash, collid);
+    for pool_root in pool_roots {
+        if result.is_ok() {
+            break;
+        }
+        result = find_file_in_source(source, &pool_root.to_string_lossy(), file_hash, collid);
+    }
+
+    result
+}
+
+/// Returns `true` if the first bytes of a file of the pool are the header of a compressed content.
+///
+/// The header of a zlib file, checked with its second byte (see `compress::is_compressed_header`), and the zstd
+/// magic are compressed headers.
+///
+/// # Arguments
+///
+/// * `header` - The first bytes of the file (at least 2 bytes, unless the file is shorter).
+///
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::pool::has_compressed_header;
+///
+/// assert!(has_compressed_header(&[0x78, 0x9c]));
+/// assert!(has_compressed_header(&[0xd7, 0x01]));
+/// assert!(!has_compressed_header(b"x = 1"));
+/// assert!(!has_compressed_header(b""));
+/// ```
+#[must_use]
+pub fn has_compressed_header(header: &[u8]) -> bool {
+    header.starts_with(&ZSTD_MAGIC) || is_compressed_header(header)
+}
+
+/// Checks if a file of the pool is compressed.
+///
+/// A file of the pool directory is always stored as is: its content isn't read, as any plain file can start with a
+/// compressed header (a git object, a `.zst` file, ...). A file of the cpool directory can be stored uncompressed,
+/// so its compression is checked from its first bytes (see `has_compressed_header`).
+///
+/// # Arguments
+///
+/// * `source` - The source used to access the files of the pool.
+/// * `path` - The path of the file.
+/// * `in_cpool` - `true` if the file is in the cpool directory (see `find_file_in_pool_roots`).
+///
+/// # Errors
+///
+/// If the file of the cpool directory can't be opened or read.
+pub fn is_compressed(
+    source: &dyn PoolSource,
+    path: &Path,
+    in_cpool: bool,
+) -> std::io::Result<bool> {
+    if !in_cpool {
+        return Ok(false);
+    }
+
+    let mut header = Vec::with_capacity(ZSTD_MAGIC.len());
+    source
+        .open(path)?
+        .take(ZSTD_MAGIC.len() as u64)
+        .read_to_end(&mut header)?;
+
+    Ok(has_compressed_header(&header))
+}
+
+/// Returns a reader on the content of a file of the pool.
+///
+/// The content of a file of the pool directory is returned as is. The content of a file of the cpool directory is
+/// decompressed if its first bytes are a compressed header (see `has_compressed_header`), so a file stored
+/// uncompressed in the cpool directory is read correctly.
+///
+/// # Arguments
+///
+/// * `reader` - The reader on the file of the pool.
+/// * `in_cpool` - `true` if the file is in the cpool directory (see `find_file_in_pool_roots`).
+///
+/// # Errors
+///
+/// If the first bytes of the file can't be read, or if the content is compressed with zstd and the `zstd` feature
+/// is not enabled.
+pub fn open_content<R: Read + Send + Sync + 'static>(
+    reader: R,
+    in_cpool: bool,
+) -> std::io::Result<Box<dyn Read + Send + Sync>> {
+    let mut reader = BufReader::new(reader);
+    if !in_cpool {
+        return Ok(Box::new(reader));
+    }
+
+    if has_compressed_header(reader.fill_buf()?) {
+        Ok(Box::new(BackupPCReader::autodetect(reader)?))
+    } else {
+        debug!("File of the cpool directory stored uncompressed");
+        Ok(Box::new(reader))
+    }
+}
+
+/// Returns the exact size of the content of a pool file.
+///
+/// The content of the file is read through the source (see `open_content`), and discarded while counting the
+/// bytes: this is O(n) in the size of the file, so it should only be used as a fallback when the size stored in the
+/// attributes of the file is zero or not reliable.
+///
+/// # Arguments
+///
+/// * `source` - The source used to access the files of the pool.
+/// * `path` - The path of the file in the pool (see `find_file_in_pool_roots`).
+/// * `in_cpool` - `true` if the file is in the cpool directory.
+///
+/// # Returns
+///
+/// The size of the (uncompressed) content of the file.
+///
+/// # Errors
+///
+/// If the file can't be read, or if its content can't be decompressed.
+pub fn decompressed_size(
+    source: &dyn PoolSource,
+    path: &Path,
+    in_cpool: bool,
+) -> std::io::Result<u64> {
+    let mut reader = open_content(source.open(path)?, in_cpool)?;
+
+    std::io::copy(&mut reader, &mut std::io::sink())
+}
+
+/// Returns the size taken on disk by a file of the pool (the size of the compressed file for the cpool).
+///
+/// # Arguments
+///
+/// * `source` - The source used to access the files of the pool.
+/// * `topdir` - The top directory path where the `BackupPC` pool is located.
+/// * `pool_roots` - The additional pool roots, searched after the top directory (see `find_file_in_pool_roots`).
+/// * `file_hash` - The digest of the file.
+///
+/// # Returns
+///
+/// The size of the file of the pool, and `true` if the file is in the cpool directory.
+///
+/// # Errors
+///
+/// An error of kind `NotFound` if the file is not in the pool, or the error of the `stat` of the file.
+pub fn stored_size(
+    source: &dyn PoolSource,
+    topdir: &str,
+    pool_roots: &[PathBuf],
+    file_hash: &[u8],
+) -> std::io::Result<(u64, bool)> {
+    let (path, is_compressed) =
+        find_file_in_pool_roots(source, topdir, pool_roots, file_hash, None)
+            .map_err(|message| std::io::Error::new(std::io::ErrorKind::NotFound, message))?;
+
+    Ok((source.file_size(Path::new(&path))?, is_compressed))
+}
+
+/// Opens a file of the pool directly by its digest, without resolving any path of a backup.
+///
+/// The digest is validated before looking for the file. If the file is found in the cpool directory, the content
+/// is uncompressed while reading (see `open_content`).
+///
+/// # Arguments
+///
+/// * `topdir` - The top directory path where the `BackupPC` pool is located.
+/// * `digest` - The digest of the file as an hexadecimal string.
+///
+/// # Returns
+///
+/// A boxed reader on the (uncompressed) content of the file.
+///
+/// # Errors
+///
+/// - If the digest isn't a valid hexadecimal string or is shorter than a MD5 digest.
+/// - If the file is not found in the pool or cpool directory.
+/// - If the file can't be opened.
+pub fn read_by_digest(topdir: &str, digest: &str) -> util::Result<Box<dyn Read + Sync + Send>> {
+    read_by_digest_from_source(&LocalSource, topdir, digest)
+}
+
+/// Opens a file of the pool of a pool source directly by its digest (see `read_by_digest`).
+///
+/// # Arguments
+///
+/// * `source` - The source used to access the files of the top directory.
+/// * `topdir` - The top directory path where the `BackupPC` pool is located.
+/// * `digest` - The digest of the file as an hexadecimal string.
+///
+/// # Errors
+///
+/// - If the digest isn't a valid hexadecimal string or is shorter than a MD5 digest.
+/// - If the file is not found in the pool or cpool directory.
+/// - If the file can't be opened.
+pub fn read_by_digest_from_source(
+    source: &dyn PoolSource,
+    topdir: &str,
+    digest: &str,
+) -> util::Result<Box<dyn Read + Sync + Send>> {
+    read_by_digest_from_pool_roots(source, topdir, &[], digest)
+}
+
+/// Opens a file of the pool directly by its digest, looking for it in the top directory, then in each additional
+/// pool root (see `find_file_in_pool_roots`).
+///
+/// # Arguments
+///
+/// * `source` - The source used to access the files of the pool.
+/// * `topdir` - The top directory path where the `BackupPC` pool is located.
+/// * `pool_roots` - The additional pool roots, searched after the top directory.
+/// * `digest` - The digest of the file as an hexadecimal string.
+///
+/// # Errors
+///
+/// - If the digest isn't a valid hexadecimal string or is shorter than a MD5 digest.
+/// - If the file is not found in any pool root.
+/// - If the file can't be opened.
+pub fn read_by_digest_from_pool_roots(
+    source: &dyn PoolSource,
+    topdir: &str,
+    pool_roots: &[PathBuf],
+    digest: &str,
+) -> util::Result<Box<dyn Read + Sync + Send>> {
+    let file_hash = parse_digest(digest)?;
+    let (file_path, in_cpool) =
+        find_file_in_pool_roots(source, topdir, pool_roots, &file_hash, None)
+            .map_err(|message| std::io::Error::new(std::io::ErrorKind::NotFound, message))?;
+
+    Ok(open_content(source.open(Path::new(&file_path))?, in_cpool)?)
+}
+
+/// Error returned when a pool file is empty while the file referencing it is not.
+///
+/// An interrupted backup can leave a zero-length file in the pool. Without this error, the content of the file would
+/// be read as empty, which can't be distinguished from a file that is really empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruncatedPoolFileError {
+    /// The path of the pool file.
+    pub path: PathBuf,
+    /// The size of the file referencing the pool file, from its attributes.
+    pub expected_size: u64,
+}
+
+impl fmt::Display for TruncatedPoolFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Pool file {} is empty but {} bytes are expected: truncated pool file",
+            self.path.display(),
+            self.expected_size
+        )
+    }
+}
+
+impl Error for TruncatedPoolFileError {}
+
+/// Aggregated statistics of the files of the pool.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Number of files in the pool and cpool directories.
+    pub files: u64,
+    /// Size of the files on disk, in bytes.
+    pub size: u64,
+    /// Number of files in the cpool directory.
+    pub compressed_files: u64,
+    /// Size of the files of the cpool directory on disk, in bytes.
+    pub compressed_size: u64,
+    /// Number of files in the pool directory.
+    pub uncompressed_files: u64,
+    /// Size of the files of the pool directory on disk, in bytes.
+    pub uncompressed_size: u64,
+    /// Number of files of each two-level bucket (ex: `cpool/7e/1a`).
+    pub buckets: BTreeMap<String, u64>,
+    /// Number of directories or files that couldn't be read.
+    pub errors: u64,
+}
+
+/// Scans the pool and cpool directories and aggregates the statistics of their files.
+///
+/// The scan is independent of any host. Directories or files that can't be read are logged, counted in
+/// `PoolStats::errors`, and skipped.
+///
+/// # Arguments
+///
+/// * `topdir` - The top directory path where the `BackupPC` pool is located.
+///
+/// # Returns
+///
+/// The statistics of the pool.
+///
+/// # Errors
+///
+/// If neither the pool nor the cpool directory can be read.
+pub fn scan_pool(topdir: &str) -> util::Result<PoolStats> {
+    let mut stats = PoolStats::default();
+    let mut scanned = false;
+
+    for (pool, is_compressed) in [("pool", false), ("cpool", true)] {
+        let pool_dir = Path::new(topdir).join(pool);
+        let Ok(firsts) = std::fs::read_dir(&pool_dir) else {
+            debug!("Can't read pool directory {pool_dir:?}");
+            continue;
+        };
+        scanned = true;
+
+        for first in firsts {
+            let Some(first) = scan_entry(first, &mut stats) else {
+                continue;
+            };
+            let Ok(seconds) = std::fs::read_dir(first.path()) else {
+                scan_error(&first.path(), &mut stats);
+                continue;
+            };
+
+            for second in seconds {
+                let Some(second) = scan_entry(second, &mut stats) else {
+                    continue;
+                };
+                let Ok(files) = std::fs::read_dir(second.path()) else {
+                    scan_error(&second.path(), &mut stats);
+                    continue;
+                };
+
+                let bucket = format!(
+                    "{pool}/{}/{}",
+                    first.file_name().to_string_lossy(),
+                    second.file_name().to_string_lossy()
+                );
+                for file in files {
+                    let Some(metadata) = scan_entry(file, &mut stats)
+                        .and_then(|file| file.metadata().ok())
+                        .filter(std::fs::Metadata::is_file)
+                    else {
+                        continue;
+                    };
+
+                    stats.files += 1;
+                    stats.size += metadata.len();
+                    if is_compressed {
+                        stats.compressed_files += 1;
+                        stats.compressed_size += metadata.len();
+                    } else {
+                        stats.uncompressed_files += 1;
+                        stats.uncompressed_size += metadata.len();
+                    }
+                    *stats.buckets.entry(bucket.clone()).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    if !scanned {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("No pool or cpool directory in {topdir}"),
+        )
+        .into());
+    }
+
+    Ok(stats)
+}
+
+fn scan_entry(
+    entry: std::io::Result<std::fs::DirEntry>,
+    stats: &mut PoolStats,
+) -> Option<std::fs::DirEntry> {
+    match entry {
+        Ok(entry) => Some(entry),
+        Err(err) => {
+            warn!("Error reading pool entry: {err}");
+            stats.errors += 1;
+            None
+        }
+    }
+}
+
+fn scan_error(path: &Path, stats: &mut PoolStats) {
+    warn!("Error reading pool directory {path:?}");
+    stats.errors += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_pool() {
+        let topdir = std::env::temp_dir().join(format!("bpc_scan_pool_{}", std::process::id()));
+        let bucket = topdir.join("cpool").join("10").join("12");
+        std::fs::create_dir_all(&bucket).unwrap();
+        std::fs::write(bucket.join("10121314"), [0u8; 10]).unwrap();
+        std::fs::write(bucket.join("10131415"), [0u8; 5]).unwrap();
+        let bucket = topdir.join("pool").join("20").join("22");
+        std::fs::create_dir_all(&bucket).unwrap();
+        std::fs::write(bucket.join("20222324"), [0u8; 7]).unwrap();
+
+        let stats = scan_pool(topdir.to_str().unwrap()).unwrap();
+
+        assert_eq!(stats.files, 3);
+        assert_eq!(stats.size, 22);
+        assert_eq!(stats.compressed_files, 2);
+        assert_eq!(stats.compressed_size, 15);
+        assert_eq!(stats.uncompressed_files, 1);
+        assert_eq!(stats.uncompressed_size, 7);
+        assert_eq!(stats.buckets.get("cpool/10/12"), Some(&2));
+        assert_eq!(stats.buckets.get("pool/20/22"), Some(&1));
+        assert_eq!(stats.errors, 0);
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_find_file_in_pool_roots() {
+        let base = std::env::temp_dir().join(format!("bpc_pool_roots_{}", std::process::id()));
+        let topdir = base.join("topdir");
+        let roots = vec![base.join("first"), base.join("second")];
+        std::fs::create_dir_all(topdir.join("cpool")).unwrap();
+        let bucket = roots[1].join("cpool").join("10").join("10");
+        std::fs::create_dir_all(&bucket).unwrap();
+        let digest: Vec<u8> = (0x10..0x20).collect();
+        let hex = util::vec_to_hex_string(&digest);
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, b"content").unwrap();
+        std::fs::write(bucket.join(&hex), encoder.finish().unwrap()).unwrap();
+
+        let topdir = topdir.to_str().unwrap();
+        assert!(find_file_in_source(&LocalSource, topdir, &digest, None).is_err());
+        let (path, is_compressed) =
+            find_file_in_pool_roots(&LocalSource, topdir, &roots, &digest, None).unwrap();
+        assert_eq!(Path::new(&path), bucket.join(&hex));
+        assert!(is_compressed);
+
+        let mut content = String::new();
+        read_by_digest_from_pool_roots(&LocalSource, topdir, &roots, &hex)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "content");
+        assert!(read_by_digest_from_pool_roots(&LocalSource, topdir, &roots[..1], &hex).is_err());
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn test_compression_detected_from_content() {
+        use flate2::write::ZlibEncoder;
+        use std::io::Write;
+
+        let topdir = std::env::temp_dir().join(format!("bpc_detect_{}", std::process::id()));
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"compressed").unwrap();
+        let compressed = encoder.finish().unwrap();
+        // A plain file with a zlib header in the pool directory, a plain file starting with `x` and a compressed file
+        // in the cpool directory
+        let files = [
+            (
+                "pool",
+                (0x10..0x20).collect::<Vec<u8>>(),
+                compressed.clone(),
+            ),
+            ("cpool", (0x20..0x30).collect(), b"x plain".to_vec()),
+            ("cpool", (0x30..0x40).collect(), compressed.clone()),
+        ];
+        let topdir_str = topdir.to_str().unwrap();
+        for (pool, digest, content) in &files {
+            let (pool_path, cpool_path) = bucket_path(topdir_str, digest);
+            let path = if *pool == "pool" {
+                pool_path
+            } else {
+                cpool_path
+            };
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        let read = |digest: &[u8]| {
+            let mut content = Vec::new();
+            read_by_digest(topdir_str, &util::vec_to_hex_string(digest))
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            content
+        };
+        assert_eq!(read(&files[0].1), compressed);
+        // "x " isn't a zlib header (the second byte has the flag of a preset dictionary)
+        assert_eq!(read(&files[1].1), b"x plain");
+        assert_eq!(read(&files[2].1), b"compressed");
+
+        let compression = |digest: &[u8]| {
+            let (path, in_cpool) = find_file_in_backuppc(topdir_str, digest, None).unwrap();
+            is_compressed(&LocalSource, Path::new(&path), in_cpool).unwrap()
+        };
+        assert!(!compression(&files[0].1));
+        assert!(!compression(&files[1].1));
+        assert!(compression(&files[2].1));
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[test]
+    fn test_decompressed_size() {
+        use flate2::write::ZlibEncoder;
+        use std::io::Write;
+
+        let dir =
+            std::env::temp_dir().join(format!("bpc_decompressed_size_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&[b'a'; 10_000]).unwrap();
+        std::fs::write(dir.join("compressed"), encoder.finish().unwrap()).unwrap();
+        std::fs::write(dir.join("uncompressed"), [0u8; 7]).unwrap();
+
+        let size =
+            |name: &str, in_cpool: bool| decompressed_size(&LocalSource, &dir.join(name), in_cpool);
+        assert_eq!(size("compressed", true).unwrap(), 10_000);
+        assert_eq!(size("uncompressed", false).unwrap(), 7);
+        // A file of the cpool directory stored uncompressed
+        assert_eq!(size("uncompressed", true).unwrap(), 7);
+        assert!(size("missing", true).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stored_size() {
+        let topdir = std::env::temp_dir().join(format!("bpc_stored_size_{}", std::process::id()));
+        let digest: Vec<u8> = (0x10..0x20).collect();
+        let cpool_dir = topdir.join("cpool").join("10").join("10");
+        std::fs::create_dir_all(&cpool_dir).unwrap();
+        std::fs::write(cpool_dir.join(util::vec_to_hex_string(&digest)), [0u8; 12]).unwrap();
+
+        let topdir_str = topdir.to_str().unwrap();
+        assert_eq!(
+            stored_size(&LocalSource, topdir_str, &[], &digest).unwrap(),
+            (12, true)
+        );
+        assert_eq!(
+            stored_size(&LocalSource, topdir_str, &[], &[0x20; 16])
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::NotFound
+        );
+
+        std::fs::remove_dir_all(&topdir).unwrap();
+    }
+
+    #[test]
+    fn test_scan_pool_without_pool() {
+        assert!(scan_pool("/nonexistent/backuppc").is_err());
+    }
+}
@@ -0,0 +1,353 @@
+use log::warn;
+use std::io::{self, Read};
+
+/// Maximum number of bytes reserved in advance by `read_all_to_vec`: the size hint comes from the attributes of the
+/// file, which can't be trusted to reserve an arbitrary amount of memory.
+const MAX_PREALLOCATION: u64 = 64 * 1024 * 1024;
+
+/// Reads the whole content of a reader, reserving the expected size of the content first.
+///
+/// `Read::read_to_end` grows its buffer as the content is read, reallocating and copying it several times for a large
+/// content. When the size of the content is known (the size stored in the attributes of a file), the buffer is
+/// allocated once. The reservation is capped to 64 MiB, and the content can be shorter or longer than the hint.
+///
+/// ```
+/// use backuppc_pool_reader::reader::read_all_to_vec;
+///
+/// let content = read_all_to_vec(&b"content"[..], 7).unwrap();
+/// assert_eq!(content, b"content");
+/// assert_eq!(content.capacity(), 7);
+/// ```
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read to its end.
+/// * `size_hint` - The expected size of the content.
+///
+/// # Returns
+///
+/// The content of the reader.
+///
+/// # Errors
+///
+/// If the reader fails.
+pub fn read_all_to_vec<R: Read>(mut reader: R, size_hint: u64) -> io::Result<Vec<u8>> {
+    let capacity = usize::try_from(size_hint.min(MAX_PREALLOCATION)).unwrap_or_default();
+    let mut content = Vec::with_capacity(capacity);
+    reader.read_to_end(&mut content)?;
+
+    Ok(content)
+}
+
+/// Skips the first bytes of a reader, reading and discarding them by chunks.
+///
+/// The content of a compressed file can't be seeked: the bytes before an offset are decompressed and discarded.
+///
+/// ```
+/// use backuppc_pool_reader::reader::skip;
+/// use std::io::Read;
+///
+/// let mut reader = &b"0123456789"[..];
+/// assert_eq!(skip(&mut reader, 4, 3).unwrap(), 4);
+/// assert_eq!(reader, b"456789");
+/// assert_eq!(skip(&mut reader, 10, 3).unwrap(), 6);
+/// ```
+///
+/// # Arguments
+///
+/// * `reader` - The reader to skip the bytes of.
+/// * `count` - The number of bytes to skip.
+/// * `chunk_size` - The size of the chunks read (see `BackupPCConfig::read_chunk_size`).
+///
+/// # Returns
+///
+/// The number of bytes skipped, less than `count` if the content ends before.
+///
+/// # Errors
+///
+/// If the reader fails.
+pub fn skip<R: Read + ?Sized>(reader: &mut R, count: u64, chunk_size: usize) -> io::Result<u64> {
+    let chunk_size = usize::try_from(count).map_or(chunk_size, |count| count.min(chunk_size));
+    let mut buffer = vec![0; chunk_size.max(1)];
+
+    let mut skipped = 0;
+    while skipped < count {
+        let len = usize::try_from(count - skipped).map_or(buffer.len(), |r| r.min(buffer.len()));
+        match reader.read(&mut buffer[..len]) {
+            Ok(0) => break,
+            Ok(read) => skipped += read as u64,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(skipped)
+}
+
+/// A reader that pads the content of the inner reader with zeros up to an expected size.
+///
+/// `BackupPC` advertises the size of a file in its attributes. Sparse files (or files with holes) can decode to less
+/// bytes than this size. To keep the reported size and the content consistent, the missing bytes at the end of the
+/// file are read as zeros, as the holes of a sparse file would be.
+///
+/// If the inner reader produces more bytes than the expected size, the content is returned as is.
+///
+/// `BackupPC` v4 stores the content of a file in a single pool file (it never splits a file in multiple chunks), so
+/// a content that doesn't match the expected size can also be a truncated or corrupted pool file. The mismatch is
+/// logged as a warning and the number of bytes added is available with `padded`, so it is never silent. In strict
+/// mode (see `with_strict`), the mismatch is an error instead: `UnexpectedEof` for a content shorter than the expected
+/// size, and `InvalidData` for a longer content.
+pub struct PaddedReader<R: Read> {
+    inner: R,
+    size: u64,
+    position: u64,
+    inner_eof: bool,
+    padded: u64,
+    oversized: bool,
+    strict: bool,
+}
+
+impl<R: Read> PaddedReader<R> {
+    /// Creates a new `PaddedReader` that pads the inner reader up to `size` bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The reader to pad.
+    /// * `size` - The expected size of the content.
+    ///
+    /// # Returns
+    ///
+    /// A new `PaddedReader` instance.
+    pub fn new(inner: R, size: u64) -> Self {
+        Self {
+            inner,
+            size,
+            position: 0,
+            inner_eof: false,
+            padded: 0,
+            oversized: false,
+            strict: false,
+        }
+    }
+
+    /// Defines if a content that doesn't match the expected size is an error instead of being padded or kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `strict` - `true` to fail on a size mismatch.
+    ///
+    /// # Returns
+    ///
+    /// The `PaddedReader` instance, in strict mode or not.
+    #[must_use]
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Returns the number of zeros added after the content of the inner reader so far.
+    pub fn padded(&self) -> u64 {
+        self.padded
+    }
+}
+
+impl<R: Read> Read for PaddedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if !self.inner_eof {
+            let count = self.inner.read(buf)?;
+            if count > 0 {
+                self.position += count as u64;
+                if self.position > self.size && self.strict {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Content is longer than the expected size of {} bytes",
+                            self.size
+                        ),
+                    ));
+                }
+                if self.position > self.size && !self.oversized {
+                    self.oversized = true;
+                    warn!(
+                        "Content is longer than the expected size of {} bytes",
+                        self.size
+                    );
+                }
+                return Ok(count);
+            }
+            self.inner_eof = true;
+            if self.position < self.size && self.strict {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "Content is {} bytes long but {} bytes are expected",
+                        self.position, self.size
+                    ),
+                ));
+            }
+            if self.position < self.size {
+                warn!(
+                    "Content is {} bytes long but {} bytes are expected: padded with zeros",
+                    self.position, self.size
+                );
+            }
+        }
+
+        let remaining = self.size.saturating_sub(self.position);
+        let count = usize::try_from(remaining).map_or(buf.len(), |r| r.min(buf.len()));
+        buf[..count].fill(0);
+        self.position += count as u64;
+        self.padded += count as u64;
+
+        Ok(count)
+    }
+}
+
+/// A reader that fails when the inner reader produces more than a maximum number of bytes.
+///
+/// A small compressed pool file can decompress to a huge content (a decompression bomb), and the size stored in the
+/// attributes of a file can't be trusted to bound it (see `PaddedReader`). Unlike `std::io::Take`, which silently
+/// stops at the limit, the content past the limit is an error of kind `InvalidData`, so a truncated content is never
+/// served as the whole file.
+///
+/// ```
+/// use backuppc_pool_reader::reader::LimitedReader;
+/// use std::io::Read;
+///
+/// let mut content = Vec::new();
+/// let mut reader = LimitedReader::new(&b"0123456789"[..], 4);
+/// assert!(reader.read_to_end(&mut content).is_err());
+/// ```
+pub struct LimitedReader<R: Read> {
+    inner: R,
+    limit: u64,
+    position: u64,
+    exceeded: bool,
+}
+
+impl<R: Read> LimitedReader<R> {
+    /// Creates a new `LimitedReader` failing after `limit` bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The reader to limit.
+    /// * `limit` - The maximum number of bytes the inner reader can produce.
+    ///
+    /// # Returns
+    ///
+    /// A new `LimitedReader` instance.
+    pub fn new(inner: R, limit: u64) -> Self {
+        Self {
+            inner,
+            limit,
+            position: 0,
+            exceeded: false,
+        }
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.position == self.limit {
+            // The content must end at the limit: a byte more is an error
+            if !self.exceeded && self.inner.read(&mut [0; 1])? == 0 {
+                return Ok(0);
+            }
+            self.exceeded = true;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Content is longer than the limit of {} bytes", self.limit),
+            ));
+        }
+
+        let remaining = self.limit - self.position;
+        let len = usize::try_from(remaining).map_or(buf.len(), |r| r.min(buf.len()));
+        let count = self.inner.read(&mut buf[..len])?;
+        self.position += count as u64;
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_padded_reader_pads_short_content() {
+        let mut reader = PaddedReader::new(Cursor::new(vec![1u8, 2, 3]), 8);
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).unwrap();
+
+        assert_eq!(content, vec![1, 2, 3, 0, 0, 0, 0, 0]);
+        assert_eq!(reader.padded(), 5);
+    }
+
+    #[test]
+    fn test_padded_reader_keeps_longer_content() {
+        let mut reader = PaddedReader::new(Cursor::new(vec![1u8, 2, 3, 4]), 2);
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).unwrap();
+
+        assert_eq!(content, vec![1, 2, 3, 4]);
+        assert_eq!(reader.padded(), 0);
+    }
+
+    #[test]
+    fn test_padded_reader_strict_fails_on_short_content() {
+        let mut reader = PaddedReader::new(Cursor::new(vec![1u8, 2, 3]), 8).with_strict(true);
+        let mut content = Vec::new();
+        let err = reader.read_to_end(&mut content).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(reader.padded(), 0);
+    }
+
+    #[test]
+    fn test_padded_reader_strict_fails_on_long_content() {
+        let mut reader = PaddedReader::new(Cursor::new(vec![1u8, 2, 3, 4]), 2).with_strict(true);
+        let mut content = Vec::new();
+        let err = reader.read_to_end(&mut content).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_padded_reader_strict_accepts_exact_content() {
+        let mut reader = PaddedReader::new(Cursor::new(vec![1u8, 2, 3]), 3).with_strict(true);
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).unwrap();
+
+        assert_eq!(content, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_limited_reader() {
+        let mut content = Vec::new();
+        let mut reader = LimitedReader::new(Cursor::new(vec![1u8, 2, 3, 4]), 4);
+        reader.read_to_end(&mut content).unwrap();
+        assert_eq!(content, vec![1, 2, 3, 4]);
+
+        let mut content = Vec::new();
+        let mut reader = LimitedReader::new(Cursor::new(vec![1u8, 2, 3, 4, 5]), 4);
+        let err = reader.read_to_end(&mut content).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(content, vec![1, 2, 3, 4]);
+
+        // The error is returned again on the next reads
+        assert!(reader.read(&mut [0; 8]).is_err());
+
+        // A limit of zero only accepts an empty content
+        let mut reader = LimitedReader::new(Cursor::new(Vec::new()), 0);
+        assert_eq!(reader.read(&mut [0; 8]).unwrap(), 0);
+    }
+}
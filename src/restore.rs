@@ -0,0 +1,334 @@
+use filetime::FileTime;
+use log::{info, warn};
+use std::fs::{self, File, Permissions};
+use std::io;
+use std::os::unix::fs::{lchown, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use crate::decode_attribut::{FileAttributes, FileType};
+use crate::util::Result;
+use crate::view::BackupPC;
+
+/// Owner given to the restored files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Ownership {
+    /// The uid and gid of the backup are applied as-is (`--numeric-ids`). Changing the owner of a file needs to be
+    /// root: for the other users, the failure is logged and the file keeps its owner.
+    Numeric,
+    /// The files are owned by the user doing the restore (the owner of the backup is ignored).
+    #[default]
+    CurrentUser,
+}
+
+/// Metadata of the backup applied to the restored files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataOptions {
+    /// Owner given to the restored files.
+    pub ownership: Ownership,
+    /// Apply the permissions of the backup (including the setuid, setgid, and sticky bits when the file is given the
+    /// owner of the backup).
+    pub apply_mode: bool,
+    /// Apply the modification time of the backup.
+    pub apply_mtime: bool,
+}
+
+impl Default for MetadataOptions {
+    fn default() -> Self {
+        MetadataOptions {
+            ownership: Ownership::CurrentUser,
+            apply_mode: true,
+            apply_mtime: true,
+        }
+    }
+}
+
+/// Applies the metadata of a file of the backup to a restored file.
+///
+/// The owner is changed first (changing the owner clears the setuid and setgid bits), then the modification time (set
+/// on the path, so a file that the permissions of the backup make unreadable, as `0200`, still gets it), then the
+/// permissions. The permissions and the modification time of a symbolic link are not changed (they can't be changed
+/// on Linux), only its owner. The setuid, setgid, and sticky bits are only restored if the file was given the owner
+/// of the backup: a file owned by the user doing the restore never gets them.
+///
+/// # Arguments
+///
+/// * `path` - The path of the restored file.
+/// * `attributes` - The attributes of the file in the backup.
+/// * `options` - The metadata to apply.
+///
+/// # Errors
+///
+/// If the permissions or the modification time can't be applied, or if the owner can't be changed for another
+/// reason than the lack of privileges (a permission error is only logged, so a restore done by a user other than
+/// root still succeeds).
+pub fn apply_metadata(
+    path: &Path,
+    attributes: &FileAttributes,
+    options: &MetadataOptions,
+) -> io::Result<()> {
+    let mut owned = false;
+    if options.ownership == Ownership::Numeric {
+        match lchown(path, Some(attributes.uid), Some(attributes.gid)) {
+            Ok(()) => owned = true,
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => warn!(
+                "Can't change the owner of {} to {}:{}: {err}",
+                path.display(),
+                attributes.uid,
+                attributes.gid
+            ),
+            Err(err) => return Err(err),
+        }
+    }
+
+    if attributes.type_ == FileType::Symlink {
+        return Ok(());
+    }
+
+    if options.apply_mtime {
+        #[allow(clippy::cast_possible_wrap)]
+        let mtime = FileTime::from_unix_time(attributes.mtime as i64, 0);
+        filetime::set_file_mtime(path, mtime)?;
+    }
+
+    if options.apply_mode {
+        let mask = if owned { 0o7777 } else { 0o777 };
+        let mode = u32::from(attributes.mode) & mask;
+        fs::set_permissions(path, Permissions::from_mode(mode))?;
+    }
+
+    Ok(())
+}
+
+/// Writes a file of a backup to `dest` (or to a file with the same name in `dest` if it is a directory), and applies
+/// the metadata of the backup.
+///
+/// # Arguments
+///
+/// * `view` - The view of the backups.
+/// * `path` - The path of the file in the view (host, backup number, share, and path).
+/// * `dest` - The file to write, or the directory where it is written.
+/// * `options` - The metadata to apply.
+///
+/// # Returns
+///
+/// The path of the written file.
+///
+/// # Errors
+///
+/// If the file doesn't exist in the backup (`NotFound`), if it isn't a regular file (`InvalidInput`), or if it
+/// can't be read or written.
+pub fn restore_file(
+    view: &BackupPC,
+    path: &[&str],
+    dest: &Path,
+    options: &MetadataOptions,
+) -> Result<PathBuf> {
+    let Some((filename, parent)) = path.split_last() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "No file specified").into());
+    };
+
+    let display_path = path.join("/");
+    let file = view
+        .list(parent)?
+        .into_iter()
+        .find(|file| file.has_name(filename) && file.type_ != FileType::Deleted)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("File not found: {display_path}"),
+            )
+        })?;
+    if !matches!(file.type_, FileType::File | FileType::Hardlink) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{display_path} is not a regular file (use export or mount for the directories)"
+            ),
+        )
+        .into());
+    }
+
+    let dest = if dest.is_dir() {
+        dest.join(file.display_name().as_ref())
+    } else {
+        dest.to_path_buf()
+    };
+    info!("Write {display_path} to {}", dest.display());
+
+    let mut reader = view.read_file(path)?;
+    let mut out = File::create(&dest)?;
+    io::copy(&mut reader, &mut out)?;
+    drop(out);
+
+    apply_metadata(&dest, &file, options)?;
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attribute_file::MockSearchTrait;
+    use crate::hosts::{BackupInformation, MockHostsTrait};
+    use crate::util::vec_to_hex_string;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn test_apply_metadata() {
+        let dir = std::env::temp_dir().join(format!("bpc_restore_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file");
+        fs::write(&path, b"content").unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+
+        let mut attributes = FileAttributes::from_host("file".to_string());
+        attributes.type_ = FileType::File;
+        attributes.mode = 0o640;
+        attributes.mtime = 1_700_000_000;
+        // The owner of the file (changing the owner to itself works without privileges)
+        attributes.uid = metadata.uid();
+        attributes.gid = metadata.gid();
+
+        let options = MetadataOptions {
+            ownership: Ownership::Numeric,
+            ..MetadataOptions::default()
+        };
+        apply_metadata(&path, &attributes, &options).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.mode() & 0o7777, 0o640);
+        assert_eq!(metadata.mtime(), 1_700_000_000);
+
+        let options = MetadataOptions {
+            apply_mode: false,
+            apply_mtime: false,
+            ..MetadataOptions::default()
+        };
+        attributes.mode = 0o600;
+        attributes.mtime = 0;
+        apply_metadata(&path, &attributes, &options).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.mode() & 0o7777, 0o640);
+        assert_eq!(metadata.mtime(), 1_700_000_000);
+
+        // A mode without the read permission doesn't prevent the modification time from being applied
+        attributes.mode = 0o200;
+        attributes.mtime = 1_600_000_000;
+        apply_metadata(&path, &attributes, &MetadataOptions::default()).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.mode() & 0o7777, 0o200);
+        assert_eq!(metadata.mtime(), 1_600_000_000);
+
+        // The setuid, setgid, and sticky bits are only restored with the owner of the backup
+        attributes.mode = 0o7755;
+        apply_metadata(&path, &attributes, &MetadataOptions::default()).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o7777, 0o755);
+
+        let options = MetadataOptions {
+            ownership: Ownership::Numeric,
+            ..MetadataOptions::default()
+        };
+        apply_metadata(&path, &attributes, &options).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o7777, 0o7755);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_file() {
+        let topdir = std::env::temp_dir().join(format!("bpc_restore_file_{}", std::process::id()));
+        let digest: Vec<u8> = (0x50..0x60).collect();
+        let pool_dir = topdir.join("pool").join("50").join("50");
+        fs::create_dir_all(&pool_dir).unwrap();
+        fs::write(pool_dir.join(vec_to_hex_string(&digest)), b"restored").unwrap();
+        let dest = topdir.join("dest");
+        fs::create_dir_all(&dest).unwrap();
+
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+        hosts_mock.expect_list_backups_to_fill().returning(|_, _| {
+            Ok(vec![BackupInformation {
+                num: 1,
+                backup_type: "full".to_string(),
+                start_time: 0,
+                end_time: 0,
+                n_files: 0,
+                size: 0,
+                n_files_exist: 0,
+                size_exist: 0,
+                n_files_new: 0,
+                size_new: 0,
+                xfer_errs: 0,
+                xfer_bad_file: 0,
+                xfer_bad_share: 0,
+                tar_errs: 0,
+                compress: 0,
+                size_exist_comp: 0,
+                size_new_comp: 0,
+                no_fill: 0,
+                fill_from_num: 0,
+                mangle: 0,
+                xfer_method: "rsync".to_string(),
+                level: 0,
+                charset: "utf-8".to_string(),
+                version: "4.0.0".to_string(),
+                inode_last: 0,
+            }])
+        });
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_none() && path.is_none())
+            .returning(|_, _, _, _| Ok(vec![FileAttributes::from_share("/share".to_string())]));
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, _| share.is_some())
+            .returning(move |_, _, _, _| {
+                let mut file = FileAttributes::from_host("file".to_string());
+                file.type_ = FileType::File;
+                file.mode = 0o640;
+                file.mtime = 1_700_000_000;
+                file.size = 8;
+                file.bpc_digest.len = 16;
+                file.bpc_digest.digest.clone_from(&digest);
+                let mut dir = FileAttributes::from_host("dir".to_string());
+                dir.type_ = FileType::Dir;
+                Ok(vec![file, dir])
+            });
+        let view = BackupPC::new(topdir.to_str().unwrap(), hosts_mock, search_mock);
+
+        let path = restore_file(
+            &view,
+            &["pc-1", "1", "share", "file"],
+            &dest,
+            &MetadataOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(path, dest.join("file"));
+        assert_eq!(fs::read(&path).unwrap(), b"restored");
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.mode() & 0o7777, 0o640);
+        assert_eq!(metadata.mtime(), 1_700_000_000);
+
+        let err = restore_file(
+            &view,
+            &["pc-1", "1", "share", "dir"],
+            &dest,
+            &MetadataOptions::default(),
+        )
+        .unwrap_err();
+        let err = err.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = restore_file(
+            &view,
+            &["pc-1", "1", "share", "missing"],
+            &dest,
+            &MetadataOptions::default(),
+        )
+        .unwrap_err();
+        let err = err.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        fs::remove_dir_all(topdir).unwrap();
+    }
+}
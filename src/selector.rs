@@ -0,0 +1,381 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use crate::hosts::{BackupInformation, HostsTrait};
+use crate::time_style::TimeStyle;
+use crate::util::{parse_rfc3339, Result};
+
+/// A reference to a backup of a host, resolved to a backup number against the backups of the host.
+///
+/// The grammar of a selector is:
+///
+/// - `N`: the backup number `N`.
+/// - `latest`: the newest complete backup (the one with the highest number, partial backups excluded).
+/// - `latest-<type>`: the newest backup of the given type (ex: `latest-full`, `latest-incr`, `latest-partial`).
+/// - `<selector>-K`: the `K`-th backup before the backup selected by `N`, `latest`, or `latest-<type>`, counting the
+///   complete backups of the host by number (ex: `10-1` is the backup before the backup 10, `latest-full-1` the
+///   backup before the latest full backup). `<selector>-0` is the backup selected, even if it is partial.
+/// - `<date>`: the backup showing the host at a date in RFC 3339 (ex: `2024-03-01T12:00:00Z`, `2024-03-01`), see
+///   `backup_at_time`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackupSelector {
+    /// The backup with this number.
+    Number(u32),
+    /// The newest backup, of the given type if any.
+    Latest(Option<String>),
+    /// The backup found this number of backups before the selected one.
+    Before(Box<BackupSelector>, u32),
+    /// The latest backup completed at or before this time, in seconds since the Unix epoch.
+    At(u64),
+}
+
+impl BackupSelector {
+    /// Resolves the selector to a backup number.
+    ///
+    /// # Arguments
+    ///
+    /// * `backups` - The backups of the host (in any order).
+    ///
+    /// # Returns
+    ///
+    /// The number of the backup selected.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `NotFound` if no backup matches the selector, or of kind `InvalidData` if the backup number is
+    /// listed several times in the backups (the selector is ambiguous).
+    pub fn resolve(&self, backups: &[BackupInformation]) -> io::Result<u32> {
+        match self {
+            BackupSelector::Number(number) => {
+                match backups
+                    .iter()
+                    .filter(|backup| backup.num == *number)
+                    .count()
+                {
+                    0 => Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("Backup {number} not found"),
+                    )),
+                    1 => Ok(*number),
+                    _ => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Backup {number} is listed several times"),
+                    )),
+                }
+            }
+            BackupSelector::Latest(backup_type) => backups
+                .iter()
+                .filter(|backup| match backup_type {
+                    Some(backup_type) => backup.backup_type == *backup_type,
+                    None => !backup.is_partial(),
+                })
+                .map(|backup| backup.num)
+                .max()
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, format!("No backup for {self}"))
+                }),
+            BackupSelector::Before(selector, count) => {
+                let number = selector.resolve(backups)?;
+                // The selected backup can be a partial backup, not counted in the complete backups: no backup before
+                // it is the backup itself
+                if *count == 0 {
+                    return Ok(number);
+                }
+                let mut numbers = backups
+                    .iter()
+                    .filter(|backup| !backup.is_partial())
+                    .map(|backup| backup.num)
+                    .collect::<Vec<u32>>();
+                numbers.sort_unstable();
+                let position = numbers.partition_point(|num| *num < number);
+                position
+                    .checked_sub(*count as usize)
+                    .map(|position| numbers[position])
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("No backup {count} before backup {number}"),
+                        )
+                    })
+            }
+            BackupSelector::At(timestamp) => backup_at_time(backups, *timestamp),
+        }
+    }
+
+    /// Resolves the selector to a backup number of a host.
+    ///
+    /// # Arguments
+    ///
+    /// * `hosts` - The hosts of the pool.
+    /// * `hostname` - The host of the backup.
+    ///
+    /// # Returns
+    ///
+    /// The number of the backup selected.
+    ///
+    /// # Errors
+    ///
+    /// If the backups of the host can't be read, or if the selector can't be resolved (see `resolve`).
+    pub fn resolve_for_host(&self, hosts: &dyn HostsTrait, hostname: &str) -> Result<u32> {
+        let backups = hosts.list_backups(hostname)?;
+        Ok(self.resolve(&backups)?)
+    }
+
+    fn parse_base(value: &str) -> Option<Self> {
+        match value.split_once('-') {
+            None if value == "latest" => Some(BackupSelector::Latest(None)),
+            None => value.parse().ok().map(BackupSelector::Number),
+            Some(("latest", backup_type))
+                if !backup_type.is_empty()
+                    && backup_type.chars().all(|c| c.is_ascii_alphabetic()) =>
+            {
+                Some(BackupSelector::Latest(Some(backup_type.to_string())))
+            }
+            Some(_) => None,
+        }
+    }
+}
+
+/// Selects the backup showing the state of a host at a given time.
+///
+/// The backup selected is the latest backup completed at or before the timestamp (the backup whose end time is the
+/// closest before the timestamp). A timestamp after the last backup selects the latest backup. The partial backups
+/// are excluded.
+///
+/// # Arguments
+///
+/// * `backups` - The backups of the host (in any order).
+/// * `timestamp` - The time, in seconds since the Unix epoch.
+///
+/// # Returns
+///
+/// The number of the backup selected.
+///
+/// # Errors
+///
+/// An error of kind `NotFound` if no backup was completed at this time (the timestamp is before the first backup).
+pub fn backup_at_time(backups: &[BackupInformation], timestamp: u64) -> io::Result<u32> {
+    backups
+        .iter()
+        .filter(|backup| backup.end_time <= timestamp && !backup.is_partial())
+        .max_by_key(|backup| (backup.end_time, backup.num))
+        .map(|backup| backup.num)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No backup completed at or before {timestamp}"),
+            )
+        })
+}
+
+impl FromStr for BackupSelector {
+    type Err = io::Error;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        // A date has two hyphens, which no other selector has (except `latest-<type>-K`)
+        if value.matches('-').count() >= 2 && !value.starts_with("latest") {
+            if let Some(timestamp) = parse_rfc3339(value) {
+                return Ok(BackupSelector::At(timestamp));
+            }
+        }
+
+        let selector = match value.rsplit_once('-') {
+            Some((base, count))
+                if !count.is_empty() && count.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                Self::parse_base(base)
+                    .zip(count.parse().ok())
+                    .map(|(base, count)| BackupSelector::Before(Box::new(base), count))
+            }
+            _ => Self::parse_base(value),
+        };
+
+        selector.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid backup selector {value} (expected N, latest, latest-<type>, <selector>-K, or a date)"
+                ),
+            )
+        })
+    }
+}
+
+impl fmt::Display for BackupSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupSelector::Number(number) => write!(f, "{number}"),
+            BackupSelector::Latest(None) => write!(f, "latest"),
+            BackupSelector::Latest(Some(backup_type)) => write!(f, "latest-{backup_type}"),
+            BackupSelector::Before(selector, count) => write!(f, "{selector}-{count}"),
+            BackupSelector::At(timestamp) => {
+                let style = TimeStyle::Format("%Y-%m-%dT%H:%M:%SZ".to_string());
+                write!(f, "{}", style.format(*timestamp, *timestamp))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hosts::MockHostsTrait;
+    use mockall::predicate::eq;
+
+    fn backup(num: u32, backup_type: &str) -> BackupInformation {
+        let mut backups = std::collections::HashMap::new();
+        backups.insert("num".to_string(), num.to_string());
+        backups.insert("type".to_string(), backup_type.to_string());
+        BackupInformation::from_values(&backups)
+    }
+
+    #[test]
+    fn test_parse_backup_selector() {
+        assert_eq!(
+            "10".parse::<BackupSelector>().unwrap(),
+            BackupSelector::Number(10)
+        );
+        assert_eq!(
+            "latest".parse::<BackupSelector>().unwrap(),
+            BackupSelector::Latest(None)
+        );
+        assert_eq!(
+            "latest-full".parse::<BackupSelector>().unwrap(),
+            BackupSelector::Latest(Some("full".to_string()))
+        );
+        assert_eq!(
+            "latest-full-2".parse::<BackupSelector>().unwrap(),
+            BackupSelector::Before(
+                Box::new(BackupSelector::Latest(Some("full".to_string()))),
+                2
+            )
+        );
+        assert_eq!(
+            "10-1".parse::<BackupSelector>().unwrap().to_string(),
+            "10-1"
+        );
+        assert_eq!(
+            "2024-03-01T01:00:00+01:00"
+                .parse::<BackupSelector>()
+                .unwrap(),
+            BackupSelector::At(1_709_251_200)
+        );
+        assert_eq!(
+            "2024-03-01".parse::<BackupSelector>().unwrap().to_string(),
+            "2024-03-01T00:00:00Z"
+        );
+        for invalid in [
+            "",
+            "-1",
+            "latest-",
+            "10-1-1",
+            "first",
+            "latest-1-full",
+            "2024-02-30",
+        ] {
+            let err = invalid.parse::<BackupSelector>().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_resolve_backup_selector() {
+        let backups = vec![
+            backup(3, "incr"),
+            backup(1, "full"),
+            backup(2, "incr"),
+            backup(5, "full"),
+            backup(6, "incr"),
+        ];
+        let resolve = |selector: &str| {
+            selector
+                .parse::<BackupSelector>()
+                .unwrap()
+                .resolve(&backups)
+        };
+
+        assert_eq!(resolve("2").unwrap(), 2);
+        assert_eq!(resolve("latest").unwrap(), 6);
+        assert_eq!(resolve("latest-full").unwrap(), 5);
+        assert_eq!(resolve("latest-full-1").unwrap(), 3);
+        assert_eq!(resolve("5-3").unwrap(), 1);
+        assert_eq!(resolve("4").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(resolve("5-4").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(
+            resolve("latest-partial").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!(
+            BackupSelector::Latest(None)
+                .resolve(&[])
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+
+        let duplicated = vec![backup(1, "full"), backup(1, "full")];
+        assert_eq!(
+            BackupSelector::Number(1)
+                .resolve(&duplicated)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_resolve_backup_selector_skips_partial_backups() {
+        let mut backups = vec![backup(1, "full"), backup(2, "incr"), backup(3, "partial")];
+        for (backup, end_time) in backups.iter_mut().zip([100, 200, 300]) {
+            backup.end_time = end_time;
+        }
+        let resolve = |selector: &str| {
+            selector
+                .parse::<BackupSelector>()
+                .unwrap()
+                .resolve(&backups)
+                .unwrap()
+        };
+
+        assert_eq!(resolve("latest"), 2);
+        assert_eq!(resolve("latest-partial"), 3);
+        assert_eq!(resolve("3"), 3);
+        assert_eq!(resolve("3-1"), 2);
+        // The newest backup is partial: no complete backup is at or after it
+        assert_eq!(resolve("3-0"), 3);
+        assert_eq!(resolve("latest-partial-0"), 3);
+        assert_eq!(resolve("latest-partial-2"), 1);
+        assert_eq!(backup_at_time(&backups, 1000).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_backup_at_time() {
+        let mut backups = vec![backup(1, "full"), backup(2, "incr"), backup(3, "incr")];
+        for (backup, end_time) in backups.iter_mut().zip([100, 200, 300]) {
+            backup.start_time = end_time - 50;
+            backup.end_time = end_time;
+        }
+
+        assert_eq!(backup_at_time(&backups, 100).unwrap(), 1);
+        assert_eq!(backup_at_time(&backups, 260).unwrap(), 2);
+        assert_eq!(backup_at_time(&backups, 1000).unwrap(), 3);
+        assert_eq!(
+            backup_at_time(&backups, 99).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!(BackupSelector::At(260).resolve(&backups).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_resolve_backup_selector_for_host() {
+        let mut hosts = MockHostsTrait::new();
+        hosts
+            .expect_list_backups()
+            .with(eq("pc-1"))
+            .returning(|_| Ok(vec![backup(1, "full"), backup(2, "incr")]));
+
+        let selector = BackupSelector::Latest(Some("full".to_string()));
+        assert_eq!(selector.resolve_for_host(&hosts, "pc-1").unwrap(), 1);
+    }
+}
@@ -0,0 +1,567 @@
+use libc::{EIO, ESTALE};
+use log::warn;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// An entry of a directory of the pool source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceEntry {
+    /// Name of the entry in its directory.
+    pub name: String,
+    /// `true` if the entry is a directory.
+    pub is_dir: bool,
+}
+
+/// Access to the files of a `BackupPC` top directory.
+///
+/// The reader only needs to open files and to list directories of the top directory (pool files, attrib files,
+/// backups files, ...). The source abstracts this access, so the pool can be read from the local filesystem
+/// (`LocalSource`) or from a remote server (`SftpSource`, behind the `sftp` feature).
+///
+/// The paths given to the source are the paths of the files on the machine hosting the pool (the top directory
+/// joined with the path of the file).
+pub trait PoolSource: Send + Sync {
+    /// Opens a file for reading.
+    ///
+    /// # Errors
+    ///
+    /// If the file doesn't exist or can't be opened.
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send + Sync>>;
+
+    /// Lists the entries of a directory.
+    ///
+    /// # Errors
+    ///
+    /// If the directory doesn't exist or can't be read.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<SourceEntry>>;
+
+    /// Returns `true` if the file (or directory) exists.
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Returns the size of a file, as stored (the size of the compressed file for the cpool).
+    ///
+    /// By default, the file is read to count its bytes: the sources able to read the metadata of the files return
+    /// the size without reading them.
+    ///
+    /// # Errors
+    ///
+    /// If the file doesn't exist or can't be read.
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        io::copy(&mut self.open(path)?, &mut io::sink())
+    }
+}
+
+/// The pool is read from the local filesystem (the top directory is mounted on this machine).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalSource;
+
+impl PoolSource for LocalSource {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send + Sync>> {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<SourceEntry>> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn!("Error reading directory: {}, {err}", path.display());
+                    continue;
+                }
+            };
+            let Some(name) = entry.file_name().to_str().map(ToString::to_string) else {
+                continue;
+            };
+            entries.push(SourceEntry {
+                name,
+                is_dir: entry.path().is_dir(),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        Ok(std::fs::metadata(path)?.len())
+    }
+}
+
+/// The pool is read from the local filesystem, the files being memory-mapped instead of read with buffered reads.
+///
+/// Mapping a file avoids the read syscalls, which helps when many small uncompressed files (attrib files, inode
+/// files) are read, or when a file is read several times. The compressed files are decompressed from the mapped
+/// content.
+#[cfg(feature = "mmap")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MmapSource;
+
+#[cfg(feature = "mmap")]
+impl MmapSource {
+    /// Maps a file in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The mapped file, readable as a slice or with `Read`.
+    ///
+    /// # Errors
+    ///
+    /// If the file doesn't exist, or can't be opened or mapped.
+    pub fn map(path: &Path) -> io::Result<MappedFile> {
+        let file = File::open(path)?;
+        // An empty file can't be mapped
+        let map = if file.metadata()?.len() == 0 {
+            None
+        } else {
+            // SAFETY: the files of the pool are never modified once written (a new file is written instead).
+            Some(unsafe { memmap2::Mmap::map(&file)? })
+        };
+
+        Ok(MappedFile { map, position: 0 })
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl PoolSource for MmapSource {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send + Sync>> {
+        Ok(Box::new(Self::map(path)?))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<SourceEntry>> {
+        LocalSource.read_dir(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        LocalSource.exists(path)
+    }
+
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        LocalSource.file_size(path)
+    }
+}
+
+/// A file mapped in memory by `MmapSource`.
+#[cfg(feature = "mmap")]
+pub struct MappedFile {
+    map: Option<memmap2::Mmap>,
+    position: usize,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedFile {
+    /// Returns the whole content of the file.
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl Read for MappedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = &self.as_slice()[self.position..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// The policy used to retry the operations of a pool source failing with a transient error.
+///
+/// On a networked pool (NFS, SFTP), an operation can fail occasionally (`EIO`, `ESTALE`, timeout, ...) and succeed
+/// when tried again. The operation is retried `retries` times, waiting `backoff` before the first retry, then twice
+/// longer before each new retry. The other errors (ex: `NotFound`, `PermissionDenied`, `InvalidData`) are returned
+/// immediately.
+///
+/// ```
+/// use backuppc_pool_reader::source::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new(3, Duration::from_millis(100));
+/// assert_eq!(policy.delay(2), Duration::from_millis(400));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt (0 to disable the retries).
+    pub retries: u32,
+    /// Time waited before the first retry.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 0,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Creates a new retry policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `retries` - The number of retries after the first attempt.
+    /// * `backoff` - The time waited before the first retry (doubled before each new retry).
+    #[must_use]
+    pub fn new(retries: u32, backoff: Duration) -> Self {
+        RetryPolicy { retries, backoff }
+    }
+
+    /// Returns the time waited before the retry `attempt` (starting at 0).
+    #[must_use]
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(1 << attempt.min(16))
+    }
+
+    /// Returns `true` if the error is transient, and the operation can be retried.
+    #[must_use]
+    pub fn is_transient(err: &io::Error) -> bool {
+        matches!(err.raw_os_error(), Some(EIO | ESTALE))
+            || matches!(
+                err.kind(),
+                io::ErrorKind::Interrupted
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+            )
+    }
+
+    /// Runs an operation, retrying it while it fails with a transient error.
+    ///
+    /// # Errors
+    ///
+    /// The error of the operation, if it isn't transient or if it still fails after the last retry.
+    pub fn retry<T>(&self, mut operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut attempt = 0;
+        loop {
+            match operation() {
+                Err(err) if attempt < self.retries && Self::is_transient(&err) => {
+                    warn!(
+                        "Transient error (retry {}/{}): {err}",
+                        attempt + 1,
+                        self.retries
+                    );
+                    std::thread::sleep(self.delay(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Wraps a source so its operations are retried with this policy.
+    ///
+    /// # Returns
+    ///
+    /// The source itself if the retries are disabled, a `RetrySource` otherwise.
+    #[must_use]
+    pub fn wrap(self, source: Arc<dyn PoolSource>) -> Arc<dyn PoolSource> {
+        if self.retries == 0 {
+            source
+        } else {
+            Arc::new(RetrySource::new(source, self))
+        }
+    }
+}
+
+/// A source retrying the opening, the listing, and the reading of the files of another source when they fail with
+/// a transient error (see `RetryPolicy`).
+pub struct RetrySource {
+    inner: Arc<dyn PoolSource>,
+    policy: RetryPolicy,
+}
+
+impl RetrySource {
+    /// Creates a new source retrying the operations of `inner` with the given policy.
+    #[must_use]
+    pub fn new(inner: Arc<dyn PoolSource>, policy: RetryPolicy) -> Self {
+        RetrySource { inner, policy }
+    }
+}
+
+impl PoolSource for RetrySource {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send + Sync>> {
+        let reader = self.policy.retry(|| self.inner.open(path))?;
+        Ok(Box::new(RetryReader {
+            inner: reader,
+            policy: self.policy,
+        }))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<SourceEntry>> {
+        self.policy.retry(|| self.inner.read_dir(path))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path)
+    }
+
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        self.policy.retry(|| self.inner.file_size(path))
+    }
+}
+
+/// A reader retrying the reads failing with a transient error.
+///
+/// A failed read doesn't consume any byte of the file, so the same read is done again.
+struct RetryReader {
+    inner: Box<dyn Read + Send + Sync>,
+    policy: RetryPolicy,
+}
+
+impl Read for RetryReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let inner = &mut self.inner;
+        self.policy.retry(|| inner.read(buf))
+    }
+}
+
+/// The pool is read from a remote server over SFTP.
+///
+/// The session is authenticated with the keys of the SSH agent, or with a private key file.
+#[cfg(feature = "sftp")]
+pub struct SftpSource {
+    sftp: ssh2::Sftp,
+    // The session must outlive the SFTP channel
+    _session: ssh2::Session,
+}
+
+#[cfg(feature = "sftp")]
+impl SftpSource {
+    /// Connects to a SSH server and opens a SFTP channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server (ex: `backuppc.example.com:22`).
+    /// * `username` - The user used to authenticate.
+    /// * `private_key` - The private key file used to authenticate. If `None`, the keys of the SSH agent are used.
+    ///
+    /// # Returns
+    ///
+    /// A new `SftpSource` instance.
+    ///
+    /// # Errors
+    ///
+    /// If the server can't be reached, if the authentication fails, or if the SFTP channel can't be opened.
+    pub fn connect(address: &str, username: &str, private_key: Option<&Path>) -> io::Result<Self> {
+        let stream = std::net::TcpStream::connect(address)?;
+        let mut session = ssh2::Session::new()?;
+        session.set_tcp_stream(stream);
+        session.handshake()?;
+
+        match private_key {
+            Some(private_key) => session.userauth_pubkey_file(username, None, private_key, None)?,
+            None => session.userauth_agent(username)?,
+        }
+
+        let sftp = session.sftp()?;
+        Ok(Self {
+            sftp,
+            _session: session,
+        })
+    }
+}
+
+#[cfg(feature = "sftp")]
+impl PoolSource for SftpSource {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send + Sync>> {
+        Ok(Box::new(BufReader::new(self.sftp.open(path)?)))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<SourceEntry>> {
+        Ok(self
+            .sftp
+            .readdir(path)?
+            .into_iter()
+            .filter_map(|(path, stat)| {
+                Some(SourceEntry {
+                    name: path.file_name()?.to_str()?.to_string(),
+                    is_dir: stat.is_dir(),
+                })
+            })
+            .collect())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.sftp.stat(path).is_ok()
+    }
+
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        self.sftp
+            .stat(path)?
+            .size
+            .ok_or_else(|| io::Error::other(format!("No size for {}", path.display())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_source() {
+        let topdir = std::env::temp_dir().join(format!("bpc_source_{}", std::process::id()));
+        std::fs::create_dir_all(topdir.join("pc").join("pc-1")).unwrap();
+        std::fs::write(topdir.join("pc").join("backups"), b"content").unwrap();
+
+        let source = LocalSource;
+        let mut entries = source.read_dir(&topdir.join("pc")).unwrap();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(
+            entries,
+            vec![
+                SourceEntry {
+                    name: "backups".to_string(),
+                    is_dir: false
+                },
+                SourceEntry {
+                    name: "pc-1".to_string(),
+                    is_dir: true
+                },
+            ]
+        );
+
+        let mut content = String::new();
+        source
+            .open(&topdir.join("pc").join("backups"))
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "content");
+        assert!(source.exists(&topdir.join("pc").join("pc-1")));
+        assert!(!source.exists(&topdir.join("pc").join("pc-2")));
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap_source() {
+        let topdir = std::env::temp_dir().join(format!("bpc_mmap_source_{}", std::process::id()));
+        std::fs::create_dir_all(&topdir).unwrap();
+        std::fs::write(topdir.join("file"), b"content").unwrap();
+        std::fs::write(topdir.join("empty"), b"").unwrap();
+
+        let mapped = MmapSource::map(&topdir.join("file")).unwrap();
+        assert_eq!(mapped.as_slice(), b"content");
+
+        let mut content = String::new();
+        MmapSource
+            .open(&topdir.join("file"))
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "content");
+
+        let mut empty = Vec::new();
+        MmapSource
+            .open(&topdir.join("empty"))
+            .unwrap()
+            .read_to_end(&mut empty)
+            .unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(
+            MmapSource
+                .open(&topdir.join("missing"))
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+
+        std::fs::remove_dir_all(topdir).unwrap();
+    }
+
+    /// A source whose files fail to open, then fail to read, once before succeeding.
+    struct FlakySource {
+        error: fn() -> io::Error,
+        opens: std::sync::Mutex<u32>,
+    }
+
+    struct FlakyReader {
+        error: Option<io::Error>,
+        content: &'static [u8],
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if let Some(err) = self.error.take() {
+                return Err(err);
+            }
+            self.content.read(buf)
+        }
+    }
+
+    impl PoolSource for FlakySource {
+        fn open(&self, _path: &Path) -> io::Result<Box<dyn Read + Send + Sync>> {
+            let mut opens = self.opens.lock().unwrap();
+            *opens += 1;
+            if *opens == 1 {
+                return Err((self.error)());
+            }
+            Ok(Box::new(FlakyReader {
+                error: Some((self.error)()),
+                content: b"content",
+            }))
+        }
+
+        fn read_dir(&self, _path: &Path) -> io::Result<Vec<SourceEntry>> {
+            Err((self.error)())
+        }
+
+        fn exists(&self, _path: &Path) -> bool {
+            true
+        }
+    }
+
+    fn flaky_source(error: fn() -> io::Error) -> Arc<FlakySource> {
+        Arc::new(FlakySource {
+            error,
+            opens: std::sync::Mutex::new(0),
+        })
+    }
+
+    #[test]
+    fn test_retry_source() {
+        let policy = RetryPolicy::new(2, Duration::from_millis(1));
+
+        let inner = flaky_source(|| io::Error::from_raw_os_error(EIO));
+        let source = policy.wrap(inner.clone());
+        let mut content = String::new();
+        source
+            .open(Path::new("pool/file"))
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "content");
+        assert_eq!(*inner.opens.lock().unwrap(), 2);
+
+        // The errors that aren't transient are not retried
+        let inner = flaky_source(|| io::Error::from(io::ErrorKind::NotFound));
+        let source = policy.wrap(inner.clone());
+        let err = source.open(Path::new("pool/file")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(*inner.opens.lock().unwrap(), 1);
+
+        // Without retries, the first error is returned
+        let inner = flaky_source(|| io::Error::from_raw_os_error(ESTALE));
+        let source = RetryPolicy::default().wrap(inner.clone());
+        assert!(source.open(Path::new("pool/file")).is_err());
+        assert!(source.read_dir(Path::new("pool")).is_err());
+    }
+}
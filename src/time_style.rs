@@ -0,0 +1,341 @@
+use std::str::FromStr;
+
+/// Age (in seconds) under which a time is recent, and printed with its hour instead of its year (half of a mean
+/// Gregorian year, as `ls`).
+const RECENT_AGE: u64 = 31_556_952 / 2;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// Format of the times printed in a listing, as the `--time-style` option of `ls`.
+///
+/// The times are printed in UTC by `format`, and in the local time zone (the `TZ` environment variable, or the time
+/// zone of the system) by `format_local`.
+///
+/// ```
+/// use backuppc_pool_reader::time_style::TimeStyle;
+///
+/// let now = 1_705_000_000; // 2024-01-11
+/// let style: TimeStyle = "long-iso".parse().unwrap();
+/// assert_eq!(style.format(1_704_067_200, now), "2024-01-01 00:00");
+///
+/// let style = TimeStyle::default();
+/// assert_eq!(style.format(1_704_067_200, now), "Jan  1 00:00");
+/// assert_eq!(style.format(1_600_000_000, now), "Sep 13  2020");
+///
+/// let style: TimeStyle = "+%d/%m/%Y".parse().unwrap();
+/// assert_eq!(style.format(1_704_067_200, now), "01/01/2024");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimeStyle {
+    /// The month, the day, and the hour for a recent time, or the year for a time older than six months (or in the
+    /// future): `Jan 12 14:22`, `Jan 12  2020`.
+    #[default]
+    Locale,
+    /// The date and the hour for a recent time, or the date only: `01-12 14:22`, `2020-01-12 `.
+    Iso,
+    /// The date and the hour: `2024-01-12 14:22`.
+    LongIso,
+    /// The date, the time with nanoseconds, and the time zone: `2024-01-12 14:22:05.000000000 +0000`.
+    FullIso,
+    /// A format of `strftime` (ex: `%Y-%m-%d %H:%M:%S`). Two formats separated by a newline are used for the old and
+    /// the recent times.
+    Format(String),
+}
+
+impl TimeStyle {
+    /// Formats a time in UTC.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - The time to format, in seconds since the Unix epoch.
+    /// * `now` - The current time, in seconds since the Unix epoch, to know if the time is recent.
+    ///
+    /// # Returns
+    ///
+    /// The formatted time.
+    #[must_use]
+    pub fn format(&self, timestamp: u64, now: u64) -> String {
+        self.format_with_offset(timestamp, now, 0)
+    }
+
+    /// Formats a time in the local time zone (see `local_offset`).
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - The time to format, in seconds since the Unix epoch.
+    /// * `now` - The current time, in seconds since the Unix epoch, to know if the time is recent.
+    ///
+    /// # Returns
+    ///
+    /// The formatted time.
+    #[must_use]
+    pub fn format_local(&self, timestamp: u64, now: u64) -> String {
+        self.format_with_offset(timestamp, now, local_offset(timestamp))
+    }
+
+    /// Formats a time in a time zone given by its offset from UTC.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - The time to format, in seconds since the Unix epoch.
+    /// * `now` - The current time, in seconds since the Unix epoch, to know if the time is recent.
+    /// * `offset` - The offset of the time zone from UTC, in seconds (ex: 3600 for UTC+1).
+    ///
+    /// # Returns
+    ///
+    /// The formatted time.
+    #[must_use]
+    pub fn format_with_offset(&self, timestamp: u64, now: u64, offset: i64) -> String {
+        let recent = timestamp <= now && now - timestamp < RECENT_AGE;
+        let format = match self {
+            TimeStyle::Locale if recent => "%b %e %H:%M",
+            TimeStyle::Locale => "%b %e  %Y",
+            TimeStyle::Iso if recent => "%m-%d %H:%M",
+            TimeStyle::Iso => "%Y-%m-%d ",
+            TimeStyle::LongIso => "%Y-%m-%d %H:%M",
+            TimeStyle::FullIso => "%Y-%m-%d %H:%M:%S.%N %z",
+            TimeStyle::Format(format) => match format.split_once('\n') {
+                Some((_, recent_format)) if recent => recent_format,
+                Some((old_format, _)) => old_format,
+                None => format,
+            },
+        };
+
+        strftime(format, timestamp, offset)
+    }
+}
+
+/// Returns the offset from UTC (in seconds) of the local time zone at a time: the time zone of the `TZ` environment
+/// variable, or the time zone of the system. The offset is 0 if the time zone can't be read.
+#[must_use]
+pub fn local_offset(timestamp: u64) -> i64 {
+    let Ok(time) = libc::time_t::try_from(timestamp) else {
+        return 0;
+    };
+    // SAFETY: `tm` is a plain C struct, valid when zeroed, and `localtime_r` only writes to it
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return 0;
+    }
+
+    tm.tm_gmtoff as i64
+}
+
+impl FromStr for TimeStyle {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "locale" => Ok(TimeStyle::Locale),
+            "iso" => Ok(TimeStyle::Iso),
+            "long-iso" => Ok(TimeStyle::LongIso),
+            "full-iso" => Ok(TimeStyle::FullIso),
+            _ => match value.strip_prefix('+') {
+                Some(format) => Ok(TimeStyle::Format(format.to_string())),
+                None => Err(format!(
+                    "Invalid time style {value} (expected locale, iso, long-iso, full-iso, or +FORMAT)"
+                )),
+            },
+        }
+    }
+}
+
+/// A time of the proleptic Gregorian calendar, in UTC.
+struct DateTime {
+    year: i64,
+    month: usize,
+    day: u64,
+    hour: u64,
+    minute: u64,
+    second: u64,
+    weekday: usize,
+    day_of_year: u64,
+}
+
+impl DateTime {
+    fn from_timestamp(timestamp: u64) -> Self {
+        let days = timestamp / 86_400;
+        let seconds = timestamp % 86_400;
+
+        // Civil date of the number of days since the epoch (the inverse of `util::parse_rfc3339`)
+        #[allow(clippy::cast_possible_wrap)]
+        let shifted = days as i64 + 719_468;
+        let era = shifted.div_euclid(146_097);
+        let day_of_era = shifted - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_shifted_year =
+            day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_shifted_year + 2) / 153;
+        let day = day_of_shifted_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        let is_leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let days_before_month = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let (month, day) = (month as usize, day as u64);
+        let day_of_year = days_before_month[month - 1] + day + u64::from(is_leap && month > 2);
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: seconds / 3600,
+            minute: seconds / 60 % 60,
+            second: seconds % 60,
+            // The epoch is a Thursday
+            #[allow(clippy::cast_possible_truncation)]
+            weekday: ((days + 4) % 7) as usize,
+            day_of_year,
+        }
+    }
+}
+
+/// Formats a time with the conversions of `strftime` (`%Y`, `%m`, `%d`, `%e`, `%H`, `%M`, `%S`, `%N`, `%b`, `%B`, `%a`,
+/// `%A`, `%y`, `%j`, `%s`, `%z`, `%Z`, `%F`, `%T`, `%R`, `%D`, `%n`, `%t`, and `%%`), in the time zone of the offset
+/// (in seconds from UTC). An unknown conversion is printed as is.
+fn strftime(format: &str, timestamp: u64, offset: i64) -> String {
+    let local = timestamp.saturating_add_signed(offset);
+    let time = DateTime::from_timestamp(local);
+    let zone = format!(
+        "{}{:02}{:02}",
+        if offset < 0 { '-' } else { '+' },
+        offset.unsigned_abs() / 3600,
+        offset.unsigned_abs() / 60 % 60
+    );
+    let mut output = String::new();
+
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => output.push_str(&time.year.to_string()),
+            Some('y') => output.push_str(&format!("{:02}", time.year.rem_euclid(100))),
+            Some('m') => output.push_str(&format!("{:02}", time.month)),
+            Some('d') => output.push_str(&format!("{:02}", time.day)),
+            Some('e') => output.push_str(&format!("{:2}", time.day)),
+            Some('H') => output.push_str(&format!("{:02}", time.hour)),
+            Some('M') => output.push_str(&format!("{:02}", time.minute)),
+            Some('S') => output.push_str(&format!("{:02}", time.second)),
+            // The backups only store whole seconds
+            Some('N') => output.push_str("000000000"),
+            Some('b' | 'h') => output.push_str(&MONTHS[time.month - 1][..3]),
+            Some('B') => output.push_str(MONTHS[time.month - 1]),
+            Some('a') => output.push_str(&WEEKDAYS[time.weekday][..3]),
+            Some('A') => output.push_str(WEEKDAYS[time.weekday]),
+            Some('j') => output.push_str(&format!("{:03}", time.day_of_year)),
+            Some('s') => output.push_str(&timestamp.to_string()),
+            Some('z') => output.push_str(&zone),
+            // The names of the time zones aren't known, only their offset
+            Some('Z') if offset == 0 => output.push_str("UTC"),
+            Some('Z') => output.push_str(&zone),
+            Some('F') => output.push_str(&strftime("%Y-%m-%d", timestamp, offset)),
+            Some('T') => output.push_str(&strftime("%H:%M:%S", timestamp, offset)),
+            Some('R') => output.push_str(&strftime("%H:%M", timestamp, offset)),
+            Some('D') => output.push_str(&strftime("%m/%d/%y", timestamp, offset)),
+            Some('n') => output.push('\n'),
+            Some('t') => output.push('\t'),
+            Some('%') => output.push('%'),
+            Some(other) => {
+                output.push('%');
+                output.push(other);
+            }
+            None => output.push('%'),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_styles() {
+        // 2024-02-29 14:22:05 UTC, a Thursday
+        let time = 1_709_216_525;
+        let now = time + 3600;
+        let style = |style: &str| style.parse::<TimeStyle>().unwrap();
+
+        assert_eq!(style("locale").format(time, now), "Feb 29 14:22");
+        assert_eq!(style("iso").format(time, now), "02-29 14:22");
+        assert_eq!(style("long-iso").format(time, now), "2024-02-29 14:22");
+        assert_eq!(
+            style("full-iso").format(time, now),
+            "2024-02-29 14:22:05.000000000 +0000"
+        );
+
+        // An old time, or a time in the future, is printed with its year
+        let old = now + 365 * 86_400;
+        assert_eq!(style("locale").format(time, old), "Feb 29  2024");
+        assert_eq!(style("iso").format(time, old), "2024-02-29 ");
+        assert_eq!(style("locale").format(now, time), "Feb 29  2024");
+
+        assert_eq!(
+            style("+%a %A %b %B %j %y %D %T %s %Z %%").format(time, now),
+            "Thu Thursday Feb February 060 24 02/29/24 14:22:05 1709216525 UTC %"
+        );
+        assert_eq!(style("+%Y\n%H:%M").format(time, now), "14:22");
+        assert_eq!(style("+%Y\n%H:%M").format(time, old), "2024");
+        assert_eq!(style("+%q %").format(time, now), "%q %");
+
+        assert_eq!(TimeStyle::default().format(0, 0), "Jan  1 00:00");
+        assert_eq!(
+            style("+%F %A").format(4_102_444_800, 0),
+            "2100-01-01 Friday"
+        );
+        assert!("posix".parse::<TimeStyle>().is_err());
+    }
+
+    #[test]
+    fn test_time_styles_with_offset() {
+        // 2024-02-29 23:30:00 UTC
+        let time = 1_709_249_400;
+        let style = |style: &str| style.parse::<TimeStyle>().unwrap();
+
+        assert_eq!(
+            style("full-iso").format_with_offset(time, time, 3600),
+            "2024-03-01 00:30:00.000000000 +0100"
+        );
+        assert_eq!(
+            style("+%F %R %Z").format_with_offset(time, time, -5 * 3600 - 1800),
+            "2024-02-29 18:00 -0530"
+        );
+        assert_eq!(style("+%Z %s").format(time, time), "UTC 1709249400");
+        assert_eq!(
+            style("+%s").format_with_offset(time, time, 3600),
+            "1709249400"
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use std::fmt::Write;
+use std::path::PathBuf;
 use std::{collections::HashSet, hash::Hash};
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -32,13 +33,58 @@ pub fn vec_to_hex_string(vec: &[u8]) -> String {
 ///
 /// # Panics
 ///
-/// Panics if the input string is not a valid hexadecimal string.
+/// Panics if the input string is not a valid hexadecimal string (see `try_hex_string_to_vec`).
 #[must_use]
 pub fn hex_string_to_vec(hex_string: &str) -> Vec<u8> {
+    try_hex_string_to_vec(hex_string).expect("Invalid hexadecimal string")
+}
+
+/// Converts a hexadecimal string to a vector of bytes, failing on an invalid string.
+///
+/// # Arguments
+///
+/// * `hex_string` - A reference to a hexadecimal string.
+///
+/// # Returns
+///
+/// A vector of bytes representing the hexadecimal values in the string.
+///
+/// # Errors
+///
+/// An `io::Error` with the kind `InvalidData` if the string has an odd length or contains a character that isn't an
+/// hexadecimal digit.
+///
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::util::try_hex_string_to_vec;
+///
+/// assert_eq!(try_hex_string_to_vec("00ff1a").unwrap(), vec![0x00, 0xff, 0x1a]);
+/// assert!(try_hex_string_to_vec("0ff").is_err());
+/// assert!(try_hex_string_to_vec("zz").is_err());
+/// // The sign accepted by `u8::from_str_radix` isn't an hexadecimal digit
+/// assert!(try_hex_string_to_vec("+f").is_err());
+/// ```
+pub fn try_hex_string_to_vec(hex_string: &str) -> std::io::Result<Vec<u8>> {
+    let invalid = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Invalid hexadecimal string: {hex_string}"),
+        )
+    };
+    if hex_string.len() % 2 == 1 || !hex_string.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
     hex_string
         .as_bytes()
         .chunks(2)
-        .map(|chunk| u8::from_str_radix(&String::from_utf8(chunk.to_vec()).unwrap(), 16).unwrap())
+        .map(|chunk| {
+            std::str::from_utf8(chunk)
+                .ok()
+                .and_then(|chunk| u8::from_str_radix(chunk, 16).ok())
+                .ok_or_else(invalid)
+        })
         .collect()
 }
 
@@ -51,6 +97,20 @@ pub fn hex_string_to_vec(hex_string: &str) -> Vec<u8> {
 /// # Returns
 ///
 /// A mangled filename where certain characters are replaced with their hexadecimal representation.
+///
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::util::{mangle_filename, unmangle_filename};
+///
+/// assert_eq!(mangle_filename("My Documents"), "fMy Documents");
+/// assert_eq!(mangle_filename("/100% Données"), "f%2f100%25 Données");
+/// assert_eq!(unmangle_filename("f%2f100%25 Données"), "/100% Données");
+/// assert_eq!(unmangle_filename("fDonn%c3%a9es"), "Données");
+/// // An invalid escape is kept as is
+/// assert_eq!(unmangle_filename("f100%"), "100%");
+/// assert_eq!(unmangle_filename("f%zz%2"), "%zz%2");
+/// ```
 #[must_use]
 pub fn mangle_filename(path_um: &str) -> String {
     let mut path = String::new();
@@ -73,44 +133,156 @@ pub fn mangle_filename(path_um: &str) -> String {
     path
 }
 
-/// Unmangles a filename by replacing hexadecimal representations with their original characters.
+/// Mangles a filename given as the bytes stored in the backup, that may not be valid UTF-8 (see `mangle_filename`).
+///
+/// The valid UTF-8 sequences are mangled as with `mangle_filename`, and the other bytes are escaped in hexadecimal.
+/// As `mangle_filename` never escapes the bytes that aren't ASCII, these escapes are restored unambiguously by
+/// `mangled_path` when the mangled path is turned into a path of the file system.
 ///
 /// # Arguments
 ///
-/// * `path_m` - The mangled filename.
+/// * `name` - The bytes of the filename.
 ///
 /// # Returns
 ///
-/// An unmangled filename where hexadecimal representations are replaced with their original characters.
+/// A mangled filename, the bytes that aren't valid UTF-8 being escaped.
 ///
-/// # Panics
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::util::mangle_filename_bytes;
 ///
-/// Panics if the input string is not a valid mangled filename.
+/// assert_eq!(mangle_filename_bytes(b"caf\xe9 100%"), "fcaf%e9 100%25");
+/// assert_eq!(mangle_filename_bytes("café".as_bytes()), "fcafé");
+/// ```
 #[must_use]
-pub fn unmangle_filename(path_m: &str) -> String {
-    let mut path = String::new();
+pub fn mangle_filename_bytes(name: &[u8]) -> String {
+    if name.is_empty() {
+        return String::new();
+    }
 
-    if path_m.is_empty() {
-        return path;
+    // The mangled valid sequences, without the prefix of the mangled name
+    let mangle_valid = |valid: &str| {
+        mangle_filename(valid)
+            .get(1..)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let mut path = String::from("f");
+    let mut rest = name;
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                path.push_str(&mangle_valid(valid));
+                return path;
+            }
+            Err(err) => {
+                let (valid, invalid) = rest.split_at(err.valid_up_to());
+                path.push_str(&mangle_valid(
+                    std::str::from_utf8(valid).unwrap_or_default(),
+                ));
+                let invalid_len = err.error_len().unwrap_or(invalid.len());
+                for byte in &invalid[..invalid_len] {
+                    let _ = write!(path, "%{byte:02x}");
+                }
+                rest = &invalid[invalid_len..];
+            }
+        }
     }
+}
+
+/// Turns a mangled path into a path of the file system, restoring the bytes escaped by `mangle_filename_bytes`.
+///
+/// Only the escapes of the bytes that aren't ASCII (`%80` to `%ff`) are restored: the other escapes (`%25`, `%2f`,
+/// ...) are part of the names stored on disk.
+///
+/// # Arguments
+///
+/// * `path` - The mangled path.
+///
+/// # Returns
+///
+/// The path of the file system.
+///
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::util::mangled_path;
+/// use std::os::unix::ffi::OsStrExt;
+///
+/// let path = mangled_path("pc/f%2fhome/fcaf%e9");
+/// assert_eq!(path.as_os_str().as_bytes(), b"pc/f%2fhome/fcaf\xe9");
+/// ```
+#[must_use]
+pub fn mangled_path(path: &str) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
 
-    let mut chars = path_m.chars();
+        let bytes = path.as_bytes();
+        let mut restored = Vec::with_capacity(bytes.len());
+        let mut index = 0;
+        while index < bytes.len() {
+            let escaped = (bytes[index] == b'%')
+                .then(|| bytes.get(index + 1..index + 3))
+                .flatten()
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())
+                .filter(|byte| *byte >= 0x80);
+            match escaped {
+                Some(byte) => {
+                    restored.push(byte);
+                    index += 3;
+                }
+                None => {
+                    restored.push(bytes[index]);
+                    index += 1;
+                }
+            }
+        }
 
-    if chars.next().unwrap() != 'f' {
-        return path;
+        PathBuf::from(std::ffi::OsString::from_vec(restored))
     }
+    #[cfg(not(unix))]
+    PathBuf::from(path)
+}
+
+/// Unmangles a filename by replacing hexadecimal representations with their original characters.
+///
+/// # Arguments
+///
+/// * `path_m` - The mangled filename.
+///
+/// # Returns
+///
+/// An unmangled filename where hexadecimal representations are replaced with their original characters. A `%` that
+/// isn't followed by two hexadecimal digits is kept as is.
+#[must_use]
+pub fn unmangle_filename(path_m: &str) -> String {
+    let Some(path_m) = path_m.strip_prefix('f') else {
+        return String::new();
+    };
 
-    while let Some(c) = chars.next() {
-        if c == '%' {
-            let hex = chars.next().unwrap().to_string() + &chars.next().unwrap().to_string();
-            let byte = u8::from_str_radix(&hex, 16).unwrap();
-            path.push(byte as char);
+    // The escaped characters are decoded as bytes, so an escaped UTF-8 sequence is decoded as a single character
+    let bytes = path_m.as_bytes();
+    let mut path = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| bytes.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| try_hex_string_to_vec(hex).ok());
+        if let Some(escaped) = escaped {
+            path.extend_from_slice(&escaped);
+            index += 3;
         } else {
-            path.push(c);
+            path.push(bytes[index]);
+            index += 1;
         }
     }
 
-    path
+    String::from_utf8_lossy(&path).into_owned()
 }
 
 /// Mangles a file path by applying the `mangle_filename` function to each component of the path.
@@ -147,3 +319,284 @@ pub fn unique<T: Eq + Hash + Clone>(iterable: impl IntoIterator<Item = T>) -> Ve
     let unique_elts: HashSet<T> = HashSet::from_iter(iterable);
     unique_elts.into_iter().collect()
 }
+
+/// Escapes a string to be written inside the quotes of a JSON string.
+///
+/// # Arguments
+///
+/// * `value` - The string to escape.
+///
+/// # Returns
+///
+/// The escaped string (without the surrounding quotes).
+///
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::util::json_escape;
+///
+/// assert_eq!(json_escape("a \"b\"\n"), "a \\\"b\\\"\\n");
+/// ```
+#[must_use]
+pub fn json_escape(value: &str) -> String {
+    value.chars().fold(String::new(), |mut output, c| {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(output, "\\u{:04x}", c as u32);
+            }
+            c => output.push(c),
+        }
+        output
+    })
+}
+
+/// Escapes a field of a CSV record.
+///
+/// A field containing a comma, a quote, or a line break is quoted, and its quotes are doubled.
+///
+/// # Arguments
+///
+/// * `value` - The field to escape.
+///
+/// # Returns
+///
+/// The field as written in the CSV record.
+///
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::util::csv_escape;
+///
+/// assert_eq!(csv_escape("file.txt"), "file.txt");
+/// assert_eq!(csv_escape("a, \"b\""), "\"a, \"\"b\"\"\"");
+/// ```
+#[must_use]
+pub fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Returns the name of the entry of an inode in the inode directory of a backup, as written by `BackupPC`.
+///
+/// The entry is named with the bytes of the inode in little endian, up to the last non-zero byte (at least one byte,
+/// so the inode 0 is named `00`), written in hexadecimal.
+///
+/// # Arguments
+///
+/// * `inode` - The inode number.
+///
+/// # Returns
+///
+/// The name of the entry of the inode.
+///
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::util::inode_name;
+///
+/// assert_eq!(inode_name(0x20805), "050802");
+/// assert_eq!(inode_name(0), "00");
+/// ```
+#[must_use]
+pub fn inode_name(inode: u64) -> String {
+    let bytes = inode.to_le_bytes();
+    let len = bytes
+        .iter()
+        .rposition(|&x| x != 0)
+        .map_or(1, |last| last + 1);
+
+    vec_to_hex_string(&bytes[..len])
+}
+
+/// Parses the name of the entry of an inode in the inode directory of a backup.
+///
+/// The entry is named with the bytes of the inode in little endian, written in hexadecimal. The name is compared as a
+/// number, so a name padded with zero bytes (ex: `0508020000000000` for the inode 0x20805, named `050802` by
+/// `BackupPC`) still matches its inode.
+///
+/// # Arguments
+///
+/// * `name` - The name of the entry.
+///
+/// # Returns
+///
+/// The inode, or `None` if the name isn't the hexadecimal form of a 64 bits number.
+///
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::util::parse_inode_name;
+///
+/// assert_eq!(parse_inode_name("050802"), Some(0x20805));
+/// assert_eq!(parse_inode_name("0508020000000000"), Some(0x20805));
+/// assert_eq!(parse_inode_name("not-an-inode"), None);
+/// assert_eq!(parse_inode_name("+5"), None);
+/// ```
+#[must_use]
+pub fn parse_inode_name(name: &str) -> Option<u64> {
+    let bytes = try_hex_string_to_vec(name).ok()?;
+    if bytes.is_empty() || bytes.iter().skip(8).any(|&byte| byte != 0) {
+        return None;
+    }
+
+    let mut inode = [0u8; 8];
+    for (index, byte) in bytes.iter().take(8).enumerate() {
+        inode[index] = *byte;
+    }
+    Some(u64::from_le_bytes(inode))
+}
+
+/// Parses a RFC 3339 date and time (ex: `2024-03-01T12:00:00Z`, `2024-03-01T12:00:00+01:00`) to a Unix timestamp.
+///
+/// A date without time (ex: `2024-03-01`) is read as midnight UTC. The fraction of the seconds is ignored.
+///
+/// # Arguments
+///
+/// * `value` - The date and time to parse.
+///
+/// # Returns
+///
+/// The number of seconds since the Unix epoch, or `None` if the value is not a valid date (or is before the epoch).
+///
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::util::parse_rfc3339;
+///
+/// assert_eq!(parse_rfc3339("2024-03-01T00:00:00Z"), Some(1_709_251_200));
+/// assert_eq!(parse_rfc3339("2024-03-01T01:00:00.5+01:00"), Some(1_709_251_200));
+/// assert_eq!(parse_rfc3339("2024-03-01"), Some(1_709_251_200));
+/// assert_eq!(parse_rfc3339("2024-02-29"), Some(1_709_164_800));
+/// assert_eq!(parse_rfc3339("2024-13-01"), None);
+/// assert_eq!(parse_rfc3339("2024-02-31"), None);
+/// assert_eq!(parse_rfc3339("2023-02-29"), None);
+/// ```
+#[must_use]
+pub fn parse_rfc3339(value: &str) -> Option<u64> {
+    let (date, time) = match value.split_once(['T', 't', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+
+    let mut date = date.splitn(3, '-');
+    let year: i64 = date.next()?.parse().ok()?;
+    let month: i64 = date.next()?.parse().ok()?;
+    let day: i64 = date.next()?.parse().ok()?;
+    let is_leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if is_leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if !(1..=days_in_month).contains(&day) {
+        return None;
+    }
+
+    let (seconds, offset) = match time {
+        None => (0, 0),
+        Some(time) => {
+            let (time, offset) = match time.strip_suffix(['Z', 'z']) {
+                Some(time) => (time, 0),
+                None => {
+                    let (time, offset) = time.split_at(time.rfind(['+', '-'])?);
+                    let sign = if offset.starts_with('-') { -1 } else { 1 };
+                    let (hours, minutes) = offset[1..].split_once(':')?;
+                    let hours: i64 = hours.parse().ok()?;
+                    let minutes: i64 = minutes.parse().ok()?;
+                    (time, sign * (hours * 3600 + minutes * 60))
+                }
+            };
+
+            let mut time = time.splitn(3, ':');
+            let hours: i64 = time.next()?.parse().ok()?;
+            let minutes: i64 = time.next()?.parse().ok()?;
+            let seconds = time.next()?;
+            let seconds: i64 = seconds
+                .split_once('.')
+                .map_or(seconds, |(seconds, _)| seconds)
+                .parse()
+                .ok()?;
+            if !(0..24).contains(&hours)
+                || !(0..60).contains(&minutes)
+                || !(0..=60).contains(&seconds)
+            {
+                return None;
+            }
+
+            (hours * 3600 + minutes * 60 + seconds, offset)
+        }
+    };
+
+    // Number of days since the epoch of the civil date (proleptic Gregorian calendar)
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    u64::try_from(days * 86_400 + seconds - offset).ok()
+}
+
+/// Formats a size as the `-h` option of `ls` does: in bytes below 1024, else with a unit (K, M, G, ...) in powers of
+/// 1024, rounded up to one decimal below 10 and to an integer above.
+///
+/// # Arguments
+///
+/// * `size` - The size in bytes.
+///
+/// # Returns
+///
+/// The size with its unit.
+///
+/// # Examples
+///
+/// ```
+/// use backuppc_pool_reader::util::human_size;
+///
+/// assert_eq!(human_size(512), "512");
+/// assert_eq!(human_size(1024), "1.0K");
+/// assert_eq!(human_size(1500), "1.5K");
+/// assert_eq!(human_size(10_000), "9.8K");
+/// assert_eq!(human_size(20_000), "20K");
+/// assert_eq!(human_size(1_048_575), "1.0M");
+/// assert_eq!(human_size(5 * 1024 * 1024 * 1024), "5.0G");
+/// ```
+#[must_use]
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+
+    if size < 1024 {
+        return size.to_string();
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    loop {
+        // Round up as ls does, then move to the next unit if the rounded value reaches 1024
+        let rounded = if value < 10.0 {
+            (value * 10.0).ceil() / 10.0
+        } else {
+            value.ceil()
+        };
+        if rounded >= 1024.0 && unit + 1 < UNITS.len() {
+            value /= 1024.0;
+            unit += 1;
+        } else if rounded < 10.0 {
+            return format!("{rounded:.1}{}", UNITS[unit]);
+        } else {
+            return format!("{rounded:.0}{}", UNITS[unit]);
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use log::info;
+use log::{info, warn};
 use lru::LruCache;
+use std::cmp::Reverse;
 use std::collections::HashMap;
-use std::fs::File;
 /// In this application we have
 /// - the host list
 /// - the backup list of a host
@@ -14,12 +14,22 @@ use std::fs::File;
 /// - merge the list of file list from incremental backups
 /// - cache the metadata of the files in case of multiple access
 ///
-use std::io::Read;
+use std::io::{BufRead, BufReader, Cursor, Read};
 use std::num::NonZeroUsize;
-
-use crate::compress::BackupPCReader;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::cache::{CacheMetrics, MeteredCache};
+use crate::cancel::CancellationToken;
+use crate::clock::{Clock, SystemClock};
+use crate::config::{BackupPCConfig, PoolConfig, POOL_SIZES_CACHE_SIZE};
 use crate::decode_attribut::{FileAttributes, FileType};
 
+use crate::attribute_file::{select_inode, Search, ATTRIB_PREFIX};
+use crate::hosts::Hosts;
+
 #[cfg(not(test))]
 use crate::attribute_file::SearchTrait;
 #[cfg(not(test))]
@@ -29,29 +39,220 @@ use crate::hosts::HostsTrait;
 use crate::attribute_file::SearchTrait;
 #[cfg(test)]
 use crate::hosts::HostsTrait;
-use crate::pool::find_file_in_backuppc;
-use crate::util::{unique, vec_to_hex_string, Result};
+use crate::pool::{
+    decompressed_size, find_file_in_pool_roots, open_content, parse_digest,
+    read_by_digest_from_pool_roots, TruncatedPoolFileError,
+};
+use crate::reader::{read_all_to_vec, skip, LimitedReader, PaddedReader};
+use crate::selector::{backup_at_time, BackupSelector};
+use crate::source::{LocalSource, PoolSource};
+#[cfg(feature = "encoding")]
+use crate::util::mangle_filename_bytes;
+use crate::util::{inode_name, mangle, mangle_filename, unique, Result};
 
 // Empty md5 digest (Vec<u8>) : d41d8cd98f00b204e9800998ecf8427e
 const EMPTY_MD5_DIGEST: [u8; 16] = [
     0xd4, 0x1d, 0x8c, 0xd9, 0x8f, 0x00, 0xb2, 0x04, 0xe9, 0x80, 0x09, 0x98, 0xec, 0xf8, 0x42, 0x7e,
 ];
 
+/// Returns the key of the listing of a path in the cache of the view.
+fn cache_key(path: &[&str]) -> String {
+    path.iter()
+        .filter(|s| !s.is_empty())
+        .map(std::string::ToString::to_string)
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Returns the location of an inode in the inode directory of a backup: the directory, the prefix of the attrib file,
+/// and the name of the entry of the inode in this attrib file.
+///
+/// This is the scheme of `BackupPC` (`inodePath` in `bpc_attribCache.c`): the directory and the attrib file are
+/// selected with 7 bits each (bits 17 to 23 and bits 10 to 16), so the inodes above 2^24 share the buckets of the
+/// lower inodes. The entry is named as `util::inode_name`.
+fn inode_location(inode: u64) -> (String, String, String) {
+    let attrib_path = format!("inode/{:02x}", inode >> 17 & 0x7F);
+    let attrib_file = format!("attrib{:02x}_", inode >> 10 & 0x7F);
+
+    (attrib_path, attrib_file, inode_name(inode))
+}
+
+/// Returns the key of the cache of the attrib file where an inode is stored (see `inode_location`).
+fn inode_cache_key(hostname: &str, backup_number: u32, inode: u64) -> String {
+    let (attrib_path, attrib_file, _) = inode_location(inode);
+    format!("inode:{hostname}/{backup_number}/{attrib_path}/{attrib_file}")
+}
+
+fn directory_not_found(path: &[&str]) -> Box<dyn std::error::Error> {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("Directory not found: {}", path.join("/")),
+    )
+    .into()
+}
+
+/// The view on the hosts, backups, and files of a `BackupPC` pool.
+///
+/// The cache of the view is protected by a mutex, so the view can be used from multiple threads through a shared
+/// reference (see `SharedBackupPC`).
 pub struct BackupPC {
     topdir: String,
     hosts: Box<dyn HostsTrait>,
     search: Box<dyn SearchTrait>,
-    cache: LruCache<String, Vec<FileAttributes>>,
+    source: Arc<dyn PoolSource>,
+    pool_roots: Vec<PathBuf>,
+    clock: Arc<dyn Clock>,
+    cache: Mutex<MeteredCache<String, Vec<FileAttributes>>>,
+    inode_paths: Mutex<MeteredCache<(String, u32), InodePaths>>,
+    /// Index of the sizes of the contents of the listed files, by digest (see `pool_file_size`).
+    pool_sizes: Mutex<MeteredCache<Vec<u8>, u64>>,
+    small_files: Mutex<SmallFileCache>,
+    small_file_size: u64,
+    read_chunk_size: usize,
+    show_deleted: bool,
+    hide_partial: bool,
+    case_insensitive: bool,
+    windows_separators: bool,
+    lenient: bool,
+    strict_size: bool,
+    skipped_errors: AtomicUsize,
+    scan_inodes: bool,
+    backup_order: BackupOrder,
+    max_read_size: Option<u64>,
+    cancel: CancellationToken,
+    #[cfg(feature = "encoding")]
+    transcode_names: bool,
+}
+
+/// Cache of the decompressed content of small files, keyed by digest.
+///
+/// The cache is bounded by the total size of the contents: the least recently used contents are evicted when a new
+/// content doesn't fit.
+struct SmallFileCache {
+    contents: LruCache<Vec<u8>, Arc<[u8]>>,
+    size: usize,
+    capacity: usize,
+}
+
+impl SmallFileCache {
+    fn new(capacity: usize) -> Self {
+        SmallFileCache {
+            contents: LruCache::unbounded(),
+            size: 0,
+            capacity,
+        }
+    }
+
+    /// A cache with a capacity of 0 is disabled: the small files are read as the other files.
+    fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    fn get(&mut self, digest: &[u8]) -> Option<Arc<[u8]>> {
+        self.contents.get(digest).cloned()
+    }
+
+    fn insert(&mut self, digest: Vec<u8>, content: Arc<[u8]>) {
+        if content.len() > self.capacity {
+            return;
+        }
+
+        self.size += content.len();
+        if let Some(previous) = self.contents.put(digest, content) {
+            self.size -= previous.len();
+        }
+        while self.size > self.capacity {
+            match self.contents.pop_lru() {
+                Some((_, evicted)) => self.size -= evicted.len(),
+                None => break,
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.contents.clear();
+        self.size = 0;
+    }
+}
+
+/// Order of the backups listed in the directory of a host.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackupOrder {
+    /// Sort the backups by name, as the other directories.
+    #[default]
+    Name,
+    /// Sort the backups by start time, the most recent backup first.
+    NewestFirst,
+}
+
+impl FromStr for BackupOrder {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "name" => Ok(BackupOrder::Name),
+            "newest-first" => Ok(BackupOrder::NewestFirst),
+            _ => Err(format!(
+                "Invalid backup order {value} (expected name or newest-first)"
+            )),
+        }
+    }
+}
+
+/// Paths of the files with hard links of a backup, grouped by inode.
+type InodePaths = HashMap<u64, Vec<Vec<String>>>;
+
+/// The content of a file of the view, with the metadata resolved to open it (see `BackupPC::read_file_with_meta`).
+pub struct FileReader {
+    /// The reader on the (decompressed) content of the file.
+    pub reader: Box<dyn Read + Sync + Send>,
+    /// The type of the file (the content of a directory is empty).
+    pub type_: FileType,
+    /// The size of the file, as stored in its attributes.
+    pub size: u64,
+    /// `true` if the content of the file is stored compressed (in the cpool), as stored in its attributes.
+    pub is_compressed: bool,
+    /// The digest of the content of the file in the pool (empty for a file without content).
+    pub digest: Vec<u8>,
+}
+
+impl Read for FileReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+/// How a path inside a backup maps onto the shares of the backup.
+///
+/// For a backup with the shares `/home` and `/volume1/test`, the path `volume1` is not in a share and contains the
+/// sub-directory `test` (leading to the share `/volume1/test`), while the path `volume1/test/dir` is the directory
+/// `dir` of the share `/volume1/test`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareResolution {
+    /// The sub-directories of the path that lead to a share (the first component after the path of the shares
+    /// starting with the path).
+    pub subdirs: Vec<String>,
+    /// The share containing the path (the most specific one), if any.
+    pub selected_share: Option<String>,
+    /// The number of components of the path used by the selected share. The remaining components are the path
+    /// inside the share.
+    pub share_depth: usize,
+}
+
+/// Returns `true` if the content of the file is stored in the pool (directories and empty files have no content).
+fn has_content(file: &FileAttributes) -> bool {
+    file.type_ != FileType::Dir
+        && file.bpc_digest.len > 2
+        && !file.bpc_digest.digest.eq(&EMPTY_MD5_DIGEST)
 }
 
-fn sanitize_path(path: &str) -> Vec<&str> {
-    path.split('/')
+/// Splits a path in its components, on the slashes and, if `backslash` is `true`, on the backslashes.
+fn sanitize_path(path: &str, backslash: bool) -> Vec<&str> {
+    path.split(|c| c == '/' || (backslash && c == '\\'))
         .filter(|s| !s.is_empty())
         .collect::<Vec<&str>>()
 }
 
-const CACHE_SIZE: usize = 1000;
-
 /// Implementation of the `BackupPC` struct.
 impl BackupPC {
     /// Creates a new `BackupPC` instance with the given parameters.
@@ -71,11 +272,54 @@ impl BackupPC {
     /// The method can't panic
     #[must_use]
     pub fn new(topdir: &str, hosts: Box<dyn HostsTrait>, search: Box<dyn SearchTrait>) -> Self {
-        BackupPC {
-            topdir: topdir.to_string(),
-            hosts,
-            search,
-            cache: LruCache::new(NonZeroUsize::new(CACHE_SIZE).unwrap()),
+        Self::new_with_config(topdir, hosts, search, &BackupPCConfig::default())
+    }
+
+    /// Checks that a directory is the top directory of a `BackupPC` pool.
+    ///
+    /// The top directory must contain the `pc` directory (the backups of the hosts), and a `pool` or a `cpool`
+    /// directory (the files of the backups). Checking them up front gives a clear error when the top directory is
+    /// wrong, instead of an error on the first file read.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source used to access the files of the top directory.
+    /// * `topdir` - The top directory path.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `NotFound` naming what is missing if the top directory doesn't exist, or doesn't contain the
+    /// expected directories.
+    pub fn validate_topdir(source: &dyn PoolSource, topdir: &str) -> Result<()> {
+        let path = Path::new(topdir);
+        if !source.exists(path) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("The BackupPC top directory {topdir} doesn't exist (see BPC_TOPDIR)"),
+            )
+            .into());
+        }
+
+        let mut missing = Vec::new();
+        if !source.exists(&path.join("pc")) {
+            missing.push("the pc directory");
+        }
+        if !source.exists(&path.join("pool")) && !source.exists(&path.join("cpool")) {
+            missing.push("a pool or cpool directory");
+        }
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!(
+                    "{topdir} is not a BackupPC top directory: {} {} missing (see BPC_TOPDIR)",
+                    missing.join(" and "),
+                    if missing.len() > 1 { "are" } else { "is" }
+                ),
+            )
+            .into())
         }
     }
 
@@ -103,413 +347,1765 @@ impl BackupPC {
         search: Box<dyn SearchTrait>,
         capacity: usize,
     ) -> Self {
-        BackupPC {
-            topdir: topdir.to_string(),
+        let config = BackupPCConfig::default().with_view_cache_size(capacity);
+
+        Self::new_with_config(topdir, hosts, search, &config)
+    }
+
+    /// Creates a new `BackupPC` instance tuned with the given configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `topdir` - The top directory path.
+    /// * `hosts` - A boxed trait object implementing the `HostsTrait` trait.
+    /// * `search` - A boxed trait object implementing the `SearchTrait` trait.
+    /// * `config` - The configuration (the cache capacities of the view and the cache of the small files are used).
+    ///
+    /// # Returns
+    ///
+    /// A new `BackupPC` instance.
+    ///
+    /// # Panics
+    ///
+    /// If a cache capacity is zero.
+    #[must_use]
+    pub fn new_with_config(
+        topdir: &str,
+        hosts: Box<dyn HostsTrait>,
+        search: Box<dyn SearchTrait>,
+        config: &BackupPCConfig,
+    ) -> Self {
+        Self::from_parts(
+            topdir,
             hosts,
             search,
-            cache: LruCache::new(NonZeroUsize::new(capacity).unwrap()),
-        }
+            Arc::new(LocalSource),
+            Vec::new(),
+            config,
+        )
     }
 
-    /// Lists the files from the specified inode in the backuppc inode directory.
+    /// Creates a new `BackupPC` instance reading the pool described by the configuration.
     ///
-    /// The result is cached for performance.
+    /// The hosts and the attrib files are read with `Hosts::with_config` and `Search::with_config`, from the same
+    /// configuration, so the view, the hosts, and the search share the top directory, the source (with its retry
+    /// policy), and the additional pool roots of the pool.
     ///
     /// # Arguments
     ///
-    /// * `hostname` - The hostname of the backup.
-    /// * `backup_number` - The backup number.
-    /// * `inode` - The inode number.
+    /// * `pool` - The configuration of the pool.
+    /// * `config` - The configuration (the cache capacities of the view and the cache of the small files are used).
     ///
     /// # Returns
     ///
-    /// A vector of `FileAttributes` instances.
-    fn list_file_from_inode(
-        &mut self,
-        hostname: &str,
-        backup_number: u32,
-        inode: u64,
-    ) -> Result<Vec<FileAttributes>> {
-        let inode_dir = inode >> 17 & 0x7F;
-        let inode_file = inode >> 10 & 0x7F;
-        let attrib_path = format!("inode/{inode_dir:02x}");
-        let attrib_file = format!("attrib{inode_file:02x}_");
+    /// A new `BackupPC` instance.
+    ///
+    /// # Panics
+    ///
+    /// If a cache capacity is zero.
+    #[must_use]
+    pub fn with_pool_config(pool: &PoolConfig, config: &BackupPCConfig) -> Self {
+        Self::from_parts(
+            &pool.topdir,
+            Box::new(Hosts::with_config(pool)),
+            Box::new(Search::with_config(pool)),
+            pool.source(),
+            pool.pool_roots.clone(),
+            config,
+        )
+    }
 
-        let key = format!("{attrib_path}/{attrib_file}");
+    /// Creates a new `BackupPC` instance from all its parts, used by the other constructors.
+    ///
+    /// # Panics
+    ///
+    /// If a cache capacity is zero.
+    fn from_parts(
+        topdir: &str,
+        hosts: Box<dyn HostsTrait>,
+        search: Box<dyn SearchTrait>,
+        source: Arc<dyn PoolSource>,
+        pool_roots: Vec<PathBuf>,
+        config: &BackupPCConfig,
+    ) -> Self {
+        BackupPC {
+            topdir: topdir.to_string(),
+            hosts,
+            search,
+            source,
+            pool_roots,
+            clock: Arc::new(SystemClock),
+            cache: Mutex::new(MeteredCache::new(
+                NonZeroUsize::new(config.view_cache_size).unwrap(),
+            )),
+            inode_paths: Mutex::new(MeteredCache::new(
+                NonZeroUsize::new(config.inode_paths_cache_size).unwrap(),
+            )),
+            pool_sizes: Mutex::new(MeteredCache::new(
+                NonZeroUsize::new(POOL_SIZES_CACHE_SIZE).unwrap(),
+            )),
+            small_files: Mutex::new(SmallFileCache::new(config.small_file_cache_size)),
+            small_file_size: config.small_file_size,
+            read_chunk_size: config.read_chunk_size,
+            show_deleted: false,
+            hide_partial: false,
+            case_insensitive: false,
+            windows_separators: false,
+            lenient: false,
+            strict_size: false,
+            skipped_errors: AtomicUsize::new(0),
+            scan_inodes: false,
+            backup_order: BackupOrder::Name,
+            max_read_size: None,
+            cancel: CancellationToken::new(),
+            #[cfg(feature = "encoding")]
+            transcode_names: false,
+        }
+    }
 
-        info!("List file from inode {inode} with the key {key}");
+    /// Defines the clock giving the current time, used to date the mount of the file system built on the view (see
+    /// `BackupPCFS::new_with_view`).
+    ///
+    /// By default, the clock of the system is used. A `ManualClock` can be given to control the current time in
+    /// tests.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The clock of the view.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
 
-        if let Some(cached_result) = self.cache.get(&key) {
-            return Ok(cached_result.clone());
-        }
+    /// Returns the clock of the view (see `set_clock`).
+    #[must_use]
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
 
-        let mut result =
-            self.search
-                .list_attributes(hostname, backup_number, &attrib_path, &attrib_file)?;
+    /// Sets the token used to abort the traversals of the view (`paths_for_inode`, `list_non_utf8_names`, ...).
+    ///
+    /// By default, each view has its own token, that is never cancelled unless `cancellation_token` is used. A token
+    /// can be shared between the view and a signal handler, or the `destroy` of the file system, to stop the
+    /// traversals in progress.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token of the view.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancel = token;
+    }
 
-        result.sort_by(|a, b| a.name.cmp(&b.name));
-        self.cache.put(key, result.clone());
+    /// Returns the token used to abort the traversals of the view (see `set_cancellation_token`).
+    #[must_use]
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancel
+    }
 
-        Ok(result)
+    /// Returns the size of the chunks used to read (and skip) the content of a file (see
+    /// `BackupPCConfig::read_chunk_size`).
+    #[must_use]
+    pub fn read_chunk_size(&self) -> usize {
+        self.read_chunk_size
+    }
+
+    /// Returns the counters of the accesses to the cache of the listings of the view, to tune its capacity (see
+    /// `BackupPCConfig::view_cache_size`).
+    #[must_use]
+    pub fn cache_metrics(&self) -> CacheMetrics {
+        self.cache().metrics()
     }
 
-    /// Gets the inode from the specified path.
+    /// Defines if the files deleted in an incremental backup are listed.
     ///
-    /// This method use the method `list_file_from_inode` to get the inode from the path. As the previous method cache
-    /// the result, this method have good performance is the read are sequential.
+    /// By default, a file deleted in an incremental backup (a `FileType::Deleted` entry) is removed from the
+    /// listing. When `show_deleted` is `true`, the deleted file is kept in the listing as a tombstone with the type
+    /// `FileType::Deleted`. A tombstone can't be read and doesn't exist for `exists`.
     ///
     /// # Arguments
     ///
-    /// * `hostname` - The hostname of the backup.
-    /// * `backup_number` - The backup number.
-    /// * `inode` - The inode number.
+    /// * `show_deleted` - `true` to list the deleted files.
+    pub fn set_show_deleted(&mut self, show_deleted: bool) {
+        self.show_deleted = show_deleted;
+        self.cache().clear();
+        self.inode_paths().clear();
+    }
+
+    /// Defines if the partial backups are hidden.
     ///
-    /// # Returns
+    /// A partial backup (see `BackupInformation::is_partial`) doesn't contain all the files of the host. When
+    /// `hide_partial` is `true`, the partial backups are not listed in the backups of a host, and their files can't be
+    /// accessed.
     ///
-    /// A `FileAttributes` instance.
-    fn get_inode(
-        &mut self,
-        hostname: &str,
-        backup_number: u32,
-        inode: u64,
-    ) -> Result<Option<FileAttributes>> {
-        let mut inode_vec = inode.to_le_bytes().to_vec();
-        if let Some(last_non_zero) = inode_vec.iter().rposition(|&x| x != 0) {
-            inode_vec.truncate(last_non_zero + 1);
-        }
-
-        let inode_str = vec_to_hex_string(&inode_vec);
-
-        info!("Search inode {inode} with the str form {inode_str}");
-
-        let files = self.list_file_from_inode(hostname, backup_number, inode)?;
-        let inode = files.iter().find(|i| i.name == inode_str);
+    /// # Arguments
+    ///
+    /// * `hide_partial` - `true` to hide the partial backups.
+    pub fn set_hide_partial(&mut self, hide_partial: bool) {
+        self.hide_partial = hide_partial;
+        self.cache().clear();
+        self.inode_paths().clear();
+    }
 
-        Ok(inode.cloned())
+    /// Defines if the names of the path are matched case-insensitively.
+    ///
+    /// Windows (SMB) shares are case-insensitive, so a path typed by a user can differ in case from the path stored
+    /// in the backup. When `case_insensitive` is `true`, each component of a path is matched with the stored name
+    /// ignoring the case (an exact match is preferred). The results keep the casing stored in the backup.
+    ///
+    /// # Arguments
+    ///
+    /// * `case_insensitive` - `true` to ignore the case when matching the names of a path.
+    pub fn set_case_insensitive(&mut self, case_insensitive: bool) {
+        self.case_insensitive = case_insensitive;
     }
 
-    /// Lists the files from the specified directory.
+    /// Defines if the backslashes of the paths of the Windows backups are separators.
+    ///
+    /// By default, a backslash is a literal character of a name, as on the other systems: the names stored in the
+    /// backup are matched as is. When `windows_separators` is `true`, the backslashes of the paths (and of the share
+    /// names) of the backups made with the `smb` transfer method are handled as the slashes, so a Windows path
+    /// (ex: `Users\bob\notes.txt`) can be used. The backups made with another transfer method are not changed, as
+    /// their names can contain backslashes.
     ///
     /// # Arguments
     ///
-    /// * `hostname` - The hostname of the backup.
-    /// * `backup_number` - The backup number.
-    /// * `share` - The share name.
-    /// * `filename` - The filename.
+    /// * `windows_separators` - `true` to split the paths of the Windows backups on the backslashes.
+    pub fn set_windows_separators(&mut self, windows_separators: bool) {
+        self.windows_separators = windows_separators;
+        self.cache().clear();
+        self.inode_paths().clear();
+    }
+
+    /// Defines if a directory whose attrib file can't be decoded stops the listing.
     ///
-    /// # Returns
+    /// By default, an attrib file that can't be read or decoded (in the current backup or in a backup it is filled
+    /// from), or an inode that can't be resolved, is an error. When `lenient` is `true`, the error is logged and
+    /// skipped: the files of the other backups are still listed, so a corrupt directory is listed as empty instead
+    /// of failing. A listing that skipped an error isn't cached, so the directory is read again on the next access.
     ///
-    /// A vector of `FileAttributes` instances.
+    /// # Arguments
     ///
-    /// # Errors
+    /// * `lenient` - `true` to continue the listing past a corrupt attrib file.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+        self.cache().clear();
+        self.inode_paths().clear();
+    }
+
+    /// Defines if a content that doesn't match the size of its file is an error.
     ///
-    /// An error can't be returned if the hosts, backup, can't be read
+    /// By default, a content shorter than the size stored in the attributes of its file is padded with zeros, and a
+    /// longer content is returned as is, with a warning (see `PaddedReader`). When `strict_size` is `true`, reading
+    /// such a content fails instead: with an error of kind `UnexpectedEof` if it is shorter, and `InvalidData` if it
+    /// is longer, so a truncated or corrupted pool file is never served as the content of the file.
     ///
-    fn list_file_from_dir(
-        &mut self,
-        hostname: &str,
-        backup_number: u32,
-        share: Option<&str>,
-        filename: Option<&str>,
-    ) -> Result<Vec<FileAttributes>> {
-        info!(
-            "List file from dir: {hostname}/{backup_number}/{}/{}",
-            share.unwrap_or_default(),
-            filename.unwrap_or_default()
-        );
-        // First search the next oldest filled backup next to the current backup
-        let backups_to_search = self.hosts.list_backups_to_fill(hostname, backup_number);
+    /// # Arguments
+    ///
+    /// * `strict_size` - `true` to fail on a content that doesn't match the size of its file.
+    pub fn set_strict_size(&mut self, strict_size: bool) {
+        self.strict_size = strict_size;
+        self.small_files().clear();
+    }
 
-        // Next search the file from the oldest filled backup to the current backup
-        let mut files: HashMap<String, FileAttributes> = HashMap::new();
-        for backup in backups_to_search {
-            info!("Search in backup: {backup}", backup = backup.num);
+    /// Defines how the inodes of the hard links are read when their attrib file isn't cached.
+    ///
+    /// By default, the whole attrib file of an inode is decoded and cached (see `resolve_inode`), so the next inodes
+    /// of the same attrib file are found in memory. When `scan_inodes` is `true`, an attrib file not in the cache is
+    /// scanned for the inode instead (see `get_inode`), without being kept in memory: this fits the backups with very
+    /// large inode attrib files, at the cost of a new scan for each inode.
+    ///
+    /// # Arguments
+    ///
+    /// * `scan_inodes` - `true` to scan the attrib files of the inodes instead of caching them.
+    pub fn set_scan_inodes(&mut self, scan_inodes: bool) {
+        self.scan_inodes = scan_inodes;
+    }
 
-            let files_from_backup = self
-                .search
-                .list_file_from_dir(hostname, backup.num, share, filename)?;
+    /// Returns `true` if the backslashes of the paths of the backup are separators (see `set_windows_separators`).
+    fn has_windows_separators(&self, hostname: &str, backup_number: &str) -> Result<bool> {
+        if !self.windows_separators {
+            return Ok(false);
+        }
 
-            for mut file in files_from_backup {
-                if file.type_ == FileType::Deleted {
-                    files.remove(&file.name);
-                } else {
-                    if file.nlinks > 0 {
-                        let inode = file.inode;
-                        info!(
-                            "File {file} has nlinks {nlinks} (inode: {inode})",
-                            file = file.name,
-                            nlinks = file.nlinks
-                        );
-                        let inode_file = self.get_inode(hostname, backup.num, inode)?;
-                        if let Some(inode_file) = inode_file {
-                            file.bpc_digest = inode_file.bpc_digest.clone();
-                        }
-                    }
+        Ok(self
+            .hosts
+            .list_backups(hostname)?
+            .iter()
+            .any(|backup| backup.num.to_string() == backup_number && backup.xfer_method == "smb"))
+    }
 
-                    files.insert(file.name.clone(), file);
-                }
-            }
+    /// Splits the components of the path on the backslashes, if they are separators in the backup of the path.
+    fn split_separators<'a>(&self, path: &[&'a str]) -> Result<Vec<&'a str>> {
+        let path: Vec<&'a str> = path.iter().copied().filter(|s| !s.is_empty()).collect();
+        if path.len() <= 2 || !path[2..].iter().any(|component| component.contains('\\')) {
+            return Ok(path);
+        }
+        if !self.has_windows_separators(path[0], path[1])? {
+            return Ok(path);
         }
 
-        Ok(files.values().cloned().collect())
+        Ok(path[..2]
+            .iter()
+            .copied()
+            .chain(
+                path[2..]
+                    .iter()
+                    .flat_map(|component| sanitize_path(component, true)),
+            )
+            .collect())
     }
 
-    /// Lists the shares of the specified backup.
+    /// Defines the order of the backups listed in the directory of a host.
     ///
-    /// # Arguments
+    /// By default, the backups are sorted by name, as the other directories. With `BackupOrder::NewestFirst`, the
+    /// most recent backup is listed first.
     ///
-    /// * `hostname` - The hostname of the backup.
-    /// * `backup_number` - The backup number.
+    /// # Arguments
     ///
-    /// # Returns
+    /// * `backup_order` - The order of the backups of a host.
+    pub fn set_backup_order(&mut self, backup_order: BackupOrder) {
+        self.backup_order = backup_order;
+        self.cache().clear();
+    }
+
+    /// Defines the maximum number of bytes produced by a read of a file.
     ///
-    /// A vector of share names.
+    /// By default, the content of a file is read whole. When serving untrusted clients, a limit guards against a
+    /// decompression bomb (a small compressed pool file expanding to a huge content): `read_file` and `read_range`
+    /// fail with an error of kind `InvalidData` when the content of the file goes past the limit (see
+    /// `LimitedReader`).
     ///
-    /// # Errors
+    /// # Arguments
     ///
-    /// An error can't be returned if the hosts, backup, can't be read
-    pub fn list_shares(&mut self, hostname: &str, backup_number: u32) -> Result<Vec<String>> {
-        info!("List shares: {hostname}/{backup_number}");
-        let files = self.list_file_from_dir(hostname, backup_number, None, None)?;
-        let shares = files
-            .iter()
-            .filter(|f| f.type_ == FileType::Dir)
-            .map(|f| f.name.clone())
-            .collect();
-        Ok(shares)
+    /// * `max_read_size` - The maximum number of bytes of the content of a file, or `None` for no limit.
+    pub fn set_max_read_size(&mut self, max_read_size: Option<u64>) {
+        self.max_read_size = max_read_size;
     }
 
-    /// Lists the shares of the specified path.
+    /// Defines if the names that aren't valid UTF-8 are transcoded from the charset of their backup.
+    ///
+    /// `BackupPC` records the charset of the client in each backup (`BackupInformation::charset`, ex: `cp1252` for
+    /// a Windows client). When `transcode_names` is `true`, the names of the files of a backup that aren't valid
+    /// UTF-8 are transcoded from this charset (see `FileAttributes::transcode_name`), so they are listed with their
+    /// characters instead of an empty name. The files can be read by their transcoded name, and the directories are
+    /// listed by their transcoded name (their directory in the backup being found by the stored bytes).
     ///
     /// # Arguments
     ///
-    /// * `hostname` - The hostname of the backup.
-    /// * `backup_number` - The backup number.
-    /// * `path` - The path to the file.
+    /// * `transcode_names` - `true` to transcode the names from the charset of the backup.
+    #[cfg(feature = "encoding")]
+    pub fn set_transcode_names(&mut self, transcode_names: bool) {
+        self.transcode_names = transcode_names;
+        self.cache().clear();
+    }
+
+    /// Transcodes the names of the files of a backup that aren't valid UTF-8 from the charset of the backup.
+    #[cfg(feature = "encoding")]
+    fn transcode_names_of(
+        &self,
+        hostname: &str,
+        backup_number: &str,
+        files: &mut [FileAttributes],
+    ) -> Result<()> {
+        if files.iter().all(|file| file.name_bytes.is_none()) {
+            return Ok(());
+        }
+
+        let charset = self
+            .hosts
+            .list_backups(hostname)?
+            .into_iter()
+            .find(|backup| backup.num.to_string() == backup_number)
+            .map(|backup| backup.charset)
+            .unwrap_or_default();
+        if charset.is_empty() {
+            return Ok(());
+        }
+
+        for file in files {
+            file.transcode_name(&charset);
+        }
+
+        Ok(())
+    }
+
+    /// Builds the mangled path of a directory of a share whose path contains transcoded names (see
+    /// `set_transcode_names`): the directory of a transcoded name is named in the backup with the bytes stored in the
+    /// attrib file, found in the listing of its parent.
     ///
-    /// # Returns
+    /// Returns `None` if no name of the path is transcoded, the directory being found by its path.
+    #[cfg(feature = "encoding")]
+    fn raw_attrib_path(
+        &self,
+        path: &[&str],
+        share: &str,
+        share_depth: usize,
+    ) -> Result<Option<String>> {
+        let first = 2 + share_depth;
+        // A transcoded name has characters that aren't ASCII (the stored bytes weren't valid UTF-8)
+        if !self.transcode_names || path[first..].iter().all(|name| name.is_ascii()) {
+            return Ok(None);
+        }
+
+        let mut transcoded = false;
+        let mut components = vec![mangle_filename(share)];
+        for depth in first..path.len() {
+            let raw_name = self
+                .list_exact(&path[..depth])?
+                .into_iter()
+                .find(|file| file.type_ == FileType::Dir && file.has_name(path[depth]))
+                .and_then(|file| file.name_bytes);
+            match raw_name {
+                Some(raw_name) => {
+                    transcoded = true;
+                    components.push(mangle_filename_bytes(&raw_name));
+                }
+                None => components.push(mangle_filename(path[depth])),
+            }
+        }
+
+        Ok(transcoded.then(|| components.join("/")))
+    }
+
+    /// Resolves each component of the path to the name stored in the backup, if the view is case-insensitive.
     ///
-    /// A tuple containing the shares, the selected share, and the share size.
+    /// Components that don't match any file are kept as is.
     ///
-    /// # Errors
+    /// The backslashes of the path are split first, if they are separators (see `set_windows_separators`).
+    fn resolve_case(&self, path: &[&str]) -> Result<Vec<String>> {
+        let path = self.split_separators(path)?;
+        let path = path.iter();
+        if !self.case_insensitive {
+            return Ok(path.map(std::string::ToString::to_string).collect());
+        }
+
+        let mut resolved: Vec<String> = Vec::new();
+        for component in path {
+            let parent = resolved.iter().map(String::as_str).collect::<Vec<&str>>();
+            let files = self.list_exact(&parent)?;
+            let files = files.iter().filter(|f| f.type_ != FileType::Deleted);
+
+            let name = files
+                .clone()
+                .find(|f| f.has_name(component))
+                .or_else(|| {
+                    let component = component.to_lowercase();
+                    files.clone().find(|f| f.name.to_lowercase() == component)
+                })
+                .map_or_else(|| (*component).to_string(), |f| f.name.clone());
+
+            resolved.push(name);
+        }
+
+        Ok(resolved)
+    }
+
+    fn cache(&self) -> MutexGuard<'_, MeteredCache<String, Vec<FileAttributes>>> {
+        // The cache only contains cloned values, so it is still consistent if a thread panicked
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn inode_paths(&self) -> MutexGuard<'_, MeteredCache<(String, u32), InodePaths>> {
+        self.inode_paths
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn pool_sizes(&self) -> MutexGuard<'_, MeteredCache<Vec<u8>, u64>> {
+        self.pool_sizes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Keeps a listing in the cache, and the sizes of the contents of its files in the index of `pool_file_size`.
     ///
-    /// An error can't be returned if the hosts, backup, can't be read
-    fn list_shares_of(
-        &mut self,
+    /// The size of a content only depends on its digest, so the index isn't cleared with the cache.
+    fn cache_listing(&self, key: String, listing: &[FileAttributes]) {
+        let mut pool_sizes = self.pool_sizes();
+        for file in listing.iter().filter(|file| has_content(file)) {
+            pool_sizes.put(file.bpc_digest.digest.clone(), file.size);
+        }
+        drop(pool_sizes);
+
+        self.cache().put(key, listing.to_vec());
+    }
+
+    /// Lists the files from the specified inode in the backuppc inode directory.
+    ///
+    /// The result is cached for performance (see `resolve_inode` for the layout of the inode directory).
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The hostname of the backup.
+    /// * `backup_number` - The backup number.
+    /// * `inode` - The inode number.
+    ///
+    /// # Returns
+    ///
+    /// A vector of `FileAttributes` instances.
+    fn list_file_from_inode(
+        &self,
         hostname: &str,
         backup_number: u32,
-        path: &[&str],
-    ) -> Result<(Vec<String>, Option<String>, usize)> {
-        info!(
-            "List shares of: {hostname}/{backup_number}/{path}",
-            path = path.join("/")
-        );
-        let shares = self.list_file_from_dir(hostname, backup_number, None, None)?;
-        let mut shares = shares.iter().map(|share| &share.name).collect::<Vec<_>>();
+        inode: u64,
+    ) -> Result<Vec<FileAttributes>> {
+        let (attrib_path, attrib_file, _) = inode_location(inode);
 
-        let mut selected_share: Option<String> = None;
-        let mut share_size = 0;
+        let key = inode_cache_key(hostname, backup_number, inode);
 
-        // Ensure that shares are sorted by length (longest last) to ensure that the selected share is the share that
-        // is the most specific
-        shares.sort_by_key(|a| a.len());
+        info!("List file from inode {inode} with the key {key}");
 
-        // Filter the shares that are not in the path
-        let shares: Vec<String> = shares
-            .into_iter()
-            .filter_map(|share| {
-                let share_array = sanitize_path(share);
+        if let Some(cached_result) = self.cache().get(&key) {
+            return Ok(cached_result.clone());
+        }
 
-                if path.starts_with(&share_array) || path.eq(&share_array) {
-                    share_size = share_array.len();
-                    selected_share = Some(share.clone());
-                    None
-                } else if share_array.starts_with(path) {
-                    Some(share_array[path.len()..][0].to_string())
-                } else {
-                    None
-                }
-            })
-            .collect();
+        let mut result =
+            self.search
+                .list_attributes(hostname, backup_number, &attrib_path, &attrib_file)?;
 
-        let shares = unique(shares);
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        self.cache_listing(key, &result);
 
-        Ok((shares, selected_share, share_size))
+        Ok(result)
     }
 
-    /// Lists the files from the specified path (no cache).
+    /// Resolves the attributes of an inode (the content shared by the hard links to a file).
+    ///
+    /// The files with hard links (`nlinks > 0`) share their attributes through the inode directory of the backup.
+    /// The attributes of the inode `inode` are stored in the attrib file
+    /// `inode/<(inode >> 17) & 0x7f>/attrib<(inode >> 10) & 0x7f>_<digest>` (the numbers are written as 2 hexadecimal
+    /// digits). In this file, the entry is named with the bytes of the inode in little endian, without the trailing
+    /// zero bytes, written in hexadecimal (a name padded with zero bytes is also accepted). Only 7 bits select the
+    /// directory and the attrib file, as in `BackupPC`: the inodes above 2^24 are stored in the same attrib files as
+    /// the lower inodes, and told apart by their name.
+    ///
+    /// The attrib files are cached, so resolving inodes that are close to each other is fast. When the inodes are
+    /// scanned (see `set_scan_inodes`), an attrib file not in the cache is scanned with `get_inode` instead.
     ///
     /// # Arguments
     ///
-    /// * `path` - The path to the file.
+    /// * `hostname` - The hostname of the backup.
+    /// * `backup_number` - The backup number.
+    /// * `inode` - The inode number.
     ///
     /// # Returns
     ///
-    /// A vector of `FileAttributes` instances.
+    /// The attributes of the inode, or `None` if the inode is not in the inode directory of the backup.
     ///
     /// # Errors
     ///
-    /// An error can't be returned if the hosts, backup, can't be read
-    pub fn direct_list(&mut self, path: &[&str]) -> Result<Vec<FileAttributes>> {
-        info!("List: {path}", path = path.join("/"));
-        match path.len() {
-            0 => {
-                let hosts = self.hosts.list_hosts()?;
-                Ok(hosts.into_iter().map(FileAttributes::from_host).collect())
-            }
-            1 => {
-                let backups = self.hosts.list_backups(path[0]);
-                match backups {
-                    Ok(backups) => Ok(backups
-                        .into_iter()
-                        .map(|a| FileAttributes::from_backup(&a))
-                        .collect()),
-                    Err(err) => {
-                        // If the file isn't found, it's because we should return empty vec
-                        if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
-                            if io_err.kind() == std::io::ErrorKind::NotFound {
-                                Ok(Vec::new())
-                            } else {
-                                Err(err)
-                            }
-                        } else {
-                            Err(err)
-                        }
-                    }
-                }
-            }
-            _ => {
-                let (shares, selected_share, share_size) =
-                    self.list_shares_of(path[0], path[1].parse::<u32>().unwrap_or(0), &path[2..])?;
+    /// An error can be returned if the attrib file of the inode can't be read.
+    pub fn resolve_inode(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+        inode: u64,
+    ) -> Result<Option<FileAttributes>> {
+        if self.scan_inodes
+            && !self
+                .cache()
+                .contains(&inode_cache_key(hostname, backup_number, inode))
+        {
+            return self.get_inode(hostname, backup_number, inode);
+        }
 
-                let shares = shares.into_iter().map(FileAttributes::from_share).collect();
+        let (_, _, inode_str) = inode_location(inode);
 
-                match selected_share {
-                    None => Ok(shares),
-                    Some(selected_share) => {
-                        let files = self.list_file_from_dir(
-                            path[0],
-                            path[1].parse::<u32>().unwrap_or(0),
-                            Some(&selected_share),
-                            Some(&path[(2 + share_size)..].join("/")),
-                        )?;
+        info!("Search inode {inode} with the str form {inode_str}");
 
-                        // Add detected shares to files
-                        let mut files = files
-                            .into_iter()
-                            .chain(shares)
-                            .collect::<Vec<FileAttributes>>();
+        let files = self.list_file_from_inode(hostname, backup_number, inode)?;
 
-                        files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(select_inode(files, inode))
+    }
 
-                        Ok(files)
-                    }
-                }
-            }
-        }
+    /// Reads the attributes of an inode, scanning its attrib file up to the entry of the inode.
+    ///
+    /// Unlike `resolve_inode`, the attrib file is neither fully decoded nor cached: its entries are decoded one by
+    /// one, up to the entry named after the inode (see `SearchTrait::find_inode`). This fits a backup with millions
+    /// of inodes in a single attrib file, where keeping the whole file in memory is heavy. The entry selected is the
+    /// same as with `resolve_inode`, unless the name of the inode is duplicated in the attrib file.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The hostname of the backup.
+    /// * `backup_number` - The backup number.
+    /// * `inode` - The inode number.
+    ///
+    /// # Returns
+    ///
+    /// The attributes of the inode, or `None` if the inode is not in the inode directory of the backup.
+    ///
+    /// # Errors
+    ///
+    /// An error can be returned if the attrib file of the inode can't be read.
+    pub fn get_inode(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+        inode: u64,
+    ) -> Result<Option<FileAttributes>> {
+        let (attrib_path, attrib_file, inode_str) = inode_location(inode);
+
+        info!("Scan inode {inode} with the str form {inode_str}");
+
+        self.search
+            .find_inode(hostname, backup_number, &attrib_path, &attrib_file, inode)
     }
 
-    /// Lists the files from the specified path.
+    /// Finds all the paths of a backup that are hard links to the same inode.
     ///
-    /// A cache is used to store the result of the search.
+    /// The whole backup is traversed to collect the files with hard links (`nlinks > 0`), grouped by inode. The
+    /// traversal is done once per backup: the groups are cached, so the next lookups in the same backup are fast. The
+    /// groups of the last backups looked up are kept (see `BackupPCConfig::inode_paths_cache_size`).
     ///
     /// # Arguments
     ///
-    /// * `path` - The path to the file.
+    /// * `hostname` - The hostname of the backup.
+    /// * `backup_number` - The backup number.
+    /// * `inode` - The inode number.
     ///
     /// # Returns
     ///
-    /// A vector of `FileAttributes` instances.
+    /// The paths (relative to the backup, as components) of the files linked to the inode, sorted.
     ///
     /// # Errors
     ///
-    /// An error can't be returned if the hosts, backup, can't be read
+    /// A `Cancelled` error is returned if the token of the view is cancelled during the traversal (see
+    /// `set_cancellation_token`). An error can be returned if a directory of the backup can't be read.
+    pub fn paths_for_inode(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+        inode: u64,
+    ) -> Result<Vec<Vec<String>>> {
+        self.paths_for_inode_cancellable(hostname, backup_number, inode, &self.cancel)
+    }
+
+    /// Finds all the paths of a backup that are hard links to the same inode (see `paths_for_inode`).
     ///
-    pub fn list(&mut self, path: &[&str]) -> Result<Vec<FileAttributes>> {
-        let key = path
-            .iter()
-            .filter(|s| !s.is_empty())
-            .map(std::string::ToString::to_string)
-            .collect::<Vec<String>>()
-            .join("/");
+    /// The traversal of the backup can be aborted with the `token`. A cancelled traversal is not cached.
+    ///
+    /// # Errors
+    ///
+    /// A `Cancelled` error is returned if the token is cancelled during the traversal. An error can be returned if a
+    /// directory of the backup can't be read.
+    pub fn paths_for_inode_cancellable(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+        inode: u64,
+        token: &CancellationToken,
+    ) -> Result<Vec<Vec<String>>> {
+        let key = (hostname.to_string(), backup_number);
+        if let Some(groups) = self.inode_paths().get(&key) {
+            return Ok(groups.get(&inode).cloned().unwrap_or_default());
+        }
 
-        if let Some(cached_result) = self.cache.get(&key) {
-            return Ok(cached_result.clone());
+        info!("Collect the hard links of {hostname}/{backup_number}");
+        let skipped_errors = self.skipped_errors.load(Ordering::Relaxed);
+        let backup_number_str = backup_number.to_string();
+        let mut groups = InodePaths::new();
+        let mut directories: Vec<Vec<String>> = vec![Vec::new()];
+        while let Some(directory) = directories.pop() {
+            token.check()?;
+            let path = [hostname, &backup_number_str]
+                .into_iter()
+                .chain(directory.iter().map(String::as_str))
+                .collect::<Vec<&str>>();
+
+            for file in self.list(&path)? {
+                let mut file_path = directory.clone();
+                file_path.push(file.name.clone());
+
+                if file.type_ == FileType::Dir {
+                    directories.push(file_path);
+                } else if file.nlinks > 0 && file.type_ != FileType::Deleted {
+                    groups.entry(file.inode).or_default().push(file_path);
+                }
+            }
+        }
+        for paths in groups.values_mut() {
+            paths.sort();
         }
 
-        let mut result = self.direct_list(path)?;
-        result.sort_by(|a, b| a.name.cmp(&b.name));
-        self.cache.put(key, result.clone());
+        let paths = groups.get(&inode).cloned().unwrap_or_default();
+        if self.skipped_errors.load(Ordering::Relaxed) == skipped_errors {
+            self.inode_paths().put(key, groups);
+        }
 
-        Ok(result)
+        Ok(paths)
     }
 
-    /// Reads a file from the specified path.
+    /// Lists the files from the specified directory.
     ///
     /// # Arguments
     ///
-    /// * `path` - The path to the file.
+    /// * `hostname` - The hostname of the backup.
+    /// * `backup_number` - The backup number.
+    /// * `share` - The share name.
+    /// * `filename` - The filename.
+    /// * `attrib_path` - The mangled path of the directory in the backup, used instead of the share and the filename
+    ///   when the path contains transcoded names (see `raw_attrib_path`).
     ///
     /// # Returns
     ///
-    /// A boxed trait object implementing the `Read`, `Sync`, and `Send` traits.
+    /// A vector of `FileAttributes` instances.
     ///
     /// # Errors
     ///
-    /// If the file is not found, an error is returned.
+    /// An error can't be returned if the hosts, backup, can't be read
     ///
-    pub fn read_file(&mut self, path: &[&str]) -> Result<Box<dyn Read + Sync + Send>> {
-        info!("Read file: {path}", path = path.join("/"));
-        let filename = path.last().ok_or_else(|| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to get filename: {}", path.join("/")),
-            )
-        })?;
-        let path = &path[..path.len() - 1];
+    fn list_file_from_dir(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+        share: Option<&str>,
+        filename: Option<&str>,
+        attrib_path: Option<&str>,
+    ) -> Result<Vec<FileAttributes>> {
+        info!(
+            "List file from dir: {hostname}/{backup_number}/{}/{}",
+            share.unwrap_or_default(),
+            filename.unwrap_or_default()
+        );
+        // First search the next oldest filled backup next to the current backup
+        let backups_to_search = self.hosts.list_backups_to_fill(hostname, backup_number)?;
 
-        let attributes = self.list(path)?;
+        // Next search the file from the oldest filled backup to the current backup. The files are matched by the
+        // bytes of their name, so the names that aren't valid UTF-8 (decoded as empty names) are kept apart
+        let mut files: HashMap<Vec<u8>, FileAttributes> = HashMap::new();
+        for backup in backups_to_search {
+            info!("Search in backup: {backup}", backup = backup.num);
 
-        let file = attributes
-            .into_iter()
-            .find(|f| f.name.eq(*filename))
-            .ok_or_else(|| {
-                std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    format!("File not found (not in attributs): {}", path.join("/")),
-                )
-            })?;
+            let files_from_backup = match attrib_path {
+                Some(attrib_path) => {
+                    self.search
+                        .list_attributes(hostname, backup.num, attrib_path, ATTRIB_PREFIX)
+                }
+                None => self
+                    .search
+                    .list_file_from_dir(hostname, backup.num, share, filename),
+            };
+            let files_from_backup = match files_from_backup {
+                Ok(files) => files,
+                Err(err) if self.lenient => {
+                    self.skipped_errors.fetch_add(1, Ordering::Relaxed);
+                    warn!(
+                        "Skip the directory {}/{} of the backup {}: {err}",
+                        share.unwrap_or_default(),
+                        filename.unwrap_or_default(),
+                        backup.num
+                    );
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
 
-        if file.bpc_digest.len > 2 && file.bpc_digest.digest.ne(&EMPTY_MD5_DIGEST) {
-            let md5_hash = file.bpc_digest.digest;
-            match find_file_in_backuppc(&self.topdir, &md5_hash, None) {
-                Ok((file_path, is_compressed)) => {
-                    if is_compressed {
-                        let input_file = File::open(file_path)?;
-                        Ok(Box::new(BackupPCReader::new(input_file)))
+            for mut file in files_from_backup {
+                if file.type_ == FileType::Deleted {
+                    if self.show_deleted && files.contains_key(file.raw_name()) {
+                        files.insert(file.raw_name().to_vec(), file);
                     } else {
-                        let input_file = File::open(file_path)?;
-                        Ok(Box::new(std::io::BufReader::new(input_file)))
+                        files.remove(file.raw_name());
+                    }
+                } else {
+                    if file.nlinks > 0 {
+                        let inode = file.inode;
+                        info!(
+                            "File {file} has nlinks {nlinks} (inode: {inode})",
+                            file = file.name,
+                            nlinks = file.nlinks
+                        );
+                        let inode_file = match self.resolve_inode(hostname, backup.num, inode) {
+                            Ok(inode_file) => inode_file,
+                            Err(err) if self.lenient => {
+                                self.skipped_errors.fetch_add(1, Ordering::Relaxed);
+                                warn!("Can't resolve the inode {inode} of {}: {err}", file.name);
+                                None
+                            }
+                            Err(err) => return Err(err),
+                        };
+                        if let Some(inode_file) = inode_file {
+                            file.bpc_digest = inode_file.bpc_digest.clone();
+                        }
                     }
+
+                    files.insert(file.raw_name().to_vec(), file);
                 }
-                Err(message) => Err(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    message.to_string(),
-                )
-                .into()),
             }
-        } else {
-            Ok(Box::new(std::io::empty()))
         }
-    }
-}
 
-//
-// Test of the BackupPCView
-//
-// Test of the method list
-//
-// Methods fril HostsTrait should be mocked
+        Ok(files.into_values().collect())
+    }
+
+    /// Lists the files of a backup whose name isn't valid UTF-8.
+    ///
+    /// The names that aren't valid UTF-8 are decoded as empty names (or transcoded, see `set_transcode_names`), their
+    /// bytes being kept in `FileAttributes::name_bytes`. The backup is walked as it is listed, so the files of an
+    /// incremental backup that come from the backups it is filled from are also checked. The directories whose name
+    /// isn't valid UTF-8 are not walked (they are reported in their parent directory).
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The hostname of the backup.
+    /// * `backup_number` - The backup number.
+    ///
+    /// # Returns
+    ///
+    /// The path of the directory in the backup (starting with a slash, empty for the root of the backup) and the raw
+    /// bytes of the name of each file, sorted.
+    ///
+    /// # Errors
+    ///
+    /// A `Cancelled` error is returned if the token of the view is cancelled during the walk (see
+    /// `set_cancellation_token`). An error can be returned if a directory of the backup can't be listed.
+    pub fn list_non_utf8_names(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        let backup_number = backup_number.to_string();
+        let mut names = Vec::new();
+        let mut directories: Vec<Vec<String>> = vec![Vec::new()];
+        while let Some(directory) = directories.pop() {
+            self.cancel.check()?;
+            let path = [hostname, &backup_number]
+                .into_iter()
+                .chain(directory.iter().map(String::as_str))
+                .collect::<Vec<&str>>();
+
+            for file in self.list(&path)? {
+                if file.type_ == FileType::Deleted {
+                    continue;
+                }
+                if let Some(name_bytes) = file.name_bytes {
+                    let directory_path = directory
+                        .iter()
+                        .map(|component| format!("/{component}"))
+                        .collect::<String>();
+                    names.push((directory_path, name_bytes));
+                } else if file.type_ == FileType::Dir {
+                    let mut subdirectory = directory.clone();
+                    subdirectory.push(file.name);
+                    directories.push(subdirectory);
+                }
+            }
+        }
+        names.sort();
+
+        Ok(names)
+    }
+
+    /// Resolves a backup selector (ex: `latest-full`, `10-1`) to the number of a backup of a host.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The hostname of the backup.
+    /// * `selector` - The backup selector (see `BackupSelector` for the grammar).
+    ///
+    /// # Returns
+    ///
+    /// The number of the backup selected.
+    ///
+    /// # Errors
+    ///
+    /// If the backups of the host can't be read, or if no backup (or several backups) match the selector.
+    pub fn resolve_backup(&self, hostname: &str, selector: &BackupSelector) -> Result<u32> {
+        selector.resolve_for_host(self.hosts.as_ref(), hostname)
+    }
+
+    /// Lists the files of a directory as it was at a given time.
+    ///
+    /// The backup used is the latest backup of the host completed at or before the timestamp (see
+    /// `selector::backup_at_time`), and the directory is listed as with `list`.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The hostname of the backup.
+    /// * `timestamp` - The time, in seconds since the Unix epoch.
+    /// * `path` - The path of the directory inside the backup (without the hostname and the backup number).
+    ///
+    /// # Returns
+    ///
+    /// A vector of `FileAttributes` instances.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `NotFound` if no backup was completed at this time, or if the directory doesn't exist. An
+    /// error is also returned if the backups of the host can't be read.
+    pub fn list_at_time(
+        &self,
+        hostname: &str,
+        timestamp: u64,
+        path: &[&str],
+    ) -> Result<Vec<FileAttributes>> {
+        let backups = self.hosts.list_backups(hostname)?;
+        let backup_number = backup_at_time(&backups, timestamp)?.to_string();
+
+        let mut backup_path = vec![hostname, backup_number.as_str()];
+        backup_path.extend_from_slice(path);
+        self.list(&backup_path)
+    }
+
+    /// Lists the shares of the specified backup.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The hostname of the backup.
+    /// * `backup_number` - The backup number.
+    ///
+    /// # Returns
+    ///
+    /// A vector of share names.
+    ///
+    /// # Errors
+    ///
+    /// An error can't be returned if the hosts, backup, can't be read
+    pub fn list_shares(&self, hostname: &str, backup_number: u32) -> Result<Vec<String>> {
+        info!("List shares: {hostname}/{backup_number}");
+        let files = self.list_file_from_dir(hostname, backup_number, None, None, None)?;
+        let shares = files
+            .iter()
+            .filter(|f| f.type_ == FileType::Dir)
+            .map(|f| f.name.clone())
+            .collect();
+        Ok(shares)
+    }
+
+    /// Resolves how a path inside a backup maps onto the shares of the backup.
+    ///
+    /// When the shares overlap (ex: `/` and `/home`), the path belongs to the most specific share containing it
+    /// (`home/user` is the directory `user` of the share `/home`), and the more specific shares are sub-directories of
+    /// the path (`home` is a sub-directory of the root of the share `/`).
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The hostname of the backup.
+    /// * `backup_number` - The backup number.
+    /// * `path` - The path inside the backup (without the hostname and the backup number).
+    ///
+    /// # Returns
+    ///
+    /// A `ShareResolution` containing the sub-directories leading to other shares, the share containing the path,
+    /// and the number of components of the path used by this share.
+    ///
+    /// # Errors
+    ///
+    /// An error can't be returned if the hosts, backup, can't be read
+    pub fn list_shares_of(
+        &self,
+        hostname: &str,
+        backup_number: u32,
+        path: &[&str],
+    ) -> Result<ShareResolution> {
+        info!(
+            "List shares of: {hostname}/{backup_number}/{path}",
+            path = path.join("/")
+        );
+        let mut shares = self.list_file_from_dir(hostname, backup_number, None, None, None)?;
+        // Sort the shares by name so the share selected among equivalent shares (ex: `/home` and `/home/`) doesn't
+        // depend on the order of the attrib file
+        shares.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let backslash = shares.iter().any(|share| share.name.contains('\\'))
+            && self.has_windows_separators(hostname, &backup_number.to_string())?;
+
+        let mut selected_share: Option<&str> = None;
+        let mut share_size = 0;
+        let mut subdirs = Vec::new();
+
+        for share in &shares {
+            let share_array = sanitize_path(&share.name, backslash);
+
+            if path.starts_with(&share_array) {
+                // When shares overlap (ex: `/` and `/home`), the path belongs to the most specific share: the one
+                // with the most components
+                if selected_share.is_none() || share_array.len() > share_size {
+                    share_size = share_array.len();
+                    selected_share = Some(&share.name);
+                }
+            } else if share_array.starts_with(path) {
+                subdirs.push(share_array[path.len()].to_string());
+            }
+        }
+
+        let shares = unique(subdirs);
+
+        Ok(ShareResolution {
+            subdirs: shares,
+            selected_share: selected_share.map(ToString::to_string),
+            share_depth: share_size,
+        })
+    }
+
+    fn is_partial_backup(&self, hostname: &str, backup_number: &str) -> Result<bool> {
+        Ok(self
+            .hosts
+            .list_backups(hostname)?
+            .iter()
+            .any(|backup| backup.num.to_string() == backup_number && backup.is_partial()))
+    }
+
+    /// Lists the files from the specified path (no cache).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the file.
+    ///
+    /// # Returns
+    ///
+    /// A vector of `FileAttributes` instances.
+    ///
+    /// # Errors
+    ///
+    /// An error can't be returned if the hosts, backup, can't be read
+    pub fn direct_list(&self, path: &[&str]) -> Result<Vec<FileAttributes>> {
+        info!("List: {path}", path = path.join("/"));
+        match path.len() {
+            0 => {
+                let hosts = self.hosts.list_hosts()?;
+                Ok(hosts.into_iter().map(FileAttributes::from_host).collect())
+            }
+            1 => {
+                let backups = self.hosts.list_backups(path[0]);
+                match backups {
+                    Ok(backups) => Ok(backups
+                        .into_iter()
+                        .filter(|backup| !(self.hide_partial && backup.is_partial()))
+                        .map(|a| FileAttributes::from_backup(&a))
+                        .collect()),
+                    Err(err) => {
+                        // If the file isn't found, it's because we should return empty vec
+                        if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+                            if io_err.kind() == std::io::ErrorKind::NotFound {
+                                Ok(Vec::new())
+                            } else {
+                                Err(err)
+                            }
+                        } else {
+                            Err(err)
+                        }
+                    }
+                }
+            }
+            _ => {
+                if self.hide_partial && self.is_partial_backup(path[0], path[1])? {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("Backup {} of {} is partial", path[1], path[0]),
+                    )
+                    .into());
+                }
+
+                let ShareResolution {
+                    subdirs,
+                    selected_share,
+                    share_depth,
+                } =
+                    self.list_shares_of(path[0], path[1].parse::<u32>().unwrap_or(0), &path[2..])?;
+
+                let shares = subdirs
+                    .iter()
+                    .cloned()
+                    .map(FileAttributes::from_share)
+                    .collect();
+
+                match selected_share {
+                    None => Ok(shares),
+                    Some(selected_share) => {
+                        #[cfg(feature = "encoding")]
+                        let attrib_path =
+                            self.raw_attrib_path(path, &selected_share, share_depth)?;
+                        #[cfg(not(feature = "encoding"))]
+                        let attrib_path: Option<String> = None;
+                        let files = self.list_file_from_dir(
+                            path[0],
+                            path[1].parse::<u32>().unwrap_or(0),
+                            Some(&selected_share),
+                            Some(&path[(2 + share_depth)..].join("/")),
+                            attrib_path.as_deref(),
+                        )?;
+
+                        // Add detected shares to files. A more specific share replaces the entry of the same
+                        // name of the selected share (ex: the directory `home` of the share `/` is the share `/home`)
+                        let mut files = files
+                            .into_iter()
+                            .filter(|file| !subdirs.contains(&file.name))
+                            .chain(shares)
+                            .collect::<Vec<FileAttributes>>();
+
+                        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+                        Ok(files)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lists the files from the specified path.
+    ///
+    /// A cache is used to store the result of the search.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the file.
+    ///
+    /// # Returns
+    ///
+    /// A vector of `FileAttributes` instances. The vector is empty if the directory exists but has no entry.
+    ///
+    /// # Errors
+    ///
+    /// An `io::Error` with the kind `NotFound` is returned if the path isn't a directory (the path doesn't exist or
+    /// is a file). An error can be returned if the hosts, backup, can't be read.
+    ///
+    pub fn list(&self, path: &[&str]) -> Result<Vec<FileAttributes>> {
+        let path = self.resolve_case(path)?;
+        let path = path.iter().map(String::as_str).collect::<Vec<&str>>();
+
+        let files = self.list_exact(&path)?;
+        // An empty listing is also returned for a directory that doesn't exist
+        if files.is_empty() && !self.is_directory(&path)? {
+            return Err(directory_not_found(&path));
+        }
+
+        Ok(files)
+    }
+
+    /// Counts the files of the specified path.
+    ///
+    /// The directory is listed as with `list`, but a listing already in the cache is counted without being cloned.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the directory.
+    ///
+    /// # Returns
+    ///
+    /// The number of files of the directory.
+    ///
+    /// # Errors
+    ///
+    /// An `io::Error` with the kind `NotFound` is returned if the path isn't a directory (see `list`). An error can be
+    /// returned if the hosts, backup, can't be read.
+    pub fn count(&self, path: &[&str]) -> Result<usize> {
+        let path = self.resolve_case(path)?;
+        let path = path.iter().map(String::as_str).collect::<Vec<&str>>();
+
+        let cached = self.cache().get(&cache_key(&path)).map(Vec::len);
+        let count = match cached {
+            Some(count) => count,
+            None => self.list_exact(&path)?.len(),
+        };
+        if count == 0 && !self.is_directory(&path)? {
+            return Err(directory_not_found(&path));
+        }
+
+        Ok(count)
+    }
+
+    /// Checks that each component of the path is a directory listed in its parent.
+    fn is_directory(&self, path: &[&str]) -> Result<bool> {
+        for (depth, name) in path.iter().enumerate() {
+            let parent = match self.list_exact(&path[..depth]) {
+                Ok(parent) => parent,
+                Err(err) => match err.downcast_ref::<std::io::Error>() {
+                    Some(io_err) if io_err.kind() == std::io::ErrorKind::NotFound => {
+                        return Ok(false)
+                    }
+                    _ => return Err(err),
+                },
+            };
+            if !parent
+                .iter()
+                .any(|f| f.name.eq(*name) && f.type_ == FileType::Dir)
+            {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Lists the files from the specified path, the names of the path being matched exactly.
+    fn list_exact(&self, path: &[&str]) -> Result<Vec<FileAttributes>> {
+        let key = cache_key(path);
+
+        if let Some(cached_result) = self.cache().get(&key) {
+            return Ok(cached_result.clone());
+        }
+
+        let skipped_errors = self.skipped_errors.load(Ordering::Relaxed);
+        let mut result = self.direct_list(path)?;
+        #[cfg(feature = "encoding")]
+        if self.transcode_names && path.len() > 2 {
+            self.transcode_names_of(path[0], path[1], &mut result)?;
+        }
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        if path.len() == 1 && self.backup_order == BackupOrder::NewestFirst {
+            // The modification time of a backup is its start time (see `FileAttributes::from_backup`)
+            result.sort_by_key(|backup| {
+                Reverse((backup.mtime, backup.name.parse::<u32>().unwrap_or(0)))
+            });
+        }
+        // A listing missing the files of a corrupt attrib file (see `set_lenient`) isn't kept
+        if self.skipped_errors.load(Ordering::Relaxed) == skipped_errors {
+            self.cache_listing(key, &result);
+        }
+
+        Ok(result)
+    }
+
+    /// Checks if the specified path exists.
+    ///
+    /// The path is resolved through the merge of the incremental backups, so a file deleted in the requested backup
+    /// (a `FileType::Deleted` entry) doesn't exist. The content of the file is not read.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the file.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the path resolves to a live entry, `false` otherwise.
+    ///
+    /// # Errors
+    ///
+    /// An error can be returned if the hosts, backup, can't be read
+    pub fn exists(&self, path: &[&str]) -> Result<bool> {
+        let path = match self.resolve_case(path) {
+            Ok(path) => path,
+            Err(err) => {
+                if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+                    if io_err.kind() == std::io::ErrorKind::NotFound {
+                        return Ok(false);
+                    }
+                }
+                return Err(err);
+            }
+        };
+        let path = path.iter().map(String::as_str).collect::<Vec<&str>>();
+        let Some((filename, parent)) = path.split_last() else {
+            return Ok(true);
+        };
+
+        let attributes = match self.list_exact(parent) {
+            Ok(attributes) => attributes,
+            Err(err) => {
+                if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+                    if io_err.kind() == std::io::ErrorKind::NotFound {
+                        return Ok(false);
+                    }
+                }
+                return Err(err);
+            }
+        };
+
+        Ok(attributes
+            .iter()
+            .any(|f| f.has_name(filename) && f.type_ != FileType::Deleted))
+    }
+
+    /// Reads a file from the specified path.
+    ///
+    /// If the content decoded from the pool is shorter than the size stored in the attributes (sparse files), the
+    /// content is padded with zeros up to that size, so the content is always consistent with the advertised size.
+    ///
+    /// If the file isn't found in the pool, the copy of the file stored in the backup directory is read instead (see
+    /// `backup_copy_path`).
+    ///
+    /// If a maximum read size is defined (see `set_max_read_size`), the reader fails when the content goes past it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the file.
+    ///
+    /// # Returns
+    ///
+    /// A boxed trait object implementing the `Read`, `Sync`, and `Send` traits.
+    ///
+    /// # Errors
+    ///
+    /// If the file is not found, an error is returned.
+    ///
+    pub fn read_file(&self, path: &[&str]) -> Result<Box<dyn Read + Sync + Send>> {
+        Ok(self.read_file_with_meta(path)?.reader)
+    }
+
+    /// Reads a file from the specified path, with the metadata of the file resolved to open it.
+    ///
+    /// The content is read as with `read_file`. The type, the size, the compression, and the digest of the file come
+    /// from the attributes read to find the file, so the caller doesn't have to list the parent directory again.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the file.
+    ///
+    /// # Returns
+    ///
+    /// A `FileReader` with the reader on the content of the file and its metadata.
+    ///
+    /// # Errors
+    ///
+    /// If the file is not found, an error is returned.
+    pub fn read_file_with_meta(&self, path: &[&str]) -> Result<FileReader> {
+        info!("Read file: {path}", path = path.join("/"));
+        let file = self.find_file(path)?;
+        let reader = self.open_limited(path, &file)?;
+
+        Ok(FileReader {
+            reader,
+            type_: file.type_.clone(),
+            size: file.size,
+            is_compressed: file.compress > 0,
+            digest: if has_content(&file) {
+                file.bpc_digest.digest
+            } else {
+                Vec::new()
+            },
+        })
+    }
+
+    /// Reads a range of bytes of a file from the specified path.
+    ///
+    /// The range is clamped to the size of the file: fewer bytes are returned if the range ends after the end of
+    /// the file, and no byte if it starts after the end of the file. As the content of compressed files can't be
+    /// seeked, the bytes before the range are decompressed and skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the file.
+    /// * `start` - The offset of the first byte to read.
+    /// * `len` - The number of bytes to read.
+    ///
+    /// # Returns
+    ///
+    /// The bytes of the range.
+    ///
+    /// # Errors
+    ///
+    /// If the file is not found or can't be read, an error is returned.
+    pub fn read_range(&self, path: &[&str], start: u64, len: u64) -> Result<Vec<u8>> {
+        info!(
+            "Read range {start}+{len} of file: {path}",
+            path = path.join("/")
+        );
+        let file = self.find_file(path)?;
+
+        if start >= file.size {
+            return Ok(Vec::new());
+        }
+        let len = len.min(file.size - start);
+
+        let mut reader = self.open_limited(path, &file)?;
+        skip(&mut reader, start, self.read_chunk_size)?;
+
+        // The size of the file comes from its attributes: it only bounds the preallocation of the buffer
+        Ok(read_all_to_vec(reader.take(len), len)?)
+    }
+
+    /// Finds the attributes of the file at the specified path.
+    fn find_file(&self, path: &[&str]) -> Result<FileAttributes> {
+        let path = self.resolve_case(path)?;
+        let filename = path.last().ok_or_else(|| {
+            std::io::Error::other(format!("Failed to get filename: {}", path.join("/")))
+        })?;
+        let path = path[..path.len() - 1]
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+
+        let attributes = self.list_exact(&path)?;
+
+        let file = attributes
+            .into_iter()
+            .find(|f| f.has_name(filename) && f.type_ != FileType::Deleted)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("File not found (not in attributs): {}", path.join("/")),
+                )
+            })?;
+
+        Ok(file)
+    }
+
+    /// Reads a file of the pool directly by its digest, without resolving any path.
+    ///
+    /// # Arguments
+    ///
+    /// * `digest` - The digest of the file, in hexadecimal.
+    ///
+    /// # Returns
+    ///
+    /// A reader on the (decompressed) content of the pool file.
+    ///
+    /// # Errors
+    ///
+    /// If the digest is not a valid hexadecimal string, if the file is not found in the pool, or if it can't be
+    /// opened.
+    pub fn read_by_digest(&self, digest: &str) -> Result<Box<dyn Read + Sync + Send>> {
+        read_by_digest_from_pool_roots(self.source.as_ref(), &self.topdir, &self.pool_roots, digest)
+    }
+
+    /// Returns the size of the content of a file of the pool, by its digest, without reading it when possible.
+    ///
+    /// The size is taken from the attributes of a file with this content, when a listing containing one was cached by
+    /// the view, or from a previous call. Otherwise, the size of a file of the pool directory (stored uncompressed) is read from its
+    /// metadata, and only the content of a file of the cpool directory is decompressed to count its bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `digest` - The digest of the file, in hexadecimal.
+    ///
+    /// # Returns
+    ///
+    /// The size of the (uncompressed) content of the pool file.
+    ///
+    /// # Errors
+    ///
+    /// If the digest is not a valid hexadecimal string, if the file is not found in the pool, or if it can't be
+    /// read.
+    pub fn pool_file_size(&self, digest: &str) -> Result<u64> {
+        let digest = parse_digest(digest)?;
+        if let Some(&size) = self.pool_sizes().get(&digest) {
+            return Ok(size);
+        }
+
+        let (path, in_cpool) = find_file_in_pool_roots(
+            self.source.as_ref(),
+            &self.topdir,
+            &self.pool_roots,
+            &digest,
+            None,
+        )
+        .map_err(|message| std::io::Error::new(std::io::ErrorKind::NotFound, message))?;
+        let path = Path::new(&path);
+        let size = if in_cpool {
+            decompressed_size(self.source.as_ref(), path, true)?
+        } else {
+            self.source.file_size(path)?
+        };
+        self.pool_sizes().put(digest, size);
+
+        Ok(size)
+    }
+
+    /// Returns the on-disk path of the content of a file in the pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the file.
+    ///
+    /// # Returns
+    ///
+    /// The path of the file in the pool (or cpool) and whether it is compressed, or `None` if the file has no
+    /// content in the pool (directories, empty files, ...).
+    ///
+    /// # Errors
+    ///
+    /// If the file is not found, or if its content is not found in the pool, an error is returned.
+    pub fn pool_path_of(&self, path: &[&str]) -> Result<Option<(PathBuf, bool)>> {
+        let file = self.find_file(path)?;
+
+        self.locate_file(&file)
+    }
+
+    /// Finds the file containing the content of a file in the pool from its attributes.
+    fn locate_file(&self, file: &FileAttributes) -> Result<Option<(PathBuf, bool)>> {
+        if !has_content(file) {
+            return Ok(None);
+        }
+
+        match find_file_in_pool_roots(
+            self.source.as_ref(),
+            &self.topdir,
+            &self.pool_roots,
+            &file.bpc_digest.digest,
+            None,
+        ) {
+            Ok((file_path, is_compressed)) => Ok(Some((PathBuf::from(file_path), is_compressed))),
+            Err(message) => Err(std::io::Error::new(std::io::ErrorKind::NotFound, message).into()),
+        }
+    }
+
+    /// Opens the content of a file from its attributes, using the cache for the small files.
+    ///
+    /// The whole content of a file smaller than the small file size is decompressed once and kept in the cache, so
+    /// the next openings of a file with the same digest don't read the pool again.
+    fn open_file_cached(&self, file: &FileAttributes) -> Result<Box<dyn Read + Sync + Send>> {
+        let digest = &file.bpc_digest.digest;
+        if file.size > self.small_file_size
+            || !has_content(file)
+            || !self.small_files().is_enabled()
+        {
+            return self.open_file(file);
+        }
+
+        let cached = self.small_files().get(digest);
+        if let Some(content) = cached.filter(|content| content.len() as u64 == file.size) {
+            return Ok(Box::new(Cursor::new(content)));
+        }
+
+        let content = read_all_to_vec(self.open_file(file)?, file.size)?;
+        let content: Arc<[u8]> = Arc::from(content);
+        self.small_files().insert(digest.clone(), content.clone());
+
+        Ok(Box::new(Cursor::new(content)))
+    }
+
+    /// Opens the content of a file, failing if the content is longer than the maximum read size (see
+    /// `set_max_read_size`).
+    ///
+    /// The decompressed content of the pool file is also limited before it is cached (see `open_file`), so a small
+    /// file expanding to a huge content isn't read whole in the cache.
+    fn open_limited(
+        &self,
+        path: &[&str],
+        file: &FileAttributes,
+    ) -> Result<Box<dyn Read + Sync + Send>> {
+        let reader = self.open_file_or_backup_copy(path, file)?;
+        Ok(self.limit_read_size(reader))
+    }
+
+    /// Wraps a reader in a `LimitedReader` if a maximum read size is defined (see `set_max_read_size`).
+    fn limit_read_size(&self, reader: Box<dyn Read + Sync + Send>) -> Box<dyn Read + Sync + Send> {
+        match self.max_read_size {
+            Some(max_read_size) => Box::new(LimitedReader::new(reader, max_read_size)),
+            None => reader,
+        }
+    }
+
+    /// Opens the content of a file, from the pool or from the copy stored in the backup directory.
+    ///
+    /// The content of a file is read from the pool (see `open_file_cached`). When the file isn't found in the pool,
+    /// the copy stored at the mangled path of the file in the backup directory is read instead, compressed or not.
+    fn open_file_or_backup_copy(
+        &self,
+        path: &[&str],
+        file: &FileAttributes,
+    ) -> Result<Box<dyn Read + Sync + Send>> {
+        let err = match self.open_file_cached(file) {
+            Ok(reader) => {
+                if has_content(file) {
+                    info!("Read {path} from the pool", path = path.join("/"));
+                }
+                return Ok(reader);
+            }
+            Err(err) => err,
+        };
+
+        let not_found = err
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|io_err| io_err.kind() == std::io::ErrorKind::NotFound);
+        if !not_found {
+            return Err(err);
+        }
+
+        let Some(backup_path) = self.backup_copy_path(path)? else {
+            return Err(err);
+        };
+        if !self.source.exists(&backup_path) {
+            return Err(err);
+        }
+
+        info!(
+            "Read {path} from the backup directory (not in the pool): {backup_path}",
+            path = path.join("/"),
+            backup_path = backup_path.display()
+        );
+        // The copy is compressed if the backup was made with compression, the compression is detected from its content
+        let reader = open_content(self.source.open(&backup_path)?, true)?;
+        Ok(Box::new(
+            PaddedReader::new(reader, file.size).with_strict(self.strict_size),
+        ))
+    }
+
+    /// Builds the path of the copy of a file stored in the backup directory.
+    ///
+    /// The copy is stored at the mangled path of the file in the backup directory: the file `dir/file` of the share
+    /// `/home` of the backup 1 of `pc-1` is stored in `pc/pc-1/1/f%2fhome/fdir/ffile`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the file (the hostname, the backup number, the share and the path inside the share).
+    ///
+    /// # Returns
+    ///
+    /// The path of the copy, or `None` if the path isn't inside a share.
+    ///
+    /// # Errors
+    ///
+    /// An error can be returned if the shares of the backup can't be read.
+    fn backup_copy_path(&self, path: &[&str]) -> Result<Option<PathBuf>> {
+        let path = self.resolve_case(path)?;
+        let path = path.iter().map(String::as_str).collect::<Vec<&str>>();
+        if path.len() < 4 {
+            return Ok(None);
+        }
+
+        let backup_number = path[1].parse::<u32>().unwrap_or(0);
+        let ShareResolution {
+            selected_share,
+            share_depth,
+            ..
+        } = self.list_shares_of(path[0], backup_number, &path[2..])?;
+        let Some(share) = selected_share else {
+            return Ok(None);
+        };
+
+        Ok(Some(
+            Path::new(&self.topdir)
+                .join("pc")
+                .join(path[0])
+                .join(backup_number.to_string())
+                .join(mangle_filename(&share))
+                .join(mangle(&path[(2 + share_depth)..].join("/"))),
+        ))
+    }
+
+    fn small_files(&self) -> MutexGuard<'_, SmallFileCache> {
+        self.small_files
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Opens the content of a file from its attributes.
+    ///
+    /// The decompressed content is limited to the maximum read size (see `set_max_read_size`).
+    ///
+    /// # Errors
+    ///
+    /// A `TruncatedPoolFileError` is returned if the pool file is empty while the size of the file isn't zero.
+    fn open_file(&self, file: &FileAttributes) -> Result<Box<dyn Read + Sync + Send>> {
+        match self.locate_file(file)? {
+            Some((file_path, is_compressed)) => {
+                let mut input_file = BufReader::new(self.source.open(&file_path)?);
+                if file.size > 0 && input_file.fill_buf()?.is_empty() {
+                    return Err(Box::new(TruncatedPoolFileError {
+                        path: file_path,
+                        expected_size: file.size,
+                    }));
+                }
+
+                let reader = self.limit_read_size(open_content(input_file, is_compressed)?);
+                Ok(Box::new(
+                    PaddedReader::new(reader, file.size).with_strict(self.strict_size),
+                ))
+            }
+            None => Ok(Box::new(std::io::empty())),
+        }
+    }
+}
+
+/// A thread-safe handle on a `BackupPC` view.
+///
+/// The handle can be cloned and sent to other threads, all clones sharing the same view and the same cache. It is
+/// used to serve the view from multiple threads (multi-threaded FUSE dispatch, HTTP server, ...).
+#[derive(Clone)]
+pub struct SharedBackupPC {
+    inner: Arc<BackupPC>,
+}
+
+impl SharedBackupPC {
+    /// Creates a new shared handle on the given view.
+    ///
+    /// # Arguments
+    ///
+    /// * `view` - The view to share.
+    ///
+    /// # Returns
+    ///
+    /// A new `SharedBackupPC` instance.
+    #[must_use]
+    pub fn new(view: BackupPC) -> Self {
+        SharedBackupPC {
+            inner: Arc::new(view),
+        }
+    }
+
+    /// Lists the files from the specified path (see `BackupPC::list`).
+    ///
+    /// # Errors
+    ///
+    /// An error can be returned if the hosts, backup, can't be read
+    pub fn list(&self, path: &[&str]) -> Result<Vec<FileAttributes>> {
+        self.inner.list(path)
+    }
+
+    /// Counts the files of the specified path (see `BackupPC::count`).
+    ///
+    /// # Errors
+    ///
+    /// An error can be returned if the path isn't a directory or if the hosts, backup, can't be read
+    pub fn count(&self, path: &[&str]) -> Result<usize> {
+        self.inner.count(path)
+    }
+
+    /// Reads a file from the specified path (see `BackupPC::read_file`).
+    ///
+    /// # Errors
+    ///
+    /// If the file is not found, an error is returned.
+    pub fn read_file(&self, path: &[&str]) -> Result<Box<dyn Read + Sync + Send>> {
+        self.inner.read_file(path)
+    }
+
+    /// Reads a file from the specified path, with its metadata (see `BackupPC::read_file_with_meta`).
+    ///
+    /// # Errors
+    ///
+    /// If the file is not found, an error is returned.
+    pub fn read_file_with_meta(&self, path: &[&str]) -> Result<FileReader> {
+        self.inner.read_file_with_meta(path)
+    }
+
+    /// Reads a range of bytes of a file from the specified path (see `BackupPC::read_range`).
+    ///
+    /// # Errors
+    ///
+    /// If the file is not found or can't be read, an error is returned.
+    pub fn read_range(&self, path: &[&str], start: u64, len: u64) -> Result<Vec<u8>> {
+        self.inner.read_range(path, start, len)
+    }
+
+    /// Checks if the specified path exists (see `BackupPC::exists`).
+    ///
+    /// # Errors
+    ///
+    /// An error can be returned if the hosts, backup, can't be read
+    pub fn exists(&self, path: &[&str]) -> Result<bool> {
+        self.inner.exists(path)
+    }
+
+    /// Returns the on-disk path of the content of a file (see `BackupPC::pool_path_of`).
+    ///
+    /// # Errors
+    ///
+    /// If the file is not found, or if its content is not found in the pool, an error is returned.
+    pub fn pool_path_of(&self, path: &[&str]) -> Result<Option<(PathBuf, bool)>> {
+        self.inner.pool_path_of(path)
+    }
+
+    /// Returns the token used to abort the traversals of the view (see `BackupPC::cancellation_token`).
+    #[must_use]
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        self.inner.cancellation_token()
+    }
+
+    /// Returns the size of the chunks used to read the content of a file (see `BackupPC::read_chunk_size`).
+    #[must_use]
+    pub fn read_chunk_size(&self) -> usize {
+        self.inner.read_chunk_size()
+    }
+}
+
+//
+// Test of the BackupPCView
+//
+// Test of the method list
+//
+// Methods fril HostsTrait should be mocked
 
 #[cfg(test)]
 mod tests {
@@ -517,354 +2113,1823 @@ mod tests {
     use crate::attribute_file::MockSearchTrait;
     use crate::decode_attribut::FileType;
     use crate::hosts::{BackupInformation, MockHostsTrait};
+    use crate::util::{parse_inode_name, vec_to_hex_string};
     use mockall::predicate::*;
 
-    // Befor each test we create all the mock of the view with the following structure
-    // /var/lib/backuppc
-    // ├── pc-1
-    // │   ├── 1
-    // │   │   ├── volume1
-    // │   │   │   ├── test
-    // │   │   │   │   ├── supertest
-    // │   │   │   │   │   ├── de
-    // │   │   │   │   │   │   ├── test
-    // │   │   │   │   │   │   │   ├── file1
-    // │   │   │   │   │   │   │   ├── file2
-    // │   │   │   │   │   │   │   ├── file3
-    // │   │   │   │   │   │   ├── en
-    // │   │   │   │   │   │   ├── es
-    // │   │   │   │   │   │   └── fr
-    // │   │   │   │   │   └── test2
-    // │   │   │   │   └── toto
-    // │   │   │   └── test2
-    // │   └── 2
-    // │   │   ├── volume1
-    // │   │   │   ├── test
-    // │   │   │   └── test2
-    // │   │   └── volume2
-    // └── pc-2
-    // │   ├── 1
-    // │   ├── 2
-    // │   └── 3
-    // └── pc-3
-    fn create_mock_backup(num: u32) -> BackupInformation {
-        BackupInformation {
-            num,
-            backup_type: "full".to_string(),
-            start_time: 0,
-            end_time: 0,
-            n_files: 0,
-            size: 0,
-            n_files_exist: 0,
-            size_exist: 0,
-            n_files_new: 0,
-            size_new: 0,
-            xfer_errs: 0,
-            xfer_bad_file: 0,
-            xfer_bad_share: 0,
-            tar_errs: 0,
-            compress: 0,
-            size_exist_comp: 0,
-            size_new_comp: 0,
-            no_fill: 0,
-            fill_from_num: 0,
-            mangle: 0,
-            xfer_method: "rsync".to_string(),
-            level: 0,
-            charset: "utf-8".to_string(),
-            version: "4.0.0".to_string(),
-            inode_last: 0,
-        }
+    // Befor each test we create all the mock of the view with the following structure
+    // /var/lib/backuppc
+    // ├── pc-1
+    // │   ├── 1
+    // │   │   ├── volume1
+    // │   │   │   ├── test
+    // │   │   │   │   ├── supertest
+    // │   │   │   │   │   ├── de
+    // │   │   │   │   │   │   ├── test
+    // │   │   │   │   │   │   │   ├── file1
+    // │   │   │   │   │   │   │   ├── file2
+    // │   │   │   │   │   │   │   ├── file3
+    // │   │   │   │   │   │   ├── en
+    // │   │   │   │   │   │   ├── es
+    // │   │   │   │   │   │   └── fr
+    // │   │   │   │   │   └── test2
+    // │   │   │   │   └── toto
+    // │   │   │   └── test2
+    // │   └── 2
+    // │   │   ├── volume1
+    // │   │   │   ├── test
+    // │   │   │   └── test2
+    // │   │   └── volume2
+    // └── pc-2
+    // │   ├── 1
+    // │   ├── 2
+    // │   └── 3
+    // └── pc-3
+    fn create_mock_backup(num: u32) -> BackupInformation {
+        BackupInformation {
+            num,
+            backup_type: "full".to_string(),
+            start_time: 0,
+            end_time: 0,
+            n_files: 0,
+            size: 0,
+            n_files_exist: 0,
+            size_exist: 0,
+            n_files_new: 0,
+            size_new: 0,
+            xfer_errs: 0,
+            xfer_bad_file: 0,
+            xfer_bad_share: 0,
+            tar_errs: 0,
+            compress: 0,
+            size_exist_comp: 0,
+            size_new_comp: 0,
+            no_fill: 0,
+            fill_from_num: 0,
+            mangle: 0,
+            xfer_method: "rsync".to_string(),
+            level: 0,
+            charset: "utf-8".to_string(),
+            version: "4.0.0".to_string(),
+            inode_last: 0,
+        }
+    }
+
+    fn create_file_attributes(name: &str, type_: FileType) -> FileAttributes {
+        FileAttributes {
+            name: name.to_string(),
+            name_bytes: None,
+            raw_type: type_.clone() as u64,
+            type_,
+            compress: 0,
+
+            mode: 0,
+            uid: 0,
+            gid: 0,
+            nlinks: 0,
+
+            mtime: 0,
+            size: 0,
+            inode: 0,
+
+            bpc_digest: crate::decode_attribut::BpcDigest {
+                len: 0,
+                digest: Vec::new(),
+            },
+            xattr_num_entries: 0,
+            xattrs: Vec::new(),
+        }
+    }
+
+    fn create_view() -> BackupPC {
+        let topdir = "/var/lib/backuppc";
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+
+        let hosts = vec!["pc-1".to_string(), "pc-2".to_string(), "pc-3".to_string()];
+
+        let backups_pc1 = vec![create_mock_backup(1), create_mock_backup(2)];
+
+        let backups_pc2 = vec![
+            create_mock_backup(1),
+            create_mock_backup(2),
+            create_mock_backup(3),
+        ];
+
+        let backups_pc3 = Vec::<BackupInformation>::new();
+
+        hosts_mock
+            .expect_list_hosts()
+            .returning(move || Ok(hosts.clone()));
+
+        hosts_mock
+            .expect_list_backups()
+            .with(eq("pc-1"))
+            .returning(move |_| Ok(backups_pc1.clone()));
+
+        hosts_mock
+            .expect_list_backups()
+            .with(eq("pc-2"))
+            .returning(move |_| Ok(backups_pc2.clone()));
+
+        hosts_mock
+            .expect_list_backups()
+            .with(eq("pc-3"))
+            .returning(move |_| Ok(backups_pc3.clone()));
+
+        hosts_mock
+            .expect_list_backups_to_fill()
+            .with(eq("pc-1"), eq(1))
+            .returning(|_, _| Ok(vec![create_mock_backup(1)]));
+
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|hostname, backup_number, share, path| {
+                hostname == "pc-1" && backup_number == &1 && share.is_none() && path.is_none()
+            })
+            .returning(move |_, _, _, _| {
+                Ok(vec![
+                    create_file_attributes("/home", FileType::Dir),
+                    create_file_attributes("/volume1/test", FileType::Dir),
+                    create_file_attributes("/volume1/test2", FileType::Dir),
+                ])
+            });
+
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|hostname, backup_number, share, path| {
+                hostname == "pc-1" && backup_number == &2 && share.is_none() && path.is_none()
+            })
+            .returning(move |_, _, _, _| {
+                Ok(vec![
+                    create_file_attributes("/volume1/test", FileType::Dir),
+                    create_file_attributes("/volume1/test2", FileType::Dir),
+                    create_file_attributes("/volume2", FileType::Dir),
+                ])
+            });
+
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|hostname, backup_number, share, path| {
+                hostname == "pc-1"
+                    && backup_number == &1
+                    && share.is_some_and(|share| share == "/volume1/test")
+                    && path.is_some_and(|path| path.is_empty())
+            })
+            .returning(move |_, _, _, _| {
+                Ok(vec![
+                    create_file_attributes("supertest", FileType::Dir),
+                    create_file_attributes("toto", FileType::Dir),
+                ])
+            });
+
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|hostname, backup_number, share, path| {
+                hostname == "pc-1"
+                    && backup_number == &1
+                    && share.is_some_and(|share| share == "/volume1/test")
+                    && path.is_some_and(|path| path == "supertest")
+            })
+            .returning(move |_, _, _, _| {
+                Ok(vec![
+                    create_file_attributes("de", FileType::Dir),
+                    create_file_attributes("test2", FileType::Dir),
+                ])
+            });
+
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|hostname, backup_number, share, path| {
+                hostname == "pc-1"
+                    && backup_number == &1
+                    && share.is_some_and(|share| share == "/volume1/test")
+                    && path.is_some_and(|path| path == "supertest/de")
+            })
+            .returning(move |_, _, _, _| {
+                Ok(vec![
+                    create_file_attributes("test", FileType::Dir),
+                    create_file_attributes("en", FileType::Dir),
+                    create_file_attributes("es", FileType::Dir),
+                    create_file_attributes("fr", FileType::Dir),
+                ])
+            });
+
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|hostname, backup_number, share, path| {
+                hostname == "pc-1"
+                    && backup_number == &1
+                    && share.is_some_and(|share| share == "/volume1/test")
+                    && path.is_some_and(|path| path == "supertest/de/test")
+            })
+            .returning(move |_, _, _, _| {
+                Ok(vec![
+                    create_file_attributes("file1", FileType::File),
+                    create_file_attributes("file2", FileType::File),
+                    create_file_attributes("file3", FileType::File),
+                ])
+            });
+
+        BackupPC::new(topdir, hosts_mock, search_mock)
+    }
+
+    // Returns a top directory in the temporary directory, named after the test and the process.
+    fn temp_topdir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("bpc_{name}_{}", std::process::id()))
+    }
+
+    // Compresses a content with zlib, as the files of the cpool directory.
+    fn compress(content: &[u8]) -> Vec<u8> {
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, content).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    // Writes a file in the directory `pool` (pool or cpool) of the top directory, and returns its path.
+    fn write_pool_file(topdir: &Path, pool: &str, digest: &[u8], content: &[u8]) -> PathBuf {
+        let pool_dir = topdir
+            .join(pool)
+            .join(format!("{:02x}", digest[0] & 0xfe))
+            .join(format!("{:02x}", digest[1] & 0xfe));
+        std::fs::create_dir_all(&pool_dir).unwrap();
+        let pool_file = pool_dir.join(vec_to_hex_string(digest));
+        std::fs::write(&pool_file, content).unwrap();
+        pool_file
+    }
+
+    // Creates the mocks of the backup 1 of the host pc-1 (filled from itself) with the share "/share": the files of the
+    // share are listed by the expectations added by each test to the search mock.
+    fn create_share_mocks() -> (Box<MockHostsTrait>, Box<MockSearchTrait>) {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+        hosts_mock
+            .expect_list_backups_to_fill()
+            .returning(|_, _| Ok(vec![create_mock_backup(1)]));
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_none() && path.is_none())
+            .returning(|_, _, _, _| Ok(vec![create_file_attributes("/share", FileType::Dir)]));
+        (hosts_mock, search_mock)
+    }
+
+    #[test]
+    fn test_list_not_found_and_empty_directories() {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+        hosts_mock
+            .expect_list_hosts()
+            .returning(|| Ok(vec!["pc-1".to_string(), "pc-3".to_string()]));
+        hosts_mock
+            .expect_list_backups()
+            .with(eq("pc-1"))
+            .returning(|_| Ok(vec![create_mock_backup(1)]));
+        hosts_mock
+            .expect_list_backups()
+            .returning(|_| Err(std::io::Error::from(std::io::ErrorKind::NotFound).into()));
+        hosts_mock
+            .expect_list_backups_to_fill()
+            .with(eq("pc-1"), eq(1))
+            .returning(|_, _| Ok(vec![create_mock_backup(1)]));
+        hosts_mock
+            .expect_list_backups_to_fill()
+            .returning(|_, _| Ok(Vec::new()));
+        search_mock
+            .expect_list_file_from_dir()
+            .returning(|_, _, share, path| match (share, path) {
+                (None, None) => Ok(vec![create_file_attributes("/share", FileType::Dir)]),
+                (Some(_), Some("")) => Ok(vec![
+                    create_file_attributes("empty", FileType::Dir),
+                    create_file_attributes("file", FileType::File),
+                ]),
+                _ => Ok(Vec::new()),
+            });
+        let view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+
+        let is_not_found = |path: &[&str]| {
+            view.list(path)
+                .unwrap_err()
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound)
+        };
+
+        assert!(view.list(&["pc-3"]).unwrap().is_empty());
+        assert!(is_not_found(&["pc-4"]));
+        assert!(is_not_found(&["pc-1", "2"]));
+        assert!(view
+            .list(&["pc-1", "1", "share", "empty"])
+            .unwrap()
+            .is_empty());
+        assert!(is_not_found(&["pc-1", "1", "share", "missing"]));
+        assert!(is_not_found(&["pc-1", "1", "share", "file"]));
+        assert!(is_not_found(&["pc-1", "1", "share", "missing", "sub"]));
+    }
+
+    #[test]
+    fn test_list_lenient() {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+        hosts_mock
+            .expect_list_hosts()
+            .returning(|| Ok(vec!["pc-1".to_string()]));
+        hosts_mock
+            .expect_list_backups()
+            .returning(|_| Ok(vec![create_mock_backup(1), create_mock_backup(2)]));
+        hosts_mock
+            .expect_list_backups_to_fill()
+            .returning(|_, _| Ok(vec![create_mock_backup(1), create_mock_backup(2)]));
+        search_mock
+            .expect_list_file_from_dir()
+            .returning(|_, num, share, path| match (num, share, path) {
+                (_, None, _) => Ok(vec![create_file_attributes("/share", FileType::Dir)]),
+                (_, Some(_), Some("")) => Ok(vec![
+                    create_file_attributes("corrupt", FileType::Dir),
+                    create_file_attributes("partial", FileType::Dir),
+                ]),
+                (_, Some(_), Some("corrupt")) | (1, Some(_), Some("partial")) => Err(
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, "corrupt attrib").into(),
+                ),
+                _ => Ok(vec![create_file_attributes("file", FileType::File)]),
+            });
+        let mut view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+
+        // By default, a corrupt attrib file fails the listing
+        assert!(view.list(&["pc-1", "2", "share", "corrupt"]).is_err());
+        assert!(view.list(&["pc-1", "2", "share", "partial"]).is_err());
+        assert_eq!(view.list(&["pc-1", "2", "share"]).unwrap().len(), 2);
+
+        view.set_lenient(true);
+        assert!(view
+            .list(&["pc-1", "2", "share", "corrupt"])
+            .unwrap()
+            .is_empty());
+        let files = view.list(&["pc-1", "2", "share", "partial"]).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "file");
+        assert_eq!(view.list(&["pc-1", "2", "share"]).unwrap().len(), 2);
+
+        // The listings that skipped an error aren't cached, the others are
+        let misses = view.cache_metrics().misses;
+        view.list(&["pc-1", "2", "share", "partial"]).unwrap();
+        assert_eq!(view.cache_metrics().misses, misses + 1);
+        view.list(&["pc-1", "2", "share"]).unwrap();
+        assert_eq!(view.cache_metrics().misses, misses + 1);
+    }
+
+    #[test]
+    fn test_list_at_time() {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+        let backup = |num: u32, end_time: u64| BackupInformation {
+            start_time: end_time - 50,
+            end_time,
+            ..create_mock_backup(num)
+        };
+        hosts_mock
+            .expect_list_hosts()
+            .returning(|| Ok(vec!["pc-1".to_string()]));
+        hosts_mock
+            .expect_list_backups()
+            .with(eq("pc-1"))
+            .returning(move |_| Ok(vec![backup(1, 100), backup(2, 200)]));
+        hosts_mock
+            .expect_list_backups_to_fill()
+            .returning(move |_, num| Ok(vec![create_mock_backup(num)]));
+        search_mock
+            .expect_list_file_from_dir()
+            .returning(|_, num, share, _| match (num, share) {
+                (_, None) => Ok(vec![create_file_attributes("/share", FileType::Dir)]),
+                (1, Some(_)) => Ok(vec![create_file_attributes("old", FileType::File)]),
+                (_, Some(_)) => Ok(vec![create_file_attributes("new", FileType::File)]),
+            });
+        let view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+
+        let names = |timestamp: u64| -> Vec<String> {
+            view.list_at_time("pc-1", timestamp, &["share"])
+                .unwrap()
+                .into_iter()
+                .map(|file| file.name)
+                .collect()
+        };
+        assert_eq!(names(150), vec!["old"]);
+        assert_eq!(names(200), vec!["new"]);
+        assert_eq!(names(1000), vec!["new"]);
+
+        let err = view.list_at_time("pc-1", 99, &["share"]).unwrap_err();
+        assert!(err
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn test_list_with_partial_backups_hidden() {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+        hosts_mock.expect_list_backups().returning(|_| {
+            Ok(vec![
+                create_mock_backup(1),
+                BackupInformation {
+                    backup_type: "partial".to_string(),
+                    ..create_mock_backup(2)
+                },
+            ])
+        });
+        hosts_mock
+            .expect_list_backups_to_fill()
+            .returning(|_, num| Ok(vec![create_mock_backup(num)]));
+        search_mock
+            .expect_list_file_from_dir()
+            .returning(|_, _, _, _| Ok(vec![create_file_attributes("/share", FileType::Dir)]));
+        let mut view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+
+        assert_eq!(view.list(&["pc-1"]).unwrap().len(), 2);
+        assert_eq!(view.list(&["pc-1", "2"]).unwrap().len(), 1);
+
+        view.set_hide_partial(true);
+        let backups = view.list(&["pc-1"]).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].name, "1");
+        assert_eq!(view.list(&["pc-1", "1"]).unwrap().len(), 1);
+        assert!(view.list(&["pc-1", "2"]).is_err());
+    }
+
+    #[test]
+    fn test_list_backups_newest_first() {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let search_mock = Box::new(MockSearchTrait::new());
+        hosts_mock.expect_list_backups().returning(|_| {
+            Ok([(2, 200), (10, 1000), (9, 900), (1, 100)]
+                .into_iter()
+                .map(|(num, start_time)| BackupInformation {
+                    start_time,
+                    ..create_mock_backup(num)
+                })
+                .collect())
+        });
+        let mut view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+        let names = |view: &BackupPC| {
+            view.list(&["pc-1"])
+                .unwrap()
+                .into_iter()
+                .map(|backup| backup.name)
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(names(&view), vec!["1", "10", "2", "9"]);
+
+        view.set_backup_order(BackupOrder::NewestFirst);
+        assert_eq!(names(&view), vec!["10", "9", "2", "1"]);
+
+        assert_eq!(
+            "newest-first".parse::<BackupOrder>(),
+            Ok(BackupOrder::NewestFirst)
+        );
+        assert!("oldest".parse::<BackupOrder>().is_err());
+    }
+
+    #[test]
+    fn test_count() {
+        let view = create_view();
+
+        assert_eq!(view.count(&["pc-1", "1", "volume1", "test"]).unwrap(), 2);
+        // The second count uses the listing in the cache
+        assert_eq!(view.count(&["pc-1", "1", "volume1", "test"]).unwrap(), 2);
+        assert_eq!(view.count(&["pc-2"]).unwrap(), 3);
+        assert!(view.count(&["pc-1", "1", "missing"]).is_err());
+    }
+
+    #[test]
+    fn test_list_host_empty() {
+        let view = create_view();
+
+        let result = view.list(&[]);
+        assert!(result.is_ok());
+
+        let mut result = result.unwrap();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0], create_file_attributes("pc-1", FileType::Dir));
+        assert_eq!(result[1], create_file_attributes("pc-2", FileType::Dir));
+        assert_eq!(result[2], create_file_attributes("pc-3", FileType::Dir));
+    }
+
+    #[test]
+    fn test_list_host_pc1() {
+        let view = create_view();
+
+        let result = view.list(&["pc-1"]);
+        assert!(result.is_ok());
+
+        let mut result = result.unwrap();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+
+        println!("{:?}", result);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0], create_file_attributes("1", FileType::Dir));
+        assert_eq!(result[1], create_file_attributes("2", FileType::Dir));
+    }
+
+    #[test]
+    fn test_list_host_pc1_backup1() {
+        let view = create_view();
+
+        let result = view.list(&["pc-1", "1"]);
+        assert!(result.is_ok());
+
+        let mut result = result.unwrap();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+
+        println!("{:?}", result);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0], create_file_attributes("home", FileType::Dir));
+        assert_eq!(result[1], create_file_attributes("volume1", FileType::Dir));
+    }
+
+    #[test]
+    fn test_list_host_pc1_backup1_volume1() {
+        let view = create_view();
+
+        let result = view.list(&["pc-1", "1", "volume1"]);
+        assert!(result.is_ok());
+
+        let mut result = result.unwrap();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+
+        println!("{:?}", result);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0], create_file_attributes("test", FileType::Dir));
+        assert_eq!(result[1], create_file_attributes("test2", FileType::Dir));
+    }
+
+    #[test]
+    fn test_list_host_pc1_backup1_volume1_test() {
+        let view = create_view();
+
+        let result = view.list(&["pc-1", "1", "volume1", "test"]);
+        assert!(result.is_ok());
+
+        let mut result = result.unwrap();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+
+        println!("{:?}", result);
+        assert_eq!(result.len(), 2);
+        assert_eq!(
+            result[0],
+            create_file_attributes("supertest", FileType::Dir)
+        );
+        assert_eq!(result[1], create_file_attributes("toto", FileType::Dir));
+    }
+
+    #[test]
+    fn test_list_host_pc1_backup1_volume1_test_supertest() {
+        let view = create_view();
+
+        let result = view.list(&["pc-1", "1", "volume1", "test", "supertest"]);
+        assert!(result.is_ok());
+
+        let mut result = result.unwrap();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+
+        println!("{:?}", result);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0], create_file_attributes("de", FileType::Dir));
+        assert_eq!(result[1], create_file_attributes("test2", FileType::Dir));
+    }
+
+    #[test]
+    fn test_resolve_inode() {
+        let hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+
+        // The inode 0x20805 is in the directory 0x01, in the attrib file 0x02, with the name 050802
+        search_mock
+            .expect_list_attributes()
+            .withf(|hostname, backup_number, attrib_path, attrib_file| {
+                hostname == "pc-1"
+                    && backup_number == &1
+                    && attrib_path == "inode/01"
+                    && attrib_file == "attrib02_"
+            })
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(vec![
+                    create_file_attributes("050802", FileType::File),
+                    create_file_attributes("060802", FileType::File),
+                ])
+            });
+
+        let view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+
+        let inode = view.resolve_inode("pc-1", 1, 0x20805).unwrap();
+        assert_eq!(inode.unwrap().name, "050802");
+        let inode = view.resolve_inode("pc-1", 1, 0x20806).unwrap();
+        assert_eq!(inode.unwrap().name, "060802");
+        assert!(view.resolve_inode("pc-1", 1, 0x20807).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_resolve_inode_with_zero_padded_name() {
+        let hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+
+        search_mock
+            .expect_list_attributes()
+            .withf(|_, _, attrib_path, attrib_file| {
+                attrib_path == "inode/01" && attrib_file == "attrib02_"
+            })
+            .returning(|_, _, _, _| {
+                Ok(vec![
+                    create_file_attributes("0508020000000000", FileType::File),
+                    create_file_attributes("06080200", FileType::File),
+                    create_file_attributes("not-an-inode", FileType::File),
+                ])
+            });
+
+        let view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+
+        let inode = view.resolve_inode("pc-1", 1, 0x20805).unwrap();
+        assert_eq!(inode.unwrap().name, "0508020000000000");
+        let inode = view.resolve_inode("pc-1", 1, 0x20806).unwrap();
+        assert_eq!(inode.unwrap().name, "06080200");
+        assert!(view.resolve_inode("pc-1", 1, 0x20807).unwrap().is_none());
+
+        assert_eq!(parse_inode_name("00"), Some(0));
+        assert_eq!(parse_inode_name("ffffffffffffffff00"), Some(u64::MAX));
+        assert_eq!(parse_inode_name("000000000000000001"), None);
+        assert_eq!(parse_inode_name(""), None);
     }
 
-    fn create_file_attributes(name: &str, type_: FileType) -> FileAttributes {
-        FileAttributes {
-            name: name.to_string(),
-            type_,
-            compress: 0,
+    #[test]
+    fn test_get_inode() {
+        let hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
 
-            mode: 0,
-            uid: 0,
-            gid: 0,
-            nlinks: 0,
+        search_mock
+            .expect_find_inode()
+            .withf(|_, _, attrib_path, attrib_file, _| {
+                attrib_path == "inode/01" && attrib_file == "attrib02_"
+            })
+            .times(4)
+            .returning(|_, _, _, _, inode| {
+                Ok(["0508020000000000", "060802"]
+                    .into_iter()
+                    .map(|name| create_file_attributes(name, FileType::File))
+                    .find(|file| parse_inode_name(&file.name) == Some(inode)))
+            });
+        search_mock
+            .expect_list_attributes()
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(vec![
+                    create_file_attributes("050802", FileType::Dir),
+                    create_file_attributes("060802", FileType::Dir),
+                ])
+            });
 
-            mtime: 0,
-            size: 0,
-            inode: 0,
+        let mut view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+
+        let inode = view.get_inode("pc-1", 1, 0x20805).unwrap();
+        assert_eq!(inode.unwrap().name, "0508020000000000");
+        let inode = view.get_inode("pc-1", 1, 0x20806).unwrap();
+        assert_eq!(inode.unwrap().name, "060802");
+        assert!(view.get_inode("pc-1", 1, 0x20807).unwrap().is_none());
+
+        // The attrib file is scanned while it isn't cached, then the cached entries are used
+        view.set_scan_inodes(true);
+        let inode = view.resolve_inode("pc-1", 1, 0x20806).unwrap();
+        assert_eq!(inode.unwrap().type_, FileType::File);
+        view.set_scan_inodes(false);
+        let inode = view.resolve_inode("pc-1", 1, 0x20805).unwrap();
+        assert_eq!(inode.unwrap().type_, FileType::Dir);
+        view.set_scan_inodes(true);
+        let inode = view.resolve_inode("pc-1", 1, 0x20806).unwrap();
+        assert_eq!(inode.unwrap().type_, FileType::Dir);
+    }
 
-            bpc_digest: crate::decode_attribut::BpcDigest {
-                len: 0,
-                digest: Vec::new(),
-            },
-            xattr_num_entries: 0,
-            xattrs: Vec::new(),
-        }
+    #[test]
+    fn test_validate_topdir() {
+        let topdir = temp_topdir("validate_topdir");
+        let error = |topdir: &Path| {
+            BackupPC::validate_topdir(&LocalSource, topdir.to_str().unwrap())
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert!(error(&topdir).contains("doesn't exist"));
+
+        std::fs::create_dir_all(&topdir).unwrap();
+        let message = error(&topdir);
+        assert!(message.contains("the pc directory and a pool or cpool directory are missing"));
+
+        std::fs::create_dir_all(topdir.join("pc")).unwrap();
+        assert!(error(&topdir).contains("a pool or cpool directory is missing"));
+
+        std::fs::create_dir_all(topdir.join("cpool")).unwrap();
+        BackupPC::validate_topdir(&LocalSource, topdir.to_str().unwrap()).unwrap();
+
+        std::fs::remove_dir_all(&topdir).unwrap();
     }
 
-    fn create_view() -> BackupPC {
-        let topdir = "/var/lib/backuppc";
+    #[test]
+    fn test_with_pool_config() {
+        let topdir = temp_topdir("pool_config");
+        std::fs::create_dir_all(topdir.join("pc").join("pc-1")).unwrap();
+        std::fs::create_dir_all(topdir.join("pc").join("pc-2")).unwrap();
+        let pool =
+            PoolConfig::new(topdir.to_str().unwrap()).with_pool_roots(vec![topdir.join("pool2")]);
+
+        let view = BackupPC::with_pool_config(&pool, &BackupPCConfig::default());
+        let mut hosts = view
+            .list(&[])
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name)
+            .collect::<Vec<_>>();
+        hosts.sort();
+        assert_eq!(hosts, vec!["pc-1", "pc-2"]);
+        assert_eq!(view.pool_roots, pool.pool_roots);
+        assert!(Arc::ptr_eq(&view.source, &pool.source));
+
+        std::fs::remove_dir_all(&topdir).unwrap();
+    }
+
+    #[test]
+    fn test_inode_location() {
+        let location = |inode: u64| {
+            let (path, file, name) = inode_location(inode);
+            format!("{path}/{file}{name}")
+        };
+
+        assert_eq!(location(0), "inode/00/attrib00_00");
+        assert_eq!(location(0x3ff), "inode/00/attrib00_ff03");
+        assert_eq!(location(0x400), "inode/00/attrib01_0004");
+        assert_eq!(location(0x1ffff), "inode/00/attrib7f_ffff01");
+        assert_eq!(location(0x20000), "inode/01/attrib00_000002");
+        assert_eq!(location(0xff_ffff), "inode/7f/attrib7f_ffffff");
+        // Above 2^24, the buckets wrap around, the name is still unique
+        assert_eq!(location(0x100_0000), "inode/00/attrib00_00000001");
+        assert_eq!(location(0x1_2345_6789), "inode/22/attrib59_8967452301");
+    }
+
+    #[test]
+    fn test_paths_for_inode() {
+        let (hosts_mock, mut search_mock) = create_share_mocks();
+        search_mock
+            .expect_list_attributes()
+            .returning(|_, _, _, _| Ok(Vec::new()));
+
+        let hard_link = |name: &str, inode: u64| {
+            let mut file = create_file_attributes(name, FileType::File);
+            file.nlinks = 2;
+            file.inode = inode;
+            file
+        };
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_some() && path == &Some(""))
+            .times(1)
+            .returning(move |_, _, _, _| {
+                Ok(vec![
+                    hard_link("a", 5),
+                    hard_link("other", 6),
+                    create_file_attributes("dir", FileType::Dir),
+                ])
+            });
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_some() && path == &Some("dir"))
+            .times(1)
+            .returning(move |_, _, _, _| {
+                let mut not_linked = create_file_attributes("c", FileType::File);
+                not_linked.inode = 5;
+                Ok(vec![hard_link("b", 5), not_linked])
+            });
+
+        let view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+
+        assert_eq!(
+            view.paths_for_inode("pc-1", 1, 5).unwrap(),
+            vec![vec!["share", "a"], vec!["share", "dir", "b"]]
+        );
+        // The second lookup uses the cached groups
+        assert_eq!(
+            view.paths_for_inode("pc-1", 1, 6).unwrap(),
+            vec![vec!["share", "other"]]
+        );
+        assert!(view.paths_for_inode("pc-1", 1, 7).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_paths_for_inode_cache_is_bounded() {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+        hosts_mock
+            .expect_list_backups_to_fill()
+            .returning(|_, num| Ok(vec![create_mock_backup(num)]));
+        search_mock
+            .expect_list_attributes()
+            .returning(|_, _, _, _| Ok(Vec::new()));
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_none() && path.is_none())
+            .returning(|_, _, _, _| Ok(vec![create_file_attributes("/share", FileType::Dir)]));
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, _| share.is_some())
+            .returning(|_, _, _, _| {
+                let mut file = create_file_attributes("a", FileType::File);
+                file.nlinks = 2;
+                file.inode = 5;
+                Ok(vec![file])
+            });
+
+        let config = BackupPCConfig::default().with_inode_paths_cache_size(1);
+        let view = BackupPC::new_with_config("/var/lib/backuppc", hosts_mock, search_mock, &config);
+        let key = |num: u32| ("pc-1".to_string(), num);
+
+        assert_eq!(
+            view.paths_for_inode("pc-1", 1, 5).unwrap(),
+            vec![vec!["share", "a"]]
+        );
+        assert!(view.inode_paths().contains(&key(1)));
+
+        // Only the hard links of the last backup are kept
+        assert_eq!(
+            view.paths_for_inode("pc-1", 2, 5).unwrap(),
+            vec![vec!["share", "a"]]
+        );
+        assert!(view.inode_paths().contains(&key(2)));
+        assert!(!view.inode_paths().contains(&key(1)));
+    }
+
+    #[test]
+    fn test_paths_for_inode_in_deep_directories() {
+        // A chain of nested directories share/d/d/.../d with a hard link at the bottom
+        const DEPTH: usize = 2000;
+
         let mut hosts_mock = Box::new(MockHostsTrait::new());
         let mut search_mock = Box::new(MockSearchTrait::new());
+        hosts_mock
+            .expect_list_backups_to_fill()
+            .returning(|_, _| Ok(vec![create_mock_backup(1)]));
+        search_mock
+            .expect_list_attributes()
+            .returning(|_, _, _, _| Ok(Vec::new()));
+        search_mock
+            .expect_list_file_from_dir()
+            .returning(|_, _, share, path| {
+                if share.is_none() {
+                    return Ok(vec![create_file_attributes("/share", FileType::Dir)]);
+                }
+                let depth = path
+                    .unwrap_or_default()
+                    .split('/')
+                    .filter(|s| !s.is_empty())
+                    .count();
+                if depth < DEPTH {
+                    return Ok(vec![create_file_attributes("d", FileType::Dir)]);
+                }
+                let mut leaf = create_file_attributes("leaf", FileType::File);
+                leaf.nlinks = 2;
+                leaf.inode = 5;
+                Ok(vec![leaf])
+            });
+        let view = BackupPC::new_with_capacity("/var/lib/backuppc", hosts_mock, search_mock, 16);
+
+        // The traversal uses a work stack, so it doesn't depend on the depth of the tree: run it with a small stack
+        let paths = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || view.paths_for_inode("pc-1", 1, 5).unwrap())
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].len(), DEPTH + 2);
+        assert_eq!(paths[0].last().unwrap(), "leaf");
+    }
 
-        let hosts = vec!["pc-1".to_string(), "pc-2".to_string(), "pc-3".to_string()];
+    #[test]
+    fn test_paths_for_inode_cancelled() {
+        let hosts_mock = Box::new(MockHostsTrait::new());
+        let search_mock = Box::new(MockSearchTrait::new());
+        let view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+
+        let token = CancellationToken::new();
+        token.clone().cancel();
+
+        let err = view
+            .paths_for_inode_cancellable("pc-1", 1, 5, &token)
+            .unwrap_err();
+        assert!(err.downcast_ref::<crate::cancel::Cancelled>().is_some());
+    }
 
-        let backups_pc1 = vec![create_mock_backup(1), create_mock_backup(2)];
+    #[test]
+    fn test_list_non_utf8_names_cancelled() {
+        let hosts_mock = Box::new(MockHostsTrait::new());
+        let search_mock = Box::new(MockSearchTrait::new());
+        let mut view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+
+        let token = CancellationToken::new();
+        view.set_cancellation_token(token.clone());
+        token.cancel();
+
+        let err = view.list_non_utf8_names("pc-1", 1).unwrap_err();
+        assert!(err.downcast_ref::<crate::cancel::Cancelled>().is_some());
+        let err = view.paths_for_inode("pc-1", 1, 5).unwrap_err();
+        assert!(err.downcast_ref::<crate::cancel::Cancelled>().is_some());
+    }
 
-        let backups_pc2 = vec![
-            create_mock_backup(1),
-            create_mock_backup(2),
-            create_mock_backup(3),
-        ];
+    #[test]
+    fn test_list_case_insensitive() {
+        let mut view = create_view();
+        view.set_case_insensitive(true);
 
-        let backups_pc3 = Vec::<BackupInformation>::new();
+        let mut result = view
+            .list(&["PC-1", "1", "Volume1", "TEST", "SuperTest"])
+            .unwrap();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0], create_file_attributes("de", FileType::Dir));
+        assert_eq!(result[1], create_file_attributes("test2", FileType::Dir));
+        assert!(view
+            .exists(&["pc-1", "1", "VOLUME1", "test", "supertest", "DE"])
+            .unwrap());
+    }
 
+    #[test]
+    fn test_windows_separators() {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+        hosts_mock.expect_list_backups().returning(|_| {
+            let mut windows = create_mock_backup(1);
+            windows.xfer_method = "smb".to_string();
+            Ok(vec![windows, create_mock_backup(2)])
+        });
         hosts_mock
-            .expect_list_hosts()
-            .returning(move || Ok(hosts.clone()));
+            .expect_list_backups_to_fill()
+            .returning(|_, num| Ok(vec![create_mock_backup(num)]));
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_none() && path.is_none())
+            .returning(|_, _, _, _| {
+                Ok(vec![
+                    create_file_attributes("C$", FileType::Dir),
+                    create_file_attributes("D:\\Data", FileType::Dir),
+                ])
+            });
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_some() && path.is_some_and(|path| path.is_empty()))
+            .returning(|_, _, _, _| {
+                Ok(vec![
+                    create_file_attributes("Users", FileType::Dir),
+                    create_file_attributes("a\\b.txt", FileType::File),
+                ])
+            });
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_some() && path == &Some("Users"))
+            .returning(|_, _, _, _| Ok(vec![create_file_attributes("notes.txt", FileType::File)]));
+
+        let mut view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+
+        // By default, a backslash is a character of the name
+        assert!(view.exists(&["pc-1", "1", "C$", "a\\b.txt"]).unwrap());
+        assert!(!view
+            .exists(&["pc-1", "1", "C$", "Users\\notes.txt"])
+            .unwrap());
+        assert!(view.exists(&["pc-1", "1", "D:\\Data", "Users"]).unwrap());
+        let names = |view: &BackupPC| {
+            view.list(&["pc-1", "1"])
+                .unwrap()
+                .into_iter()
+                .map(|file| file.name)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(names(&view), ["C$", "D:\\Data"]);
+
+        // The listings cached with the backslashes as characters are dropped
+        view.set_windows_separators(true);
+        assert_eq!(names(&view), ["C$", "D:"]);
+        assert!(view
+            .exists(&["pc-1", "1", "C$", "Users\\notes.txt"])
+            .unwrap());
+        assert!(view
+            .exists(&["pc-1", "1", "C$\\Users", "notes.txt"])
+            .unwrap());
+        assert_eq!(view.list(&["pc-1", "1", "C$\\Users"]).unwrap().len(), 1);
+        // The share names are split as the paths
+        assert!(view.exists(&["pc-1", "1", "D:", "Data", "Users"]).unwrap());
+        assert!(view.exists(&["pc-1", "1", "D:\\Data\\Users"]).unwrap());
+        // The backups made with another transfer method keep the backslashes in the names
+        assert!(view.exists(&["pc-1", "2", "C$", "a\\b.txt"]).unwrap());
+        assert!(!view
+            .exists(&["pc-1", "2", "C$", "Users\\notes.txt"])
+            .unwrap());
+    }
+
+    #[test]
+    fn test_list_host_pc1_backup1_volume1_test_supertest_de() {
+        let view = create_view();
+
+        let result = view.list(&["pc-1", "1", "volume1", "test", "supertest", "de"]);
+        assert!(result.is_ok());
+
+        let mut result = result.unwrap();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+
+        println!("{:?}", result);
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0], create_file_attributes("en", FileType::Dir));
+        assert_eq!(result[1], create_file_attributes("es", FileType::Dir));
+        assert_eq!(result[2], create_file_attributes("fr", FileType::Dir));
+        assert_eq!(result[3], create_file_attributes("test", FileType::Dir));
+    }
+
+    #[test]
+    fn test_list_host_pc1_backup1_volume1_test_supertest_de_test() {
+        let view = create_view();
+
+        let result = view.list(&["pc-1", "1", "volume1", "test", "supertest", "de", "test"]);
+        assert!(result.is_ok());
+
+        let mut result = result.unwrap();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+
+        println!("{:?}", result);
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0], create_file_attributes("file1", FileType::File));
+        assert_eq!(result[1], create_file_attributes("file2", FileType::File));
+        assert_eq!(result[2], create_file_attributes("file3", FileType::File));
+    }
+
+    #[test]
+    fn test_list_shares_of() {
+        let view = create_view();
+
+        let mut resolution = view.list_shares_of("pc-1", 1, &["volume1"]).unwrap();
+        resolution.subdirs.sort();
+        assert_eq!(
+            resolution,
+            ShareResolution {
+                subdirs: vec!["test".to_string(), "test2".to_string()],
+                selected_share: None,
+                share_depth: 0,
+            }
+        );
+
+        let resolution = view
+            .list_shares_of("pc-1", 1, &["volume1", "test", "supertest"])
+            .unwrap();
+        assert_eq!(
+            resolution,
+            ShareResolution {
+                subdirs: Vec::new(),
+                selected_share: Some("/volume1/test".to_string()),
+                share_depth: 2,
+            }
+        );
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn test_transcode_names() {
+        let (mut hosts_mock, mut search_mock) = create_share_mocks();
+        hosts_mock.expect_list_backups().returning(|_| {
+            let mut backup = create_mock_backup(1);
+            backup.charset = "cp1252".to_string();
+            Ok(vec![backup])
+        });
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, _| share.is_some())
+            .returning(|_, _, _, _| {
+                let mut file = create_file_attributes("", FileType::File);
+                file.name_bytes = Some(b"caf\xe9".to_vec());
+                let mut directory = create_file_attributes("", FileType::Dir);
+                directory.name_bytes = Some(b"dossi\xe9".to_vec());
+                Ok(vec![
+                    file,
+                    directory,
+                    create_file_attributes("plain", FileType::File),
+                ])
+            });
+        // The directory of a transcoded name is found by the bytes of its name
+        search_mock
+            .expect_list_attributes()
+            .withf(|_, _, attrib_path, _| attrib_path == "f%2fshare/fdossi%e9")
+            .returning(|_, _, _, _| Ok(vec![create_file_attributes("inner", FileType::File)]));
+
+        let mut view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+        let path = ["pc-1", "1", "share"];
+        let names = |view: &BackupPC| {
+            let mut names = view
+                .list(&path)
+                .unwrap()
+                .iter()
+                .map(|file| file.display_name().into_owned())
+                .collect::<Vec<String>>();
+            names.sort();
+            names
+        };
+
+        assert_eq!(names(&view), vec!["caf\u{fffd}", "dossi\u{fffd}", "plain"]);
+
+        view.set_transcode_names(true);
+        assert_eq!(names(&view), vec!["café", "dossié", "plain"]);
+        assert!(view.exists(&["pc-1", "1", "share", "café"]).unwrap());
+        let files = view.list(&["pc-1", "1", "share", "dossié"]).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "inner");
+        assert!(view
+            .exists(&["pc-1", "1", "share", "dossié", "inner"])
+            .unwrap());
+    }
 
+    #[test]
+    fn test_list_non_utf8_names() {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
         hosts_mock
-            .expect_list_backups()
-            .with(eq("pc-1"))
-            .returning(move |_| Ok(backups_pc1.clone()));
+            .expect_list_backups_to_fill()
+            .returning(|_, _| Ok(vec![create_mock_backup(1), create_mock_backup(2)]));
+        let non_utf8 = |bytes: &[u8], type_: FileType| {
+            let mut file = create_file_attributes("", type_);
+            file.name_bytes = Some(bytes.to_vec());
+            file
+        };
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_none() && path.is_none())
+            .returning(|_, _, _, _| Ok(vec![create_file_attributes("/share", FileType::Dir)]));
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_some() && path == &Some(""))
+            .returning(move |_, num, _, _| {
+                Ok(match num {
+                    1 => vec![
+                        non_utf8(b"caf\xe9", FileType::File),
+                        create_file_attributes("dir", FileType::Dir),
+                    ],
+                    _ => vec![
+                        non_utf8(b"\xff", FileType::File),
+                        non_utf8(b"\xc0", FileType::Dir),
+                        create_file_attributes("plain", FileType::File),
+                    ],
+                })
+            });
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_some() && path == &Some("dir"))
+            .returning(move |_, num, _, _| {
+                Ok(match num {
+                    1 => vec![non_utf8(b"\xfe", FileType::File)],
+                    _ => Vec::new(),
+                })
+            });
 
-        hosts_mock
-            .expect_list_backups()
-            .with(eq("pc-2"))
-            .returning(move |_| Ok(backups_pc2.clone()));
+        let view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
 
-        hosts_mock
-            .expect_list_backups()
-            .with(eq("pc-3"))
-            .returning(move |_| Ok(backups_pc3.clone()));
+        // The names of the backup 1 are found through the backup 2 filled from it, and the names that aren't valid
+        // UTF-8 are kept apart
+        assert_eq!(
+            view.list_non_utf8_names("pc-1", 2).unwrap(),
+            vec![
+                ("/share".to_string(), b"caf\xe9".to_vec()),
+                ("/share".to_string(), vec![0xc0]),
+                ("/share".to_string(), vec![0xff]),
+                ("/share/dir".to_string(), vec![0xfe]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list_overlapping_shares() {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
 
         hosts_mock
             .expect_list_backups_to_fill()
-            .with(eq("pc-1"), eq(1))
-            .returning(|_, _| vec![create_mock_backup(1)]);
+            .returning(|_, _| Ok(vec![create_mock_backup(1)]));
 
         search_mock
             .expect_list_file_from_dir()
-            .withf(|hostname, backup_number, share, path| {
-                hostname == "pc-1" && backup_number == &1 && share.is_none() && path.is_none()
-            })
-            .returning(move |_, _, _, _| {
+            .withf(|_, _, share, path| share.is_none() && path.is_none())
+            .returning(|_, _, _, _| {
                 Ok(vec![
+                    create_file_attributes("/home/user", FileType::Dir),
+                    create_file_attributes("/", FileType::Dir),
                     create_file_attributes("/home", FileType::Dir),
-                    create_file_attributes("/volume1/test", FileType::Dir),
-                    create_file_attributes("/volume1/test2", FileType::Dir),
                 ])
             });
-
         search_mock
             .expect_list_file_from_dir()
-            .withf(|hostname, backup_number, share, path| {
-                hostname == "pc-1" && backup_number == &2 && share.is_none() && path.is_none()
-            })
-            .returning(move |_, _, _, _| {
+            .withf(|_, _, share, path| share == &Some("/") && path == &Some(""))
+            .returning(|_, _, _, _| {
                 Ok(vec![
-                    create_file_attributes("/volume1/test", FileType::Dir),
-                    create_file_attributes("/volume1/test2", FileType::Dir),
-                    create_file_attributes("/volume2", FileType::Dir),
+                    create_file_attributes("etc", FileType::Dir),
+                    create_file_attributes("home", FileType::Dir),
                 ])
             });
-
         search_mock
             .expect_list_file_from_dir()
-            .withf(|hostname, backup_number, share, path| {
-                hostname == "pc-1"
-                    && backup_number == &1
-                    && share.is_some_and(|share| share == "/volume1/test")
-                    && path.is_some_and(|path| path.is_empty())
-            })
-            .returning(move |_, _, _, _| {
+            .withf(|_, _, share, path| share == &Some("/home") && path == &Some(""))
+            .returning(|_, _, _, _| {
                 Ok(vec![
-                    create_file_attributes("supertest", FileType::Dir),
-                    create_file_attributes("toto", FileType::Dir),
+                    create_file_attributes("other", FileType::Dir),
+                    create_file_attributes("user", FileType::Dir),
                 ])
             });
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share == &Some("/home/user") && path == &Some("docs"))
+            .returning(|_, _, _, _| Ok(vec![create_file_attributes("file", FileType::File)]));
+
+        let view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+
+        let resolution = view.list_shares_of("pc-1", 1, &[]).unwrap();
+        assert_eq!(
+            resolution,
+            ShareResolution {
+                subdirs: vec!["home".to_string()],
+                selected_share: Some("/".to_string()),
+                share_depth: 0,
+            }
+        );
+        let resolution = view.list_shares_of("pc-1", 1, &["home", "user"]).unwrap();
+        assert_eq!(resolution.selected_share, Some("/home/user".to_string()));
+        assert_eq!(resolution.share_depth, 2);
+        let resolution = view.list_shares_of("pc-1", 1, &["etc"]).unwrap();
+        assert_eq!(resolution.selected_share, Some("/".to_string()));
+        assert_eq!(resolution.share_depth, 0);
+
+        let names = |path: &[&str]| {
+            view.list(path)
+                .unwrap()
+                .into_iter()
+                .map(|file| file.name)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(names(&["pc-1", "1"]), vec!["etc", "home"]);
+        assert_eq!(names(&["pc-1", "1", "home"]), vec!["other", "user"]);
+        assert_eq!(names(&["pc-1", "1", "home", "user", "docs"]), vec!["file"]);
+    }
+
+    #[test]
+    fn test_exists_with_deleted_file_in_incremental() {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+
+        // Backup 4 is filled, backup 3 is an incremental where the file "deleted" doesn't exist
+        hosts_mock
+            .expect_list_backups_to_fill()
+            .with(eq("pc-1"), eq(3))
+            .returning(|_, _| Ok(vec![create_mock_backup(4), create_mock_backup(3)]));
 
         search_mock
             .expect_list_file_from_dir()
-            .withf(|hostname, backup_number, share, path| {
-                hostname == "pc-1"
-                    && backup_number == &1
-                    && share.is_some_and(|share| share == "/volume1/test")
-                    && path.is_some_and(|path| path == "supertest")
-            })
-            .returning(move |_, _, _, _| {
-                Ok(vec![
-                    create_file_attributes("de", FileType::Dir),
-                    create_file_attributes("test2", FileType::Dir),
-                ])
-            });
+            .withf(|_, _, share, path| share.is_none() && path.is_none())
+            .returning(|_, _, _, _| Ok(vec![create_file_attributes("/share", FileType::Dir)]));
 
         search_mock
             .expect_list_file_from_dir()
-            .withf(|hostname, backup_number, share, path| {
-                hostname == "pc-1"
-                    && backup_number == &1
-                    && share.is_some_and(|share| share == "/volume1/test")
-                    && path.is_some_and(|path| path == "supertest/de")
+            .withf(|_, backup_number, share, path| {
+                backup_number == &4
+                    && share.is_some_and(|share| share == "/share")
+                    && path.is_some_and(|path| path.is_empty())
             })
-            .returning(move |_, _, _, _| {
+            .returning(|_, _, _, _| {
                 Ok(vec![
-                    create_file_attributes("test", FileType::Dir),
-                    create_file_attributes("en", FileType::Dir),
-                    create_file_attributes("es", FileType::Dir),
-                    create_file_attributes("fr", FileType::Dir),
+                    create_file_attributes("kept", FileType::File),
+                    create_file_attributes("deleted", FileType::File),
                 ])
             });
 
         search_mock
             .expect_list_file_from_dir()
-            .withf(|hostname, backup_number, share, path| {
-                hostname == "pc-1"
-                    && backup_number == &1
-                    && share.is_some_and(|share| share == "/volume1/test")
-                    && path.is_some_and(|path| path == "supertest/de/test")
+            .withf(|_, backup_number, share, path| {
+                backup_number == &3
+                    && share.is_some_and(|share| share == "/share")
+                    && path.is_some_and(|path| path.is_empty())
             })
-            .returning(move |_, _, _, _| {
+            .returning(|_, _, _, _| Ok(vec![create_file_attributes("deleted", FileType::Deleted)]));
+
+        let view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+
+        assert!(view.exists(&["pc-1", "3", "share", "kept"]).unwrap());
+        assert!(!view.exists(&["pc-1", "3", "share", "deleted"]).unwrap());
+        assert!(!view.exists(&["pc-1", "3", "share", "missing"]).unwrap());
+    }
+
+    #[test]
+    fn test_list_with_deleted_files_shown() {
+        let mut hosts_mock = Box::new(MockHostsTrait::new());
+        let mut search_mock = Box::new(MockSearchTrait::new());
+
+        hosts_mock
+            .expect_list_backups_to_fill()
+            .with(eq("pc-1"), eq(3))
+            .returning(|_, _| Ok(vec![create_mock_backup(4), create_mock_backup(3)]));
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_none() && path.is_none())
+            .returning(|_, _, _, _| Ok(vec![create_file_attributes("/share", FileType::Dir)]));
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, backup_number, share, _| backup_number == &4 && share.is_some())
+            .returning(|_, _, _, _| {
                 Ok(vec![
-                    create_file_attributes("file1", FileType::File),
-                    create_file_attributes("file2", FileType::File),
-                    create_file_attributes("file3", FileType::File),
+                    create_file_attributes("kept", FileType::File),
+                    create_file_attributes("deleted", FileType::File),
                 ])
             });
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, backup_number, share, _| backup_number == &3 && share.is_some())
+            .returning(|_, _, _, _| Ok(vec![create_file_attributes("deleted", FileType::Deleted)]));
 
-        BackupPC::new(topdir, hosts_mock, search_mock)
+        let mut view = BackupPC::new("/var/lib/backuppc", hosts_mock, search_mock);
+        let path = ["pc-1", "3", "share"];
+
+        assert_eq!(view.list(&path).unwrap().len(), 1);
+
+        view.set_show_deleted(true);
+        let mut files = view.list(&path).unwrap();
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].name, "deleted");
+        assert_eq!(files[0].type_, FileType::Deleted);
+        assert!(!view.exists(&["pc-1", "3", "share", "deleted"]).unwrap());
+        assert!(view.read_file(&["pc-1", "3", "share", "deleted"]).is_err());
     }
 
     #[test]
-    fn test_list_host_empty() {
-        let mut view = create_view();
+    fn test_shared_view_from_multiple_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<BackupPC>();
+        assert_send_sync::<SharedBackupPC>();
+
+        let view = SharedBackupPC::new(create_view());
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let view = view.clone();
+                scope.spawn(move || {
+                    let result = view.list(&["pc-1", "1", "volume1", "test"]).unwrap();
+                    assert_eq!(result.len(), 2);
+                });
+            }
+        });
+    }
 
-        let result = view.list(&[]);
-        assert!(result.is_ok());
+    #[test]
+    fn test_overlapping_reads_from_multiple_threads() {
+        let topdir = temp_topdir("overlapping_reads");
+        // A small file (kept in the small file cache) and a large file (read from the pool at each read)
+        let small_digest: Vec<u8> = (0x50..0x60).collect();
+        let large_digest: Vec<u8> = (0x60..0x70).collect();
+        let small_content = (0..65_536).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let large_content = (0..300_000).map(|i| (i % 241) as u8).collect::<Vec<u8>>();
+        write_pool_file(&topdir, "pool", &small_digest, &small_content);
+        write_pool_file(&topdir, "pool", &large_digest, &large_content);
+
+        let (hosts_mock, mut search_mock) = create_share_mocks();
+        let files = [
+            ("small", small_content.len(), small_digest),
+            ("large", large_content.len(), large_digest),
+        ];
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, _| share.is_some())
+            .returning(move |_, _, _, _| {
+                Ok(files
+                    .iter()
+                    .map(|(name, size, digest)| {
+                        let mut file = create_file_attributes(name, FileType::File);
+                        file.compress = 0;
+                        file.size = *size as u64;
+                        file.bpc_digest.len = 16;
+                        file.bpc_digest.digest.clone_from(digest);
+                        file
+                    })
+                    .collect())
+            });
 
-        let mut result = result.unwrap();
-        result.sort_by(|a, b| a.name.cmp(&b.name));
+        // The threads share the view (and its caches) without any lock around it, and start together so their reads
+        // overlap
+        let view = SharedBackupPC::new(BackupPC::new(
+            topdir.to_str().unwrap(),
+            hosts_mock,
+            search_mock,
+        ));
+        let barrier = Arc::new(std::sync::Barrier::new(8));
+        let contents = Arc::new([("small", small_content), ("large", large_content)]);
+        let threads = (0..8_usize)
+            .map(|thread| {
+                let view = view.clone();
+                let barrier = barrier.clone();
+                let contents = contents.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    for i in 0..50 {
+                        let (name, content) = &contents[(thread + i) % 2];
+                        let path = ["pc-1", "1", "share", name];
+                        let offset = (thread * 7919 + i * 1231) % (content.len() - 300);
+                        let data = view.read_range(&path, offset as u64, 300).unwrap();
+                        assert_eq!(data, content[offset..offset + 300]);
+
+                        let mut reader = view.read_file(&path).unwrap();
+                        skip(&mut reader, offset as u64, 1000).unwrap();
+                        let mut data = vec![0; 300];
+                        reader.read_exact(&mut data).unwrap();
+                        assert_eq!(data, content[offset..offset + 300]);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
 
-        assert_eq!(result.len(), 3);
-        assert_eq!(result[0], create_file_attributes("pc-1", FileType::Dir));
-        assert_eq!(result[1], create_file_attributes("pc-2", FileType::Dir));
-        assert_eq!(result[2], create_file_attributes("pc-3", FileType::Dir));
+        std::fs::remove_dir_all(&topdir).unwrap();
     }
 
     #[test]
-    fn test_list_host_pc1() {
-        let mut view = create_view();
+    fn test_read_file_with_empty_pool_file() {
+        let topdir = temp_topdir("truncated");
+        let digest: Vec<u8> = (0x20..0x30).collect();
+        write_pool_file(&topdir, "cpool", &digest, b"");
 
-        let result = view.list(&["pc-1"]);
-        assert!(result.is_ok());
+        let (hosts_mock, mut search_mock) = create_share_mocks();
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, _| share.is_some())
+            .returning(move |_, _, _, _| {
+                let mut file = create_file_attributes("file", FileType::File);
+                file.size = 10;
+                file.bpc_digest.len = 16;
+                file.bpc_digest.digest.clone_from(&digest);
+                Ok(vec![file])
+            });
 
-        let mut result = result.unwrap();
-        result.sort_by(|a, b| a.name.cmp(&b.name));
+        let view = BackupPC::new(topdir.to_str().unwrap(), hosts_mock, search_mock);
+        let err = view
+            .read_file(&["pc-1", "1", "share", "file"])
+            .err()
+            .unwrap();
+        let err = err.downcast_ref::<TruncatedPoolFileError>().unwrap();
+        assert_eq!(err.expected_size, 10);
 
-        println!("{:?}", result);
-        assert_eq!(result.len(), 2);
-        assert_eq!(result[0], create_file_attributes("1", FileType::Dir));
-        assert_eq!(result[1], create_file_attributes("2", FileType::Dir));
+        std::fs::remove_dir_all(topdir).unwrap();
     }
 
     #[test]
-    fn test_list_host_pc1_backup1() {
-        let mut view = create_view();
-
-        let result = view.list(&["pc-1", "1"]);
-        assert!(result.is_ok());
+    fn test_read_file_with_strict_size() {
+        let topdir = temp_topdir("strict_size");
+        let digest: Vec<u8> = (0x30..0x40).collect();
+        write_pool_file(&topdir, "cpool", &digest, &compress(b"12345"));
 
-        let mut result = result.unwrap();
-        result.sort_by(|a, b| a.name.cmp(&b.name));
+        let (hosts_mock, mut search_mock) = create_share_mocks();
+        // The same 5 bytes content for a file of 8 bytes (short), of 5 bytes, and of 2 bytes (long)
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, _| share.is_some())
+            .returning(move |_, _, _, _| {
+                Ok([("short", 8), ("exact", 5), ("long", 2)]
+                    .into_iter()
+                    .map(|(name, size)| {
+                        let mut file = create_file_attributes(name, FileType::File);
+                        file.size = size;
+                        file.bpc_digest.len = 16;
+                        file.bpc_digest.digest.clone_from(&digest);
+                        file
+                    })
+                    .collect())
+            });
 
-        println!("{:?}", result);
-        assert_eq!(result.len(), 2);
-        assert_eq!(result[0], create_file_attributes("home", FileType::Dir));
-        assert_eq!(result[1], create_file_attributes("volume1", FileType::Dir));
+        let mut view = BackupPC::new(topdir.to_str().unwrap(), hosts_mock, search_mock);
+        let read = |view: &BackupPC, name: &str| -> std::io::Result<Vec<u8>> {
+            let mut content = Vec::new();
+            view.read_file(&["pc-1", "1", "share", name])
+                .map_err(|err| std::io::Error::other(err.to_string()))?
+                .read_to_end(&mut content)?;
+            Ok(content)
+        };
+
+        // By default, the short content is padded, and the long content is kept
+        assert_eq!(read(&view, "short").unwrap(), b"12345\0\0\0");
+        assert_eq!(read(&view, "long").unwrap(), b"12345");
+
+        view.set_strict_size(true);
+        assert_eq!(read(&view, "exact").unwrap(), b"12345");
+        let err = view
+            .read_file(&["pc-1", "1", "share", "short"])
+            .err()
+            .unwrap();
+        assert!(err
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|err| err.kind() == std::io::ErrorKind::UnexpectedEof));
+        let err = view
+            .read_file(&["pc-1", "1", "share", "long"])
+            .err()
+            .unwrap();
+        assert!(err
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|err| err.kind() == std::io::ErrorKind::InvalidData));
+
+        std::fs::remove_dir_all(topdir).unwrap();
     }
 
     #[test]
-    fn test_list_host_pc1_backup1_volume1() {
-        let mut view = create_view();
+    fn test_small_file_cache_evicts_least_recently_used() {
+        let mut cache = SmallFileCache::new(10);
+        cache.insert(vec![1], Arc::from(vec![0; 4]));
+        cache.insert(vec![2], Arc::from(vec![0; 4]));
+        assert!(cache.get(&[1]).is_some());
+
+        cache.insert(vec![3], Arc::from(vec![0; 4]));
+        assert!(cache.get(&[1]).is_some());
+        assert!(cache.get(&[2]).is_none());
+        assert!(cache.get(&[3]).is_some());
+        assert_eq!(cache.size, 8);
+
+        cache.insert(vec![4], Arc::from(vec![0; 11]));
+        assert!(cache.get(&[4]).is_none());
+    }
 
-        let result = view.list(&["pc-1", "1", "volume1"]);
-        assert!(result.is_ok());
+    #[test]
+    fn test_small_file_cache_disabled() {
+        let topdir = temp_topdir("no_cache");
+        let digest: Vec<u8> = (0x40..0x50).collect();
+        let pool_file = write_pool_file(&topdir, "pool", &digest, b"0123456789");
 
-        let mut result = result.unwrap();
-        result.sort_by(|a, b| a.name.cmp(&b.name));
+        let (hosts_mock, mut search_mock) = create_share_mocks();
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, _| share.is_some())
+            .returning(move |_, _, _, _| {
+                let mut file = create_file_attributes("file", FileType::File);
+                file.size = 10;
+                file.bpc_digest.len = 16;
+                file.bpc_digest.digest.clone_from(&digest);
+                Ok(vec![file])
+            });
 
-        println!("{:?}", result);
-        assert_eq!(result.len(), 2);
-        assert_eq!(result[0], create_file_attributes("test", FileType::Dir));
-        assert_eq!(result[1], create_file_attributes("test2", FileType::Dir));
+        let config = BackupPCConfig::default().with_small_file_cache_size(0);
+        let view =
+            BackupPC::new_with_config(topdir.to_str().unwrap(), hosts_mock, search_mock, &config);
+        let path = ["pc-1", "1", "share", "file"];
+        let read = || {
+            let mut content = String::new();
+            view.read_file(&path)
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            content
+        };
+
+        assert_eq!(read(), "0123456789");
+        // The content is read again from the pool
+        std::fs::write(&pool_file, b"9876543210").unwrap();
+        assert_eq!(read(), "9876543210");
+
+        std::fs::remove_dir_all(topdir).unwrap();
     }
 
     #[test]
-    fn test_list_host_pc1_backup1_volume1_test() {
-        let mut view = create_view();
-
-        let result = view.list(&["pc-1", "1", "volume1", "test"]);
-        assert!(result.is_ok());
+    fn test_read_range_and_pool_path_of() {
+        let topdir = temp_topdir("read_range");
+        let digest: Vec<u8> = (0x10..0x20).collect();
+        let pool_file = write_pool_file(&topdir, "pool", &digest, b"0123456789");
 
-        let mut result = result.unwrap();
-        result.sort_by(|a, b| a.name.cmp(&b.name));
+        let (hosts_mock, mut search_mock) = create_share_mocks();
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, _| share.is_some())
+            .returning(move |_, _, _, _| {
+                let mut file = create_file_attributes("file", FileType::File);
+                file.size = 10;
+                file.bpc_digest.len = 16;
+                file.bpc_digest.digest.clone_from(&digest);
+                Ok(vec![file])
+            });
 
-        println!("{:?}", result);
-        assert_eq!(result.len(), 2);
+        let view = BackupPC::new(topdir.to_str().unwrap(), hosts_mock, search_mock);
+        let path = ["pc-1", "1", "share", "file"];
+
+        let mut file = view.read_file_with_meta(&path).unwrap();
+        assert_eq!(file.size, 10);
+        assert!(!file.is_compressed);
+        assert_eq!(file.digest, (0x10..0x20).collect::<Vec<u8>>());
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "0123456789");
+        // A directory has no content in the pool
+        let file = view.read_file_with_meta(&["pc-1", "1", "share"]).unwrap();
+        assert!(file.digest.is_empty());
+
+        assert_eq!(view.read_range(&path, 2, 5).unwrap(), b"23456");
+        assert_eq!(view.read_range(&path, 8, 10).unwrap(), b"89");
+        assert!(view.read_range(&path, 20, 10).unwrap().is_empty());
         assert_eq!(
-            result[0],
-            create_file_attributes("supertest", FileType::Dir)
+            view.pool_path_of(&path).unwrap(),
+            Some((pool_file.clone(), false))
         );
-        assert_eq!(result[1], create_file_attributes("toto", FileType::Dir));
+        assert_eq!(view.pool_path_of(&["pc-1", "1", "share"]).unwrap(), None);
+
+        // The content of the small file is now read from the cache
+        std::fs::write(&pool_file, b"9876543210").unwrap();
+        let mut content = String::new();
+        view.read_file(&path)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "0123456789");
+
+        std::fs::remove_dir_all(topdir).unwrap();
     }
 
     #[test]
-    fn test_list_host_pc1_backup1_volume1_test_supertest() {
-        let mut view = create_view();
-
-        let result = view.list(&["pc-1", "1", "volume1", "test", "supertest"]);
-        assert!(result.is_ok());
-
-        let mut result = result.unwrap();
-        result.sort_by(|a, b| a.name.cmp(&b.name));
+    fn test_max_read_size() {
+        let topdir = temp_topdir("max_read");
+        let digest: Vec<u8> = (0x30..0x40).collect();
+        write_pool_file(&topdir, "pool", &digest, b"0123456789");
+        // A small compressed pool file expanding to a large content
+        let bomb_digest: Vec<u8> = (0x50..0x60).collect();
+        write_pool_file(&topdir, "cpool", &bomb_digest, &compress(&[b'a'; 100_000]));
+
+        let (hosts_mock, mut search_mock) = create_share_mocks();
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, _| share.is_some())
+            .returning(move |_, _, _, _| {
+                // The pool files are longer than the size of the files
+                let mut file = create_file_attributes("file", FileType::File);
+                file.size = 4;
+                file.bpc_digest.len = 16;
+                file.bpc_digest.digest.clone_from(&digest);
+                let mut bomb = create_file_attributes("bomb", FileType::File);
+                bomb.size = 4;
+                bomb.bpc_digest.len = 16;
+                bomb.bpc_digest.digest.clone_from(&bomb_digest);
+                Ok(vec![file, bomb])
+            });
 
-        println!("{:?}", result);
-        assert_eq!(result.len(), 2);
-        assert_eq!(result[0], create_file_attributes("de", FileType::Dir));
-        assert_eq!(result[1], create_file_attributes("test2", FileType::Dir));
+        let mut view = BackupPC::new(topdir.to_str().unwrap(), hosts_mock, search_mock);
+        let read = |view: &BackupPC, path: &[&str]| -> Result<Vec<u8>> {
+            let mut content = Vec::new();
+            view.read_file(path)?.read_to_end(&mut content)?;
+            Ok(content)
+        };
+        let kind =
+            |err: Box<dyn std::error::Error>| err.downcast::<std::io::Error>().unwrap().kind();
+        let path = ["pc-1", "1", "share", "file"];
+
+        assert_eq!(read(&view, &path).unwrap(), b"0123456789");
+
+        view.set_max_read_size(Some(10));
+        assert_eq!(read(&view, &path).unwrap(), b"0123456789");
+        assert_eq!(view.read_range(&path, 2, 2).unwrap(), b"23");
+
+        view.set_max_read_size(Some(4));
+        let err = read(&view, &path).unwrap_err();
+        assert_eq!(kind(err), std::io::ErrorKind::InvalidData);
+        // The bytes skipped before the range are counted
+        view.set_max_read_size(Some(1));
+        assert!(view.read_range(&path, 2, 2).is_err());
+
+        // The limit applies to the decompressed content before it is cached as a small file
+        view.set_max_read_size(Some(1000));
+        let err = read(&view, &["pc-1", "1", "share", "bomb"]).unwrap_err();
+        assert_eq!(kind(err), std::io::ErrorKind::InvalidData);
+        let bomb_digest: Vec<u8> = (0x50..0x60).collect();
+        assert!(view.small_files().get(&bomb_digest).is_none());
+
+        std::fs::remove_dir_all(topdir).unwrap();
     }
 
     #[test]
-    fn test_list_host_pc1_backup1_volume1_test_supertest_de() {
-        let mut view = create_view();
+    fn test_pool_file_size() {
+        let topdir = temp_topdir("pool_file_size");
+        let digest: Vec<u8> = (0x30..0x40).collect();
+        write_pool_file(&topdir, "pool", &digest, b"0123456789");
+        let compressed_digest: Vec<u8> = (0x50..0x60).collect();
+        write_pool_file(
+            &topdir,
+            "cpool",
+            &compressed_digest,
+            &compress(&[b'a'; 1000]),
+        );
+        // A file of a listing gives the size of a content that isn't in the pool
+        let listed_digest: Vec<u8> = (0x70..0x80).collect();
 
-        let result = view.list(&["pc-1", "1", "volume1", "test", "supertest", "de"]);
-        assert!(result.is_ok());
+        let (hosts_mock, mut search_mock) = create_share_mocks();
+        let listed = listed_digest.clone();
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, _| share.is_some())
+            .returning(move |_, _, _, _| {
+                let mut file = create_file_attributes("file", FileType::File);
+                file.size = 42;
+                file.bpc_digest.len = 16;
+                file.bpc_digest.digest.clone_from(&listed);
+                Ok(vec![file])
+            });
 
-        let mut result = result.unwrap();
-        result.sort_by(|a, b| a.name.cmp(&b.name));
+        let view = BackupPC::new(topdir.to_str().unwrap(), hosts_mock, search_mock);
+        assert_eq!(
+            view.pool_file_size(&vec_to_hex_string(&digest)).unwrap(),
+            10
+        );
+        assert_eq!(
+            view.pool_file_size(&vec_to_hex_string(&compressed_digest))
+                .unwrap(),
+            1000
+        );
+        assert!(view
+            .pool_file_size(&vec_to_hex_string(&listed_digest))
+            .is_err());
 
-        println!("{:?}", result);
-        assert_eq!(result.len(), 4);
-        assert_eq!(result[0], create_file_attributes("en", FileType::Dir));
-        assert_eq!(result[1], create_file_attributes("es", FileType::Dir));
-        assert_eq!(result[2], create_file_attributes("fr", FileType::Dir));
-        assert_eq!(result[3], create_file_attributes("test", FileType::Dir));
+        view.list(&["pc-1", "1", "share"]).unwrap();
+        assert_eq!(
+            view.pool_file_size(&vec_to_hex_string(&listed_digest))
+                .unwrap(),
+            42
+        );
+        // The size is still known once the listing is evicted from the cache
+        view.cache().clear();
+        assert_eq!(
+            view.pool_file_size(&vec_to_hex_string(&listed_digest))
+                .unwrap(),
+            42
+        );
+
+        std::fs::remove_dir_all(topdir).unwrap();
     }
 
     #[test]
-    fn test_list_host_pc1_backup1_volume1_test_supertest_de_test() {
-        let mut view = create_view();
-
-        let result = view.list(&["pc-1", "1", "volume1", "test", "supertest", "de", "test"]);
-        assert!(result.is_ok());
+    fn test_read_file_from_backup_directory() {
+        let topdir = temp_topdir("backup_copy");
+        let backup_dir = topdir.join("pc").join("pc-1").join("1").join("f%2fshare");
+        std::fs::create_dir_all(backup_dir.join("fdir")).unwrap();
+        std::fs::write(backup_dir.join("fcompressed"), compress(b"compressed")).unwrap();
+        std::fs::write(backup_dir.join("fdir").join("fplain"), b"plain").unwrap();
+
+        let (hosts_mock, mut search_mock) = create_share_mocks();
+        // The digests of the files are not in the pool
+        let file_with_digest = |name: &str, size: u64, seed: u8| {
+            let mut file = create_file_attributes(name, FileType::File);
+            file.size = size;
+            file.bpc_digest.len = 16;
+            file.bpc_digest.digest = (seed..seed + 16).collect();
+            file
+        };
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_some() && path == &Some(""))
+            .returning(move |_, _, _, _| {
+                Ok(vec![
+                    file_with_digest("compressed", 10, 0x40),
+                    file_with_digest("missing", 10, 0x50),
+                    create_file_attributes("dir", FileType::Dir),
+                ])
+            });
+        search_mock
+            .expect_list_file_from_dir()
+            .withf(|_, _, share, path| share.is_some() && path == &Some("dir"))
+            .returning(move |_, _, _, _| Ok(vec![file_with_digest("plain", 5, 0x60)]));
+
+        let view = BackupPC::new(topdir.to_str().unwrap(), hosts_mock, search_mock);
+        let read = |path: &[&str]| {
+            let mut content = String::new();
+            view.read_file(path)
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            content
+        };
+
+        assert_eq!(read(&["pc-1", "1", "share", "compressed"]), "compressed");
+        assert_eq!(read(&["pc-1", "1", "share", "dir", "plain"]), "plain");
+        assert_eq!(
+            view.read_range(&["pc-1", "1", "share", "dir", "plain"], 1, 2)
+                .unwrap(),
+            b"la"
+        );
 
-        let mut result = result.unwrap();
-        result.sort_by(|a, b| a.name.cmp(&b.name));
+        // Without a copy in the backup directory, the error of the pool is returned
+        let err = view
+            .read_file(&["pc-1", "1", "share", "missing"])
+            .err()
+            .unwrap();
+        assert!(err
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound));
 
-        println!("{:?}", result);
-        assert_eq!(result.len(), 3);
-        assert_eq!(result[0], create_file_attributes("file1", FileType::File));
-        assert_eq!(result[1], create_file_attributes("file2", FileType::File));
-        assert_eq!(result[2], create_file_attributes("file3", FileType::File));
+        std::fs::remove_dir_all(topdir).unwrap();
     }
 }